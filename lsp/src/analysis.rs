@@ -0,0 +1,430 @@
+//! Editor queries over one assembled document.
+//!
+//! [`Analysis`] assembles the document text with
+//! [`assemble_source`](lc3_assembler::assemble_source) and lexes it once more
+//! on its own, so symbol positions refer to the text in the editor.
+//! Diagnostics and listings are mapped back through the assembly's
+//! [`SourceMap`](lc3_assembler::source_map::SourceMap), so problems inside
+//! included files are dropped and those in an included macro's body are
+//! shown on the invocation.  All positions are 0-based lines and character
+//! columns, as LSP expects.
+
+use lc3_assembler::error::Span;
+use lc3_assembler::first_pass::symbol_table::SymbolKind;
+use lc3_assembler::lexer::token::{Token, TokenKind};
+use lc3_assembler::lexer::tokenize;
+use lc3_assembler::source_map::SourceLocation;
+use lc3_assembler::{assemble_source, AssembleOptions, Assembly};
+
+/// Every instruction, pseudo-op and TRAP alias the assembler accepts.
+pub const MNEMONICS: &[&str] = &[
+    "ADD", "AND", "NOT", "BR", "BRN", "BRZ", "BRP", "BRNZ", "BRNP", "BRZP", "BRNZP", "JMP", "JSR",
+    "JSRR", "LD", "LDI", "LDR", "LEA", "ST", "STI", "STR", "TRAP", "RTI", "RET", "GETC", "OUT",
+    "PUTS", "IN", "PUTSP", "HALT",
+];
+
+/// Assembler directives, including the preprocessor and macro ones.
+pub const DIRECTIVES: &[&str] = &[
+    ".ORIG",
+    ".END",
+    ".FILL",
+    ".BLKW",
+    ".STRINGZ",
+    ".EQU",
+    ".SET",
+    ".INCLUDE",
+    ".MACRO",
+    ".ENDM",
+    ".REPT",
+    ".IRP",
+    ".ENDR",
+    ".EXTERNAL",
+    ".GLOBAL",
+    ".IF",
+    ".IFDEF",
+    ".IFNDEF",
+    ".ELSE",
+    ".ENDIF",
+];
+
+/// At most this many words are listed in a hover; longer `.BLKW`/`.STRINGZ`
+/// data is summarised.
+const HOVER_WORDS: usize = 8;
+
+/// A single-line range of characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub range: Range,
+    pub severity: Severity,
+    /// Stable diagnostic code, e.g. `E026`.
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// What a completion inserts, for the editor's icon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Keyword,
+    Register,
+    Macro,
+    Symbol,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: CompletionKind,
+    pub detail: Option<String>,
+}
+
+/// One document, assembled.
+pub struct Analysis {
+    asm: Assembly,
+    tokens: Vec<Token>,
+}
+
+impl Analysis {
+    /// Assemble `text`; `name` is its path, used to resolve `.INCLUDE`.
+    pub fn new(text: &str, name: &str) -> Self {
+        Self {
+            asm: assemble_source(text, name, &AssembleOptions::default()),
+            tokens: tokenize(text).tokens,
+        }
+    }
+
+    /// Errors and warnings, limited to lines that exist in the document.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let line_count = self.asm.source.lines().count();
+        let errors = self
+            .asm
+            .located_errors()
+            .map(|(_, loc, e)| (Severity::Error, e.kind.code(), &e.message, loc));
+        let warnings = self
+            .asm
+            .located_warnings()
+            .map(|(_, loc, w)| (Severity::Warning, w.kind.code(), &w.message, loc));
+        errors
+            .chain(warnings)
+            .filter_map(|(severity, code, message, loc)| {
+                let range = self.location_range(loc)?;
+                (range.line < line_count).then(|| Diagnostic {
+                    range,
+                    severity,
+                    code,
+                    message: message.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Where the symbol under the cursor is defined.
+    pub fn definition(&self, line: usize, character: usize) -> Option<Range> {
+        let name = self.symbol_at(line, character)?;
+        self.symbol_tokens(name)
+            .find(|&i| self.is_definition(i))
+            .map(|i| token_range(&self.tokens[i]))
+    }
+
+    /// Every mention of the symbol under the cursor, optionally including its
+    /// definition.
+    pub fn references(
+        &self,
+        line: usize,
+        character: usize,
+        include_declaration: bool,
+    ) -> Vec<Range> {
+        let Some(name) = self.symbol_at(line, character) else {
+            return Vec::new();
+        };
+        self.symbol_tokens(name)
+            .filter(|&i| include_declaration || !self.is_definition(i))
+            .map(|i| token_range(&self.tokens[i]))
+            .collect()
+    }
+
+    /// Markdown describing the symbol under the cursor and the words the
+    /// cursor's line assembled to.
+    pub fn hover(&self, line: usize, character: usize) -> Option<String> {
+        let mut parts = Vec::new();
+
+        if let Some(name) = self.symbol_at(line, character) {
+            if let Some(sym) = self.asm.symbol_table().lookup(name) {
+                parts.push(match sym.kind {
+                    SymbolKind::Label => format!("`{name}` — label at x{:04X}", sym.value as u16),
+                    SymbolKind::Constant => {
+                        format!(
+                            "`{name}` — constant = {} (x{:04X})",
+                            sym.value, sym.value as u16
+                        )
+                    }
+                    SymbolKind::Variable => {
+                        format!(
+                            "`{name}` — variable = {} (x{:04X})",
+                            sym.value, sym.value as u16
+                        )
+                    }
+                });
+            }
+        }
+
+        let info = self.asm.encoded.line_infos.iter().find(|info| {
+            let span = Span::point(
+                self.asm.first.source_lines[info.source_line_idx].line_number,
+                1,
+            );
+            let loc = self.asm.source_map.resolve(span);
+            !info.words.is_empty() && loc.file == self.asm.name && loc.line == line + 1
+        });
+        if let Some(info) = info {
+            let mut listing = String::from("```\n");
+            for (i, word) in info.words.iter().take(HOVER_WORDS).enumerate() {
+                let addr = info.address.wrapping_add(i as u16);
+                listing.push_str(&format!("x{addr:04X}  x{word:04X}  {word:016b}\n"));
+            }
+            if info.words.len() > HOVER_WORDS {
+                listing.push_str(&format!("… {} words in total\n", info.words.len()));
+            }
+            listing.push_str("```");
+            parts.push(listing);
+        }
+
+        (!parts.is_empty()).then(|| parts.join("\n\n"))
+    }
+
+    /// Mnemonics, directives, registers, macros and symbols.
+    pub fn completions(&self) -> Vec<Completion> {
+        let keyword = |label: &str| Completion {
+            label: label.to_string(),
+            kind: CompletionKind::Keyword,
+            detail: None,
+        };
+        let mut items: Vec<Completion> = MNEMONICS
+            .iter()
+            .chain(DIRECTIVES)
+            .map(|m| keyword(m))
+            .collect();
+
+        items.extend((0..8).map(|r| Completion {
+            label: format!("R{r}"),
+            kind: CompletionKind::Register,
+            detail: None,
+        }));
+
+        items.extend(self.asm.macros.iter().map(|m| {
+            let params: Vec<String> = m
+                .params
+                .iter()
+                .zip(&m.defaults)
+                .map(|(p, default)| match default {
+                    Some(default) => format!("%{p}={default}"),
+                    None => format!("%{p}"),
+                })
+                .collect();
+            Completion {
+                label: m.name.clone(),
+                kind: CompletionKind::Macro,
+                detail: Some(
+                    format!(".MACRO {} {}", m.name, params.join(", "))
+                        .trim_end()
+                        .to_string(),
+                ),
+            }
+        }));
+
+        let table = self.asm.symbol_table();
+        items.extend(
+            table
+                .sorted_by_name()
+                .into_iter()
+                .map(|(name, addr)| Completion {
+                    label: name.to_string(),
+                    kind: CompletionKind::Symbol,
+                    detail: Some(format!("x{addr:04X}")),
+                }),
+        );
+        items.extend(table.constants().into_iter().map(|(name, sym)| Completion {
+            label: name.to_string(),
+            kind: CompletionKind::Symbol,
+            detail: Some(format!("= {}", sym.value)),
+        }));
+
+        items
+    }
+
+    // ── Helpers ──────────────────────────────────────────────────────────────
+
+    /// Name of the defined symbol whose token covers the position.
+    fn symbol_at(&self, line: usize, character: usize) -> Option<&str> {
+        self.tokens.iter().find_map(|tok| match &tok.kind {
+            TokenKind::Label(name) => {
+                let range = token_range(tok);
+                let covers = range.line == line && (range.start..=range.end).contains(&character);
+                (covers && self.asm.symbol_table().lookup(name).is_some()).then_some(name.as_str())
+            }
+            _ => None,
+        })
+    }
+
+    /// Indices of the tokens that name `name`.
+    fn symbol_tokens<'a>(&'a self, name: &'a str) -> impl Iterator<Item = usize> + 'a {
+        self.tokens
+            .iter()
+            .enumerate()
+            .filter(move |(_, tok)| matches!(&tok.kind, TokenKind::Label(n) if n == name))
+            .map(|(i, _)| i)
+    }
+
+    /// A label token defines its symbol when it starts the line.
+    fn is_definition(&self, index: usize) -> bool {
+        index == 0 || self.tokens[index - 1].kind == TokenKind::Newline
+    }
+
+    /// Where a diagnostic belongs in this document: its own position, or the
+    /// macro invocation that brought in a line from another file.
+    fn location_range(&self, loc: &SourceLocation) -> Option<Range> {
+        if loc.file == self.asm.name {
+            let start = loc.col.saturating_sub(1);
+            return Some(Range {
+                line: loc.line - 1,
+                start,
+                end: start + loc.width,
+            });
+        }
+        let site = loc
+            .expansions
+            .iter()
+            .find(|exp| exp.file == self.asm.name)?;
+        let first = self
+            .tokens
+            .iter()
+            .find(|tok| tok.span.line == site.line && tok.kind != TokenKind::Newline);
+        Some(first.map(token_range).unwrap_or(Range {
+            line: site.line - 1,
+            start: 0,
+            end: 1,
+        }))
+    }
+}
+
+fn token_range(tok: &Token) -> Range {
+    let start = tok.span.col - 1;
+    Range {
+        line: tok.span.line - 1,
+        start,
+        end: start + tok.lexeme.chars().count(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "\
+.ORIG x3000
+LOOP ADD R0, R0, #1
+     BRnzp LOOP
+     LD R1, COUNT
+COUNT .FILL #10
+.END
+";
+
+    fn analyse(text: &str) -> Analysis {
+        Analysis::new(text, "<test>")
+    }
+
+    #[test]
+    fn definition_and_references_of_a_label() {
+        let a = analyse(PROGRAM);
+        let def = Range {
+            line: 1,
+            start: 0,
+            end: 4,
+        };
+        assert_eq!(a.definition(2, 12), Some(def));
+        assert_eq!(a.definition(1, 2), Some(def));
+        assert_eq!(
+            a.references(1, 0, true),
+            vec![
+                def,
+                Range {
+                    line: 2,
+                    start: 11,
+                    end: 15
+                }
+            ]
+        );
+        assert_eq!(
+            a.references(1, 0, false),
+            vec![Range {
+                line: 2,
+                start: 11,
+                end: 15
+            }]
+        );
+        assert_eq!(a.definition(1, 6), None, "ADD is not a symbol");
+    }
+
+    #[test]
+    fn hover_shows_address_and_encoding() {
+        let a = analyse(PROGRAM);
+        let text = a.hover(3, 12).unwrap();
+        assert!(text.contains("`COUNT` — label at x3003"), "{text}");
+        assert!(text.contains("x3002  x2200"), "{text}");
+        assert_eq!(a.hover(0, 1), None);
+    }
+
+    #[test]
+    fn diagnostics_cover_the_bad_token() {
+        let a = analyse(".ORIG x3000\nADD R0, R0, #99\nBR NOWHERE\n.END\n");
+        let diags = a.diagnostics();
+        assert_eq!(diags.len(), 2, "{diags:?}");
+        assert!(diags.iter().all(|d| d.severity == Severity::Error));
+        assert!(diags
+            .iter()
+            .any(|d| d.range.line == 2 && d.message.contains("NOWHERE")));
+    }
+
+    #[test]
+    fn diagnostics_in_macro_bodies_cover_the_written_text() {
+        let a = analyse(
+            ".MACRO BAD %REGISTER\nADD %REGISTER, %REGISTER, #99\n.ENDM\n.ORIG x3000\nBAD R1\n.END\n",
+        );
+        let diags = a.diagnostics();
+        let imm = diags.iter().find(|d| d.message.contains("99")).unwrap();
+        assert_eq!(
+            imm.range,
+            Range {
+                line: 1,
+                start: 26,
+                end: 29
+            }
+        );
+    }
+
+    #[test]
+    fn completes_registers_mnemonics_and_macros() {
+        let a = analyse(
+            ".MACRO PUSH %R\nADD R6, R6, #-1\nSTR %R, R6, #0\n.ENDM\n.ORIG x3000\nHALT\n.END\n",
+        );
+        let items = a.completions();
+        let find = |label: &str| items.iter().find(|c| c.label == label);
+        assert_eq!(find("R7").unwrap().kind, CompletionKind::Register);
+        assert_eq!(find("LDR").unwrap().kind, CompletionKind::Keyword);
+        let push = find("PUSH").unwrap();
+        assert_eq!(push.kind, CompletionKind::Macro);
+        assert_eq!(push.detail.as_deref(), Some(".MACRO PUSH %R"));
+    }
+}
@@ -0,0 +1,20 @@
+//! # LC-3 Language Server
+//!
+//! The library behind `lc3-lsp`, a Language Server Protocol server for LC-3
+//! assembly.  Editors start it and talk JSON-RPC over stdin/stdout.
+//!
+//! - [`analysis`] — diagnostics, definitions, references, hover and
+//!   completion for one document, computed with the `lc3_assembler` pipeline.
+//! - [`server`] — maps LSP requests and notifications onto an [`Analysis`].
+//! - [`transport`] — `Content-Length` message framing.
+//!
+//! Documents are synchronised in full and re-assembled on every change.
+//! Hover shows a symbol's value and the address and machine words of the
+//! line under the cursor, taken from `EncodeResult::line_infos`.
+
+pub mod analysis;
+pub mod server;
+pub mod transport;
+
+pub use analysis::Analysis;
+pub use server::Server;
@@ -0,0 +1,61 @@
+use std::io::{self, BufReader};
+
+use lc3_lsp::{transport, Server};
+
+fn print_help() {
+    println!("lc3-lsp {}", env!("CARGO_PKG_VERSION"));
+    println!("Language Server Protocol server for LC-3 assembly");
+    println!();
+    println!("USAGE:");
+    println!("  lc3-lsp            Serve LSP over stdin/stdout (started by the editor)");
+    println!();
+    println!("OPTIONS:");
+    println!("  -h, --help         Print this help message");
+    println!("  -V, --version      Print version information");
+}
+
+fn main() {
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "-V" | "--version" => {
+                println!("lc3-lsp {}", env!("CARGO_PKG_VERSION"));
+                return;
+            }
+            "-h" | "--help" => {
+                print_help();
+                return;
+            }
+            // Editors commonly pass `--stdio`; stdio is the only transport.
+            "--stdio" => {}
+            other => {
+                eprintln!("error: unknown option '{other}'");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut reader = BufReader::new(io::stdin().lock());
+    let mut writer = io::stdout().lock();
+    let mut server = Server::new();
+
+    loop {
+        let message = match transport::read_message(&mut reader) {
+            Ok(Some(message)) => message,
+            // The client went away without `exit`.
+            Ok(None) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("lc3-lsp: {e}");
+                std::process::exit(1);
+            }
+        };
+        for reply in server.handle(&message) {
+            if let Err(e) = transport::write_message(&mut writer, &reply) {
+                eprintln!("lc3-lsp: {e}");
+                std::process::exit(1);
+            }
+        }
+        if let Some(code) = server.exit_code() {
+            std::process::exit(code);
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! The LSP request and notification handlers.
+//!
+//! [`Server::handle`] takes one incoming message and returns the messages to
+//! send back, so the protocol logic can be driven without any I/O.  Documents
+//! are synchronised in full on every change and re-assembled each time; LC-3
+//! programs are small enough that this is instant.
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use crate::analysis::{Analysis, CompletionKind, Range, Severity};
+
+/// JSON-RPC error code for an unknown request method.
+const METHOD_NOT_FOUND: i64 = -32601;
+/// JSON-RPC error code for a request whose params do not fit the method.
+const INVALID_PARAMS: i64 = -32602;
+
+/// An open document and its latest analysis.
+struct Document {
+    analysis: Analysis,
+}
+
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    shutdown_requested: bool,
+    exit_code: Option<i32>,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set once the client sends `exit`: 0 after a `shutdown` request, 1
+    /// otherwise.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Handle one message and return the responses and notifications to send.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+
+        let Some(id) = message.get("id").cloned() else {
+            return self.notification(method, params);
+        };
+
+        let result = match method {
+            "initialize" => Ok(initialize_result()),
+            "shutdown" => {
+                self.shutdown_requested = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/references" => self.references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => self.completion(params),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method '{method}'"))),
+        };
+
+        vec![match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": code, "message": message},
+            }),
+        }]
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                vec![self.update(uri, text)]
+            }
+            "textDocument/didChange" => {
+                // Full sync: the last change holds the whole document.
+                let changes = params["contentChanges"].as_array();
+                match changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    Some(text) => vec![self.update(uri, text)],
+                    None => Vec::new(),
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                vec![publish_diagnostics(uri, Vec::new())]
+            }
+            "exit" => {
+                self.exit_code = Some(if self.shutdown_requested { 0 } else { 1 });
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Re-assemble `uri` and return its `publishDiagnostics` notification.
+    fn update(&mut self, uri: &str, text: &str) -> Value {
+        let analysis = Analysis::new(text, &uri_to_path(uri));
+        let diagnostics = analysis
+            .diagnostics()
+            .into_iter()
+            .map(|d| {
+                json!({
+                    "range": range_json(d.range),
+                    "severity": match d.severity {
+                        Severity::Error => 1,
+                        Severity::Warning => 2,
+                    },
+                    "code": d.code,
+                    "source": "lc3",
+                    "message": d.message,
+                })
+            })
+            .collect();
+        self.documents
+            .insert(uri.to_string(), Document { analysis });
+        publish_diagnostics(uri, diagnostics)
+    }
+
+    /// The open document and cursor position named by `params`.
+    fn position<'a>(
+        &'a self,
+        params: &Value,
+    ) -> Result<(&'a str, &'a Analysis, usize, usize), (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let (Some(line), Some(character)) = (
+            params["position"]["line"].as_u64(),
+            params["position"]["character"].as_u64(),
+        ) else {
+            return Err((INVALID_PARAMS, "missing position".to_string()));
+        };
+        let (uri, doc) = self
+            .documents
+            .get_key_value(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("document '{uri}' is not open")))?;
+        Ok((uri, &doc.analysis, line as usize, character as usize))
+    }
+
+    fn definition(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let (uri, analysis, line, character) = self.position(params)?;
+        Ok(analysis
+            .definition(line, character)
+            .map_or(Value::Null, |r| location_json(uri, r)))
+    }
+
+    fn references(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let (uri, analysis, line, character) = self.position(params)?;
+        let include_declaration = params["context"]["includeDeclaration"]
+            .as_bool()
+            .unwrap_or(true);
+        Ok(analysis
+            .references(line, character, include_declaration)
+            .into_iter()
+            .map(|r| location_json(uri, r))
+            .collect())
+    }
+
+    fn hover(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let (_, analysis, line, character) = self.position(params)?;
+        Ok(analysis.hover(line, character).map_or(
+            Value::Null,
+            |text| json!({"contents": {"kind": "markdown", "value": text}}),
+        ))
+    }
+
+    fn completion(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let (_, analysis, _, _) = self.position(params)?;
+        Ok(analysis
+            .completions()
+            .into_iter()
+            .map(|c| {
+                // LSP CompletionItemKind values.
+                let kind = match c.kind {
+                    CompletionKind::Keyword => 14,
+                    CompletionKind::Register => 6,
+                    CompletionKind::Macro => 3,
+                    CompletionKind::Symbol => 18,
+                };
+                let mut item = json!({"label": c.label, "kind": kind});
+                if let Some(detail) = c.detail {
+                    item["detail"] = Value::String(detail);
+                }
+                item
+            })
+            .collect())
+    }
+}
+
+fn initialize_result() -> Value {
+    json!({
+        "capabilities": {
+            "textDocumentSync": 1,
+            "definitionProvider": true,
+            "referencesProvider": true,
+            "hoverProvider": true,
+            "completionProvider": {},
+        },
+        "serverInfo": {"name": "lc3-lsp", "version": env!("CARGO_PKG_VERSION")},
+    })
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics},
+    })
+}
+
+fn range_json(r: Range) -> Value {
+    json!({
+        "start": {"line": r.line, "character": r.start},
+        "end": {"line": r.line, "character": r.end},
+    })
+}
+
+fn location_json(uri: &str, r: Range) -> Value {
+    json!({"uri": uri, "range": range_json(r)})
+}
+
+/// Turn a `file://` URI into a filesystem path so `.INCLUDE` can be resolved
+/// relative to it.  Other schemes are used verbatim as the display name.
+fn uri_to_path(uri: &str) -> String {
+    let Some(rest) = uri.strip_prefix("file://") else {
+        return uri.to_string();
+    };
+    let bytes = rest.as_bytes();
+    let mut path = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%' && i + 2 < bytes.len())
+            .then(|| std::str::from_utf8(&bytes[i + 1..i + 3]).ok())
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(b) => {
+                path.push(b);
+                i += 3;
+            }
+            None => {
+                path.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    let path = String::from_utf8_lossy(&path).into_owned();
+    // `file:///C:/dir` names the Windows path `C:/dir`.
+    match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => path[1..].to_string(),
+        _ => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(server: &mut Server, text: &str) -> Vec<Value> {
+        server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {"textDocument": {"uri": "file:///tmp/a.asm", "text": text}},
+        }))
+    }
+
+    #[test]
+    fn publishes_diagnostics_on_open_and_change() {
+        let mut server = Server::new();
+        let out = open(&mut server, ".ORIG x3000\nBR NOWHERE\n.END\n");
+        assert_eq!(out[0]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(
+            out[0]["params"]["diagnostics"][0]["range"]["start"]["line"],
+            1
+        );
+
+        let out = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": {"uri": "file:///tmp/a.asm", "version": 2},
+                "contentChanges": [{"text": ".ORIG x3000\nHALT\n.END\n"}],
+            },
+        }));
+        assert_eq!(out[0]["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn unknown_requests_get_an_error() {
+        let mut server = Server::new();
+        let out = server.handle(&json!({"jsonrpc": "2.0", "id": 7, "method": "workspace/symbol"}));
+        assert_eq!(out[0]["id"], 7);
+        assert_eq!(out[0]["error"]["code"], METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn exit_code_depends_on_shutdown() {
+        let mut server = Server::new();
+        server.handle(&json!({"jsonrpc": "2.0", "method": "exit"}));
+        assert_eq!(server.exit_code(), Some(1));
+
+        let mut server = Server::new();
+        server.handle(&json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"}));
+        server.handle(&json!({"jsonrpc": "2.0", "method": "exit"}));
+        assert_eq!(server.exit_code(), Some(0));
+    }
+
+    #[test]
+    fn file_uris_become_paths() {
+        assert_eq!(
+            uri_to_path("file:///home/me/my%20prog.asm"),
+            "/home/me/my prog.asm"
+        );
+        assert_eq!(uri_to_path("file:///c%3A/lab/a.asm"), "c:/lab/a.asm");
+        assert_eq!(uri_to_path("untitled:Untitled-1"), "untitled:Untitled-1");
+    }
+}
@@ -0,0 +1,73 @@
+use std::io::{BufReader, Write};
+use std::process::{Command, Stdio};
+
+use lc3_lsp::transport::{read_message, write_message};
+use serde_json::{json, Value};
+
+const URI: &str = "file:///tmp/lc3-lsp-test.asm";
+
+fn request(id: u64, method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+fn at(line: u64, character: u64) -> Value {
+    json!({"textDocument": {"uri": URI}, "position": {"line": line, "character": character}})
+}
+
+#[test]
+fn editor_session_over_stdio() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lc3-lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+    let text = ".ORIG x3000\nLOOP ADD R0, R0, #1\n     BRp LOOP\n     HALT\n.END\n";
+    let messages = [
+        request(1, "initialize", json!({"capabilities": {}})),
+        notification("initialized", json!({})),
+        notification(
+            "textDocument/didOpen",
+            json!({"textDocument": {"uri": URI, "languageId": "lc3asm", "version": 1, "text": text}}),
+        ),
+        request(2, "textDocument/definition", at(2, 10)),
+        request(3, "textDocument/hover", at(1, 1)),
+        request(4, "shutdown", Value::Null),
+        notification("exit", Value::Null),
+    ];
+    for msg in &messages {
+        write_message(&mut stdin, msg).unwrap();
+    }
+    stdin.flush().unwrap();
+
+    let init = read_message(&mut stdout).unwrap().unwrap();
+    assert_eq!(init["id"], 1);
+    assert_eq!(init["result"]["capabilities"]["hoverProvider"], true);
+
+    let diags = read_message(&mut stdout).unwrap().unwrap();
+    assert_eq!(diags["method"], "textDocument/publishDiagnostics");
+    assert_eq!(diags["params"]["diagnostics"], json!([]));
+
+    let def = read_message(&mut stdout).unwrap().unwrap();
+    assert_eq!(def["id"], 2);
+    assert_eq!(def["result"]["uri"], URI);
+    assert_eq!(
+        def["result"]["range"]["start"],
+        json!({"line": 1, "character": 0})
+    );
+
+    let hover = read_message(&mut stdout).unwrap().unwrap();
+    let value = hover["result"]["contents"]["value"].as_str().unwrap();
+    assert!(value.contains("label at x3000"), "{value}");
+    assert!(value.contains("x3000  x1021"), "{value}");
+
+    let shutdown = read_message(&mut stdout).unwrap().unwrap();
+    assert_eq!(shutdown["id"], 4);
+    assert!(child.wait().unwrap().success());
+}
@@ -0,0 +1,390 @@
+//! Breakpoints, watchpoints and the conditions that guard them.
+//!
+//! A [`Breakpoint`] stops execution before the instruction at its address; a
+//! [`Watchpoint`] stops it after an instruction reads or writes a watched
+//! word.  Either has a [`Trigger`]: an optional [`Condition`], an ignore
+//! count and a hit count.  [`BreakCommand`] parses the debugger's `b` syntax:
+//!
+//! ```text
+//! b x3000                       break at x3000
+//! b x3000 if R0 == x0A          … only while the condition holds
+//! b x3000 ignore 2              … from the third hit on
+//! b w x4000                     break after a write to x4000
+//! b r x4000-x400F               break after a read of x4000–x400F
+//! b rw x4000 if mem[x4000] > 5  either, with a condition
+//! ```
+//!
+//! Conditions compare two operands — `R0`–`R7`, `PC`, `mem[ADDR]` (or
+//! `mem[R6]`) and numbers (`x0A`, `#-1`, `10`) — with `==`, `!=`, `<`, `<=`,
+//! `>` or `>=`.  Values compare as signed 16-bit numbers.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::machine::Registers;
+use crate::memory::Memory;
+
+// ── Breakpoints and watchpoints ───────────────────────────────────────────────
+
+/// The condition, ignore count and hit count shared by [`Breakpoint`] and
+/// [`Watchpoint`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trigger {
+    /// Only stop while this holds.
+    pub condition: Option<Condition>,
+    /// Number of hits to let pass before stopping.
+    pub ignore: u64,
+    /// Times the trigger was reached with its condition true.
+    pub hits: u64,
+}
+
+impl Trigger {
+    /// True if the condition (if any) holds in the current state.
+    pub fn triggers(&self, regs: &Registers, mem: &Memory) -> bool {
+        match &self.condition {
+            Some(condition) => condition.eval(regs, mem),
+            None => true,
+        }
+    }
+
+    /// Count a hit; true once the ignore count is used up.
+    pub(crate) fn hit(&mut self) -> bool {
+        self.hits += 1;
+        self.hits > self.ignore
+    }
+
+    /// True if there is no condition and no ignore count.
+    pub fn is_plain(&self) -> bool {
+        self.condition.is_none() && self.ignore == 0
+    }
+}
+
+/// A breakpoint on an instruction address (the key in
+/// [`Machine::breakpoints`](crate::Machine::breakpoints)).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Breakpoint {
+    pub trigger: Trigger,
+}
+
+/// Which accesses a [`Watchpoint`] reacts to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    fn includes(self, write: bool) -> bool {
+        match self {
+            Access::Read => !write,
+            Access::Write => write,
+            Access::ReadWrite => true,
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Access::Read => "r",
+            Access::Write => "w",
+            Access::ReadWrite => "rw",
+        })
+    }
+}
+
+/// A watchpoint on the words `start..=end`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub access: Access,
+    /// Checked after the access; counts instructions that made a matching
+    /// access with the condition true.
+    pub trigger: Trigger,
+}
+
+impl Watchpoint {
+    pub fn new(start: u16, end: u16, access: Access) -> Self {
+        Self {
+            start,
+            end,
+            access,
+            trigger: Trigger::default(),
+        }
+    }
+
+    /// True if `addr` is one of the watched words.
+    pub fn covers(&self, addr: u16) -> bool {
+        (self.start..=self.end).contains(&addr)
+    }
+
+    /// True if a read (`write == false`) or write of `addr` is watched.
+    pub fn matches(&self, addr: u16, write: bool) -> bool {
+        self.covers(addr) && self.access.includes(write)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} x{:04X}", self.access, self.start)?;
+        if self.end != self.start {
+            write!(f, "-x{:04X}", self.end)?;
+        }
+        Ok(())
+    }
+}
+
+// ── Conditions ────────────────────────────────────────────────────────────────
+
+/// One side of a [`Condition`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operand {
+    /// General-purpose register R0–R7.
+    Reg(u8),
+    Pc,
+    /// The memory word at the operand's value.
+    Mem(Box<Operand>),
+    Value(u16),
+}
+
+impl Operand {
+    fn value(&self, regs: &Registers, mem: &Memory) -> u16 {
+        match self {
+            Operand::Reg(r) => regs.gpr[usize::from(*r)],
+            Operand::Pc => regs.pc,
+            Operand::Mem(addr) => mem.raw(addr.value(regs, mem)),
+            Operand::Value(v) => *v,
+        }
+    }
+}
+
+impl FromStr for Operand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let upper = s.to_ascii_uppercase();
+        if let Some(inner) = upper
+            .strip_prefix("MEM[")
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            return Ok(Operand::Mem(Box::new(inner.parse()?)));
+        }
+        if upper == "PC" {
+            return Ok(Operand::Pc);
+        }
+        if let Some(r) = upper.strip_prefix('R') {
+            if let Ok(r @ 0..=7) = r.parse::<u8>() {
+                return Ok(Operand::Reg(r));
+            }
+        }
+        parse_value(s)
+            .map(Operand::Value)
+            .ok_or_else(|| format!("expected R0-R7, PC, mem[ADDR] or a number, found '{s}'"))
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Reg(r) => write!(f, "R{r}"),
+            Operand::Pc => write!(f, "PC"),
+            Operand::Mem(addr) => write!(f, "mem[{addr}]"),
+            Operand::Value(v) => write!(f, "x{v:04X}"),
+        }
+    }
+}
+
+/// Comparison operator of a [`Condition`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    /// Operators longest first, so `<=` is not read as `<`.
+    const ALL: [(&'static str, CmpOp); 6] = [
+        ("==", CmpOp::Eq),
+        ("!=", CmpOp::Ne),
+        ("<=", CmpOp::Le),
+        (">=", CmpOp::Ge),
+        ("<", CmpOp::Lt),
+        (">", CmpOp::Gt),
+    ];
+}
+
+impl fmt::Display for CmpOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = CmpOp::ALL.iter().find(|(_, op)| op == self).unwrap().0;
+        f.write_str(text)
+    }
+}
+
+/// `lhs op rhs`, e.g. `R0 == x0A`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Condition {
+    pub lhs: Operand,
+    pub op: CmpOp,
+    pub rhs: Operand,
+}
+
+impl Condition {
+    /// Evaluate against the machine state, comparing signed values.
+    pub fn eval(&self, regs: &Registers, mem: &Memory) -> bool {
+        let lhs = self.lhs.value(regs, mem) as i16;
+        let rhs = self.rhs.value(regs, mem) as i16;
+        match self.op {
+            CmpOp::Eq => lhs == rhs,
+            CmpOp::Ne => lhs != rhs,
+            CmpOp::Lt => lhs < rhs,
+            CmpOp::Le => lhs <= rhs,
+            CmpOp::Gt => lhs > rhs,
+            CmpOp::Ge => lhs >= rhs,
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pos, text, op) = CmpOp::ALL
+            .iter()
+            .filter_map(|&(text, op)| s.find(text).map(|pos| (pos, text, op)))
+            .min_by_key(|&(pos, text, _)| (pos, std::cmp::Reverse(text.len())))
+            .ok_or_else(|| format!("expected a comparison such as R0 == x0A, found '{s}'"))?;
+        Ok(Condition {
+            lhs: s[..pos].parse()?,
+            op,
+            rhs: s[pos + text.len()..].parse()?,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.lhs, self.op, self.rhs)
+    }
+}
+
+// ── The `b` command ───────────────────────────────────────────────────────────
+
+/// A parsed `b` command (without the leading `b`).
+#[derive(Clone, Debug, PartialEq)]
+pub enum BreakCommand {
+    Break(u16, Breakpoint),
+    Watch(Watchpoint),
+}
+
+impl BreakCommand {
+    /// True if the command is just an address or range — the form that
+    /// toggles an existing breakpoint or watchpoint off.
+    pub fn is_plain(&self) -> bool {
+        match self {
+            BreakCommand::Break(_, bp) => bp.trigger.is_plain(),
+            BreakCommand::Watch(wp) => wp.trigger.is_plain(),
+        }
+    }
+}
+
+impl FromStr for BreakCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace().peekable();
+        let access = match words.peek().map(|w| w.to_ascii_lowercase()).as_deref() {
+            Some("r") => Some(Access::Read),
+            Some("w") => Some(Access::Write),
+            Some("rw") => Some(Access::ReadWrite),
+            _ => None,
+        };
+        if access.is_some() {
+            words.next();
+        }
+
+        let target = words.next().ok_or("expected an address")?;
+        let bad_addr = || format!("bad address '{target}'");
+        let (start, end) = match target.split_once('-') {
+            Some((start, end)) if access.is_some() => (
+                parse_addr(start).ok_or_else(bad_addr)?,
+                parse_addr(end).ok_or_else(bad_addr)?,
+            ),
+            _ => {
+                let addr = parse_addr(target).ok_or_else(bad_addr)?;
+                (addr, addr)
+            }
+        };
+        if end < start {
+            return Err(format!("range {target} ends before it starts"));
+        }
+
+        // The rest: `if CONDITION` and `ignore N`, in either order.
+        let rest: Vec<&str> = words.collect();
+        let mut trigger = Trigger::default();
+        let mut i = 0;
+        while i < rest.len() {
+            match rest[i].to_ascii_lowercase().as_str() {
+                "if" => {
+                    let len = rest[i + 1..]
+                        .iter()
+                        .position(|w| w.eq_ignore_ascii_case("ignore"))
+                        .unwrap_or(rest.len() - i - 1);
+                    let text = rest[i + 1..i + 1 + len].join(" ");
+                    trigger.condition = Some(text.parse()?);
+                    i += 1 + len;
+                }
+                "ignore" => {
+                    trigger.ignore = rest
+                        .get(i + 1)
+                        .and_then(|n| n.parse().ok())
+                        .ok_or("ignore needs a count: ignore N")?;
+                    i += 2;
+                }
+                other => return Err(format!("unexpected '{other}' (expected 'if' or 'ignore')")),
+            }
+        }
+
+        Ok(match access {
+            Some(access) => BreakCommand::Watch(Watchpoint {
+                trigger,
+                ..Watchpoint::new(start, end, access)
+            }),
+            None => BreakCommand::Break(start, Breakpoint { trigger }),
+        })
+    }
+}
+
+/// Parse an address: `x3000`, `0x3000`, `3000` (four hex digits) or a
+/// decimal number.
+pub fn parse_addr(s: &str) -> Option<u16> {
+    if s.is_empty() {
+        return None;
+    }
+    if let Some(hex) = s.strip_prefix('x').or_else(|| s.strip_prefix("0x")) {
+        u16::from_str_radix(hex, 16).ok()
+    } else if s.chars().all(|c| c.is_ascii_hexdigit()) && s.len() == 4 {
+        u16::from_str_radix(s, 16).ok()
+    } else {
+        s.parse::<u16>().ok()
+    }
+}
+
+/// Parse a number in LC-3 style: `x0A`, `0x0A`, `#-1` or `-1`/`10`.
+fn parse_value(s: &str) -> Option<u16> {
+    let lower = s.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix('x').or_else(|| lower.strip_prefix("0x")) {
+        return u16::from_str_radix(hex, 16).ok();
+    }
+    let dec = lower.strip_prefix('#').unwrap_or(&lower);
+    dec.parse::<i32>()
+        .ok()
+        .filter(|v| (-32768..=65535).contains(v))
+        .map(|v| v as u16)
+}
@@ -0,0 +1,133 @@
+//! Memory-mapped I/O devices.
+//!
+//! [`Memory`](crate::memory::Memory) routes reads and writes of device
+//! registers to a [`Device`] instead of the backing array.  The keyboard
+//! (KBSR/KBDR at xFE00/xFE02) and display (DSR/DDR at xFE04/xFE06) are built
+//! in; other devices can be attached with
+//! [`Machine::attach_device`](crate::Machine::attach_device).
+
+/// Keyboard status register: bit 15 = ready, bit 14 = interrupt enable.
+pub const KBSR: u16 = 0xFE00;
+/// Keyboard data register: the last key received.
+pub const KBDR: u16 = 0xFE02;
+/// Display status register: bit 15 = ready.
+pub const DSR: u16 = 0xFE04;
+/// Display data register: writing prints the low byte.
+pub const DDR: u16 = 0xFE06;
+
+const READY: u16 = 0x8000;
+const INTERRUPT_ENABLE: u16 = 0x4000;
+
+/// A device that owns one or more memory addresses.
+///
+/// Reads may have side effects (reading KBDR clears the keyboard's ready
+/// bit), so both operations take `&mut self`.
+pub trait Device {
+    /// True if reads and writes of `addr` belong to this device.
+    fn claims(&self, addr: u16) -> bool;
+    fn read(&mut self, addr: u16) -> u16;
+    fn write(&mut self, addr: u16, value: u16);
+}
+
+// ── Keyboard ──────────────────────────────────────────────────────────────────
+
+/// The console keyboard.
+///
+/// A key is latched with [`press`](Self::press), which sets KBSR[15];
+/// reading KBDR returns it and clears the bit again.  Only the
+/// interrupt-enable bit of KBSR is writable.
+#[derive(Clone, Debug, Default)]
+pub struct Keyboard {
+    status: u16,
+    data: u16,
+    polled: bool,
+}
+
+impl Keyboard {
+    /// A key is waiting in KBDR.
+    pub fn ready(&self) -> bool {
+        self.status & READY != 0
+    }
+
+    pub fn interrupt_enabled(&self) -> bool {
+        self.status & INTERRUPT_ENABLE != 0
+    }
+
+    /// True once the program has polled KBSR or enabled keyboard
+    /// interrupts, i.e. it reads the keyboard directly rather than via TRAPs.
+    pub fn in_use(&self) -> bool {
+        self.polled || self.interrupt_enabled()
+    }
+
+    /// Latch `key` into KBDR and set the ready bit.
+    pub fn press(&mut self, key: u8) {
+        self.data = u16::from(key);
+        self.status |= READY;
+    }
+
+    /// Consume the latched key, if any, as a KBDR read would.
+    pub fn take(&mut self) -> Option<u8> {
+        self.ready().then(|| {
+            self.status &= !READY;
+            self.data as u8
+        })
+    }
+}
+
+impl Device for Keyboard {
+    fn claims(&self, addr: u16) -> bool {
+        addr == KBSR || addr == KBDR
+    }
+
+    fn read(&mut self, addr: u16) -> u16 {
+        if addr == KBSR {
+            self.polled = true;
+            self.status
+        } else {
+            self.status &= !READY;
+            self.data
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u16) {
+        if addr == KBSR {
+            self.status = (self.status & READY) | (value & INTERRUPT_ENABLE);
+        }
+    }
+}
+
+// ── Display ───────────────────────────────────────────────────────────────────
+
+/// The console display: always ready; every DDR write queues one character
+/// for the machine's output.
+#[derive(Debug, Default)]
+pub struct Display {
+    pending: Vec<char>,
+}
+
+impl Display {
+    /// Characters written to DDR since the last call.
+    pub fn take_output(&mut self) -> Vec<char> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+impl Device for Display {
+    fn claims(&self, addr: u16) -> bool {
+        addr == DSR || addr == DDR
+    }
+
+    fn read(&mut self, addr: u16) -> u16 {
+        if addr == DSR {
+            READY
+        } else {
+            0
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u16) {
+        if addr == DDR {
+            self.pending.push(char::from(value as u8));
+        }
+    }
+}
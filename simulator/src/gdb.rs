@@ -0,0 +1,523 @@
+//! GDB remote serial protocol stub.
+//!
+//! `lc3-sim --gdb-port 1234 prog.obj` waits for a debugger on
+//! `127.0.0.1:1234` (`target remote :1234` in gdb) and serves the machine
+//! with a [`GdbStub`] until the debugger detaches or kills it.
+//!
+//! LC-3 memory is word-addressed and GDB's is byte-addressed, so the stub
+//! shows word `w` as the two bytes at `2w` (low byte) and `2w + 1`:
+//! `x3000` is at GDB address `0x6000`, and breakpoint and watchpoint
+//! addresses are halved.  The registers are R0–R7, PC and PSR, 16 bits
+//! each and little-endian, as described by [`TARGET_XML`].
+//!
+//! Supported packets: `?`, `g`/`G` and `p`/`P` (registers), `m`/`M`
+//! (memory), `s` and `c`, `Z0`/`z0` (breakpoints), `Z2`/`Z3`/`Z4` and
+//! their `z` forms (write, read and access watchpoints), `qSupported`,
+//! `qXfer:features:read`, `QStartNoAckMode`, `D` and `k`.  A `0x03` byte
+//! from the debugger interrupts `c`, including while the program waits in
+//! `GETC` or `IN` for a key.  Keys given to [`GdbStub::with_keys`] are
+//! queued for the keyboard between steps.  `HALT` is reported as the program
+//! exiting (`W00`).
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use crate::breakpoint::{Access, Breakpoint, Watchpoint};
+use crate::machine::{Machine, StepResult};
+
+/// Target description sent for `qXfer:features:read:target.xml`.
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.lc3.core">
+    <reg name="r0" bitsize="16" type="int16" regnum="0"/>
+    <reg name="r1" bitsize="16" type="int16"/>
+    <reg name="r2" bitsize="16" type="int16"/>
+    <reg name="r3" bitsize="16" type="int16"/>
+    <reg name="r4" bitsize="16" type="int16"/>
+    <reg name="r5" bitsize="16" type="int16"/>
+    <reg name="r6" bitsize="16" type="data_ptr"/>
+    <reg name="r7" bitsize="16" type="code_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="psr" bitsize="16" type="int16"/>
+  </feature>
+</target>
+"#;
+
+/// R0–R7, PC, PSR.
+const NUM_REGS: usize = 10;
+
+/// Largest packet the stub accepts or sends, advertised in `qSupported`.
+const PACKET_SIZE: u32 = 0x1000;
+
+/// One past the last GDB byte address: two bytes per LC-3 word.
+const MEMORY_BYTES: u32 = 0x2_0000;
+
+/// Steps run between checks for an interrupt from the debugger.
+const STEPS_PER_POLL: u32 = 10_000;
+
+/// Pause between checks while the program waits for a key.
+const INPUT_POLL: Duration = Duration::from_millis(10);
+
+/// Signal numbers used in stop replies.
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// Serves one debugger connection.
+pub struct GdbStub<'a> {
+    machine: &'a mut Machine,
+    stream: TcpStream,
+    /// Received bytes not yet consumed.
+    pending: Vec<u8>,
+    /// Acknowledge packets with `+`; off after `QStartNoAckMode`.
+    ack: bool,
+    /// Reply to `?`: why the machine last stopped.
+    last_stop: String,
+    /// Keys typed for the program, queued for its keyboard before each step.
+    keys: Option<Box<dyn FnMut() -> Option<u8> + 'a>>,
+}
+
+impl<'a> GdbStub<'a> {
+    pub fn new(machine: &'a mut Machine, stream: TcpStream) -> Self {
+        Self {
+            machine,
+            stream,
+            pending: Vec::new(),
+            ack: true,
+            last_stop: format!("S{SIGTRAP:02x}"),
+            keys: None,
+        }
+    }
+
+    /// Feed the program's keyboard from `keys`, which returns the next key
+    /// typed, or `None` if there is none yet.  It must not block.
+    pub fn with_keys(mut self, keys: impl FnMut() -> Option<u8> + 'a) -> Self {
+        self.keys = Some(Box::new(keys));
+        self
+    }
+
+    /// Handle packets until the debugger detaches, kills the target or
+    /// closes the connection.
+    pub fn run(mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            match packet.as_str() {
+                "k" => break,
+                "D" => {
+                    self.send_packet("OK")?;
+                    break;
+                }
+                _ => {}
+            }
+            let reply = self.handle(&packet)?;
+            self.send_packet(&reply)?;
+            if packet == "QStartNoAckMode" {
+                self.ack = false;
+            }
+        }
+        Ok(())
+    }
+
+    // ── Packets ───────────────────────────────────────────────────────────────
+
+    /// The reply to one packet ("" for packets the stub does not support).
+    fn handle(&mut self, packet: &str) -> io::Result<String> {
+        let (cmd, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let reply = match cmd {
+            "?" => self.last_stop.clone(),
+            "g" => (0..NUM_REGS).map(|r| hex_word(self.reg(r))).collect(),
+            "G" => match parse_words(args) {
+                Some(words) if words.len() == NUM_REGS => {
+                    for (r, word) in words.into_iter().enumerate() {
+                        self.set_reg(r, word);
+                    }
+                    "OK".into()
+                }
+                _ => error(),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(r) if r < NUM_REGS => hex_word(self.reg(r)),
+                _ => error(),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(r, value)| {
+                    let r = usize::from_str_radix(r, 16).ok()?;
+                    let [word] = parse_words(value)?[..] else {
+                        return None;
+                    };
+                    (r < NUM_REGS).then_some((r, word))
+                });
+                match parsed {
+                    Some((r, word)) => {
+                        self.set_reg(r, word);
+                        "OK".into()
+                    }
+                    None => error(),
+                }
+            }
+            // Reads are cut short at the end of memory and at what fits in
+            // one packet (two hex digits a byte); GDB asks again for the rest.
+            "m" => match parse_range(args) {
+                Some((addr, len)) if addr < MEMORY_BYTES => {
+                    let len = len.min(MEMORY_BYTES - addr).min(PACKET_SIZE / 2);
+                    (0..len)
+                        .map(|i| format!("{:02x}", self.read_byte(addr + i)))
+                        .collect()
+                }
+                _ => error(),
+            },
+            "M" => {
+                let parsed = args.split_once(':').and_then(|(range, data)| {
+                    let (addr, len) = parse_range(range)?;
+                    let bytes = parse_bytes(data)?;
+                    let fits = u64::from(addr) + u64::from(len) <= u64::from(MEMORY_BYTES);
+                    (fits && bytes.len() == len as usize).then_some((addr, bytes))
+                });
+                match parsed {
+                    Some((addr, bytes)) => {
+                        for (i, byte) in bytes.into_iter().enumerate() {
+                            self.write_byte(addr + i as u32, byte);
+                        }
+                        "OK".into()
+                    }
+                    None => error(),
+                }
+            }
+            "s" | "c" => {
+                if let Ok(addr) = u32::from_str_radix(args, 16) {
+                    self.machine.set_pc(word_addr(addr));
+                }
+                let result = if cmd == "s" {
+                    self.step()
+                } else {
+                    match self.resume()? {
+                        Some(result) => result,
+                        None => {
+                            self.last_stop = format!("S{SIGINT:02x}");
+                            return Ok(self.last_stop.clone());
+                        }
+                    }
+                };
+                self.last_stop = self.stop_reply(&result);
+                self.last_stop.clone()
+            }
+            "Z" | "z" => self.breakpoint(cmd == "Z", args),
+            _ => self.query(packet),
+        };
+        Ok(reply)
+    }
+
+    /// Replies to the general query and set packets the stub supports.
+    fn query(&self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return format!("PacketSize={PACKET_SIZE:x};qXfer:features:read+;QStartNoAckMode+");
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return match parse_range(range) {
+                Some((offset, len)) => {
+                    let xml = TARGET_XML.as_bytes();
+                    let start = (offset as usize).min(xml.len());
+                    let end = (start + len as usize).min(xml.len());
+                    let marker = if end == xml.len() { 'l' } else { 'm' };
+                    format!("{marker}{}", String::from_utf8_lossy(&xml[start..end]))
+                }
+                None => error(),
+            };
+        }
+        match packet {
+            "QStartNoAckMode" => "OK".into(),
+            "qAttached" => "1".into(),
+            "qC" => "QC1".into(),
+            "qfThreadInfo" => "m1".into(),
+            "qsThreadInfo" => "l".into(),
+            _ if packet.starts_with('H') => "OK".into(),
+            _ => String::new(),
+        }
+    }
+
+    /// `Z`/`z` packets: add or remove a breakpoint or watchpoint.
+    fn breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let kind = fields.next();
+        let addr = fields.next().and_then(|a| u32::from_str_radix(a, 16).ok());
+        let len = fields.next().and_then(|l| u32::from_str_radix(l, 16).ok());
+        let (Some(kind), Some(addr), Some(len)) = (kind, addr, len) else {
+            return error();
+        };
+        let access = match kind {
+            "0" => {
+                let addr = word_addr(addr);
+                if insert {
+                    self.machine.breakpoints.insert(addr, Breakpoint::default());
+                } else {
+                    self.machine.breakpoints.remove(&addr);
+                }
+                return "OK".into();
+            }
+            "2" => Access::Write,
+            "3" => Access::Read,
+            "4" => Access::ReadWrite,
+            _ => return String::new(),
+        };
+        let start = word_addr(addr);
+        let end = word_addr(addr.wrapping_add(len.max(1) - 1));
+        let watches = &mut self.machine.watchpoints;
+        if insert {
+            watches.push(Watchpoint::new(start, end, access));
+        } else if let Some(i) = watches
+            .iter()
+            .position(|w| (w.start, w.end, w.access) == (start, end, access))
+        {
+            watches.remove(i);
+        }
+        "OK".into()
+    }
+
+    /// The stop reply for a step result.
+    fn stop_reply(&self, result: &StepResult) -> String {
+        match *result {
+            StepResult::Halted => "W00".into(),
+            StepResult::IllegalInstruction(_) => format!("S{SIGILL:02x}"),
+            StepResult::PrivilegeViolation(_) => format!("S{SIGSEGV:02x}"),
+            StepResult::WatchpointHit { addr, write, .. } => {
+                let kind = self
+                    .machine
+                    .watchpoints
+                    .iter()
+                    .find(|w| w.matches(addr, write))
+                    .map_or(Access::ReadWrite, |w| w.access);
+                let name = match kind {
+                    Access::Write => "watch",
+                    Access::Read => "rwatch",
+                    Access::ReadWrite => "awatch",
+                };
+                format!("T{SIGTRAP:02x}{name}:{:x};", u32::from(addr) * 2)
+            }
+            StepResult::Ok | StepResult::BreakpointHit(_) => format!("S{SIGTRAP:02x}"),
+        }
+    }
+
+    /// Run until the machine stops, or until the debugger interrupts
+    /// (`None`).
+    fn resume(&mut self) -> io::Result<Option<StepResult>> {
+        loop {
+            for _ in 0..STEPS_PER_POLL {
+                match self.step() {
+                    StepResult::Ok => {}
+                    other => return Ok(Some(other)),
+                }
+                if self.machine.waiting_for_input {
+                    break;
+                }
+            }
+            if self.interrupted()? {
+                return Ok(None);
+            }
+            if self.machine.waiting_for_input {
+                thread::sleep(INPUT_POLL);
+            }
+        }
+    }
+
+    /// Step the machine once, after queueing any keys typed meanwhile.
+    fn step(&mut self) -> StepResult {
+        if let Some(keys) = &mut self.keys {
+            while let Some(key) = keys() {
+                self.machine.input_queue.push_back(key);
+            }
+        }
+        self.machine.step()
+    }
+
+    // ── Registers and memory ──────────────────────────────────────────────────
+
+    fn reg(&self, r: usize) -> u16 {
+        match r {
+            0..=7 => self.machine.regs.gpr[r],
+            8 => self.machine.regs.pc,
+            _ => self.machine.regs.psr(),
+        }
+    }
+
+    fn set_reg(&mut self, r: usize, value: u16) {
+        match r {
+            0..=7 => self.machine.regs.gpr[r] = value,
+            8 => self.machine.regs.pc = value,
+            _ => self.machine.regs.set_psr(value),
+        }
+    }
+
+    /// One byte of the word at `addr / 2`, without device side effects.
+    fn read_byte(&self, addr: u32) -> u8 {
+        let word = self.machine.mem.raw(word_addr(addr));
+        if addr & 1 == 0 {
+            word as u8
+        } else {
+            (word >> 8) as u8
+        }
+    }
+
+    /// Set one byte of the word at `addr / 2`, without device side effects.
+    fn write_byte(&mut self, addr: u32, byte: u8) {
+        let word_addr = word_addr(addr);
+        let word = self.machine.mem.raw(word_addr);
+        let word = if addr & 1 == 0 {
+            (word & 0xFF00) | u16::from(byte)
+        } else {
+            (word & 0x00FF) | (u16::from(byte) << 8)
+        };
+        self.machine.mem.set_raw(word_addr, word);
+    }
+
+    // ── Framing ───────────────────────────────────────────────────────────────
+
+    /// The next packet's payload, or `None` once the connection closes.
+    /// Acknowledgements and stray interrupts between packets are skipped.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let Some(start) = self.pending.iter().position(|&b| b == b'$') else {
+                self.pending.clear();
+                if !self.fill()? {
+                    return Ok(None);
+                }
+                continue;
+            };
+            let Some(hash) = self.pending[start..].iter().position(|&b| b == b'#') else {
+                if !self.fill()? {
+                    return Ok(None);
+                }
+                continue;
+            };
+            let hash = start + hash;
+            if self.pending.len() < hash + 3 {
+                if !self.fill()? {
+                    return Ok(None);
+                }
+                continue;
+            }
+            let body = self.pending[start + 1..hash].to_vec();
+            let sum = std::str::from_utf8(&self.pending[hash + 1..hash + 3])
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok());
+            self.pending.drain(..hash + 3);
+            if sum != Some(checksum(&body)) {
+                if self.ack {
+                    self.stream.write_all(b"-")?;
+                }
+                continue;
+            }
+            if self.ack {
+                self.stream.write_all(b"+")?;
+            }
+            return Ok(Some(String::from_utf8_lossy(&body).into_owned()));
+        }
+    }
+
+    /// Send `$payload#checksum`, resending until it is acknowledged.
+    fn send_packet(&mut self, payload: &str) -> io::Result<()> {
+        let framed = format!("${payload}#{:02x}", checksum(payload.as_bytes()));
+        loop {
+            self.stream.write_all(framed.as_bytes())?;
+            self.stream.flush()?;
+            if !self.ack {
+                return Ok(());
+            }
+            loop {
+                if self.pending.is_empty() && !self.fill()? {
+                    return Ok(());
+                }
+                match self.pending.remove(0) {
+                    b'+' => return Ok(()),
+                    b'-' => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Read more bytes from the debugger.  Returns `false` at end of stream.
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut buf = [0u8; 1024];
+        let n = self.stream.read(&mut buf)?;
+        self.pending.extend_from_slice(&buf[..n]);
+        Ok(n > 0)
+    }
+
+    /// True if the debugger has sent an interrupt (`0x03`), which is
+    /// consumed.  Does not block.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let filled = self.fill();
+        self.stream.set_nonblocking(false)?;
+        match filled {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+        match self.pending.iter().position(|&b| b == 0x03) {
+            Some(i) => {
+                self.pending.remove(i);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// The LC-3 word holding GDB byte address `addr`.
+fn word_addr(addr: u32) -> u16 {
+    (addr / 2) as u16
+}
+
+fn error() -> String {
+    "E01".into()
+}
+
+/// Modulo-256 sum of a packet's payload.
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+/// A word as four hex digits, low byte first.
+fn hex_word(word: u16) -> String {
+    format!("{:02x}{:02x}", word as u8, word >> 8)
+}
+
+/// Hex-encoded bytes.
+fn parse_bytes(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() & 1 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Hex-encoded little-endian words.
+fn parse_words(hex: &str) -> Option<Vec<u16>> {
+    let bytes = parse_bytes(hex)?;
+    if bytes.len() & 1 != 0 {
+        return None;
+    }
+    Some(
+        bytes
+            .chunks(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect(),
+    )
+}
+
+/// `addr,length` in hex.
+fn parse_range(s: &str) -> Option<(u32, u32)> {
+    let (addr, len) = s.split_once(',')?;
+    Some((
+        u32::from_str_radix(addr, 16).ok()?,
+        u32::from_str_radix(len, 16).ok()?,
+    ))
+}
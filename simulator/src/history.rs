@@ -0,0 +1,126 @@
+//! Reverse execution.
+//!
+//! With a history limit set, every step records what it changed: the
+//! registers, the keyboard latch, the previous contents of each memory word
+//! it wrote, and the keys it took from the input queue.  [`Machine::step_back`]
+//! undoes one step; [`Machine::reverse_continue`] keeps undoing until the PC
+//! reaches a breakpoint.  Only the most recent steps are kept.
+//!
+//! Console output and custom devices are not rolled back, and keys are
+//! returned to the front of [`input_queue`](Machine::input_queue) so
+//! re-executing the step reads them again.
+
+use std::collections::VecDeque;
+
+use crate::device::Keyboard;
+use crate::machine::{Machine, Registers, StepResult};
+
+/// Everything needed to undo one step.
+pub(crate) struct UndoRecord {
+    regs: Registers,
+    keyboard: Keyboard,
+    halted: bool,
+    waiting_for_input: bool,
+    step_count: u64,
+    /// `(address, previous value)` of each memory write, in order.
+    memory: Vec<(u16, u16)>,
+    /// Keys taken from the input queue (or the input handler), in order.
+    pub(crate) keys: Vec<u8>,
+}
+
+/// A bounded log of the most recent steps.
+#[derive(Default)]
+pub(crate) struct History {
+    records: VecDeque<UndoRecord>,
+    limit: usize,
+    /// The record for the step in progress.
+    pub(crate) current: Option<UndoRecord>,
+}
+
+impl Machine {
+    /// Keep the last `steps` steps so they can be undone.  0 (the default)
+    /// turns reverse execution off and drops the history.
+    pub fn set_history_limit(&mut self, steps: usize) {
+        self.history.limit = steps;
+        let excess = self.history.records.len().saturating_sub(steps);
+        self.history.records.drain(..excess);
+    }
+
+    /// Number of steps that can currently be undone.
+    pub fn history_len(&self) -> usize {
+        self.history.records.len()
+    }
+
+    /// Undo the most recent step.  Returns `false` if there is no history.
+    pub fn step_back(&mut self) -> bool {
+        let Some(record) = self.history.records.pop_back() else {
+            return false;
+        };
+        for &(addr, value) in record.memory.iter().rev() {
+            self.mem.restore(addr, value);
+        }
+        for &key in record.keys.iter().rev() {
+            self.input_queue.push_front(key);
+        }
+        self.regs = record.regs;
+        self.mem.keyboard = record.keyboard;
+        self.halted = record.halted;
+        self.waiting_for_input = record.waiting_for_input;
+        self.step_count = record.step_count;
+        true
+    }
+
+    /// Step back until the PC is at a breakpoint whose condition holds,
+    /// stopping after at least one step.  Returns
+    /// [`StepResult::BreakpointHit`] there, or [`StepResult::Ok`] when the
+    /// history runs out first.  Hit counts are left alone.
+    pub fn reverse_continue(&mut self) -> StepResult {
+        while self.step_back() {
+            let at_breakpoint = self
+                .breakpoints
+                .get(&self.regs.pc)
+                .is_some_and(|bp| bp.trigger.triggers(&self.regs, &self.mem));
+            if at_breakpoint {
+                return StepResult::BreakpointHit(self.regs.pc);
+            }
+        }
+        StepResult::Ok
+    }
+
+    /// Start recording the step about to run, if history is on.
+    pub(crate) fn begin_undo(&mut self) {
+        if self.history.limit == 0 {
+            return;
+        }
+        self.mem.start_undo();
+        self.history.current = Some(UndoRecord {
+            regs: self.regs.clone(),
+            keyboard: self.mem.keyboard.clone(),
+            halted: self.halted,
+            waiting_for_input: self.waiting_for_input,
+            step_count: self.step_count,
+            memory: Vec::new(),
+            keys: Vec::new(),
+        });
+    }
+
+    /// Finish the record started by [`begin_undo`](Self::begin_undo); steps
+    /// that changed nothing are not kept.
+    pub(crate) fn end_undo(&mut self) {
+        let Some(mut record) = self.history.current.take() else {
+            return;
+        };
+        record.memory = self.mem.take_undo();
+        let unchanged = record.step_count == self.step_count
+            && record.halted == self.halted
+            && record.memory.is_empty()
+            && record.keys.is_empty();
+        if unchanged {
+            return;
+        }
+        if self.history.records.len() == self.history.limit {
+            self.history.records.pop_front();
+        }
+        self.history.records.push_back(record);
+    }
+}
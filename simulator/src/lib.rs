@@ -0,0 +1,77 @@
+//! # LC-3 Simulator
+//!
+//! The simulator core behind the `lc3-sim` debugger, usable in-process —
+//! e.g. to run and check student programs from a test suite.
+//!
+//! - [`Machine`] — registers, memory, the execute loop and console I/O.
+//! - [`Memory`] — 64K words with memory-mapped devices: the keyboard and
+//!   display registers at xFE00–xFE06, plus any custom [`Device`].
+//! - [`breakpoint`] — conditional breakpoints, read/write watchpoints, and
+//!   the debugger's `b` command syntax.
+//! - [`Machine::step_back`] / [`Machine::reverse_continue`] — reverse
+//!   execution through a bounded undo log, enabled with
+//!   [`Machine::set_history_limit`].
+//! - [`source`] / [`Machine::step_line`] — source-level debugging: the
+//!   `.asm` line behind each address, stepping by statement, and
+//!   [`Machine::step_over`] / [`Machine::step_out`] for calls.
+//! - [`gdb`] — a GDB remote serial protocol stub, so gdb or a script can
+//!   drive the machine over TCP.
+//! - [`trace`] — per-instruction execution traces, written as JSON Lines or
+//!   a compact binary file.
+//! - [`disassemble`] — one-word disassembly, re-exported from
+//!   [`lc3_assembler::disasm`].
+//!
+//! The six standard TRAP routines (`GETC`, `OUT`, `PUTS`, `IN`, `PUTSP`,
+//! `HALT`) are implemented natively unless the trap vector table supplies
+//! LC-3 code for them, e.g. from an OS image loaded with
+//! [`Machine::load_os`].  The machine models the PSR, separate
+//! user and supervisor stacks, the exception vectors (x00 privilege
+//! violation, x01 illegal opcode), keyboard interrupts through x0180 and
+//! `RTI`.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use std::{cell::RefCell, rc::Rc};
+//!
+//! use lc3_assembler::{encoder::encode, first_pass::first_pass, lexer::tokenize, parser::parse_lines};
+//! use lc3_sim::{Machine, StepResult};
+//!
+//! let source = ".ORIG x3000\nLEA R0, MSG\nPUTS\nHALT\nMSG .STRINGZ \"Hi\"\n.END\n";
+//! let lexed = tokenize(source);
+//! let first = first_pass(parse_lines(&lexed.tokens).lines);
+//! let encoded = encode(&first);
+//!
+//! let mut machine = Machine::new();
+//! machine.load_segments(&encoded.segments);
+//!
+//! // Capture output through a callback (or read `machine.output()` afterwards).
+//! let out = Rc::new(RefCell::new(String::new()));
+//! let sink = Rc::clone(&out);
+//! machine.set_output_handler(move |ch| sink.borrow_mut().push(ch));
+//!
+//! assert_eq!(machine.run_steps(1_000), StepResult::Halted);
+//! assert!(out.borrow().starts_with("Hi"));
+//! assert_eq!(machine.reg(0), 0x3003);
+//! ```
+
+pub mod breakpoint;
+pub mod device;
+pub mod gdb;
+mod history;
+pub mod machine;
+pub mod memory;
+pub mod source;
+mod stepping;
+pub mod trace;
+mod trap;
+
+pub use breakpoint::{Breakpoint, Trigger, Watchpoint};
+pub use device::Device;
+pub use lc3_assembler::disasm::disassemble;
+pub use machine::{
+    CondCode, InputHandler, Machine, OutputHandler, Privilege, Registers, StepResult, TraceHandler,
+};
+pub use memory::Memory;
+pub use source::SourceView;
+pub use trace::{TraceEntry, TraceFormat, TraceWriter};
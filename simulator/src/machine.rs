@@ -1,9 +1,34 @@
-use std::collections::{HashSet, VecDeque};
+//! The LC-3 CPU: registers, the fetch/decode/execute loop and I/O hooks.
 
+use std::collections::{HashMap, VecDeque};
+
+use lc3_assembler::encoder::Segment;
+
+use crate::breakpoint::{Breakpoint, Watchpoint};
+use crate::device::Device;
+use crate::history::History;
 use crate::memory::Memory;
+use crate::trace::{MemAccess, TraceEntry};
+
+// ── Constants ─────────────────────────────────────────────────────────────────
+
+/// Base of the interrupt vector table: vector `v` is taken through
+/// `mem[x0100 + v]`.
+pub const IVT_BASE: u16 = 0x0100;
+/// Exception vector for a privilege-mode violation (`RTI` in user mode).
+pub const VEC_PRIVILEGE: u8 = 0x00;
+/// Exception vector for the reserved opcode `1101`.
+pub const VEC_ILLEGAL_OPCODE: u8 = 0x01;
+/// Interrupt vector for the keyboard (table entry x0180).
+pub const VEC_KEYBOARD: u8 = 0x80;
+/// Priority level the keyboard interrupts at.
+pub const KEYBOARD_PRIORITY: u8 = 4;
+/// Supervisor stack pointer at reset: the stack grows down from user space.
+const INITIAL_SSP: u16 = 0x3000;
 
 // ── Public types ──────────────────────────────────────────────────────────────
 
+/// The condition code set by the last instruction that wrote a register.
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum CondCode {
     N,
@@ -21,10 +46,28 @@ impl std::fmt::Display for CondCode {
     }
 }
 
+/// Processor privilege mode (PSR bit 15).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Privilege {
+    Supervisor,
+    User,
+}
+
+/// The register file, including the processor status register and the
+/// stack pointer of whichever mode is not currently running.
+#[derive(Clone, PartialEq, Debug)]
 pub struct Registers {
+    /// General-purpose registers R0–R7.  R6 is the stack pointer.
     pub gpr: [u16; 8],
     pub pc: u16,
     pub cc: CondCode,
+    pub privilege: Privilege,
+    /// Priority level 0–7 (PSR bits 10:8).
+    pub priority: u8,
+    /// Supervisor stack pointer, saved here while in user mode.
+    pub saved_ssp: u16,
+    /// User stack pointer, saved here while in supervisor mode.
+    pub saved_usp: u16,
 }
 
 impl Default for Registers {
@@ -33,18 +76,103 @@ impl Default for Registers {
             gpr: [0u16; 8],
             pc: 0x3000,
             cc: CondCode::Z,
+            privilege: Privilege::User,
+            priority: 0,
+            saved_ssp: INITIAL_SSP,
+            saved_usp: 0,
         }
     }
 }
 
+impl Registers {
+    /// The processor status register: privilege in bit 15, priority in bits
+    /// 10:8 and the condition code in bits 2:0.
+    pub fn psr(&self) -> u16 {
+        let mode = match self.privilege {
+            Privilege::Supervisor => 0,
+            Privilege::User => 0x8000,
+        };
+        let cc = match self.cc {
+            CondCode::N => 0b100,
+            CondCode::Z => 0b010,
+            CondCode::P => 0b001,
+        };
+        mode | (u16::from(self.priority & 7) << 8) | cc
+    }
+
+    /// Load privilege, priority and condition code from a PSR value.
+    pub fn set_psr(&mut self, psr: u16) {
+        self.privilege = if psr & 0x8000 != 0 {
+            Privilege::User
+        } else {
+            Privilege::Supervisor
+        };
+        self.priority = ((psr >> 8) & 7) as u8;
+        self.cc = if psr & 0b100 != 0 {
+            CondCode::N
+        } else if psr & 0b001 != 0 {
+            CondCode::P
+        } else {
+            CondCode::Z
+        };
+    }
+}
+
+/// Outcome of executing one (or a batch of) instructions.
 #[derive(Debug, PartialEq)]
 pub enum StepResult {
+    /// The instruction completed; the machine can keep going.
     Ok,
+    /// `HALT` ran or the clock was disabled.
     Halted,
+    /// Execution stopped before the instruction at this address.
     BreakpointHit(u16),
+    /// The instruction just executed read (`write == false`) or wrote the
+    /// watched word at `addr`; `value` is the word read or written.
+    WatchpointHit { addr: u16, value: u16, write: bool },
+    /// The fetched word (returned here) has the reserved opcode `1101` and
+    /// no illegal-opcode handler is installed at x0101.
     IllegalInstruction(u16),
+    /// The fetched word (returned here) needs supervisor mode and no
+    /// privilege-violation handler is installed at x0100.
+    PrivilegeViolation(u16),
 }
 
+/// Called with every character the program writes (`OUT`, `PUTS`, …).
+pub type OutputHandler = Box<dyn FnMut(char)>;
+
+/// Asked for a keyboard character when `GETC`/`IN` find the input queue
+/// empty.  Returning `None` stalls the machine until input is queued.
+pub type InputHandler = Box<dyn FnMut() -> Option<u8>>;
+
+/// Receives a [`TraceEntry`] for every instruction executed.
+pub type TraceHandler = Box<dyn FnMut(&TraceEntry)>;
+
+/// A complete LC-3 machine: registers, 64K words of memory and console I/O.
+///
+/// Load a program with [`load_obj`](Self::load_obj) or
+/// [`load_image`](Self::load_image), then drive it with [`step`](Self::step)
+/// or [`run_steps`](Self::run_steps).  Console output is buffered in
+/// [`output_lines`](Self::output_lines) unless an output handler is
+/// registered; keyboard input comes from [`input_queue`](Self::input_queue)
+/// or an input handler.
+///
+/// Before each instruction the next key in `input_queue` is latched into the
+/// keyboard device if it is free, so polling programs see KBSR[15] set and
+/// read the key from KBDR.  Characters written to DDR are sent to the
+/// output like `OUT`.
+///
+/// Programs start in user mode at priority 0.  Exceptions and interrupts
+/// switch to the supervisor stack, push the PSR and PC, and continue at the
+/// address in the interrupt vector table; `RTI` undoes this.  A keyboard
+/// interrupt is raised while KBSR's ready and interrupt-enable bits are both
+/// set and the current priority is below 4.
+///
+/// `TRAP` runs LC-3 code whenever the trap vector table (x0000–x00FF) has an
+/// entry for the vector — after [`load_os`](Self::load_os), or when the
+/// program fills in its own entries.  Like an interrupt it enters supervisor
+/// mode and the routine returns with `RTI`.  Vectors with an empty entry
+/// fall back to the native routines for x20–x25.
 pub struct Machine {
     pub regs: Registers,
     pub mem: Memory,
@@ -57,10 +185,18 @@ pub struct Machine {
     pub input_queue: VecDeque<u8>,
     /// Set when GETC finds `input_queue` empty; cleared once a char arrives.
     pub waiting_for_input: bool,
-    pub breakpoints: HashSet<u16>,
+    /// Breakpoints by instruction address.
+    pub breakpoints: HashMap<u16, Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
     pub step_count: u64,
-    /// When true, OUT/PUTS write directly to stdout instead of output_lines.
-    pub headless: bool,
+    /// Receives console output instead of `output_buf`/`output_lines`.
+    on_output: Option<OutputHandler>,
+    /// Supplies keyboard input when `input_queue` is empty.
+    on_input: Option<InputHandler>,
+    /// Receives a trace entry after every instruction.
+    on_trace: Option<TraceHandler>,
+    /// Undo log for reverse execution.
+    pub(crate) history: History,
 }
 
 impl Default for Machine {
@@ -79,34 +215,195 @@ impl Machine {
             output_lines: Vec::new(),
             input_queue: VecDeque::new(),
             waiting_for_input: false,
-            breakpoints: HashSet::new(),
+            breakpoints: HashMap::new(),
+            watchpoints: Vec::new(),
             step_count: 0,
-            headless: false,
+            on_output: None,
+            on_input: None,
+            on_trace: None,
+            history: History::default(),
         }
     }
 
-    /// Load an LC-3 object file (big-endian: origin word + code words).
-    /// Returns the origin address on success.
+    // ── Loading ───────────────────────────────────────────────────────────────
+
+    /// Load an LC-3 object file: either a classic image (big-endian origin
+    /// word + code words) or a multi-segment container.  Every segment is
+    /// copied into memory and the PC is set to the first segment's origin,
+    /// which is also returned.
     pub fn load_obj(&mut self, data: &[u8]) -> Result<u16, String> {
-        if data.len() < 2 {
-            return Err("object file too short (missing origin word)".into());
+        let segments = lc3_assembler::obj::read(data)?;
+        Ok(self.load_segments(&segments))
+    }
+
+    /// Load an operating-system image (trap vector table, service routines,
+    /// interrupt vectors).  Unlike [`load_obj`](Self::load_obj) the PC is
+    /// left alone, so the user program can be loaded before or after it.
+    pub fn load_os(&mut self, data: &[u8]) -> Result<(), String> {
+        for seg in lc3_assembler::obj::read(data)? {
+            self.mem.load(seg.origin, &seg.words);
+        }
+        Ok(())
+    }
+
+    /// Copy assembled segments into memory and point the PC at the first
+    /// one, returning its origin (x3000 if there are none).
+    pub fn load_segments(&mut self, segments: &[Segment]) -> u16 {
+        let orig = segments.first().map_or(0x3000, |seg| seg.origin);
+        for seg in segments {
+            self.mem.load(seg.origin, &seg.words);
         }
-        let orig = u16::from_be_bytes([data[0], data[1]]);
-        let words: Vec<u16> = data[2..]
-            .chunks(2)
-            .map(|c| u16::from_be_bytes([c[0], c.get(1).copied().unwrap_or(0)]))
-            .collect();
-        self.mem.load(orig, &words);
         self.regs.pc = orig;
-        Ok(orig)
+        orig
+    }
+
+    /// Copy `words` into memory at `origin` and point the PC there.
+    pub fn load_image(&mut self, origin: u16, words: &[u16]) {
+        self.mem.load(origin, words);
+        self.regs.pc = origin;
+    }
+
+    // ── Registers and memory ──────────────────────────────────────────────────
+
+    /// Value of general-purpose register `r` (0–7).
+    ///
+    /// # Panics
+    /// If `r` is greater than 7.
+    pub fn reg(&self, r: usize) -> u16 {
+        self.regs.gpr[r]
     }
 
-    /// Execute one instruction. Returns immediately if already halted.
+    /// Set general-purpose register `r` (0–7).  Condition codes are not
+    /// touched.
+    ///
+    /// # Panics
+    /// If `r` is greater than 7.
+    pub fn set_reg(&mut self, r: usize, value: u16) {
+        self.regs.gpr[r] = value;
+    }
+
+    /// Address of the next instruction to execute.
+    pub fn pc(&self) -> u16 {
+        self.regs.pc
+    }
+
+    /// Move execution to `pc`.
+    pub fn set_pc(&mut self, pc: u16) {
+        self.regs.pc = pc;
+    }
+
+    /// Current condition code.
+    pub fn cc(&self) -> CondCode {
+        self.regs.cc
+    }
+
+    /// Read memory as the program would (device registers included, so
+    /// reading KBDR consumes the key).
+    pub fn read_mem(&mut self, addr: u16) -> u16 {
+        self.mem.read(addr)
+    }
+
+    /// Write a word of memory.
+    pub fn write_mem(&mut self, addr: u16, value: u16) {
+        self.mem.write(addr, value);
+    }
+
+    // ── I/O ───────────────────────────────────────────────────────────────────
+
+    /// Send console output to `handler` instead of buffering it.
+    pub fn set_output_handler(&mut self, handler: impl FnMut(char) + 'static) {
+        self.on_output = Some(Box::new(handler));
+    }
+
+    /// Ask `handler` for keyboard input whenever the input queue runs dry.
+    pub fn set_input_handler(&mut self, handler: impl FnMut() -> Option<u8> + 'static) {
+        self.on_input = Some(Box::new(handler));
+    }
+
+    /// Call `handler` with a [`TraceEntry`] after every instruction.
+    pub fn set_tracer(&mut self, handler: impl FnMut(&TraceEntry) + 'static) {
+        self.on_trace = Some(Box::new(handler));
+    }
+
+    /// Stop tracing.
+    pub fn clear_tracer(&mut self) {
+        self.on_trace = None;
+    }
+
+    /// Everything written to the buffered console so far, lines joined with
+    /// `\n`.  Empty when an output handler is registered.
+    pub fn output(&self) -> String {
+        let mut text = String::new();
+        for line in &self.output_lines {
+            text.push_str(line);
+            text.push('\n');
+        }
+        text.push_str(&self.output_buf);
+        text
+    }
+
+    /// Next keyboard character: the key latched in the keyboard device, else
+    /// the queue, else the input handler.
+    pub(crate) fn next_input(&mut self) -> Option<u8> {
+        if let Some(key) = self.mem.keyboard.take() {
+            return Some(key);
+        }
+        let key = self
+            .input_queue
+            .pop_front()
+            .or_else(|| self.on_input.as_mut().and_then(|input| input()))?;
+        self.record_key(key);
+        Some(key)
+    }
+
+    /// Note that the current step took `key` from the input, so undoing the
+    /// step can give it back.
+    fn record_key(&mut self, key: u8) {
+        if let Some(record) = self.history.current.as_mut() {
+            record.keys.push(key);
+        }
+    }
+
+    /// True when the program has set KBSR's interrupt-enable bit.
+    pub fn keyboard_interrupt_enabled(&self) -> bool {
+        self.mem.keyboard.interrupt_enabled()
+    }
+
+    /// True when the program reads the keyboard device directly (polling
+    /// KBSR or via interrupts), so a front end should forward keys to it.
+    pub fn keyboard_in_use(&self) -> bool {
+        self.mem.keyboard.in_use()
+    }
+
+    /// Map a custom device into memory.  It receives every read and write of
+    /// the addresses it [claims](Device::claims), except the built-in
+    /// keyboard and display registers (xFE00–xFE06).
+    pub fn attach_device(&mut self, device: impl Device + 'static) {
+        self.mem.attach(Box::new(device));
+    }
+
+    // ── Execution ─────────────────────────────────────────────────────────────
+
+    /// Execute one instruction, first taking a keyboard interrupt if one is
+    /// pending. Returns immediately if already halted.
     pub fn step(&mut self) -> StepResult {
         if self.halted {
             return StepResult::Halted;
         }
-        if self.waiting_for_input {
+        self.begin_undo();
+        let result = self.step_inner();
+        self.end_undo();
+        result
+    }
+
+    fn step_inner(&mut self) -> StepResult {
+        if !self.mem.keyboard.ready() {
+            if let Some(key) = self.input_queue.pop_front() {
+                self.record_key(key);
+                self.mem.keyboard.press(key);
+            }
+        }
+        if self.waiting_for_input && !self.mem.keyboard.ready() && self.on_input.is_none() {
             return StepResult::Ok; // caller must feed input_queue first
         }
         if !self.mem.clock_enabled() {
@@ -114,20 +411,85 @@ impl Machine {
             return StepResult::Halted;
         }
 
+        // Memory accesses are recorded for the tracer and watchpoints.
+        let recording = self.on_trace.is_some() || !self.watchpoints.is_empty();
+        if recording {
+            self.mem.start_recording();
+        }
+        // Register state before the step, kept only while tracing.
+        let before = self
+            .on_trace
+            .is_some()
+            .then_some((self.regs.gpr, self.regs.cc));
+
+        if self.keyboard_interrupt_enabled()
+            && self.mem.keyboard.ready()
+            && self.regs.priority < KEYBOARD_PRIORITY
+        {
+            self.enter_service_routine(VEC_KEYBOARD, Some(KEYBOARD_PRIORITY));
+        }
+
         let pc = self.regs.pc;
-        let ir = self.mem.read(pc);
+        let ir = self.mem.fetch(pc);
         self.regs.pc = pc.wrapping_add(1);
         self.step_count += 1;
 
         let result = self.execute(ir);
+        for ch in self.mem.display.take_output() {
+            self.push_char(ch);
+        }
+        let (reads, writes) = if recording {
+            self.mem.stop_recording()
+        } else {
+            Default::default()
+        };
+        if let Some((gpr, cc)) = before {
+            self.trace(pc, ir, gpr, cc, &reads, &writes);
+        }
 
+        if result != StepResult::Ok {
+            return result;
+        }
+        if let Some(hit) = self.check_watchpoints(&reads, &writes) {
+            return hit;
+        }
         // Check breakpoints on the *next* PC (where execution will land).
-        if result == StepResult::Ok && self.breakpoints.contains(&self.regs.pc) {
-            return StepResult::BreakpointHit(self.regs.pc);
+        if let Some(bp) = self.breakpoints.get_mut(&self.regs.pc) {
+            if bp.trigger.triggers(&self.regs, &self.mem) && bp.trigger.hit() {
+                return StepResult::BreakpointHit(self.regs.pc);
+            }
         }
         result
     }
 
+    /// The first watchpoint the step's accesses set off, counting a hit on
+    /// each watchpoint they match.
+    fn check_watchpoints(
+        &mut self,
+        reads: &[MemAccess],
+        writes: &[MemAccess],
+    ) -> Option<StepResult> {
+        let mut stop = None;
+        for wp in &mut self.watchpoints {
+            let access = writes
+                .iter()
+                .map(|a| (a, true))
+                .chain(reads.iter().map(|a| (a, false)))
+                .find(|(a, write)| wp.matches(a.addr, *write));
+            let Some((access, write)) = access else {
+                continue;
+            };
+            if wp.trigger.triggers(&self.regs, &self.mem) && wp.trigger.hit() && stop.is_none() {
+                stop = Some(StepResult::WatchpointHit {
+                    addr: access.addr,
+                    value: access.value,
+                    write,
+                });
+            }
+        }
+        stop
+    }
+
     /// Run up to `max` steps, stopping at halt, breakpoint, or input wait.
     pub fn run_steps(&mut self, max: u64) -> StepResult {
         for _ in 0..max {
@@ -144,8 +506,8 @@ impl Machine {
 
     /// Push a character to the output, handling newlines as line boundaries.
     pub fn push_char(&mut self, ch: char) {
-        if self.headless {
-            print!("{ch}");
+        if let Some(output) = self.on_output.as_mut() {
+            output(ch);
         } else if ch == '\n' {
             let line = std::mem::take(&mut self.output_buf);
             self.output_lines.push(line);
@@ -161,6 +523,37 @@ impl Machine {
         }
     }
 
+    /// Send the trace entry for the instruction at `pc` that just ran,
+    /// given the registers and condition code from before it.
+    fn trace(
+        &mut self,
+        pc: u16,
+        ir: u16,
+        gpr: [u16; 8],
+        cc: CondCode,
+        reads: &[MemAccess],
+        writes: &[MemAccess],
+    ) {
+        let dest = destination_register(ir);
+        let regs = (0..8)
+            .filter(|&r| self.regs.gpr[r] != gpr[r] || dest == Some(r))
+            .map(|r| (r as u8, self.regs.gpr[r]))
+            .collect();
+        let cc = (sets_cc(ir) || self.regs.cc != cc).then_some(self.regs.cc);
+        let entry = TraceEntry {
+            step: self.step_count,
+            pc,
+            ir,
+            regs,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            cc,
+        };
+        if let Some(tracer) = self.on_trace.as_mut() {
+            tracer(&entry);
+        }
+    }
+
     // ── Instruction execution ─────────────────────────────────────────────────
 
     fn execute(&mut self, ir: u16) -> StepResult {
@@ -279,24 +672,84 @@ impl Machine {
                 }
                 self.regs.gpr[7] = saved;
             }
-            // RTI (simplified: restore PC from R7, no privilege logic)
+            // RTI: pop PC and PSR, switching back to the user stack if the
+            // restored PSR is in user mode.
             0b1000 => {
-                self.regs.pc = self.regs.gpr[7];
+                if self.regs.privilege == Privilege::User {
+                    return self.raise_exception(VEC_PRIVILEGE, ir);
+                }
+                self.regs.pc = self.pop();
+                let psr = self.pop();
+                self.regs.set_psr(psr);
+                if self.regs.privilege == Privilege::User {
+                    self.regs.saved_ssp = self.regs.gpr[6];
+                    self.regs.gpr[6] = self.regs.saved_usp;
+                }
             }
             // TRAP
             0b1111 => {
-                // Save return address
-                self.regs.gpr[7] = self.regs.pc;
                 let vect = (ir & 0xFF) as u8;
                 return self.dispatch_trap(vect);
             }
             // Illegal / reserved
             _ => {
-                return StepResult::IllegalInstruction(ir);
+                return self.raise_exception(VEC_ILLEGAL_OPCODE, ir);
             }
         }
         StepResult::Ok
     }
+
+    // ── Exceptions and interrupts ─────────────────────────────────────────────
+
+    /// Take exception `vector` for instruction `ir`.  If the vector table has
+    /// no handler for it the machine stops and reports the fault instead of
+    /// jumping to x0000.
+    fn raise_exception(&mut self, vector: u8, ir: u16) -> StepResult {
+        if self.mem.raw(IVT_BASE + u16::from(vector)) == 0 {
+            return match vector {
+                VEC_PRIVILEGE => StepResult::PrivilegeViolation(ir),
+                _ => StepResult::IllegalInstruction(ir),
+            };
+        }
+        self.enter_service_routine(vector, None);
+        StepResult::Ok
+    }
+
+    /// Enter the handler for interrupt/exception `vector` through the
+    /// interrupt vector table.  Interrupts also raise the priority level;
+    /// exceptions leave it unchanged.
+    fn enter_service_routine(&mut self, vector: u8, priority: Option<u8>) {
+        let handler = self.mem.read(IVT_BASE + u16::from(vector));
+        self.enter_supervisor(handler, priority);
+    }
+
+    /// Switch to supervisor mode and the supervisor stack, push the PSR and
+    /// PC, and continue at `handler`.  `RTI` reverses this.
+    pub(crate) fn enter_supervisor(&mut self, handler: u16, priority: Option<u8>) {
+        let psr = self.regs.psr();
+        if self.regs.privilege == Privilege::User {
+            self.regs.saved_usp = self.regs.gpr[6];
+            self.regs.gpr[6] = self.regs.saved_ssp;
+            self.regs.privilege = Privilege::Supervisor;
+        }
+        if let Some(priority) = priority {
+            self.regs.priority = priority;
+        }
+        self.push(psr);
+        self.push(self.regs.pc);
+        self.regs.pc = handler;
+    }
+
+    fn push(&mut self, value: u16) {
+        self.regs.gpr[6] = self.regs.gpr[6].wrapping_sub(1);
+        self.mem.write(self.regs.gpr[6], value);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let value = self.mem.read(self.regs.gpr[6]);
+        self.regs.gpr[6] = self.regs.gpr[6].wrapping_add(1);
+        value
+    }
 }
 
 // ── Helpers ───────────────────────────────────────────────────────────────────
@@ -308,6 +761,25 @@ pub(crate) fn sext(value: u16, bits: u8) -> u16 {
     (((value as i16) << shift) >> shift) as u16
 }
 
+/// The register `ir` writes as its destination, if it has one.
+fn destination_register(ir: u16) -> Option<usize> {
+    match ir >> 12 {
+        0b0001 | 0b0101 | 0b1001 | 0b0010 | 0b1010 | 0b0110 | 0b1110 => {
+            Some(((ir >> 9) & 7) as usize)
+        }
+        0b0100 => Some(7),
+        _ => None,
+    }
+}
+
+/// True for the instructions that set the condition code from their result.
+fn sets_cc(ir: u16) -> bool {
+    matches!(
+        ir >> 12,
+        0b0001 | 0b0101 | 0b1001 | 0b0010 | 0b1010 | 0b0110
+    )
+}
+
 /// Set condition codes from a 16-bit result (interpreted as signed).
 fn set_cc(regs: &mut Registers, value: u16) {
     regs.cc = match (value as i16).cmp(&0) {
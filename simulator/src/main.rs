@@ -1,15 +1,20 @@
-mod disasm;
-mod machine;
-mod memory;
-mod trap;
 mod tui;
 
-use std::{collections::HashMap, fs, path::Path};
-
-use lc3_assembler::{
-    encoder::encode, first_pass::first_pass, lexer::tokenize, parser::parse_lines,
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    io::{self, IsTerminal as _},
+    net::TcpListener,
+    path::Path,
+    rc::Rc,
+    sync::mpsc::{self, Receiver},
 };
-use machine::{Machine, StepResult};
+
+use lc3_assembler::debug_info::DebugInfo;
+use lc3_assembler::{assemble, AssembleOptions};
+use lc3_sim::gdb::GdbStub;
+use lc3_sim::{Machine, SourceView, StepResult, TraceFormat, TraceWriter};
 use tui::app::App;
 
 // ── CLI ───────────────────────────────────────────────────────────────────────
@@ -19,8 +24,19 @@ struct Args {
     input: String,
     /// Load a .sym file for label display.
     symbols: Option<String>,
+    /// Load a debug-info file for source-level debugging of a .obj.
+    debug_info: Option<String>,
+    /// Operating-system image (.obj or .asm) whose TRAP routines replace the
+    /// native ones.
+    os: Option<String>,
     /// Run headlessly (no TUI) and print output to stdout.
     run: bool,
+    /// Write an execution trace of the headless run here.
+    trace: Option<String>,
+    /// Trace format.  None = binary for `.bin` files, JSONL otherwise.
+    trace_format: Option<TraceFormat>,
+    /// Serve the GDB remote protocol on this local port instead of the TUI.
+    gdb_port: Option<u16>,
 }
 
 impl Args {
@@ -39,7 +55,12 @@ impl Args {
 
         let mut input = None;
         let mut symbols = None;
+        let mut debug_info = None;
+        let mut os = None;
         let mut run = false;
+        let mut trace = None;
+        let mut trace_format = None;
+        let mut gdb_port = None;
         let mut i = 1usize;
 
         while i < args.len() {
@@ -52,7 +73,53 @@ impl Args {
                     }
                     symbols = Some(args[i].to_string());
                 }
+                "-g" | "--debug-info" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("error: -g requires a filename");
+                        std::process::exit(1);
+                    }
+                    debug_info = Some(args[i].to_string());
+                }
+                "--os" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("error: --os requires a filename");
+                        std::process::exit(1);
+                    }
+                    os = Some(args[i].to_string());
+                }
                 "--run" => run = true,
+                "--trace" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("error: --trace requires a filename");
+                        std::process::exit(1);
+                    }
+                    trace = Some(args[i].to_string());
+                }
+                "--trace-format" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("error: --trace-format requires jsonl or binary");
+                        std::process::exit(1);
+                    }
+                    trace_format = Some(args[i].parse().unwrap_or_else(|e| {
+                        eprintln!("error: {e}");
+                        std::process::exit(1);
+                    }));
+                }
+                "--gdb-port" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("error: --gdb-port requires a port number");
+                        std::process::exit(1);
+                    }
+                    gdb_port = Some(args[i].parse().unwrap_or_else(|_| {
+                        eprintln!("error: invalid port '{}'", args[i]);
+                        std::process::exit(1);
+                    }));
+                }
                 other => {
                     if input.is_some() {
                         eprintln!("error: unexpected argument '{other}'");
@@ -64,6 +131,15 @@ impl Args {
             i += 1;
         }
 
+        if trace.is_some() && !run {
+            eprintln!("error: --trace requires --run");
+            std::process::exit(1);
+        }
+        if gdb_port.is_some() && run {
+            eprintln!("error: --gdb-port cannot be combined with --run");
+            std::process::exit(1);
+        }
+
         Args {
             input: input.unwrap_or_else(|| {
                 eprintln!("error: no input file");
@@ -71,7 +147,12 @@ impl Args {
                 std::process::exit(1);
             }),
             symbols,
+            debug_info,
+            os,
             run,
+            trace,
+            trace_format,
+            gdb_port,
         }
     }
 }
@@ -85,16 +166,33 @@ fn print_help() {
     println!();
     println!("OPTIONS:");
     println!("  -s, --symbols <file>   Load .sym file for label display in TUI");
+    println!("  -g, --debug-info <file>");
+    println!("                         Load lc3-assembler --debug-info output to debug a .obj");
+    println!("                         by source line");
+    println!("      --os <file>        Load an OS image (.obj or .asm); TRAPs run its routines");
     println!("      --run              Run headlessly; print output to stdout");
+    println!("      --trace <file>     With --run, record every instruction to <file>");
+    println!("      --trace-format <jsonl|binary>");
+    println!("                         Trace format (default: binary for .bin files, else jsonl)");
+    println!("      --gdb-port <port>  Serve the GDB remote protocol on 127.0.0.1:<port>");
+    println!("                         (0 picks a free port) instead of starting the TUI;");
+    println!("                         the program reads keys typed on this terminal's stdin");
     println!("  -h, --help             Print this help message");
     println!("  -V, --version          Print version information");
     println!();
     println!("TUI KEYS:");
     println!("  s        Single step");
+    println!("  n        Next: step over calls and to the next source line");
+    println!("  o        Step out: run until the current routine returns");
+    println!("  u        Step back one instruction");
     println!("  c        Continue (run to halt/breakpoint)");
+    println!("  v        Reverse continue (back to the previous breakpoint)");
     println!("  p        Pause");
     println!("  r        Reset (reload program)");
     println!("  b x3000  Toggle breakpoint at address");
+    println!("  b main.asm:12           Toggle breakpoint at a source line (.asm input)");
+    println!("  b x3000 if R0 == x0A    Conditional breakpoint (also: ignore N)");
+    println!("  b w x4000-x400F         Watch writes (r: reads, rw: both)");
     println!("  g x3000  Scroll memory panel to address");
     println!("  ↑ ↓      Scroll memory panel");
     println!("  q        Quit");
@@ -107,21 +205,29 @@ fn main() {
 
     // ── Load program ──────────────────────────────────────────────────────────
 
-    let (obj_bytes, sym_table) = load_program(&args);
+    let program = load_program(&args);
 
     // ── Headless run ──────────────────────────────────────────────────────────
 
     if args.run {
-        run_headless(obj_bytes);
+        run_headless(program.obj, program.os, &args);
+        return;
+    }
+
+    // ── GDB remote stub ───────────────────────────────────────────────────────
+
+    if let Some(port) = args.gdb_port {
+        serve_gdb(program.obj, program.os, port);
         return;
     }
 
     // ── TUI mode ──────────────────────────────────────────────────────────────
 
-    let app = App::new(obj_bytes, sym_table).unwrap_or_else(|e| {
-        eprintln!("error: {e}");
-        std::process::exit(1);
-    });
+    let app =
+        App::new(program.obj, program.os, program.symbols, program.source).unwrap_or_else(|e| {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        });
 
     if let Err(e) = tui::run(app) {
         eprintln!("TUI error: {e}");
@@ -131,110 +237,186 @@ fn main() {
 
 // ── Helpers ───────────────────────────────────────────────────────────────────
 
-/// Load the input (assembling from .asm if needed) and parse any .sym file.
-/// Returns (raw obj bytes, address→label map).
-fn load_program(args: &Args) -> (Vec<u8>, HashMap<u16, String>) {
-    let path = &args.input;
+/// Everything loaded from the command line's input files.
+struct Program {
+    obj: Vec<u8>,
+    os: Option<Vec<u8>>,
+    /// address → label, for the program and the OS.
+    symbols: HashMap<u16, String>,
+    /// Source lines behind the program's addresses, for `.asm` input.
+    source: Option<SourceView>,
+}
+
+/// Load the input and optional OS image (assembling from .asm if needed) and
+/// parse any .sym file.
+fn load_program(args: &Args) -> Program {
+    let (obj_bytes, asm_syms, asm_source) = read_object(&args.input);
+
+    // Prefer explicit .sym and debug-info files; fall back to what the
+    // assembler pass produced.
+    let mut sym_table = if let Some(ref sym_path) = args.symbols {
+        load_sym_file(sym_path)
+    } else {
+        asm_syms
+    };
+    let source = match args.debug_info {
+        Some(ref dbg_path) => Some(load_debug_info(dbg_path)),
+        None => asm_source,
+    };
+
+    // Labels from an OS source let the TUI name its service routines; the
+    // program's own labels win where both name an address.
+    let os_bytes = args.os.as_deref().map(|path| {
+        let (bytes, os_syms, _) = read_object(path);
+        for (addr, label) in os_syms {
+            sym_table.entry(addr).or_insert(label);
+        }
+        bytes
+    });
+
+    Program {
+        obj: obj_bytes,
+        os: os_bytes,
+        symbols: sym_table,
+        source,
+    }
+}
+
+/// Read an .obj file, or assemble an .asm file and return its labels and
+/// source mapping too.
+fn read_object(path: &str) -> (Vec<u8>, HashMap<u16, String>, Option<SourceView>) {
     let ext = Path::new(path)
         .extension()
         .and_then(|e| e.to_str())
         .unwrap_or("");
 
-    let (obj_bytes, asm_syms) = if ext.eq_ignore_ascii_case("asm") {
+    if ext.eq_ignore_ascii_case("asm") {
         assemble_from_source(path)
     } else {
         let bytes = fs::read(path).unwrap_or_else(|e| {
             eprintln!("error: cannot read '{}': {e}", path);
             std::process::exit(1);
         });
-        (bytes, HashMap::new())
-    };
-
-    // Prefer explicit .sym file; fall back to symbols from assembler pass.
-    let sym_table = if let Some(ref sym_path) = args.symbols {
-        load_sym_file(sym_path)
-    } else {
-        asm_syms
-    };
-
-    (obj_bytes, sym_table)
+        (bytes, HashMap::new(), None)
+    }
 }
 
 /// Assemble an .asm source file in-memory.  Exits on errors.
-fn assemble_from_source(path: &str) -> (Vec<u8>, HashMap<u16, String>) {
-    let source = fs::read_to_string(path).unwrap_or_else(|e| {
+fn assemble_from_source(path: &str) -> (Vec<u8>, HashMap<u16, String>, Option<SourceView>) {
+    let asm = assemble(path, &AssembleOptions::default()).unwrap_or_else(|e| {
         eprintln!("error: cannot read '{}': {e}", path);
         std::process::exit(1);
     });
 
-    let lexed = tokenize(&source);
-    let parsed = parse_lines(&lexed.tokens);
-    let first = first_pass(parsed.lines);
-    let encoded = encode(&first);
-
-    let all_errors: Vec<_> = lexed
-        .errors
-        .iter()
-        .chain(parsed.errors.iter())
-        .chain(first.errors.iter())
-        .chain(encoded.errors.iter())
-        .collect();
-
-    if !all_errors.is_empty() {
-        for e in &all_errors {
-            eprintln!("error:{}: {}", e.span.line, e.message);
-        }
+    asm.emit_diagnostics(std::io::stderr().is_terminal());
+    if asm.has_errors() {
         std::process::exit(1);
     }
 
-    // Serialize to the same .obj binary format the assembler writes.
-    let mut bytes = Vec::with_capacity((1 + encoded.machine_code.len()) * 2);
-    bytes.extend_from_slice(&encoded.orig_address.to_be_bytes());
-    for &w in &encoded.machine_code {
-        bytes.extend_from_slice(&w.to_be_bytes());
-    }
-
     // Build address→label map from the assembler's symbol table.
-    let syms: HashMap<u16, String> = first
-        .symbol_table
+    let syms: HashMap<u16, String> = asm
+        .symbol_table()
         .iter()
         .map(|(label, addr)| (addr, label.to_string()))
         .collect();
 
-    (bytes, syms)
+    (asm.obj_bytes(), syms, Some(SourceView::from_assembly(&asm)))
 }
 
-/// Parse a .sym file in the format `LABEL=xADDR`.
+/// Read a .sym file in the format `LABEL=xADDR`.
 fn load_sym_file(path: &str) -> HashMap<u16, String> {
     let text = fs::read_to_string(path).unwrap_or_else(|e| {
         eprintln!("warning: cannot read sym file '{}': {e}", path);
         String::new()
     });
-    let mut map = HashMap::new();
-    for line in text.lines() {
-        let line = line.trim();
-        if line.starts_with(';') || line.is_empty() {
-            continue;
-        }
-        if let Some((label, addr_str)) = line.split_once('=') {
-            let addr_str = addr_str.trim().trim_start_matches('x');
-            if let Ok(addr) = u16::from_str_radix(addr_str, 16) {
-                map.insert(addr, label.trim().to_string());
+    lc3_assembler::disasm::parse_sym_file(&text)
+}
+
+/// Read a debug-info sidecar written by `lc3-assembler --debug-info`.
+/// Exits on errors.
+fn load_debug_info(path: &str) -> SourceView {
+    let text = fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("error: cannot read '{}': {e}", path);
+        std::process::exit(1);
+    });
+    let info = DebugInfo::parse(&text).unwrap_or_else(|e| {
+        eprintln!("error: {path}: {e}");
+        std::process::exit(1);
+    });
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    SourceView::from_debug_info(&info, dir)
+}
+
+/// A machine loaded with the program whose console is this process's
+/// stdout and stdin, plus the keys typed on stdin for programs that poll the
+/// keyboard.  Exits on load errors.
+fn console_machine(obj_bytes: &[u8], os_bytes: Option<&[u8]>) -> (Machine, Rc<Receiver<u8>>) {
+    // Read stdin on its own thread so keys can reach a program that polls
+    // the keyboard or uses interrupts without blocking execution; GETC
+    // waits for them.
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        use std::io::Read;
+        for byte in std::io::stdin().lock().bytes() {
+            match byte {
+                Ok(b) if tx.send(b).is_ok() => {}
+                _ => break,
             }
         }
-    }
-    map
-}
+    });
+    let keys = Rc::new(rx);
 
-/// Headless run: execute to HALT and print output to stdout.
-fn run_headless(obj_bytes: Vec<u8>) {
     let mut machine = Machine::new();
-    machine.headless = true;
+    machine.set_output_handler(|ch| print!("{ch}"));
+    let getc_keys = Rc::clone(&keys);
+    machine.set_input_handler(move || Some(getc_keys.recv().unwrap_or(0)));
 
-    machine.load_obj(&obj_bytes).unwrap_or_else(|e| {
+    if let Some(os) = os_bytes {
+        machine.load_os(os).unwrap_or_else(|e| {
+            eprintln!("error: OS image: {e}");
+            std::process::exit(1);
+        });
+    }
+    machine.load_obj(obj_bytes).unwrap_or_else(|e| {
         eprintln!("error: {e}");
         std::process::exit(1);
     });
+    (machine, keys)
+}
+
+/// Wait for one debugger connection on `127.0.0.1:port` and serve it.
+fn serve_gdb(obj_bytes: Vec<u8>, os_bytes: Option<Vec<u8>>, port: u16) {
+    let (mut machine, keys) = console_machine(&obj_bytes, os_bytes.as_deref());
+    // Keys reach the program through the keyboard, queued by the stub as
+    // they arrive, so GETC and IN stall rather than block the connection.
+    machine.set_input_handler(|| None);
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap_or_else(|e| {
+        eprintln!("error: cannot listen on port {port}: {e}");
+        std::process::exit(1);
+    });
+    if let Ok(addr) = listener.local_addr() {
+        eprintln!("gdb: listening on {addr}");
+    }
+    let result = listener.accept().and_then(|(stream, _)| {
+        GdbStub::new(&mut machine, stream)
+            .with_keys(|| keys.try_recv().ok())
+            .run()
+    });
+    if let Err(e) = result {
+        eprintln!("error: gdb connection: {e}");
+        std::process::exit(1);
+    }
+}
+
+/// Headless run: execute to HALT, printing output to stdout and reading
+/// keyboard input from stdin.
+fn run_headless(obj_bytes: Vec<u8>, os_bytes: Option<Vec<u8>>, args: &Args) {
+    let (mut machine, keys) = console_machine(&obj_bytes, os_bytes.as_deref());
+
+    let trace = args
+        .trace
+        .as_deref()
+        .map(|path| start_trace(&mut machine, path, args));
 
     const MAX_STEPS: u64 = 10_000_000;
     loop {
@@ -243,32 +425,75 @@ fn run_headless(obj_bytes: Vec<u8>) {
             break;
         }
 
-        // If machine needs keyboard input in headless mode, read one byte from stdin.
-        if machine.waiting_for_input {
-            use std::io::Read;
-            let mut buf = [0u8; 1];
-            match std::io::stdin().read(&mut buf) {
-                Ok(1) => machine.input_queue.push_back(buf[0]),
-                _ => machine.input_queue.push_back(0),
-            }
-        }
+        machine.input_queue.extend(keys.try_iter());
 
         match machine.step() {
-            StepResult::Halted | StepResult::BreakpointHit(_) => break,
+            StepResult::Halted
+            | StepResult::BreakpointHit(_)
+            | StepResult::WatchpointHit { .. } => break,
             StepResult::IllegalInstruction(ir) => {
+                finish_trace(trace);
                 eprintln!(
                     "\nerror: illegal instruction 0x{:04X} at PC=0x{:04X}",
                     ir,
-                    machine.regs.pc.wrapping_sub(1)
+                    machine.pc().wrapping_sub(1)
+                );
+                std::process::exit(1);
+            }
+            StepResult::PrivilegeViolation(ir) => {
+                finish_trace(trace);
+                eprintln!(
+                    "\nerror: privilege mode violation 0x{:04X} at PC=0x{:04X}",
+                    ir,
+                    machine.pc().wrapping_sub(1)
                 );
                 std::process::exit(1);
             }
             StepResult::Ok => {}
         }
     }
+    finish_trace(trace);
+}
 
-    // Flush any partial output line.
-    if !machine.output_buf.is_empty() {
-        println!("{}", machine.output_buf);
+/// A trace file being written, and the first error writing it.
+type Trace = Rc<RefCell<(TraceWriter<io::BufWriter<fs::File>>, Option<io::Error>)>>;
+
+/// Create the trace file at `path` and have `machine` write to it.
+fn start_trace(machine: &mut Machine, path: &str, args: &Args) -> Trace {
+    let file = fs::File::create(path).unwrap_or_else(|e| {
+        eprintln!("error: cannot create '{path}': {e}");
+        std::process::exit(1);
+    });
+    let format = args.trace_format.unwrap_or_else(|| {
+        let ext = Path::new(path).extension().and_then(|e| e.to_str());
+        if ext.is_some_and(|e| e.eq_ignore_ascii_case("bin")) {
+            TraceFormat::Binary
+        } else {
+            TraceFormat::Jsonl
+        }
+    });
+    let trace: Trace = Rc::new(RefCell::new((
+        TraceWriter::new(io::BufWriter::new(file), format),
+        None,
+    )));
+    let sink = Rc::clone(&trace);
+    machine.set_tracer(move |entry| {
+        let (writer, error) = &mut *sink.borrow_mut();
+        if error.is_none() {
+            if let Err(e) = writer.write(entry) {
+                *error = Some(e);
+            }
+        }
+    });
+    trace
+}
+
+/// Flush the trace file, reporting any error writing it.
+fn finish_trace(trace: Option<Trace>) {
+    let Some(trace) = trace else { return };
+    let (writer, error) = &mut *trace.borrow_mut();
+    if let Some(e) = error.take().map_or_else(|| writer.finish().err(), Some) {
+        eprintln!("error: cannot write trace: {e}");
+        std::process::exit(1);
     }
 }
@@ -1,9 +1,23 @@
+use crate::device::{Device, Display, Keyboard};
+use crate::trace::MemAccess;
+
+pub use crate::device::{DDR, DSR, KBDR, KBSR};
+
 /// LC-3 word-addressed memory: 65 536 × 16-bit words.
 ///
-/// Memory-mapped I/O registers at the top of the address space are handled
-/// transparently on read; writes go directly to the backing array.
+/// Reads and writes of device registers go to the built-in [`Keyboard`] and
+/// [`Display`] or to an attached [`Device`]; everything else uses the
+/// backing array.
 pub struct Memory {
     words: Box<[u16; 65536]>,
+    pub keyboard: Keyboard,
+    pub display: Display,
+    devices: Vec<Box<dyn Device>>,
+    /// Reads and writes made since [`start_recording`](Self::start_recording).
+    recording: Option<(Vec<MemAccess>, Vec<MemAccess>)>,
+    /// `(address, previous value)` of each word written since
+    /// [`start_undo`](Self::start_undo).
+    undo: Option<Vec<(u16, u16)>>,
 }
 
 impl Default for Memory {
@@ -16,32 +30,73 @@ impl Memory {
     pub fn new() -> Self {
         let mut mem = Self {
             words: Box::new([0u16; 65536]),
+            keyboard: Keyboard::default(),
+            display: Display::default(),
+            devices: Vec::new(),
+            recording: None,
+            undo: None,
         };
         // MCR (Machine Control Register): bit 15 = clock enable; start enabled.
         mem.words[0xFFFE] = 0x8000;
         mem
     }
 
-    /// Read a word, returning synthetic values for MMIO status registers.
-    pub fn read(&self, addr: u16) -> u16 {
-        match addr {
-            0xFE00 => 0x8000, // KBSR: keyboard always ready
-            0xFE04 => 0x8000, // DSR:  display always ready
-            _ => self.words[addr as usize],
+    /// Read a word as the program sees it, including device side effects.
+    pub fn read(&mut self, addr: u16) -> u16 {
+        let value = self.fetch(addr);
+        if let Some((reads, _)) = &mut self.recording {
+            reads.push(MemAccess { addr, value });
+        }
+        value
+    }
+
+    /// Read a word like [`read`](Self::read) without recording it — used for
+    /// instruction fetch.
+    pub(crate) fn fetch(&mut self, addr: u16) -> u16 {
+        if self.keyboard.claims(addr) {
+            self.keyboard.read(addr)
+        } else if self.display.claims(addr) {
+            self.display.read(addr)
+        } else if let Some(dev) = self.devices.iter_mut().find(|d| d.claims(addr)) {
+            dev.read(addr)
+        } else {
+            self.words[addr as usize]
         }
     }
 
-    /// Write a word directly to the backing array (including MMIO space).
+    /// Write a word as the program would, including device side effects.
     pub fn write(&mut self, addr: u16, val: u16) {
-        self.words[addr as usize] = val;
+        if let Some((_, writes)) = &mut self.recording {
+            writes.push(MemAccess { addr, value: val });
+        }
+        if self.keyboard.claims(addr) {
+            self.keyboard.write(addr, val);
+        } else if self.display.claims(addr) {
+            self.display.write(addr, val);
+        } else if let Some(dev) = self.devices.iter_mut().find(|d| d.claims(addr)) {
+            dev.write(addr, val);
+        } else {
+            if let Some(undo) = &mut self.undo {
+                undo.push((addr, self.words[addr as usize]));
+            }
+            self.words[addr as usize] = val;
+        }
     }
 
-    /// Read bypassing MMIO substitution — used by the disassembler / listing.
+    /// Read the backing array, bypassing devices — used by the disassembler
+    /// / listing.
     #[inline]
     pub fn raw(&self, addr: u16) -> u16 {
         self.words[addr as usize]
     }
 
+    /// Write the backing array, bypassing devices — the counterpart of
+    /// [`raw`](Self::raw), used by the debugger.
+    #[inline]
+    pub fn set_raw(&mut self, addr: u16, val: u16) {
+        self.words[addr as usize] = val;
+    }
+
     /// Load a program image starting at `orig`.
     pub fn load(&mut self, orig: u16, words: &[u16]) {
         let start = orig as usize;
@@ -49,9 +104,40 @@ impl Memory {
         self.words[start..end].copy_from_slice(&words[..end - start]);
     }
 
+    /// Route the addresses `device` claims to it.  The built-in keyboard and
+    /// display registers cannot be overridden.
+    pub fn attach(&mut self, device: Box<dyn Device>) {
+        self.devices.push(device);
+    }
+
     /// Clock-enable bit of the Machine Control Register.
     #[inline]
     pub fn clock_enabled(&self) -> bool {
         self.words[0xFFFE] & 0x8000 != 0
     }
+
+    /// Start recording every [`read`](Self::read) and [`write`](Self::write).
+    pub(crate) fn start_recording(&mut self) {
+        self.recording = Some((Vec::new(), Vec::new()));
+    }
+
+    /// Stop recording and return the reads and writes made meanwhile.
+    pub(crate) fn stop_recording(&mut self) -> (Vec<MemAccess>, Vec<MemAccess>) {
+        self.recording.take().unwrap_or_default()
+    }
+
+    /// Start logging the previous value of every word written.
+    pub(crate) fn start_undo(&mut self) {
+        self.undo = Some(Vec::new());
+    }
+
+    /// Stop logging and return the `(address, previous value)` pairs.
+    pub(crate) fn take_undo(&mut self) -> Vec<(u16, u16)> {
+        self.undo.take().unwrap_or_default()
+    }
+
+    /// Put back a word saved by the undo log.
+    pub(crate) fn restore(&mut self, addr: u16, value: u16) {
+        self.words[addr as usize] = value;
+    }
 }
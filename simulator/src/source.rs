@@ -0,0 +1,180 @@
+//! Source-level debugging: the `.asm` file and line behind each address.
+//!
+//! A [`SourceView`] is built from an in-process [`Assembly`] with
+//! [`SourceView::from_assembly`], or for a `.obj` file from the sidecar
+//! written by `lc3-assembler --debug-info` with
+//! [`SourceView::from_debug_info`].  Every word a line emits maps to that
+//! line; code produced by a macro maps to the line in the macro's body and
+//! remembers every invocation that led to it, so a debugger can step over a
+//! whole top-level macro call, nested macros included, as one *statement*.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use lc3_assembler::assembly::Assembly;
+use lc3_assembler::debug_info::DebugInfo;
+
+/// A source file and its text.
+#[derive(Clone, Debug)]
+pub struct SourceFile {
+    /// Display name, as in the assembler's diagnostics.
+    pub name: String,
+    /// The file's lines, empty if it could not be read.
+    pub lines: Vec<String>,
+}
+
+/// A line in one of a [`SourceView`]'s files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LineRef {
+    /// Index into [`SourceView::files`].
+    pub file: usize,
+    /// 1-based line number.
+    pub line: usize,
+}
+
+/// Where the word at one address came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AddrInfo {
+    /// The line that emitted the word.
+    pub at: LineRef,
+    /// For code from a macro: each macro's name and where it was invoked,
+    /// outermost first.
+    pub expansions: Vec<(String, LineRef)>,
+}
+
+impl AddrInfo {
+    /// The line a debugger steps by: the outermost macro invocation for code
+    /// from a macro, otherwise the line itself.
+    pub fn statement(&self) -> LineRef {
+        self.expansions.first().map_or(self.at, |(_, call)| *call)
+    }
+
+    /// The line itself and every invocation it was expanded from.
+    pub fn lines(&self) -> impl Iterator<Item = LineRef> + '_ {
+        std::iter::once(self.at).chain(self.expansions.iter().map(|(_, call)| *call))
+    }
+}
+
+/// Maps addresses to source lines.
+#[derive(Clone, Debug, Default)]
+pub struct SourceView {
+    pub files: Vec<SourceFile>,
+    addrs: BTreeMap<u16, AddrInfo>,
+}
+
+impl SourceView {
+    /// The mapping for everything `asm` emitted.  File text is read from
+    /// disk, falling back to the lines the assembler saw.
+    pub fn from_assembly(asm: &Assembly) -> Self {
+        let mut view = Self::from_info(&DebugInfo::from_assembly(asm));
+        for (idx, file) in view.files.iter_mut().enumerate() {
+            file.lines = if file.name == asm.name {
+                asm.source.lines().map(str::to_string).collect()
+            } else if let Ok(text) = fs::read_to_string(&file.name) {
+                text.lines().map(str::to_string).collect()
+            } else {
+                let last = view
+                    .addrs
+                    .values()
+                    .flat_map(AddrInfo::lines)
+                    .filter(|l| l.file == idx)
+                    .map(|l| l.line)
+                    .max()
+                    .unwrap_or(0);
+                (1..=last)
+                    .map(|n| {
+                        asm.source_map
+                            .line_text(&file.name, n)
+                            .unwrap_or("")
+                            .to_string()
+                    })
+                    .collect()
+            };
+        }
+        view
+    }
+
+    /// The mapping recorded in a debug-info file.  Source files are read
+    /// from disk by the names it records, which are relative to where the
+    /// program was assembled; relative names are also tried under `dir`,
+    /// normally the directory holding the debug-info file.
+    pub fn from_debug_info(info: &DebugInfo, dir: &Path) -> Self {
+        let mut view = Self::from_info(info);
+        for file in &mut view.files {
+            let text = fs::read_to_string(&file.name)
+                .or_else(|_| fs::read_to_string(dir.join(&file.name)))
+                .unwrap_or_default();
+            file.lines = text.lines().map(str::to_string).collect();
+        }
+        view
+    }
+
+    /// The address map of `info`, with no file text yet.
+    fn from_info(info: &DebugInfo) -> Self {
+        let files = info
+            .files
+            .iter()
+            .map(|name| SourceFile {
+                name: name.clone(),
+                lines: Vec::new(),
+            })
+            .collect();
+        let addrs = info
+            .words
+            .iter()
+            .map(|w| {
+                let at = LineRef {
+                    file: w.file,
+                    line: w.line,
+                };
+                let expansions = w
+                    .expansions
+                    .iter()
+                    .map(|e| {
+                        let call = LineRef {
+                            file: e.file,
+                            line: e.line,
+                        };
+                        (e.name.clone(), call)
+                    })
+                    .collect();
+                (w.address, AddrInfo { at, expansions })
+            })
+            .collect();
+        SourceView { files, addrs }
+    }
+
+    /// Where the word at `addr` came from.
+    pub fn lookup(&self, addr: u16) -> Option<&AddrInfo> {
+        self.addrs.get(&addr)
+    }
+
+    /// True if no address has source information.
+    pub fn is_empty(&self) -> bool {
+        self.addrs.is_empty()
+    }
+
+    /// The lowest address whose [statement](AddrInfo::statement) is `line`
+    /// of `file`, or the next line after it that has code.  For a macro call
+    /// that is the first word of the whole expansion, nested macros included.
+    /// `file` may be the display name or just its file name.
+    pub fn address_of(&self, file: &str, line: usize) -> Option<u16> {
+        let idx = self.files.iter().position(|f| {
+            f.name == file || Path::new(&f.name).file_name() == Path::new(file).file_name()
+        })?;
+        self.addrs
+            .iter()
+            .filter_map(|(&addr, info)| {
+                let stmt = info.statement();
+                (stmt.file == idx && stmt.line >= line).then_some((stmt.line, addr))
+            })
+            .min()
+            .map(|(_, addr)| addr)
+    }
+
+    /// `file:line` for a line of this view.
+    pub fn describe(&self, at: LineRef) -> String {
+        format!("{}:{}", self.files[at.file].name, at.line)
+    }
+}
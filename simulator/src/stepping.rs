@@ -0,0 +1,112 @@
+//! Stepping over calls and out of routines, and by source line.
+//!
+//! [`Machine::step_over`] runs a `JSR`, `JSRR` or `TRAP` until it returns.
+//! [`Machine::step_out`] runs until the current subroutine returns with
+//! `RET` (or a service routine with `RTI`).  [`Machine::step_line`] uses a
+//! [`SourceView`] to run to the next `.asm` statement, stepping over calls
+//! and whole macro expansions.
+//!
+//! Each stops early, returning the result, on anything but
+//! [`StepResult::Ok`], or when the machine starts waiting for input.
+
+use crate::machine::{Machine, StepResult, IVT_BASE, KEYBOARD_PRIORITY, VEC_KEYBOARD};
+use crate::source::{AddrInfo, SourceView};
+
+const OP_JSR: u16 = 0b0100;
+const OP_JMP: u16 = 0b1100;
+const OP_RTI: u16 = 0b1000;
+const OP_TRAP: u16 = 0b1111;
+
+impl Machine {
+    /// Execute the instruction at PC; if it is a call (`JSR`, `JSRR` or a
+    /// `TRAP` with a routine in the vector table), keep going until it
+    /// returns to the next instruction, running at most `max` steps.
+    ///
+    /// The call has returned once PC is back at the next instruction in the
+    /// same privilege mode with the stack no deeper, so a recursive call
+    /// returning to the same address does not stop it.
+    pub fn step_over(&mut self, max: u64) -> StepResult {
+        let pc = self.regs.pc;
+        if !matches!(self.mem.raw(pc) >> 12, OP_JSR | OP_TRAP) || self.interrupt_pending() {
+            return self.step();
+        }
+        let next = pc.wrapping_add(1);
+        let (privilege, sp) = (self.regs.privilege, self.regs.gpr[6]);
+        for _ in 0..max {
+            match self.step() {
+                StepResult::Ok => {}
+                other => return other,
+            }
+            if self.waiting_for_input
+                || (self.regs.pc == next
+                    && self.regs.privilege == privilege
+                    && self.regs.gpr[6] >= sp)
+            {
+                break;
+            }
+        }
+        StepResult::Ok
+    }
+
+    /// Run until the current routine returns: through the `RET` (or `RTI`)
+    /// that leaves it, counting nested calls and interrupts so their
+    /// returns do not stop it.  Runs at most `max` steps.
+    pub fn step_out(&mut self, max: u64) -> StepResult {
+        let mut depth = 0i64;
+        for _ in 0..max {
+            let mut pc = self.regs.pc;
+            if self.interrupt_pending() {
+                depth += 1;
+                pc = self.mem.raw(IVT_BASE + u16::from(VEC_KEYBOARD));
+            }
+            let ir = self.mem.raw(pc);
+            match self.step() {
+                StepResult::Ok => {}
+                other => return other,
+            }
+            if self.waiting_for_input {
+                break;
+            }
+            match ir >> 12 {
+                OP_JSR => depth += 1,
+                OP_TRAP if self.regs.pc != pc.wrapping_add(1) => depth += 1,
+                OP_JMP if (ir >> 6) & 7 == 7 => depth -= 1,
+                OP_RTI => depth -= 1,
+                _ => {}
+            }
+            if depth < 0 {
+                break;
+            }
+        }
+        StepResult::Ok
+    }
+
+    /// Run to the next statement in `source`: step over calls until PC is on
+    /// a different line, or the top-level macro invocation it was in (nested
+    /// macros included) is finished.  Outside the mapped code this is
+    /// [`step_over`](Self::step_over).
+    pub fn step_line(&mut self, source: &SourceView, max: u64) -> StepResult {
+        let statement = |m: &Machine| source.lookup(m.regs.pc).map(AddrInfo::statement);
+        let start = statement(self);
+        let first = self.step_count;
+        loop {
+            let left = max.saturating_sub(self.step_count - first);
+            let result = self.step_over(left);
+            if result != StepResult::Ok
+                || self.waiting_for_input
+                || start.is_none()
+                || statement(self) != start
+                || self.step_count - first >= max
+            {
+                return result;
+            }
+        }
+    }
+
+    /// True if the next step will start by taking a keyboard interrupt.
+    fn interrupt_pending(&self) -> bool {
+        self.keyboard_interrupt_enabled()
+            && (self.mem.keyboard.ready() || !self.input_queue.is_empty())
+            && self.regs.priority < KEYBOARD_PRIORITY
+    }
+}
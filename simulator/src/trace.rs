@@ -0,0 +1,263 @@
+//! Execution traces: one record per executed instruction.
+//!
+//! Install a handler with [`Machine::set_tracer`](crate::Machine::set_tracer)
+//! to receive a [`TraceEntry`] after every step, and write entries with a
+//! [`TraceWriter`] as JSON Lines or as a compact binary stream that
+//! [`read_binary`] reads back.
+//!
+//! A JSONL record looks like this (addresses and values as LC-3 hex):
+//!
+//! ```text
+//! {"step":3,"pc":"x3002","ir":"x6281","asm":"LDR R1, R2, #1","regs":{"R1":"x0005"},"reads":[{"addr":"x4001","value":"x0005"}],"writes":[],"cc":"P"}
+//! ```
+//!
+//! The binary format starts with the magic `LC3T` and a big-endian `u16`
+//! version, followed by one record per instruction: step (`u64`), PC, IR,
+//! the new condition code (`u8`, 0 when unchanged), and counted lists of
+//! register writes (`u8` count of register `u8` + value `u16`) and memory
+//! reads and writes (`u16` count of address + value pairs).  All numbers are
+//! big-endian.
+
+use std::io::{self, Write};
+
+use crate::machine::CondCode;
+
+const MAGIC: &[u8; 4] = b"LC3T";
+const VERSION: u16 = 1;
+
+/// One memory access made by an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemAccess {
+    pub addr: u16,
+    pub value: u16,
+}
+
+/// Everything one instruction did.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEntry {
+    /// Value of [`Machine::step_count`](crate::Machine::step_count) after the
+    /// instruction, so the first instruction is step 1.
+    pub step: u64,
+    /// Address the instruction was fetched from.
+    pub pc: u16,
+    /// The instruction word.
+    pub ir: u16,
+    /// Registers the instruction wrote, with their new values, in register
+    /// order.
+    pub regs: Vec<(u8, u16)>,
+    /// Memory reads, in order (the instruction fetch is not included).
+    pub reads: Vec<MemAccess>,
+    /// Memory writes, in order.
+    pub writes: Vec<MemAccess>,
+    /// The condition code, if the instruction set or changed it.
+    pub cc: Option<CondCode>,
+}
+
+impl TraceEntry {
+    /// The instruction in assembly syntax.
+    pub fn disassembly(&self) -> String {
+        crate::disassemble(self.ir, self.pc, None)
+    }
+
+    /// The entry as one line of JSON (without the trailing newline).
+    pub fn to_json(&self) -> String {
+        let regs: Vec<String> = self
+            .regs
+            .iter()
+            .map(|(r, value)| format!("\"R{r}\":\"x{value:04X}\""))
+            .collect();
+        let accesses = |list: &[MemAccess]| {
+            list.iter()
+                .map(|a| {
+                    format!(
+                        "{{\"addr\":\"x{:04X}\",\"value\":\"x{:04X}\"}}",
+                        a.addr, a.value
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let cc = self
+            .cc
+            .map_or_else(|| "null".to_string(), |cc| format!("\"{cc}\""));
+        format!(
+            "{{\"step\":{},\"pc\":\"x{:04X}\",\"ir\":\"x{:04X}\",\"asm\":\"{}\",\"regs\":{{{}}},\"reads\":[{}],\"writes\":[{}],\"cc\":{cc}}}",
+            self.step,
+            self.pc,
+            self.ir,
+            self.disassembly().replace('\\', "\\\\").replace('"', "\\\""),
+            regs.join(","),
+            accesses(&self.reads),
+            accesses(&self.writes),
+        )
+    }
+
+    /// Append the entry's binary record to `out`.
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.step.to_be_bytes());
+        out.extend_from_slice(&self.pc.to_be_bytes());
+        out.extend_from_slice(&self.ir.to_be_bytes());
+        out.push(match self.cc {
+            None => 0,
+            Some(CondCode::N) => 1,
+            Some(CondCode::Z) => 2,
+            Some(CondCode::P) => 3,
+        });
+        out.push(self.regs.len() as u8);
+        for &(r, value) in &self.regs {
+            out.push(r);
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        for list in [&self.reads, &self.writes] {
+            out.extend_from_slice(&(list.len() as u16).to_be_bytes());
+            for access in list {
+                out.extend_from_slice(&access.addr.to_be_bytes());
+                out.extend_from_slice(&access.value.to_be_bytes());
+            }
+        }
+    }
+}
+
+/// Trace file format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One JSON object per line.
+    Jsonl,
+    /// The compact binary format described in the [module docs](self).
+    Binary,
+}
+
+impl std::str::FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "jsonl" | "json" => Ok(TraceFormat::Jsonl),
+            "bin" | "binary" => Ok(TraceFormat::Binary),
+            other => Err(format!(
+                "unknown trace format '{other}' (expected jsonl or binary)"
+            )),
+        }
+    }
+}
+
+/// Writes trace entries to `out` in one [`TraceFormat`].
+pub struct TraceWriter<W: Write> {
+    out: W,
+    format: TraceFormat,
+    started: bool,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(out: W, format: TraceFormat) -> Self {
+        Self {
+            out,
+            format,
+            started: false,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Write one entry (and, before the first binary record, the header).
+    pub fn write(&mut self, entry: &TraceEntry) -> io::Result<()> {
+        match self.format {
+            TraceFormat::Jsonl => writeln!(self.out, "{}", entry.to_json()),
+            TraceFormat::Binary => {
+                self.buf.clear();
+                if !self.started {
+                    self.buf.extend_from_slice(MAGIC);
+                    self.buf.extend_from_slice(&VERSION.to_be_bytes());
+                    self.started = true;
+                }
+                entry.encode(&mut self.buf);
+                self.out.write_all(&self.buf)
+            }
+        }
+    }
+
+    /// Flush the underlying writer.  An empty binary trace still gets its
+    /// header.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.format == TraceFormat::Binary && !self.started {
+            self.out.write_all(MAGIC)?;
+            self.out.write_all(&VERSION.to_be_bytes())?;
+            self.started = true;
+        }
+        self.out.flush()
+    }
+}
+
+/// Read a binary trace written by [`TraceWriter`].
+pub fn read_binary(data: &[u8]) -> Result<Vec<TraceEntry>, String> {
+    let mut r = Reader { data, pos: 0 };
+    if r.take(4)? != MAGIC {
+        return Err("not an LC-3 trace (missing LC3T header)".into());
+    }
+    let version = r.u16()?;
+    if version != VERSION {
+        return Err(format!("unsupported trace version {version}"));
+    }
+
+    let mut entries = Vec::new();
+    while r.pos < data.len() {
+        let step = u64::from_be_bytes(r.take(8)?.try_into().unwrap());
+        let pc = r.u16()?;
+        let ir = r.u16()?;
+        let cc = match r.u8()? {
+            0 => None,
+            1 => Some(CondCode::N),
+            2 => Some(CondCode::Z),
+            3 => Some(CondCode::P),
+            other => return Err(format!("invalid condition code {other} in step {step}")),
+        };
+        let regs = (0..r.u8()?)
+            .map(|_| Ok((r.u8()?, r.u16()?)))
+            .collect::<Result<_, String>>()?;
+        let mut lists = [Vec::new(), Vec::new()];
+        for list in &mut lists {
+            for _ in 0..r.u16()? {
+                list.push(MemAccess {
+                    addr: r.u16()?,
+                    value: r.u16()?,
+                });
+            }
+        }
+        let [reads, writes] = lists;
+        entries.push(TraceEntry {
+            step,
+            pc,
+            ir,
+            regs,
+            reads,
+            writes,
+            cc,
+        });
+    }
+    Ok(entries)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + n)
+            .ok_or("trace is truncated")?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+}
@@ -1,12 +1,23 @@
 /// TRAP service routine dispatch.
 ///
-/// All six standard LC-3 traps are implemented natively in Rust.
-/// The machine's `R7` is already set to the return address by `machine.rs`
-/// before this function is called.
+/// If the trap vector table has an entry for the vector (an OS image is
+/// loaded, or the program installed its own routine) the TRAP runs that LC-3
+/// code in supervisor mode.  Otherwise the six standard LC-3 traps are
+/// implemented natively in Rust.  Either way `R7` holds the return address,
+/// so a routine may end with `RTI` or, as older OS images do, with `RET`.
 use crate::machine::{Machine, StepResult};
 
 impl Machine {
     pub(crate) fn dispatch_trap(&mut self, vect: u8) -> StepResult {
+        // Save return address
+        self.regs.gpr[7] = self.regs.pc;
+
+        let routine = self.mem.read(u16::from(vect));
+        if routine != 0 {
+            self.enter_supervisor(routine, None);
+            return StepResult::Ok;
+        }
+
         match vect {
             0x20 => self.trap_getc(),
             0x21 => self.trap_out(),
@@ -28,7 +39,7 @@ impl Machine {
     /// word so the TRAP instruction re-executes on the next step, and
     /// `waiting_for_input` is set so the caller knows to feed the queue.
     fn trap_getc(&mut self) -> StepResult {
-        if let Some(ch) = self.input_queue.pop_front() {
+        if let Some(ch) = self.next_input() {
             self.regs.gpr[0] = ch as u16;
             self.waiting_for_input = false;
         } else {
@@ -62,7 +73,7 @@ impl Machine {
 
     /// IN — print a prompt then read one character (same stall logic as GETC).
     fn trap_in(&mut self) -> StepResult {
-        if self.input_queue.is_empty() {
+        if !self.waiting_for_input {
             self.push_str("Input a character> ");
         }
         self.trap_getc()
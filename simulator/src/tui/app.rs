@@ -1,6 +1,13 @@
 use std::collections::HashMap;
 
-use crate::machine::{Machine, StepResult};
+use lc3_sim::breakpoint::{parse_addr, BreakCommand};
+use lc3_sim::{Machine, SourceView, StepResult};
+
+/// Steps kept for stepping backwards.
+const HISTORY_STEPS: usize = 100_000;
+
+/// Most steps one step-over, step-out or next-line command runs.
+const STEP_LIMIT: u64 = 1_000_000;
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum AppMode {
@@ -21,16 +28,29 @@ pub struct App {
     pub status: String,
     /// Original raw .obj bytes kept for the reset command.
     pub original_obj: Vec<u8>,
+    /// OS image loaded under the program, kept for the reset command.
+    pub os_obj: Option<Vec<u8>>,
     /// address → label name (loaded from .sym file or assembler pass).
     pub sym_table: HashMap<u16, String>,
+    /// Source lines behind the program's addresses, when known.
+    pub source: Option<SourceView>,
     /// Whether the user has quit.
     pub should_quit: bool,
 }
 
 impl App {
-    pub fn new(obj: Vec<u8>, sym_table: HashMap<u16, String>) -> Result<Self, String> {
+    pub fn new(
+        obj: Vec<u8>,
+        os_obj: Option<Vec<u8>>,
+        sym_table: HashMap<u16, String>,
+        source: Option<SourceView>,
+    ) -> Result<Self, String> {
         let mut machine = Machine::new();
+        if let Some(os) = &os_obj {
+            machine.load_os(os)?;
+        }
         machine.load_obj(&obj)?;
+        machine.set_history_limit(HISTORY_STEPS);
         let pc = machine.regs.pc;
         Ok(Self {
             machine,
@@ -40,7 +60,9 @@ impl App {
             running: false,
             status: "Ready".into(),
             original_obj: obj,
+            os_obj,
             sym_table,
+            source,
             should_quit: false,
         })
     }
@@ -63,11 +85,21 @@ impl App {
                 self.status = format!("BREAK @ x{addr:04X}");
                 self.sync_scroll();
             }
+            hit @ StepResult::WatchpointHit { .. } => {
+                self.running = false;
+                self.status = watch_status(&hit);
+                self.sync_scroll();
+            }
             StepResult::IllegalInstruction(ir) => {
                 self.running = false;
                 self.status = format!("ILLEGAL x{ir:04X}");
                 self.sync_scroll();
             }
+            StepResult::PrivilegeViolation(ir) => {
+                self.running = false;
+                self.status = format!("PRIVILEGE VIOLATION x{ir:04X}");
+                self.sync_scroll();
+            }
             StepResult::Ok => {
                 self.status = "RUNNING".into();
             }
@@ -83,13 +115,19 @@ impl App {
                 self.cmd_input.push(ch);
             }
             AppMode::Normal => {
-                if self.machine.waiting_for_input {
+                if self.machine.waiting_for_input
+                    || (self.running && self.machine.keyboard_in_use())
+                {
                     // Feed the character to the machine's keyboard queue.
                     self.machine.input_queue.push_back(ch as u8);
                     return;
                 }
                 match ch {
                     's' | 'S' => self.do_step(),
+                    'n' | 'N' => self.do_next(),
+                    'o' | 'O' => self.do_step_out(),
+                    'u' | 'U' => self.do_step_back(),
+                    'v' | 'V' => self.do_reverse_continue(),
                     'c' | 'C' => self.do_continue(),
                     'p' | 'P' => self.do_pause(),
                     'r' | 'R' => self.do_reset(),
@@ -110,7 +148,9 @@ impl App {
                 self.execute_command(cmd.trim().to_string());
             }
             AppMode::Normal => {
-                if self.machine.waiting_for_input {
+                if self.machine.waiting_for_input
+                    || (self.running && self.machine.keyboard_in_use())
+                {
                     self.machine.input_queue.push_back(b'\n');
                 }
             }
@@ -123,10 +163,14 @@ impl App {
         }
     }
 
+    /// Cancel command input, or pause a running program (the only way to
+    /// pause while keys are going to a program that reads the keyboard).
     pub fn handle_escape(&mut self) {
         if self.mode == AppMode::CommandInput {
             self.cmd_input.clear();
             self.mode = AppMode::Normal;
+        } else if self.running {
+            self.do_pause();
         }
     }
 
@@ -142,26 +186,70 @@ impl App {
 
     fn do_step(&mut self) {
         if self.machine.halted {
-            self.status = "HALTED — press R to reset".into();
+            self.status = "HALTED — press R to reset or U to step back".into();
             return;
         }
-        match self.machine.step() {
-            StepResult::Ok => {
-                self.status = format!("Stepped  PC=x{:04X}", self.machine.regs.pc);
-            }
-            StepResult::Halted => {
-                self.status = "HALTED".into();
-            }
-            StepResult::BreakpointHit(a) => {
-                self.status = format!("BREAK @ x{a:04X}");
-            }
-            StepResult::IllegalInstruction(ir) => {
-                self.status = format!("ILLEGAL x{ir:04X}");
-            }
+        let result = self.machine.step();
+        self.report_step(result, "Stepped");
+    }
+
+    /// Step over calls to the next source line, or over one instruction
+    /// without source information.
+    fn do_next(&mut self) {
+        if self.machine.halted {
+            self.status = "HALTED — press R to reset or U to step back".into();
+            return;
+        }
+        let result = match &self.source {
+            Some(source) => self.machine.step_line(source, STEP_LIMIT),
+            None => self.machine.step_over(STEP_LIMIT),
+        };
+        self.report_step(result, "Next");
+    }
+
+    fn do_step_out(&mut self) {
+        if self.machine.halted {
+            self.status = "HALTED — press R to reset or U to step back".into();
+            return;
+        }
+        let result = self.machine.step_out(STEP_LIMIT);
+        self.report_step(result, "Stepped out");
+    }
+
+    /// Set the status line after a step command.  `done` names the command
+    /// for a step that completed normally.
+    fn report_step(&mut self, result: StepResult, done: &str) {
+        self.status = match result {
+            StepResult::Ok if self.machine.waiting_for_input => "Waiting for input".into(),
+            StepResult::Ok => format!("{done}  PC=x{:04X}", self.machine.regs.pc),
+            StepResult::Halted => "HALTED".into(),
+            StepResult::BreakpointHit(a) => format!("BREAK @ x{a:04X}"),
+            hit @ StepResult::WatchpointHit { .. } => watch_status(&hit),
+            StepResult::IllegalInstruction(ir) => format!("ILLEGAL x{ir:04X}"),
+            StepResult::PrivilegeViolation(ir) => format!("PRIVILEGE VIOLATION x{ir:04X}"),
+        };
+        self.sync_scroll();
+    }
+
+    fn do_step_back(&mut self) {
+        self.running = false;
+        if self.machine.step_back() {
+            self.status = format!("Stepped back  PC=x{:04X}", self.machine.regs.pc);
+        } else {
+            self.status = "No earlier step recorded".into();
         }
         self.sync_scroll();
     }
 
+    fn do_reverse_continue(&mut self) {
+        self.running = false;
+        self.status = match self.machine.reverse_continue() {
+            StepResult::BreakpointHit(addr) => format!("BREAK @ x{addr:04X} (reverse)"),
+            _ => format!("Start of history  PC=x{:04X}", self.machine.regs.pc),
+        };
+        self.sync_scroll();
+    }
+
     fn do_continue(&mut self) {
         if self.machine.halted {
             self.status = "HALTED — press R to reset".into();
@@ -178,8 +266,10 @@ impl App {
 
     fn do_reset(&mut self) {
         let obj = self.original_obj.clone();
+        let os = self.os_obj.clone();
         let syms = self.sym_table.clone();
-        match App::new(obj, syms) {
+        let source = self.source.clone();
+        match App::new(obj, os, syms, source) {
             Ok(fresh) => {
                 let scroll = fresh.machine.regs.pc;
                 *self = fresh;
@@ -199,17 +289,16 @@ impl App {
 
     fn execute_command(&mut self, cmd: String) {
         if let Some(rest) = cmd.strip_prefix("b ").or_else(|| cmd.strip_prefix("b")) {
-            match parse_addr(rest.trim()) {
-                Some(addr) => {
-                    if self.machine.breakpoints.contains(&addr) {
-                        self.machine.breakpoints.remove(&addr);
-                        self.status = format!("Breakpoint removed: x{addr:04X}");
-                    } else {
-                        self.machine.breakpoints.insert(addr);
-                        self.status = format!("Breakpoint set: x{addr:04X}");
-                    }
+            let rest = match self.resolve_source_line(rest) {
+                Ok(rest) => rest,
+                Err(e) => {
+                    self.status = format!("Bad breakpoint '{cmd}': {e}");
+                    return;
                 }
-                None => self.status = format!("Bad address: '{}'", cmd),
+            };
+            match rest.parse::<BreakCommand>() {
+                Ok(command) => self.status = self.apply_break(command),
+                Err(e) => self.status = format!("Bad breakpoint '{cmd}': {e}"),
             }
         } else if let Some(rest) = cmd.strip_prefix("g ").or_else(|| cmd.strip_prefix("g")) {
             match parse_addr(rest.trim()) {
@@ -222,6 +311,66 @@ impl App {
         }
     }
 
+    /// Rewrite a `b` command's `file:line` location as the address of that
+    /// line's first instruction.
+    fn resolve_source_line(&self, rest: &str) -> Result<String, String> {
+        let rest = rest.trim_start();
+        let (location, tail) = rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len()));
+        let Some((file, line)) = location.rsplit_once(':') else {
+            return Ok(rest.to_string());
+        };
+        let Ok(line) = line.parse::<usize>() else {
+            return Ok(rest.to_string());
+        };
+        let source = self
+            .source
+            .as_ref()
+            .ok_or("no source lines are known for this program")?;
+        match source.address_of(file, line) {
+            Some(addr) => Ok(format!("x{addr:04X}{tail}")),
+            None => Err(format!("no code at or after {file}:{line}")),
+        }
+    }
+
+    /// Add, replace or (for a plain address or range that is already set)
+    /// remove a breakpoint or watchpoint.  Returns the status line.
+    fn apply_break(&mut self, command: BreakCommand) -> String {
+        let toggle = command.is_plain();
+        match command {
+            BreakCommand::Break(addr, bp) => {
+                if toggle && self.machine.breakpoints.remove(&addr).is_some() {
+                    return format!("Breakpoint removed: x{addr:04X}");
+                }
+                let condition = bp
+                    .trigger
+                    .condition
+                    .as_ref()
+                    .map(|c| format!(" if {c}"))
+                    .unwrap_or_default();
+                self.machine.breakpoints.insert(addr, bp);
+                format!("Breakpoint set: x{addr:04X}{condition}")
+            }
+            BreakCommand::Watch(wp) => {
+                let watches = &mut self.machine.watchpoints;
+                let existing = watches
+                    .iter()
+                    .position(|w| (w.start, w.end, w.access) == (wp.start, wp.end, wp.access));
+                match existing {
+                    Some(i) if toggle => format!("Watchpoint removed: {}", watches.remove(i)),
+                    Some(i) => {
+                        watches[i] = wp;
+                        format!("Watchpoint updated: {}", watches[i])
+                    }
+                    None => {
+                        let status = format!("Watchpoint set: {wp}");
+                        watches.push(wp);
+                        status
+                    }
+                }
+            }
+        }
+    }
+
     /// Keep the memory panel centred a few lines before PC.
     fn sync_scroll(&mut self) {
         let pc = self.machine.regs.pc;
@@ -229,16 +378,13 @@ impl App {
     }
 }
 
-fn parse_addr(s: &str) -> Option<u16> {
-    if s.is_empty() {
-        return None;
-    }
-    // Accept: x3000  0x3000  3000 (pure hex)  12345 (decimal)
-    if let Some(hex) = s.strip_prefix('x').or_else(|| s.strip_prefix("0x")) {
-        u16::from_str_radix(hex, 16).ok()
-    } else if s.chars().all(|c| c.is_ascii_hexdigit()) && s.len() == 4 {
-        u16::from_str_radix(s, 16).ok()
-    } else {
-        s.parse::<u16>().ok()
+/// Status line for a [`StepResult::WatchpointHit`].
+fn watch_status(hit: &StepResult) -> String {
+    match hit {
+        StepResult::WatchpointHit { addr, value, write } => format!(
+            "WATCH {} x{addr:04X} = x{value:04X}",
+            if *write { "write" } else { "read" }
+        ),
+        _ => String::new(),
     }
 }
@@ -6,8 +6,10 @@ use ratatui::{
     Frame,
 };
 
-use crate::disasm;
 use crate::tui::app::{App, AppMode};
+use lc3_assembler::disasm;
+use lc3_sim::machine::Privilege;
+use lc3_sim::source::{AddrInfo, SourceView};
 
 pub fn render(f: &mut Frame, app: &App) {
     let area = f.area();
@@ -26,14 +28,27 @@ pub fn render(f: &mut Frame, app: &App) {
 
     render_header(f, app, outer[0]);
 
-    // ── Body: registers (left) + memory (right) ───────────────────────────────
-    let body = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Length(26), Constraint::Min(20)])
-        .split(outer[1]);
-
-    render_registers(f, app, body[0]);
-    render_memory(f, app, body[1]);
+    // ── Body: registers (left) + source (middle, .asm input) + memory ────────
+    if let Some(source) = &app.source {
+        let body = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Length(26),
+                Constraint::Percentage(55),
+                Constraint::Min(20),
+            ])
+            .split(outer[1]);
+        render_registers(f, app, body[0]);
+        render_source(f, app, source, body[1]);
+        render_memory(f, app, body[2]);
+    } else {
+        let body = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(26), Constraint::Min(20)])
+            .split(outer[1]);
+        render_registers(f, app, body[0]);
+        render_memory(f, app, body[1]);
+    }
     render_output(f, app, outer[2]);
     render_cmdbar(f, app, outer[3]);
 }
@@ -74,6 +89,15 @@ fn render_registers(f: &mut Frame, app: &App, area: Rect) {
     lines.push(Line::from(""));
     lines.push(Line::from(format!(" PC  x{:04X}", m.regs.pc)));
     lines.push(Line::from(format!(" CC  {}", m.regs.cc)));
+    let mode = match m.regs.privilege {
+        Privilege::Supervisor => "SUP",
+        Privilege::User => "USR",
+    };
+    lines.push(Line::from(format!(
+        " PSR x{:04X}  {mode} PL{}",
+        m.regs.psr(),
+        m.regs.priority
+    )));
 
     if !m.breakpoints.is_empty() {
         lines.push(Line::from(""));
@@ -81,10 +105,39 @@ fn render_registers(f: &mut Frame, app: &App, area: Rect) {
             " Breakpoints:",
             Style::default().fg(Color::Yellow),
         )));
-        let mut bps: Vec<u16> = m.breakpoints.iter().copied().collect();
-        bps.sort_unstable();
-        for bp in bps {
-            lines.push(Line::from(format!("  x{bp:04X}")));
+        let mut bps: Vec<_> = m.breakpoints.iter().collect();
+        bps.sort_unstable_by_key(|(addr, _)| **addr);
+        for (addr, bp) in bps {
+            let condition = bp
+                .trigger
+                .condition
+                .as_ref()
+                .map(|c| format!(" if {c}"))
+                .unwrap_or_default();
+            lines.push(Line::from(format!(
+                "  x{addr:04X}{condition} ({})",
+                hit_count(bp.trigger.hits, bp.trigger.ignore)
+            )));
+        }
+    }
+
+    if !m.watchpoints.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(Line::from(Span::styled(
+            " Watchpoints:",
+            Style::default().fg(Color::Magenta),
+        )));
+        for wp in &m.watchpoints {
+            let condition = wp
+                .trigger
+                .condition
+                .as_ref()
+                .map(|c| format!(" if {c}"))
+                .unwrap_or_default();
+            lines.push(Line::from(format!(
+                "  {wp}{condition} ({})",
+                hit_count(wp.trigger.hits, wp.trigger.ignore)
+            )));
         }
     }
 
@@ -92,6 +145,16 @@ fn render_registers(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(Paragraph::new(lines).block(block), area);
 }
 
+/// "3 hits", plus the ignore count if there is one.
+fn hit_count(hits: u64, ignore: u64) -> String {
+    let plural = if hits == 1 { "" } else { "s" };
+    if ignore > 0 {
+        format!("{hits} hit{plural}, ignore {ignore}")
+    } else {
+        format!("{hits} hit{plural}")
+    }
+}
+
 /// Show a u16 as its signed decimal equivalent for the register panel.
 fn signed_repr(v: u16) -> String {
     let s = v as i16;
@@ -102,6 +165,73 @@ fn signed_repr(v: u16) -> String {
     }
 }
 
+// ── Source panel ──────────────────────────────────────────────────────────────
+
+fn render_source(f: &mut Frame, app: &App, source: &SourceView, area: Rect) {
+    let pc = app.machine.regs.pc;
+    let Some(info) = source.lookup(pc) else {
+        let block = Block::default().title(" Source ").borders(Borders::ALL);
+        let text = format!(" (no source for x{pc:04X})");
+        f.render_widget(Paragraph::new(text).block(block), area);
+        return;
+    };
+    let current = info.at;
+    let file = &source.files[current.file];
+    if file.lines.is_empty() {
+        let block = Block::default().title(" Source ").borders(Borders::ALL);
+        let text = format!(" (cannot read {}; at line {})", file.name, current.line);
+        f.render_widget(Paragraph::new(text).block(block), area);
+        return;
+    }
+
+    // Lines of this file with a breakpoint on one of their addresses.
+    let break_lines: Vec<usize> = app
+        .machine
+        .breakpoints
+        .keys()
+        .filter_map(|&addr| source.lookup(addr))
+        .flat_map(AddrInfo::lines)
+        .filter(|l| l.file == current.file)
+        .map(|l| l.line)
+        .collect();
+
+    let visible = area.height.saturating_sub(2) as usize;
+    let first = current.line.saturating_sub(visible / 2).max(1);
+    let items: Vec<ListItem> = (first..first + visible)
+        .take_while(|&n| n <= file.lines.len())
+        .map(|n| {
+            let is_current = n == current.line;
+            let is_bp = break_lines.contains(&n);
+            let arrow = if is_current { "►" } else { " " };
+            let bp_mark = if is_bp { "●" } else { " " };
+            let text = format!("{bp_mark}{arrow}{n:>5}  {}", file.lines[n - 1]);
+            let style = if is_current {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+            } else if is_bp {
+                Style::default().fg(Color::Red)
+            } else {
+                Style::default()
+            };
+            ListItem::new(text).style(style)
+        })
+        .collect();
+
+    let title = if info.expansions.is_empty() {
+        format!(" {} ", file.name)
+    } else {
+        let calls: Vec<String> = info
+            .expansions
+            .iter()
+            .map(|(name, call)| format!("macro {name} from {}", source.describe(*call)))
+            .collect();
+        format!(" {}  [{}] ", file.name, calls.join(" > "))
+    };
+    let block = Block::default().title(title).borders(Borders::ALL);
+    f.render_widget(List::new(items).block(block), area);
+}
+
 // ── Memory panel ──────────────────────────────────────────────────────────────
 
 fn render_memory(f: &mut Frame, app: &App, area: Rect) {
@@ -114,10 +244,17 @@ fn render_memory(f: &mut Frame, app: &App, area: Rect) {
             let word = app.machine.mem.raw(addr);
             let dis = disasm::disassemble(word, addr, Some(&app.sym_table));
             let is_pc = addr == app.machine.regs.pc;
-            let is_bp = app.machine.breakpoints.contains(&addr);
+            let is_bp = app.machine.breakpoints.contains_key(&addr);
+            let is_watched = app.machine.watchpoints.iter().any(|w| w.covers(addr));
 
             let arrow = if is_pc { "►" } else { " " };
-            let bp_mark = if is_bp { "●" } else { " " };
+            let bp_mark = if is_bp {
+                "●"
+            } else if is_watched {
+                "◆"
+            } else {
+                " "
+            };
             let text = format!("{bp_mark}{arrow} x{addr:04X}  {word:04X}  {dis}");
 
             let style = if is_pc {
@@ -126,6 +263,8 @@ fn render_memory(f: &mut Frame, app: &App, area: Rect) {
                     .add_modifier(Modifier::BOLD)
             } else if is_bp {
                 Style::default().fg(Color::Red)
+            } else if is_watched {
+                Style::default().fg(Color::Magenta)
             } else {
                 Style::default()
             };
@@ -163,13 +302,15 @@ fn render_output(f: &mut Frame, app: &App, area: Rect) {
 // ── Command bar ───────────────────────────────────────────────────────────────
 
 fn render_cmdbar(f: &mut Frame, app: &App, area: Rect) {
-    let hint = " [s]tep  [c]ont  [p]ause  [r]eset  [b]reak  [g]oto  [q]uit";
+    let hint = " [s]tep  [n]ext  step [o]ut  [u]nstep  [c]ont  re[v]erse  [p]ause  [r]eset  [b]reak  [g]oto  [q]uit";
 
     let input_line = match app.mode {
         AppMode::CommandInput => format!("> {}_", app.cmd_input),
         AppMode::Normal => {
             if app.machine.waiting_for_input {
                 "> (type a character for GETC)".into()
+            } else if app.running && app.machine.keyboard_in_use() {
+                "> (keys go to the program's keyboard · Esc pauses)".into()
             } else {
                 String::new()
             }
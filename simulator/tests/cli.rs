@@ -0,0 +1,159 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/// A scratch directory unique to this test process and `name`.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lc3-sim-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run_sim(path: &PathBuf) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lc3-sim"))
+        .arg(path)
+        .arg("--run")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn run_asm_with_include_and_macro() {
+    let dir = scratch_dir("include");
+    fs::write(
+        dir.join("defs.asm"),
+        ".MACRO PRINT %MSG\nLEA R0, %MSG\nPUTS\n.ENDM\n",
+    )
+    .unwrap();
+    let main = dir.join("main.asm");
+    fs::write(
+        &main,
+        ".INCLUDE \"defs.asm\"\n.ORIG x3000\nPRINT HELLO\nHALT\nHELLO .STRINGZ \"included\"\n.END\n",
+    )
+    .unwrap();
+
+    let out = run_sim(&main);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with("included"));
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn assembly_errors_use_rich_diagnostics() {
+    let dir = scratch_dir("errors");
+    let path = dir.join("bad.asm");
+    fs::write(&path, ".ORIG x3000\nADD R0, R0, #99\n.END\n").unwrap();
+
+    let out = run_sim(&path);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("bad.asm:2:"), "{stderr}");
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn os_image_handles_traps() {
+    let dir = scratch_dir("os");
+    let path = dir.join("hello.asm");
+    fs::write(
+        &path,
+        ".ORIG x3000\nLEA R0, MSG\nPUTS\nHALT\nMSG .STRINGZ \"via OS\"\n.END\n",
+    )
+    .unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_lc3-sim"))
+        .arg(&path)
+        .args(["--os", "tests/programs/mini_os.asm", "--run"])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    // The OS HALT stops the clock without the native "--- HALT ---" banner.
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "via OS");
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn run_writes_an_execution_trace() {
+    let dir = scratch_dir("trace");
+    let path = dir.join("prog.asm");
+    fs::write(
+        &path,
+        ".ORIG x3000\nAND R0, R0, #0\nADD R0, R0, #2\nHALT\n.END\n",
+    )
+    .unwrap();
+    let trace = dir.join("prog.jsonl");
+
+    let out = Command::new(env!("CARGO_BIN_EXE_lc3-sim"))
+        .arg(&path)
+        .arg("--run")
+        .arg("--trace")
+        .arg(&trace)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let text = fs::read_to_string(&trace).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 3, "{text}");
+    assert!(
+        lines[1].starts_with(
+            "{\"step\":2,\"pc\":\"x3001\",\"ir\":\"x1022\",\"asm\":\"ADD R0, R0, #2\",\"regs\":{\"R0\":\"x0002\"}"
+        ),
+        "{text}"
+    );
+    assert!(lines[2].contains("\"asm\":\"HALT\""), "{text}");
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn gdb_port_serves_the_remote_protocol() {
+    let dir = scratch_dir("gdb");
+    let path = dir.join("hi.asm");
+    fs::write(
+        &path,
+        ".ORIG x3000\nLEA R0, MSG\nPUTS\nHALT\nMSG .STRINGZ \"Hi\"\n.END\n",
+    )
+    .unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lc3-sim"))
+        .arg(&path)
+        .args(["--gdb-port", "0"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut line = String::new();
+    stderr.read_line(&mut line).unwrap();
+    let addr = line
+        .trim()
+        .strip_prefix("gdb: listening on ")
+        .unwrap_or_else(|| panic!("unexpected banner: {line}"));
+
+    // Continue to HALT, which the stub reports as the program exiting.
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"$c#63").unwrap();
+    let mut reply = [0u8; 8];
+    stream.read_exact(&mut reply).unwrap();
+    assert_eq!(&reply, b"+$W00#b7");
+    stream.write_all(b"+$k#6b").unwrap();
+
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with("Hi"));
+    fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,232 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+use lc3_assembler::encoder::encode;
+use lc3_assembler::first_pass::first_pass;
+use lc3_assembler::lexer::tokenize;
+use lc3_assembler::parser::parse_lines;
+use lc3_sim::gdb::GdbStub;
+use lc3_sim::Machine;
+
+/// A minimal GDB remote protocol client.
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    /// Send `payload` and return the reply's payload.
+    fn send(&mut self, payload: &str) -> String {
+        let sum = payload.bytes().fold(0u8, |s, b| s.wrapping_add(b));
+        write!(self.stream, "${payload}#{sum:02x}").unwrap();
+        assert_eq!(self.byte(), b'+', "stub did not acknowledge {payload}");
+        self.reply()
+    }
+
+    /// Read one packet, check its checksum and acknowledge it.
+    fn reply(&mut self) -> String {
+        while self.byte() != b'$' {}
+        let mut body = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                b => body.push(b),
+            }
+        }
+        let sum = [self.byte(), self.byte()];
+        let sum = u8::from_str_radix(std::str::from_utf8(&sum).unwrap(), 16).unwrap();
+        assert_eq!(sum, body.iter().fold(0u8, |s, &b| s.wrapping_add(b)));
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(body).unwrap()
+    }
+
+    /// Send `k`, which has no reply.
+    fn kill(&mut self) {
+        self.stream.write_all(b"$k#6b").unwrap();
+        assert_eq!(self.byte(), b'+');
+    }
+
+    fn byte(&mut self) -> u8 {
+        let mut b = [0];
+        self.stream.read_exact(&mut b).unwrap();
+        b[0]
+    }
+}
+
+/// Serve `source` on a local port from another thread and connect to it.
+fn connect(source: &str) -> (Client, JoinHandle<()>) {
+    connect_with_keys(source, b"")
+}
+
+/// [`connect`], with `keys` typed for the program.
+fn connect_with_keys(source: &str, keys: &'static [u8]) -> (Client, JoinHandle<()>) {
+    let lexed = tokenize(source);
+    let first = first_pass(parse_lines(&lexed.tokens).lines);
+    let encoded = encode(&first);
+    assert!(!encoded.has_errors(), "{:?}", encoded.errors);
+    let segments = encoded.segments;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let mut machine = Machine::new();
+        machine.load_segments(&segments);
+        let (stream, _) = listener.accept().unwrap();
+        let mut keys = keys.iter().copied();
+        GdbStub::new(&mut machine, stream)
+            .with_keys(move || keys.next())
+            .run()
+            .unwrap();
+    });
+    let stream = TcpStream::connect(addr).unwrap();
+    (Client { stream }, server)
+}
+
+/// A register value in the stub's little-endian hex.
+fn le(word: u16) -> String {
+    format!("{:02x}{:02x}", word as u8, word >> 8)
+}
+
+const PROGRAM: &str = "\
+.ORIG x3000
+        AND R0, R0, #0
+        ADD R0, R0, #5
+        ST R0, SLOT
+        LD R1, SLOT
+        HALT
+SLOT    .FILL x1234
+.END
+";
+
+#[test]
+fn negotiates_features_and_target_description() {
+    let (mut gdb, server) = connect(PROGRAM);
+    let features = gdb.send("qSupported:multiprocess+;swbreak+");
+    assert!(features.contains("qXfer:features:read+"), "{features}");
+    let xml = gdb.send("qXfer:features:read:target.xml:0,fff");
+    assert!(xml.starts_with("l<?xml"), "{xml}");
+    assert!(xml.contains(r#"<reg name="pc" bitsize="16""#), "{xml}");
+    let head = gdb.send("qXfer:features:read:target.xml:0,10");
+    assert_eq!(head, "m<?xml version=\"1");
+    assert_eq!(gdb.send("?"), "S05");
+    assert_eq!(gdb.send("vMustReplyEmpty"), "");
+    assert_eq!(gdb.send("D"), "OK");
+    server.join().unwrap();
+}
+
+#[test]
+fn reads_and_writes_registers_and_memory() {
+    let (mut gdb, server) = connect(PROGRAM);
+    let regs = gdb.send("g");
+    assert_eq!(regs.len(), 10 * 4);
+    assert_eq!(&regs[32..36], le(0x3000)); // PC
+    assert_eq!(gdb.send("p8"), le(0x3000));
+
+    assert_eq!(gdb.send(&format!("P3={}", le(0xBEEF))), "OK");
+    assert_eq!(gdb.send("p3"), le(0xBEEF));
+    let mut regs = gdb.send("g");
+    regs.replace_range(4..8, &le(0x0042));
+    assert_eq!(gdb.send(&format!("G{regs}")), "OK");
+    assert_eq!(gdb.send("p1"), le(0x0042));
+    assert_eq!(gdb.send("pa"), "E01");
+
+    // x3005 (SLOT) is at byte address 0x600A.
+    assert_eq!(gdb.send("m600a,2"), le(0x1234));
+    assert_eq!(gdb.send("m600b,1"), "12");
+    assert_eq!(gdb.send(&format!("M600a,2:{}", le(0xCAFE))), "OK");
+    assert_eq!(gdb.send("m600a,2"), le(0xCAFE));
+    assert_eq!(gdb.send("M600a,1:00"), "OK");
+    assert_eq!(gdb.send("m600a,2"), le(0xCA00));
+
+    // Long reads stop at the packet size and at the end of memory.
+    assert_eq!(gdb.send("m0,ffffffff").len(), 0x1000);
+    assert_eq!(gdb.send("m1fffe,10").len(), 4);
+    assert_eq!(gdb.send("m20000,2"), "E01");
+
+    // Writes stay inside memory and bypass devices: DDR (xFE06) keeps the
+    // word instead of printing it.
+    assert_eq!(gdb.send("M1fffe,4:00000000"), "E01");
+    assert_eq!(gdb.send("M20000,2:0000"), "E01");
+    assert_eq!(gdb.send(&format!("M1fc0c,2:{}", le(0x0041))), "OK");
+    assert_eq!(gdb.send("m1fc0c,2"), le(0x0041));
+    assert_eq!(gdb.send("m0,2"), le(0));
+    gdb.kill();
+    server.join().unwrap();
+}
+
+#[test]
+fn steps_continues_and_stops_at_breakpoints_and_watchpoints() {
+    let (mut gdb, server) = connect(PROGRAM);
+    assert_eq!(gdb.send("s"), "S05");
+    assert_eq!(gdb.send("p8"), le(0x3001));
+
+    // Break before ST R0, SLOT (x3002).
+    assert_eq!(gdb.send("Z0,6004,2"), "OK");
+    assert_eq!(gdb.send("c"), "S05");
+    assert_eq!(gdb.send("p8"), le(0x3002));
+    assert_eq!(gdb.send("z0,6004,2"), "OK");
+
+    // A write watchpoint on SLOT catches the ST, a read watchpoint the LD.
+    assert_eq!(gdb.send("Z2,600a,2"), "OK");
+    assert_eq!(gdb.send("c"), "T05watch:600a;");
+    assert_eq!(gdb.send("m600a,2"), le(5));
+    assert_eq!(gdb.send("z2,600a,2"), "OK");
+    assert_eq!(gdb.send("Z3,600a,2"), "OK");
+    assert_eq!(gdb.send("c"), "T05rwatch:600a;");
+    assert_eq!(gdb.send("p1"), le(5));
+    assert_eq!(gdb.send("z3,600a,2"), "OK");
+
+    assert_eq!(gdb.send("c"), "W00");
+    gdb.kill();
+    server.join().unwrap();
+}
+
+#[test]
+fn interrupt_stops_a_running_program() {
+    let (mut gdb, server) = connect(".ORIG x3000\nLOOP BRnzp LOOP\n.END\n");
+    gdb.stream.write_all(b"$c#63").unwrap();
+    assert_eq!(gdb.byte(), b'+');
+    gdb.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(gdb.reply(), "S02");
+    assert_eq!(gdb.send("p8"), le(0x3000));
+    assert_eq!(gdb.send("QStartNoAckMode"), "OK");
+    // Without acknowledgements the client reads replies directly.
+    write!(gdb.stream, "$?#3f").unwrap();
+    assert_eq!(gdb.reply(), "S02");
+    gdb.stream.write_all(b"$k#6b").unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn interrupt_stops_a_program_waiting_for_input() {
+    let (mut gdb, server) = connect(".ORIG x3000\nGETC\nHALT\n.END\n");
+    gdb.stream.write_all(b"$c#63").unwrap();
+    assert_eq!(gdb.byte(), b'+');
+    gdb.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(gdb.reply(), "S02");
+    // Still on the GETC, which runs again once a key arrives.
+    assert_eq!(gdb.send("p8"), le(0x3000));
+    gdb.kill();
+    server.join().unwrap();
+}
+
+#[test]
+fn keys_reach_a_program_polling_the_keyboard() {
+    let (mut gdb, server) = connect_with_keys(
+        "\
+.ORIG x3000
+POLL    LDI R1, KBSR_P
+        BRzp POLL
+        LDI R0, KBDR_P
+        HALT
+KBSR_P  .FILL xFE00
+KBDR_P  .FILL xFE02
+.END
+",
+        b"z",
+    );
+    assert_eq!(gdb.send("c"), "W00");
+    assert_eq!(gdb.send("p0"), le(u16::from(b'z')));
+    gdb.kill();
+    server.join().unwrap();
+}
@@ -0,0 +1,688 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use lc3_assembler::encoder::encode;
+use lc3_assembler::first_pass::first_pass;
+use lc3_assembler::lexer::tokenize;
+use lc3_assembler::parser::parse_lines;
+use lc3_assembler::{assemble_source, AssembleOptions};
+use lc3_sim::breakpoint::{Access, BreakCommand};
+use lc3_sim::trace::{self, MemAccess};
+use lc3_sim::{
+    Breakpoint, CondCode, Device, Machine, Privilege, SourceView, StepResult, TraceEntry,
+    TraceFormat, TraceWriter, Watchpoint,
+};
+
+/// Assemble `source` and return the object bytes.
+fn obj_bytes(source: &str) -> Vec<u8> {
+    let lexed = tokenize(source);
+    let parsed = parse_lines(&lexed.tokens);
+    let first = first_pass(parsed.lines);
+    let encoded = encode(&first);
+    assert!(!encoded.has_errors(), "{:?}", encoded.errors);
+    lc3_assembler::obj::to_bytes(&encoded.segments)
+}
+
+/// Assemble `source` and load it into a fresh machine.
+fn load(source: &str) -> Machine {
+    let mut machine = Machine::new();
+    machine.load_obj(&obj_bytes(source)).unwrap();
+    machine
+}
+
+#[test]
+fn runs_to_halt_and_buffers_output() {
+    let mut m = load(".ORIG x3000\nLEA R0, MSG\nPUTS\nHALT\nMSG .STRINGZ \"Hello\\n\"\n.END\n");
+    assert_eq!(m.run_steps(100), StepResult::Halted);
+    assert!(m.output().starts_with("Hello\n"));
+}
+
+#[test]
+fn registers_and_memory_are_accessible() {
+    let mut m =
+        load(".ORIG x3000\nLD R1, VAL\nADD R1, R1, R2\nST R1, VAL\nHALT\nVAL .FILL #5\n.END\n");
+    m.set_reg(2, 10);
+    m.step();
+    assert_eq!(m.reg(1), 5);
+    m.step();
+    assert_eq!(m.cc(), CondCode::P);
+    m.step();
+    assert_eq!(m.read_mem(0x3004), 15);
+    m.write_mem(0x3004, 0xFFFF);
+    m.set_pc(0x3000);
+    m.step();
+    assert_eq!(m.reg(1), 0xFFFF);
+    assert_eq!(m.cc(), CondCode::N);
+    assert_eq!(m.pc(), 0x3001);
+}
+
+#[test]
+fn input_handler_feeds_getc() {
+    let mut m = load(".ORIG x3000\nGETC\nOUT\nGETC\nOUT\nHALT\n.END\n");
+    let mut keys = b"ok".iter().copied();
+    m.set_input_handler(move || keys.next());
+    let out = Rc::new(RefCell::new(String::new()));
+    let sink = Rc::clone(&out);
+    m.set_output_handler(move |ch| sink.borrow_mut().push(ch));
+    assert_eq!(m.run_steps(100), StepResult::Halted);
+    assert!(out.borrow().starts_with("ok"));
+}
+
+#[test]
+fn getc_stalls_until_input_is_queued() {
+    let mut m = load(".ORIG x3000\nGETC\nHALT\n.END\n");
+    m.step();
+    assert!(m.waiting_for_input);
+    assert_eq!(m.pc(), 0x3000);
+    m.input_queue.push_back(b'A');
+    m.step();
+    assert!(!m.waiting_for_input);
+    assert_eq!(m.reg(0), u16::from(b'A'));
+}
+
+#[test]
+fn breakpoints_stop_execution() {
+    let mut m = load(".ORIG x3000\nADD R0, R0, #1\nADD R0, R0, #1\nHALT\n.END\n");
+    m.breakpoints.insert(0x3001, Breakpoint::default());
+    assert_eq!(m.run_steps(100), StepResult::BreakpointHit(0x3001));
+    assert_eq!(m.reg(0), 1);
+}
+
+#[test]
+fn load_image_sets_pc() {
+    let mut m = Machine::new();
+    m.load_image(0x4000, &[0xF025]);
+    assert_eq!(m.pc(), 0x4000);
+    assert_eq!(m.step(), StepResult::Halted);
+}
+
+/// User program at x3000 that enables keyboard interrupts and spins until the
+/// ISR at x1000 stores the key in FLAG; the ISR's entry is at x0180.
+const KEYBOARD_ISR: &str = "\
+.ORIG x3000
+        LD  R6, USP
+        LD  R1, IE
+        STI R1, KBSR_P
+WAIT    LD  R0, FLAG
+        BRz WAIT
+        HALT
+USP     .FILL xFE00
+IE      .FILL x4000
+KBSR_P  .FILL xFE00
+FLAG    .FILL #0
+.END
+.ORIG x1000
+ISR     LDI R0, KBDR_P
+        STI R0, FLAG_P
+        RTI
+KBDR_P  .FILL xFE02
+FLAG_P  .FILL FLAG
+.END
+.ORIG x0180
+        .FILL ISR
+.END
+";
+
+#[test]
+fn keyboard_interrupt_runs_isr_and_returns() {
+    let mut m = load(KEYBOARD_ISR);
+    m.set_pc(0x3000);
+    assert_eq!(m.run_steps(20), StepResult::Ok);
+    assert!(m.keyboard_interrupt_enabled());
+    assert_eq!(m.read_mem(0x3009), 0);
+
+    m.input_queue.push_back(b'k');
+    m.step();
+    // The interrupt switched to the supervisor stack and raised the priority.
+    assert_eq!(m.regs.privilege, Privilege::Supervisor);
+    assert_eq!(m.regs.priority, 4);
+    assert_eq!(m.reg(6), 0x3000 - 2);
+    assert_eq!(m.regs.saved_usp, 0xFE00);
+
+    assert_eq!(m.run_steps(100), StepResult::Halted);
+    assert_eq!(m.read_mem(0x3009), u16::from(b'k'));
+    assert_eq!(m.regs.privilege, Privilege::User);
+    assert_eq!(m.regs.priority, 0);
+    assert_eq!(m.reg(6), 0xFE00);
+    assert_eq!(m.regs.saved_ssp, 0x3000);
+}
+
+#[test]
+fn interrupts_are_masked_at_equal_priority() {
+    let mut m = load(KEYBOARD_ISR);
+    m.set_pc(0x3000);
+    m.run_steps(20);
+    m.regs.priority = 4;
+    m.input_queue.push_back(b'k');
+    m.step();
+    assert_eq!(m.regs.privilege, Privilege::User);
+    // The key stays latched in the keyboard until the program reads KBDR.
+    assert!(m.mem.keyboard.ready());
+}
+
+#[test]
+fn rti_in_user_mode_is_a_privilege_violation() {
+    let mut m = load(".ORIG x3000\nRTI\n.END\n");
+    assert_eq!(m.step(), StepResult::PrivilegeViolation(0x8000));
+
+    // With a handler installed at x0100 the exception is taken instead.
+    let mut m = load(".ORIG x3000\nRTI\n.END\n.ORIG x0100\n.FILL x4000\n.END\n");
+    assert_eq!(m.step(), StepResult::Ok);
+    assert_eq!(m.pc(), 0x4000);
+    assert_eq!(m.regs.privilege, Privilege::Supervisor);
+    // Saved PSR (user, PL0, Z) and PC sit on the supervisor stack.
+    assert_eq!(m.read_mem(0x2FFF), 0x8002);
+    assert_eq!(m.read_mem(0x2FFE), 0x3001);
+}
+
+#[test]
+fn illegal_opcode_goes_through_vector_x01() {
+    let mut m = load(".ORIG x3000\n.FILL xD000\n.END\n");
+    assert_eq!(m.step(), StepResult::IllegalInstruction(0xD000));
+
+    let mut m = load(
+        ".ORIG x3000\n.FILL xD000\nHALT\n.END\n.ORIG x4000\nRTI\n.END\n.ORIG x0101\n.FILL x4000\n.END\n",
+    );
+    assert_eq!(m.step(), StepResult::Ok);
+    assert_eq!(m.pc(), 0x4000);
+    m.step(); // RTI back to the instruction after the bad opcode
+    assert_eq!(m.pc(), 0x3001);
+    assert_eq!(m.regs.privilege, Privilege::User);
+    assert_eq!(m.step(), StepResult::Halted);
+}
+
+#[test]
+fn psr_round_trips() {
+    let mut m = Machine::new();
+    assert_eq!(m.regs.psr(), 0x8002);
+    m.regs.set_psr(0x0401);
+    assert_eq!(m.regs.privilege, Privilege::Supervisor);
+    assert_eq!(m.regs.priority, 4);
+    assert_eq!(m.cc(), CondCode::P);
+    assert_eq!(m.regs.psr(), 0x0401);
+}
+
+#[test]
+fn polling_program_reads_keyboard_and_writes_display() {
+    let mut m = load(
+        "\
+.ORIG x3000
+POLL    LDI R1, KBSR_P
+        BRzp POLL
+        LDI R0, KBDR_P
+WAIT    LDI R1, DSR_P
+        BRzp WAIT
+        STI R0, DDR_P
+        HALT
+KBSR_P  .FILL xFE00
+KBDR_P  .FILL xFE02
+DSR_P   .FILL xFE04
+DDR_P   .FILL xFE06
+.END
+",
+    );
+    assert_eq!(m.run_steps(50), StepResult::Ok);
+    assert!(m.keyboard_in_use());
+
+    m.input_queue.push_back(b'z');
+    assert_eq!(m.run_steps(50), StepResult::Halted);
+    assert_eq!(m.reg(0), u16::from(b'z'));
+    // Reading KBDR cleared the ready bit.
+    assert!(!m.mem.keyboard.ready());
+    assert!(m.output().starts_with('z'));
+}
+
+#[test]
+fn custom_devices_receive_reads_and_writes() {
+    /// A counter register at xFE10 that increments on every read.
+    struct Counter(Rc<RefCell<u16>>);
+    impl Device for Counter {
+        fn claims(&self, addr: u16) -> bool {
+            addr == 0xFE10
+        }
+        fn read(&mut self, _addr: u16) -> u16 {
+            let mut n = self.0.borrow_mut();
+            *n += 1;
+            *n
+        }
+        fn write(&mut self, _addr: u16, value: u16) {
+            *self.0.borrow_mut() = value;
+        }
+    }
+
+    let state = Rc::new(RefCell::new(0));
+    let mut m =
+        load(".ORIG x3000\nLDI R0, DEV\nLDI R0, DEV\nSTI R0, DEV\nHALT\nDEV .FILL xFE10\n.END\n");
+    m.attach_device(Counter(Rc::clone(&state)));
+    m.run_steps(2);
+    assert_eq!(m.reg(0), 2);
+    m.set_reg(0, 40);
+    m.run_steps(10);
+    assert_eq!(*state.borrow(), 40);
+    assert_eq!(m.mem.raw(0xFE10), 0);
+}
+
+#[test]
+fn traps_run_os_routines_in_supervisor_mode() {
+    let os = obj_bytes(&std::fs::read_to_string("tests/programs/mini_os.asm").unwrap());
+    let mut m =
+        load(".ORIG x3000\nLEA R0, MSG\nPUTS\nGETC\nOUT\nHALT\nMSG .STRINGZ \"Hi\"\n.END\n");
+    m.load_os(&os).unwrap();
+    assert_eq!(m.pc(), 0x3000);
+
+    m.step();
+    m.step(); // TRAP x22 enters the OS routine
+    assert_eq!(m.pc(), m.read_mem(0x0022));
+    assert_eq!(m.regs.privilege, Privilege::Supervisor);
+
+    m.input_queue.push_back(b'!');
+    assert_eq!(m.run_steps(1_000), StepResult::Halted);
+    assert_eq!(m.output(), "Hi!");
+    assert_eq!(m.reg(7), 0x3005, "R7 holds the HALT's return address");
+}
+
+#[test]
+fn os_routines_can_return_with_ret() {
+    let mut m = load(
+        ".ORIG x3000\nTRAP x26\nADD R1, R0, #0\nHALT\n.END\n\
+         .ORIG x4000\nDOUBLE ADD R0, R0, R0\nRET\n.END\n\
+         .ORIG x0026\n.FILL DOUBLE\n.END\n",
+    );
+    m.set_pc(0x3000);
+    m.set_reg(0, 21);
+    m.step();
+    assert_eq!(m.reg(7), 0x3001);
+    m.step();
+    m.step(); // RET
+    assert_eq!(m.pc(), 0x3001);
+    assert_eq!(m.run_steps(10), StepResult::Halted);
+    assert_eq!(m.reg(1), 42);
+}
+
+#[test]
+fn program_can_install_its_own_trap_routine() {
+    let mut m = load(
+        ".ORIG x3000\nTRAP x26\nHALT\n.END\n\
+         .ORIG x4000\nDOUBLE ADD R0, R0, R0\nRTI\n.END\n\
+         .ORIG x0026\n.FILL DOUBLE\n.END\n",
+    );
+    m.set_pc(0x3000);
+    m.set_reg(0, 21);
+    // HALT has no table entry, so it still uses the native routine.
+    assert_eq!(m.run_steps(10), StepResult::Halted);
+    assert_eq!(m.reg(0), 42);
+    assert_eq!(m.regs.privilege, Privilege::User);
+}
+
+/// Run `m` to halt and collect its trace.
+fn trace_run(m: &mut Machine) -> Vec<TraceEntry> {
+    let entries = Rc::new(RefCell::new(Vec::new()));
+    let sink = Rc::clone(&entries);
+    m.set_tracer(move |entry| sink.borrow_mut().push(entry.clone()));
+    assert_eq!(m.run_steps(100), StepResult::Halted);
+    let entries = entries.borrow().clone();
+    entries
+}
+
+#[test]
+fn tracer_records_registers_memory_and_cc() {
+    let mut m = load(
+        ".ORIG x3000\nLEA R2, DATA\nLDR R1, R2, #1\nADD R1, R1, #-5\nSTR R1, R2, #0\n\
+         HALT\nDATA .FILL #1\n.FILL #5\n.END\n",
+    );
+    let entries = trace_run(&mut m);
+    assert_eq!(entries.len(), 5);
+
+    let ldr = &entries[1];
+    assert_eq!((ldr.step, ldr.pc, ldr.ir), (2, 0x3001, 0x6281));
+    assert_eq!(ldr.disassembly(), "LDR R1, R2, #1");
+    assert_eq!(ldr.regs, vec![(1, 5)]);
+    assert_eq!(
+        ldr.reads,
+        vec![MemAccess {
+            addr: 0x3006,
+            value: 5
+        }]
+    );
+    assert_eq!(ldr.cc, Some(CondCode::P));
+
+    // ADD writing zero still reports R1 and the CC change.
+    assert_eq!(entries[2].regs, vec![(1, 0)]);
+    assert_eq!(entries[2].cc, Some(CondCode::Z));
+
+    let str_entry = &entries[3];
+    assert!(str_entry.regs.is_empty() && str_entry.cc.is_none());
+    assert_eq!(
+        str_entry.writes,
+        vec![MemAccess {
+            addr: 0x3005,
+            value: 0
+        }]
+    );
+
+    assert_eq!(
+        entries[1].to_json(),
+        "{\"step\":2,\"pc\":\"x3001\",\"ir\":\"x6281\",\"asm\":\"LDR R1, R2, #1\",\
+         \"regs\":{\"R1\":\"x0005\"},\"reads\":[{\"addr\":\"x3006\",\"value\":\"x0005\"}],\
+         \"writes\":[],\"cc\":\"P\"}"
+    );
+}
+
+#[test]
+fn binary_trace_round_trips() {
+    let mut m = load(".ORIG x3000\nLEA R0, MSG\nPUTS\nHALT\nMSG .STRINGZ \"Hi\"\n.END\n");
+    let entries = trace_run(&mut m);
+
+    let mut bytes = Vec::new();
+    let mut writer = TraceWriter::new(&mut bytes, TraceFormat::Binary);
+    for entry in &entries {
+        writer.write(entry).unwrap();
+    }
+    writer.finish().unwrap();
+
+    assert!(bytes.starts_with(b"LC3T"));
+    assert_eq!(trace::read_binary(&bytes).unwrap(), entries);
+    assert!(trace::read_binary(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn step_back_undoes_registers_memory_and_cc() {
+    let mut m = load(
+        ".ORIG x3000\nLD R6, STACK\nADD R6, R6, #-1\nSTR R0, R6, #0\nAND R6, R6, #0\nHALT\n\
+         STACK .FILL x4000\n.END\n",
+    );
+    m.set_reg(0, 7);
+    m.set_history_limit(100);
+    assert!(!m.step_back());
+
+    assert_eq!(m.run_steps(100), StepResult::Halted);
+    assert_eq!(m.history_len(), 5);
+    assert_eq!(m.read_mem(0x3FFF), 7);
+
+    assert!(m.step_back()); // HALT
+    assert!(!m.halted);
+    assert_eq!(m.pc(), 0x3004);
+    assert!(m.step_back()); // AND R6, R6, #0 — the step that corrupted R6
+    assert_eq!(m.reg(6), 0x3FFF);
+    assert_eq!(m.cc(), CondCode::P);
+    assert!(m.step_back()); // STR
+    assert_eq!(m.read_mem(0x3FFF), 0);
+    assert_eq!(m.step_count, 2);
+
+    // Replaying gives the same result.
+    assert_eq!(m.run_steps(100), StepResult::Halted);
+    assert_eq!(m.read_mem(0x3FFF), 7);
+    assert_eq!(m.reg(6), 0);
+}
+
+#[test]
+fn history_is_bounded() {
+    let mut m = load(".ORIG x3000\nLOOP ADD R0, R0, #1\nBRnzp LOOP\n.END\n");
+    m.set_history_limit(3);
+    m.run_steps(10);
+    assert_eq!(m.history_len(), 3);
+    while m.step_back() {}
+    assert_eq!(m.step_count, 7);
+    assert_eq!(m.reg(0), 4);
+}
+
+#[test]
+fn reverse_continue_stops_at_previous_breakpoint() {
+    let mut m = load(".ORIG x3000\nAND R0, R0, #0\nADD R0, R0, #1\nADD R0, R0, #1\nHALT\n.END\n");
+    m.set_history_limit(100);
+    assert_eq!(m.run_steps(100), StepResult::Halted);
+    m.breakpoints.insert(0x3001, Breakpoint::default());
+    assert_eq!(m.reverse_continue(), StepResult::BreakpointHit(0x3001));
+    assert_eq!(m.reg(0), 0);
+    assert_eq!(m.reverse_continue(), StepResult::Ok);
+    assert_eq!(m.pc(), 0x3000);
+}
+
+#[test]
+fn step_back_returns_consumed_input() {
+    let mut m = load(".ORIG x3000\nGETC\nHALT\n.END\n");
+    m.set_history_limit(10);
+    m.input_queue.push_back(b'A');
+    m.step();
+    assert_eq!(m.reg(0), u16::from(b'A'));
+    assert!(m.step_back());
+    assert_eq!(m.input_queue.front(), Some(&b'A'));
+    m.step();
+    assert_eq!(m.reg(0), u16::from(b'A'));
+}
+
+#[test]
+fn conditional_breakpoint_with_ignore_count() {
+    let mut m = load(".ORIG x3000\nLOOP ADD R0, R0, #1\nBRnzp LOOP\n.END\n");
+    let bp: BreakCommand = "x3000 if R0 >= #3 ignore 1".parse().unwrap();
+    let BreakCommand::Break(addr, bp) = bp else {
+        panic!("expected a breakpoint")
+    };
+    m.breakpoints.insert(addr, bp);
+    // R0 reaches 3 at the third visit (skipped) and 4 at the fourth.
+    assert_eq!(m.run_steps(100), StepResult::BreakpointHit(0x3000));
+    assert_eq!(m.reg(0), 4);
+    assert_eq!(m.breakpoints[&0x3000].trigger.hits, 2);
+}
+
+#[test]
+fn watchpoints_stop_on_matching_accesses() {
+    let mut m = load(
+        ".ORIG x3000\nLD R1, A\nST R1, B\nLDI R2, PTR\nHALT\nA .FILL #9\nB .FILL #0\nPTR .FILL x4005\n.END\n",
+    );
+    m.watchpoints
+        .push(Watchpoint::new(0x3005, 0x3005, Access::Write));
+    assert_eq!(
+        m.run_steps(100),
+        StepResult::WatchpointHit {
+            addr: 0x3005,
+            value: 9,
+            write: true
+        }
+    );
+    assert_eq!(m.pc(), 0x3002);
+
+    // A read watch on a range, with a condition on memory.
+    m.watchpoints.clear();
+    let BreakCommand::Watch(wp) = "r x4000-x400F if mem[x3005] == 9".parse().unwrap() else {
+        panic!("expected a watchpoint")
+    };
+    m.watchpoints.push(wp);
+    assert!(matches!(
+        m.step(),
+        StepResult::WatchpointHit {
+            addr: 0x4005,
+            write: false,
+            ..
+        }
+    ));
+    assert_eq!(m.watchpoints[0].trigger.hits, 1);
+    assert_eq!(m.run_steps(100), StepResult::Halted);
+}
+
+#[test]
+fn break_command_syntax() {
+    let cmd: BreakCommand = "x3000 if mem[R6] != x0A".parse().unwrap();
+    let BreakCommand::Break(0x3000, bp) = &cmd else {
+        panic!("{cmd:?}")
+    };
+    assert_eq!(
+        bp.trigger.condition.as_ref().unwrap().to_string(),
+        "mem[R6] != x000A"
+    );
+    assert!(!cmd.is_plain());
+    assert!("3000".parse::<BreakCommand>().unwrap().is_plain());
+
+    let cmd: BreakCommand = "rw x4000 ignore 2".parse().unwrap();
+    let BreakCommand::Watch(wp) = cmd else {
+        panic!()
+    };
+    assert_eq!(
+        (wp.to_string(), wp.trigger.ignore),
+        ("rw x4000".to_string(), 2)
+    );
+
+    for bad in [
+        "",
+        "x3000 if R9 == 1",
+        "x3000 if R0",
+        "w x4010-x4000",
+        "x3000 when",
+    ] {
+        assert!(bad.parse::<BreakCommand>().is_err(), "{bad}");
+    }
+}
+
+const CALLS: &str = "\
+.ORIG x3000
+        AND R1, R1, #0
+        JSR SUB
+        ADD R1, R1, #1
+        HALT
+SUB     ADD R1, R1, #2
+        ST R7, SAVE
+        JSR SUB2
+        LD R7, SAVE
+        RET
+SUB2    ADD R1, R1, #4
+        RET
+SAVE    .BLKW 1
+.END
+";
+
+#[test]
+fn step_over_runs_whole_calls() {
+    let mut m = load(CALLS);
+    assert_eq!(m.step_over(100), StepResult::Ok); // AND: a plain step
+    assert_eq!(m.regs.pc, 0x3001);
+    assert_eq!(m.step_over(100), StepResult::Ok);
+    assert_eq!((m.regs.pc, m.reg(1)), (0x3002, 6));
+
+    // A breakpoint inside the call still stops it.
+    let mut m = load(CALLS);
+    m.breakpoints.insert(0x3009, Breakpoint::default());
+    m.step();
+    assert_eq!(m.step_over(100), StepResult::BreakpointHit(0x3009));
+}
+
+#[test]
+fn step_out_returns_past_nested_calls() {
+    let mut m = load(CALLS);
+    m.run_steps(3); // AND, JSR SUB, ADD
+    assert_eq!(m.regs.pc, 0x3005);
+    assert_eq!(m.step_out(100), StepResult::Ok);
+    assert_eq!((m.regs.pc, m.reg(1)), (0x3002, 6));
+}
+
+#[test]
+fn source_view_maps_addresses_and_steps_by_line() {
+    let source = "\
+.MACRO INC2 %R
+        ADD %R, %R, #1
+        ADD %R, %R, #1
+.ENDM
+.ORIG x3000
+        AND R0, R0, #0
+        INC2 R0
+        JSR DONE
+        HALT
+DONE    RET
+.END
+";
+    let asm = assemble_source(source, "prog.asm", &AssembleOptions::default());
+    assert!(!asm.has_errors());
+    let view = SourceView::from_assembly(&asm);
+    assert_eq!(view.files[0].name, "prog.asm");
+    assert_eq!(view.files[0].lines[5].trim(), "AND R0, R0, #0");
+
+    let add = view.lookup(0x3001).unwrap();
+    assert_eq!(add.at.line, 2);
+    let [(name, call)] = add.expansions.as_slice() else {
+        panic!("{add:?}")
+    };
+    assert_eq!((name.as_str(), call.line), ("INC2", 7));
+    assert_eq!(view.address_of("prog.asm", 7), Some(0x3001));
+    assert_eq!(view.address_of("prog.asm", 4), Some(0x3000)); // next line with code
+    assert_eq!(view.address_of("other.asm", 7), None);
+
+    let mut m = Machine::new();
+    m.load_obj(&asm.obj_bytes()).unwrap();
+    let line = |m: &Machine| view.lookup(m.regs.pc).map(|i| i.statement().line);
+    assert_eq!(line(&m), Some(6));
+    m.step_line(&view, 100);
+    assert_eq!(line(&m), Some(7));
+    m.step_line(&view, 100); // both ADDs of the macro
+    assert_eq!((line(&m), m.reg(0)), (Some(8), 2));
+    m.step_line(&view, 100); // over the JSR
+    assert_eq!((line(&m), m.regs.pc), (Some(9), 0x3004));
+}
+
+#[test]
+fn source_view_steps_and_breaks_on_nested_macro_calls() {
+    let source = "\
+.MACRO DELAY %R
+        ADD %R, %R, #1
+        ADD %R, %R, #1
+        ADD %R, %R, #1
+.ENDM
+.MACRO TWICE %R
+        ; two delays and one more
+        DELAY %R
+        DELAY %R
+        ADD %R, %R, #1
+.ENDM
+.ORIG x3000
+        AND R0, R0, #0
+        TWICE R0
+        HALT
+.END
+";
+    let asm = assemble_source(source, "mac.asm", &AssembleOptions::default());
+    assert!(!asm.has_errors());
+    let view = SourceView::from_assembly(&asm);
+
+    // x3004 is the first ADD of the second DELAY, inside TWICE.
+    let add = view.lookup(0x3004).unwrap();
+    assert_eq!(add.at.line, 2);
+    let calls: Vec<_> = add
+        .expansions
+        .iter()
+        .map(|(name, call)| (name.as_str(), call.line))
+        .collect();
+    assert_eq!(calls, [("TWICE", 14), ("DELAY", 9)]);
+    assert_eq!(add.statement().line, 14);
+    assert_eq!(view.lookup(0x3007).unwrap().statement().line, 14);
+
+    // A breakpoint on the call lands before any of the expansion runs.
+    assert_eq!(view.address_of("mac.asm", 14), Some(0x3001));
+    assert_eq!(view.address_of("mac.asm", 8), Some(0x3000)); // inside TWICE: next is the AND
+    assert_eq!(view.address_of("mac.asm", 15), Some(0x3008));
+    let mut m = Machine::new();
+    m.load_obj(&asm.obj_bytes()).unwrap();
+    m.breakpoints.insert(0x3001, Breakpoint::default());
+    assert_eq!(m.run_steps(100), StepResult::BreakpointHit(0x3001));
+    assert_eq!(m.reg(0), 0);
+
+    // Stepping runs the whole call, both DELAYs included, as one statement.
+    m.breakpoints.clear();
+    m.step_line(&view, 100);
+    assert_eq!((m.regs.pc, m.reg(0)), (0x3008, 7));
+}
+
+#[test]
+fn source_view_loads_from_debug_info() {
+    use lc3_assembler::debug_info::DebugInfo;
+
+    let asm = assemble_source(CALLS, "calls.asm", &AssembleOptions::default());
+    let text = DebugInfo::from_assembly(&asm).to_text("calls.obj");
+    let info = DebugInfo::parse(&text).unwrap();
+    let view = SourceView::from_debug_info(&info, std::path::Path::new("/nonexistent"));
+
+    // Same lines as an in-process assembly; the file text is not on disk.
+    let direct = SourceView::from_assembly(&asm);
+    for addr in 0x3000..0x300C {
+        assert_eq!(view.lookup(addr), direct.lookup(addr), "x{addr:04X}");
+    }
+    assert_eq!(view.address_of("calls.asm", 6), Some(0x3004));
+    assert!(view.files[0].lines.is_empty());
+}
@@ -0,0 +1,64 @@
+; Minimal LC-3 operating system used by the simulator tests.
+;
+; Fills in the trap vector table for GETC, OUT, PUTS and HALT.  Every
+; routine talks to the memory-mapped devices directly and returns with RTI.
+
+        .ORIG x0020
+        .FILL TRAP_GETC     ; x20
+        .FILL TRAP_OUT      ; x21
+        .FILL TRAP_PUTS     ; x22
+        .END
+
+        .ORIG x0025
+        .FILL TRAP_HALT     ; x25
+        .END
+
+        .ORIG x0500
+; GETC: wait for a key and return it in R0.
+TRAP_GETC   LDI R0, OS_KBSR
+            BRzp TRAP_GETC
+            LDI R0, OS_KBDR
+            RTI
+
+; OUT: write R0[7:0] to the display.
+TRAP_OUT    ST  R1, SAVE_R1
+OUT_WAIT    LDI R1, OS_DSR
+            BRzp OUT_WAIT
+            STI R0, OS_DDR
+            LD  R1, SAVE_R1
+            RTI
+
+; PUTS: write the NUL-terminated string at R0.
+TRAP_PUTS   ST  R0, SAVE_R0
+            ST  R1, SAVE_R1
+            ST  R2, SAVE_R2
+            ADD R1, R0, #0
+PUTS_LOOP   LDR R0, R1, #0
+            BRz PUTS_DONE
+PUTS_WAIT   LDI R2, OS_DSR
+            BRzp PUTS_WAIT
+            STI R0, OS_DDR
+            ADD R1, R1, #1
+            BRnzp PUTS_LOOP
+PUTS_DONE   LD  R0, SAVE_R0
+            LD  R1, SAVE_R1
+            LD  R2, SAVE_R2
+            RTI
+
+; HALT: stop the clock by clearing MCR[15].
+TRAP_HALT   LDI R0, OS_MCR
+            LD  R1, CLOCK_MASK
+            AND R0, R0, R1
+            STI R0, OS_MCR
+            BRnzp TRAP_HALT
+
+SAVE_R0     .FILL #0
+SAVE_R1     .FILL #0
+SAVE_R2     .FILL #0
+CLOCK_MASK  .FILL x7FFF
+OS_KBSR     .FILL xFE00
+OS_KBDR     .FILL xFE02
+OS_DSR      .FILL xFE04
+OS_DDR      .FILL xFE06
+OS_MCR      .FILL xFFFE
+        .END
@@ -0,0 +1,352 @@
+//! # Assembling a Whole Program
+//!
+//! [`assemble`] runs every pipeline stage — `.INCLUDE` preprocessing, macro
+//! expansion, lexing, parsing, the first pass and encoding — and gathers the
+//! outcome into one [`Assembly`].  Both `lc3-assembler` and `lc3-sim` go
+//! through it, so a program assembles the same way wherever it is loaded.
+//!
+//! ```rust,no_run
+//! use lc3_assembler::{assemble, AssembleOptions};
+//!
+//! let asm = assemble("program.asm", &AssembleOptions::default()).unwrap();
+//! asm.emit_diagnostics(true);
+//! if !asm.has_errors() {
+//!     std::fs::write("program.obj", asm.obj_bytes()).unwrap();
+//! }
+//! ```
+
+use std::path::Path;
+
+use crate::diagnostic::{self, RichDiagnostics};
+use crate::encoder::{encode, EncodeResult, RelocKind, Segment};
+use crate::error::{AsmError, ErrorKind, Span, Stage};
+use crate::first_pass::{first_pass, symbol_table::SymbolTable, FirstPassResult};
+use crate::lexer::tokenize;
+use crate::macro_expand::MacroDef;
+use crate::parser::parse_lines;
+use crate::source_map::{SourceLocation, SourceMap};
+use crate::warning::AsmWarning;
+use crate::{macro_expand, obj, preprocessor};
+
+/// Settings for [`assemble`] and [`assemble_source`].
+///
+/// Build it with `Default` and set the fields you need, so callers keep
+/// compiling as options are added.
+#[derive(Debug, Clone, Default)]
+pub struct AssembleOptions {
+    /// Produce a relocatable object for `lc3-link`: references to
+    /// `.EXTERNAL` symbols are left as relocations instead of being
+    /// reported as errors.
+    pub relocatable: bool,
+    /// `-D NAME=value` definitions, visible to `.IF`/`.IFDEF` (see
+    /// [`preprocessor`](crate::preprocessor#conditional-assembly)).
+    pub defines: Vec<(String, i32)>,
+}
+
+/// Everything produced by assembling one program.
+pub struct Assembly {
+    /// Display name used in diagnostics (the path, or `<stdin>`).
+    pub name: String,
+    /// The source as written, before includes and macros are expanded.
+    pub source: String,
+    /// First-pass output: symbol table and per-line AST.
+    pub first: FirstPassResult,
+    /// Encoder output: machine code, segments and per-line words.
+    pub encoded: EncodeResult,
+    /// Errors from every stage, in pipeline order.
+    pub errors: Vec<AsmError>,
+    /// Warnings from every stage, in pipeline order.
+    pub warnings: Vec<AsmWarning>,
+    /// Macros defined by the program or its includes, sorted by name.
+    pub macros: Vec<MacroDef>,
+    /// Maps spans in the expanded source back to the files they came from.
+    pub source_map: SourceMap,
+    /// The stage that reported each entry of `errors` / `warnings`, and where
+    /// it points in the files the user wrote.
+    error_origins: Vec<(Stage, SourceLocation)>,
+    warning_origins: Vec<(Stage, SourceLocation)>,
+}
+
+impl Assembly {
+    /// Returns `true` if any stage recorded an error.
+    #[must_use]
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    /// The assembled segments, one per `.ORIG` section.
+    #[must_use]
+    pub fn segments(&self) -> &[Segment] {
+        &self.encoded.segments
+    }
+
+    #[must_use]
+    pub fn symbol_table(&self) -> &SymbolTable {
+        &self.first.symbol_table
+    }
+
+    /// The program serialised in the `.obj` format (see [`obj::to_bytes`]).
+    #[must_use]
+    pub fn obj_bytes(&self) -> Vec<u8> {
+        obj::to_bytes(&self.encoded.segments)
+    }
+
+    /// Each error paired with the stage that reported it.
+    pub fn errors_by_stage(&self) -> impl Iterator<Item = (Stage, &AsmError)> {
+        self.located_errors().map(|(stage, _, err)| (stage, err))
+    }
+
+    /// Each warning paired with the stage that reported it.
+    pub fn warnings_by_stage(&self) -> impl Iterator<Item = (Stage, &AsmWarning)> {
+        self.located_warnings()
+            .map(|(stage, _, warn)| (stage, warn))
+    }
+
+    /// Each error with its stage and its location in the original files.
+    pub fn located_errors(&self) -> impl Iterator<Item = (Stage, &SourceLocation, &AsmError)> {
+        self.error_origins
+            .iter()
+            .zip(&self.errors)
+            .map(|((stage, loc), err)| (*stage, loc, err))
+    }
+
+    /// Each warning with its stage and its location in the original files.
+    pub fn located_warnings(&self) -> impl Iterator<Item = (Stage, &SourceLocation, &AsmWarning)> {
+        self.warning_origins
+            .iter()
+            .zip(&self.warnings)
+            .map(|((stage, loc), warn)| (*stage, loc, warn))
+    }
+
+    /// Print all warnings, then all errors, to stderr with source context.
+    pub fn emit_diagnostics(&self, color: bool) {
+        let diag = RichDiagnostics::new(&self.source, &self.name)
+            .with_color(color)
+            .with_source_map(&self.source_map);
+        for (_, loc, warn) in self.located_warnings() {
+            diag.emit_warning_at(warn, loc);
+        }
+        if !self.warnings.is_empty() {
+            diag.0.emit_warning_summary(self.warnings.len());
+        }
+        for (_, loc, err) in self.located_errors() {
+            diag.emit_error_at(err, loc);
+        }
+        if !self.errors.is_empty() {
+            diag.0.emit_error_summary(self.errors.len());
+        }
+    }
+
+    /// Write all warnings, then all errors, as JSON Lines (see
+    /// [`diagnostic::write_json`]).
+    pub fn write_diagnostics_json(&self, out: &mut impl std::io::Write) -> std::io::Result<()> {
+        let warnings: Vec<_> = self.located_warnings().collect();
+        let errors: Vec<_> = self.located_errors().collect();
+        diagnostic::write_json(out, &warnings, &errors)
+    }
+}
+
+/// Read and assemble the file at `path`.
+///
+/// Only a failure to read `path` itself is returned as `Err`; problems in the
+/// program (including missing `.INCLUDE` files) are reported in
+/// [`Assembly::errors`].
+pub fn assemble(path: impl AsRef<Path>, options: &AssembleOptions) -> std::io::Result<Assembly> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path)?;
+    Ok(assemble_source(&source, &path.to_string_lossy(), options))
+}
+
+/// Assemble `source` that was read from `name` (a path, or `"<stdin>"`).
+///
+/// `.INCLUDE` paths are resolved relative to the directory of `name`.
+#[must_use]
+pub fn assemble_source(source: &str, name: &str, options: &AssembleOptions) -> Assembly {
+    // ── Stage 0: Preprocessing (.INCLUDE expansion) ──────────────────────────
+
+    let prep = preprocessor::preprocess_with_defines(name, Some(source), &options.defines);
+
+    // Convert preprocessor and macro errors to AsmError so they flow through
+    // the same diagnostic machinery as everything else.  Their lines are not
+    // in the expanded source, so they are located as they are converted.
+    let stage_error = |kind: ErrorKind, message: &str, line: usize| {
+        AsmError::new(kind, message.to_string(), Span::point(line.max(1), 1))
+    };
+    let mut errors: Vec<AsmError> = Vec::new();
+    let mut error_origins: Vec<(Stage, SourceLocation)> = Vec::new();
+
+    // ── Stage 1: Macro expansion ─────────────────────────────────────────────
+
+    let macro_result = macro_expand::expand(&prep.source);
+
+    // Use the macro-expanded source for all downstream stages.
+    //
+    // If an `.INCLUDE` failed (e.g., an included file could not be found), the
+    // expanded source may be truncated or missing whole sections, so we fall back
+    // to the original source to avoid a cascade of spurious errors.  Errors in
+    // conditional blocks leave the output complete, so it is used as is.
+    //
+    // If only *macro expansion* had errors (wrong argument count, recursive call,
+    // etc.), the expanded source is still structurally complete: bad invocations
+    // are replaced with blank lines to preserve line numbering, and passing it
+    // downstream gives more accurate diagnostics than re-using the original text.
+    let include_failed = prep.errors.iter().any(|e| e.kind == ErrorKind::IoError);
+    let (expanded_source, source_map) = if include_failed {
+        (source, SourceMap::new(name, source, &prep, None))
+    } else {
+        (
+            macro_result.source.as_str(),
+            SourceMap::new(name, source, &prep, Some(&macro_result)),
+        )
+    };
+
+    for e in &prep.errors {
+        errors.push(stage_error(e.kind.clone(), &e.message, e.line));
+        error_origins.push((
+            Stage::Preprocess,
+            SourceLocation {
+                file: source_map.display_name(&e.file),
+                line: e.line.max(1),
+                col: 1,
+                width: 1,
+                expansions: Vec::new(),
+            },
+        ));
+    }
+    for e in &macro_result.errors {
+        errors.push(stage_error(ErrorKind::MacroError, &e.message, e.line));
+        error_origins.push((Stage::Macro, source_map.resolve_preprocessed(e.line)));
+    }
+
+    // ── Stage 2–5: Lex → Parse → First pass → Encode ─────────────────────────
+
+    let lexed = tokenize(expanded_source);
+    let parsed = parse_lines(&lexed.tokens);
+    let first = first_pass(parsed.lines);
+    let mut encoded = encode(&first);
+
+    // Without a linker to hand them to, external references are errors.
+    // With one, a PC offset into another section is checked by the linker
+    // once the sections are placed.
+    if options.relocatable {
+        let relocations = &encoded.relocations;
+        encoded.errors.retain(|e| {
+            e.kind != ErrorKind::OffsetOutOfRange
+                || !relocations
+                    .iter()
+                    .any(|r| r.kind != RelocKind::Abs16 && r.span == e.span)
+        });
+    } else {
+        for reloc in encoded.relocations.iter().filter(|r| r.external) {
+            encoded.errors.push(AsmError::new(
+                ErrorKind::UnresolvedExternal,
+                format!(
+                    "'{}' is declared .EXTERNAL; assemble with --emit rel and link with lc3-link",
+                    reloc.symbol
+                ),
+                reloc.span,
+            ));
+        }
+    }
+
+    for (stage, stage_errors) in [
+        (Stage::Lex, lexed.errors),
+        (Stage::Parse, parsed.errors),
+        (Stage::FirstPass, first.errors.clone()),
+        (Stage::Encode, encoded.errors.clone()),
+    ] {
+        for err in stage_errors {
+            error_origins.push((stage, source_map.resolve(err.span)));
+            errors.push(err);
+        }
+    }
+
+    let mut warnings: Vec<AsmWarning> = Vec::new();
+    let mut warning_origins: Vec<(Stage, SourceLocation)> = Vec::new();
+    for (stage, stage_warnings) in [
+        (Stage::FirstPass, &first.warnings),
+        (Stage::Encode, &encoded.warnings),
+    ] {
+        for warn in stage_warnings {
+            warning_origins.push((stage, source_map.resolve(warn.span)));
+            warnings.push(warn.clone());
+        }
+    }
+
+    Assembly {
+        name: name.to_string(),
+        source: source.to_string(),
+        first,
+        encoded,
+        errors,
+        warnings,
+        macros: macro_result.macros,
+        source_map,
+        error_origins,
+        warning_origins,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_errors_from_every_stage() {
+        let source = ".MACRO BAD %A\nADD %A, %A, #1\n.ENDM\n.ORIG x3000\nBAD R1, R2\nADD R0, R0, #99\n.END\n";
+        let asm = assemble_source(source, "<stdin>", &AssembleOptions::default());
+        let kinds: Vec<_> = asm.errors.iter().map(|e| e.kind.clone()).collect();
+        assert!(kinds.contains(&ErrorKind::MacroError), "{kinds:?}");
+        assert!(kinds.contains(&ErrorKind::InvalidOperandType), "{kinds:?}");
+
+        let stages: Vec<_> = asm.errors_by_stage().map(|(stage, _)| stage).collect();
+        assert_eq!(stages, vec![Stage::Macro, Stage::Encode]);
+    }
+
+    #[test]
+    fn json_diagnostics_one_object_per_line() {
+        let source = ".ORIG x3000\nUNUSED ADD R0, R0, #1\nBR NOWHERE\n.END\n";
+        let asm = assemble_source(source, "prog.asm", &AssembleOptions::default());
+        let mut out = Vec::new();
+        asm.write_diagnostics_json(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), asm.warnings.len() + asm.errors.len(), "{text}");
+        assert!(lines[0].contains("\"code\":\"W001\""), "{text}");
+        let last = lines.last().unwrap();
+        assert!(last.contains("\"kind\":\"UndefinedLabel\""), "{text}");
+        assert!(last.contains("\"file\":\"prog.asm\",\"line\":3"), "{text}");
+        assert!(last.contains("\"stage\":\"encode\""), "{text}");
+    }
+
+    #[test]
+    fn externals_need_relocatable_output() {
+        let source =
+            ".EXTERNAL F\n.ORIG x3000\nJSR F\nLD R0, FAR\n.END\n.ORIG x5000\nFAR .FILL 0\n.END\n";
+        let asm = assemble_source(source, "<stdin>", &AssembleOptions::default());
+        let kinds: Vec<_> = asm.errors.iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![ErrorKind::OffsetOutOfRange, ErrorKind::UnresolvedExternal]
+        );
+
+        let options = AssembleOptions {
+            relocatable: true,
+            ..Default::default()
+        };
+        let asm = assemble_source(source, "<stdin>", &options);
+        assert!(!asm.has_errors(), "{:?}", asm.errors);
+        assert_eq!(asm.encoded.relocations.len(), 2);
+    }
+
+    #[test]
+    fn expands_macros_before_encoding() {
+        let source = ".MACRO INC %R\nADD %R, %R, #1\n.ENDM\n.ORIG x3000\nINC R1\nHALT\n.END\n";
+        let asm = assemble_source(source, "<stdin>", &AssembleOptions::default());
+        assert!(!asm.has_errors(), "{:?}", asm.errors);
+        assert_eq!(asm.segments()[0].words, vec![0x1261, 0xF025]);
+        assert_eq!(asm.obj_bytes(), vec![0x30, 0x00, 0x12, 0x61, 0xF0, 0x25]);
+        assert_eq!(asm.macros[0].name, "INC");
+        assert_eq!(asm.macros[0].params, vec!["R"]);
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use lc3_assembler::debug_info::DebugInfo;
+use lc3_assembler::{disasm, obj};
+
+// ── CLI argument parsing ──────────────────────────────────────────────────────
+
+struct Args {
+    /// Path to the input .obj file.
+    input: String,
+    /// Optional .sym file whose labels replace generated `L_XXXX` names.
+    symbols: Option<String>,
+    /// Optional debug-info file marking which words are data.
+    debug_info: Option<String>,
+    /// Path for the output .asm file.  None = stdout.
+    output: Option<String>,
+}
+
+impl Args {
+    fn parse() -> Self {
+        let raw: Vec<String> = std::env::args().collect();
+        let args: Vec<&str> = raw.iter().map(|s| s.as_str()).collect();
+
+        if args.iter().skip(1).any(|a| *a == "--version" || *a == "-V") {
+            println!("lc3-disasm {}", env!("CARGO_PKG_VERSION"));
+            std::process::exit(0);
+        }
+        if args.len() < 2 || args.iter().skip(1).any(|a| *a == "--help" || *a == "-h") {
+            print_help();
+            std::process::exit(if args.len() < 2 { 1 } else { 0 });
+        }
+
+        let mut input = None;
+        let mut symbols = None;
+        let mut debug_info = None;
+        let mut output = None;
+        let mut i = 1usize;
+
+        while i < args.len() {
+            match args[i] {
+                "-s" | "--symbols" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("error: -s requires a filename argument");
+                        std::process::exit(1);
+                    }
+                    symbols = Some(args[i].to_string());
+                }
+                "-g" | "--debug-info" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("error: -g requires a filename argument");
+                        std::process::exit(1);
+                    }
+                    debug_info = Some(args[i].to_string());
+                }
+                "-o" | "--output" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("error: -o requires a filename argument");
+                        std::process::exit(1);
+                    }
+                    output = Some(args[i].to_string());
+                }
+                other if other.starts_with('-') => {
+                    eprintln!("error: unknown option '{other}'");
+                    std::process::exit(1);
+                }
+                other => {
+                    if input.is_some() {
+                        eprintln!("error: unexpected argument '{other}'");
+                        std::process::exit(1);
+                    }
+                    input = Some(other.to_string());
+                }
+            }
+            i += 1;
+        }
+
+        Args {
+            input: input.unwrap_or_else(|| {
+                eprintln!("error: no input file");
+                print_help();
+                std::process::exit(1);
+            }),
+            symbols,
+            debug_info,
+            output,
+        }
+    }
+}
+
+fn print_help() {
+    println!("lc3-disasm {}", env!("CARGO_PKG_VERSION"));
+    println!("Disassemble an LC-3 object file into re-assemblable source");
+    println!();
+    println!("USAGE:");
+    println!("  lc3-disasm [OPTIONS] <input.obj>");
+    println!();
+    println!("OPTIONS:");
+    println!("  -s, --symbols <file>   Use labels from a .sym file");
+    println!("  -g, --debug-info <file>");
+    println!("                         Use code/data ranges from lc3-assembler --debug-info");
+    println!("  -o, --output <file>    Write the .asm to <file> (default: stdout)");
+    println!("  -h, --help             Print this help message");
+    println!("  -V, --version          Print version information");
+}
+
+// ── Entry point ───────────────────────────────────────────────────────────────
+
+fn main() {
+    let args = Args::parse();
+
+    let bytes = fs::read(&args.input).unwrap_or_else(|e| {
+        eprintln!("error: cannot read '{}': {e}", args.input);
+        std::process::exit(1);
+    });
+    let segments = obj::read(&bytes).unwrap_or_else(|e| {
+        eprintln!("error: {}: {e}", args.input);
+        std::process::exit(1);
+    });
+
+    let syms = match &args.symbols {
+        Some(path) => {
+            let text = fs::read_to_string(path).unwrap_or_else(|e| {
+                eprintln!("error: cannot read '{path}': {e}");
+                std::process::exit(1);
+            });
+            disasm::parse_sym_file(&text)
+        }
+        None => HashMap::new(),
+    };
+
+    let debug = args.debug_info.as_ref().map(|path| {
+        let text = fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("error: cannot read '{path}': {e}");
+            std::process::exit(1);
+        });
+        DebugInfo::parse(&text).unwrap_or_else(|e| {
+            eprintln!("error: {path}: {e}");
+            std::process::exit(1);
+        })
+    });
+
+    let name = Path::new(&args.input)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(&args.input);
+    let mut text = format!("; Disassembled from {name} by lc3-disasm\n");
+    text.push_str(&disasm::disassemble_program_with(
+        &segments,
+        &syms,
+        debug.as_ref(),
+    ));
+
+    match &args.output {
+        Some(path) => fs::write(path, text).unwrap_or_else(|e| {
+            eprintln!("error: cannot write '{path}': {e}");
+            std::process::exit(1);
+        }),
+        None => print!("{text}"),
+    }
+}
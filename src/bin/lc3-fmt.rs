@@ -0,0 +1,115 @@
+use std::fs;
+use std::io::Read;
+
+use lc3_assembler::formatter;
+
+// ── CLI argument parsing ──────────────────────────────────────────────────────
+
+struct Args {
+    /// Files to format; `-` reads stdin and writes stdout.
+    inputs: Vec<String>,
+    /// Report unformatted files instead of rewriting them.
+    check: bool,
+}
+
+impl Args {
+    fn parse() -> Self {
+        let raw: Vec<String> = std::env::args().collect();
+        let args: Vec<&str> = raw.iter().map(|s| s.as_str()).collect();
+
+        if args.iter().skip(1).any(|a| *a == "--version" || *a == "-V") {
+            println!("lc3-fmt {}", env!("CARGO_PKG_VERSION"));
+            std::process::exit(0);
+        }
+        if args.len() < 2 || args.iter().skip(1).any(|a| *a == "--help" || *a == "-h") {
+            print_help();
+            std::process::exit(if args.len() < 2 { 1 } else { 0 });
+        }
+
+        let mut inputs = Vec::new();
+        let mut check = false;
+
+        for &arg in &args[1..] {
+            match arg {
+                "--check" => check = true,
+                "-" => inputs.push(arg.to_string()),
+                other if other.starts_with('-') => {
+                    eprintln!("error: unknown option '{other}'");
+                    std::process::exit(1);
+                }
+                other => inputs.push(other.to_string()),
+            }
+        }
+
+        if inputs.is_empty() {
+            eprintln!("error: no input file");
+            print_help();
+            std::process::exit(1);
+        }
+
+        Args { inputs, check }
+    }
+}
+
+fn print_help() {
+    println!("lc3-fmt {}", env!("CARGO_PKG_VERSION"));
+    println!("Format LC-3 assembly source in place");
+    println!();
+    println!("USAGE:");
+    println!("  lc3-fmt [OPTIONS] <file.asm>...");
+    println!("  lc3-fmt -              Format stdin to stdout");
+    println!();
+    println!("OPTIONS:");
+    println!("  --check                Don't write; exit 1 if any file needs formatting");
+    println!("  -h, --help             Print this help message");
+    println!("  -V, --version          Print version information");
+}
+
+// ── Entry point ───────────────────────────────────────────────────────────────
+
+fn main() {
+    let args = Args::parse();
+    let mut unformatted = false;
+
+    for input in &args.inputs {
+        let source = if input == "-" {
+            let mut buf = String::new();
+            std::io::stdin()
+                .read_to_string(&mut buf)
+                .unwrap_or_else(|e| {
+                    eprintln!("error: cannot read stdin: {e}");
+                    std::process::exit(1);
+                });
+            buf
+        } else {
+            fs::read_to_string(input).unwrap_or_else(|e| {
+                eprintln!("error: cannot read '{input}': {e}");
+                std::process::exit(1);
+            })
+        };
+
+        let formatted = formatter::format_source(&source);
+        if formatted == source {
+            if input == "-" && !args.check {
+                print!("{formatted}");
+            }
+            continue;
+        }
+
+        if args.check {
+            println!("would reformat {input}");
+            unformatted = true;
+        } else if input == "-" {
+            print!("{formatted}");
+        } else {
+            fs::write(input, formatted).unwrap_or_else(|e| {
+                eprintln!("error: cannot write '{input}': {e}");
+                std::process::exit(1);
+            });
+        }
+    }
+
+    if unformatted {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,198 @@
+use std::fs;
+use std::path::Path;
+
+use lc3_assembler::linker::{self, Placement};
+use lc3_assembler::listing;
+use lc3_assembler::obj;
+use lc3_assembler::relocatable::RelocatableObject;
+
+// ── CLI argument parsing ──────────────────────────────────────────────────────
+
+struct Args {
+    /// Relocatable objects (`.rel`) to link, in order.
+    inputs: Vec<String>,
+    /// Path for the linked `.obj`.  None = first input with `.obj`.
+    output: Option<String>,
+    /// Path for the symbol table.  None = output with `.sym`.
+    symbols: Option<String>,
+    /// Path for the link map.  None = output with `.map`.
+    map: Option<String>,
+    /// `--base` flags and placement-script lines, in command-line order.
+    placements: Vec<Placement>,
+}
+
+impl Args {
+    fn parse() -> Self {
+        let raw: Vec<String> = std::env::args().collect();
+        let args: Vec<&str> = raw.iter().map(|s| s.as_str()).collect();
+
+        if args.iter().skip(1).any(|a| *a == "--version" || *a == "-V") {
+            println!("lc3-link {}", env!("CARGO_PKG_VERSION"));
+            std::process::exit(0);
+        }
+        if args.len() < 2 || args.iter().skip(1).any(|a| *a == "--help" || *a == "-h") {
+            print_help();
+            std::process::exit(if args.len() < 2 { 1 } else { 0 });
+        }
+
+        let mut inputs = Vec::new();
+        let mut output = None;
+        let mut symbols = None;
+        let mut map = None;
+        let mut placements = Vec::new();
+
+        let mut i = 1;
+        while i < args.len() {
+            let flag = args[i];
+            let mut value = || {
+                i += 1;
+                args.get(i).copied().unwrap_or_else(|| {
+                    eprintln!("error: {flag} requires an argument");
+                    std::process::exit(1);
+                })
+            };
+            match flag {
+                "-o" | "--output" => output = Some(value().to_string()),
+                "-s" | "--symbols" => symbols = Some(value().to_string()),
+                "-m" | "--map" => map = Some(value().to_string()),
+                "--base" => {
+                    let spec = value();
+                    placements.push(Placement::parse(spec).unwrap_or_else(|e| {
+                        eprintln!("error: --base {spec}: {e}");
+                        std::process::exit(1);
+                    }));
+                }
+                "-T" | "--script" => {
+                    let path = value();
+                    let text = fs::read_to_string(path).unwrap_or_else(|e| {
+                        eprintln!("error: cannot read '{path}': {e}");
+                        std::process::exit(1);
+                    });
+                    placements.extend(linker::parse_script(&text).unwrap_or_else(|e| {
+                        eprintln!("error: {path}: {e}");
+                        std::process::exit(1);
+                    }));
+                }
+                other if other.starts_with('-') => {
+                    eprintln!("error: unknown option '{other}'");
+                    std::process::exit(1);
+                }
+                other => inputs.push(other.to_string()),
+            }
+            i += 1;
+        }
+
+        if inputs.is_empty() {
+            eprintln!("error: no input file");
+            print_help();
+            std::process::exit(1);
+        }
+
+        Args {
+            inputs,
+            output,
+            symbols,
+            map,
+            placements,
+        }
+    }
+}
+
+fn print_help() {
+    println!("lc3-link {}", env!("CARGO_PKG_VERSION"));
+    println!("Link LC-3 relocatable objects (lc3-assembler --emit rel) into one program");
+    println!();
+    println!("USAGE:");
+    println!("  lc3-link [OPTIONS] <file.rel>...");
+    println!();
+    println!("OPTIONS:");
+    println!("  -o, --output <file>    Write the program to <file> (default: <first input>.obj)");
+    println!("  -s, --symbols <file>   Write the symbol table to <file> (default: <output>.sym)");
+    println!("  -m, --map <file>       Write the link map to <file> (default: <output>.map)");
+    println!("      --base <OBJECT[:SECTION]=ADDR>");
+    println!("                         Place an object (or one of its sections) at ADDR");
+    println!("  -T, --script <file>    Read placements from <file>, one per line");
+    println!("  -h, --help             Print this help message");
+    println!("  -V, --version          Print version information");
+    println!();
+    println!("EXAMPLES:");
+    println!("  lc3-link main.rel print.rel --base print=x3100");
+    println!("  lc3-link main.rel print.rel -T layout.ld -o prog.obj");
+}
+
+// ── Entry point ───────────────────────────────────────────────────────────────
+
+fn main() {
+    let args = Args::parse();
+
+    let objects: Vec<RelocatableObject> = args
+        .inputs
+        .iter()
+        .map(|path| {
+            let data = fs::read(path).unwrap_or_else(|e| {
+                eprintln!("error: cannot read '{path}': {e}");
+                std::process::exit(1);
+            });
+            RelocatableObject::read(&data).unwrap_or_else(|e| {
+                eprintln!("error: '{path}': {e}");
+                std::process::exit(1);
+            })
+        })
+        .collect();
+
+    let result = linker::link(&objects, &args.placements);
+    if result.has_errors() {
+        for err in &result.errors {
+            eprintln!("error: {err}");
+        }
+        eprintln!(
+            "error: linking failed with {} error{}",
+            result.errors.len(),
+            if result.errors.len() == 1 { "" } else { "s" }
+        );
+        std::process::exit(1);
+    }
+
+    let output = args.output.clone().unwrap_or_else(|| {
+        Path::new(&args.inputs[0])
+            .with_extension("obj")
+            .to_string_lossy()
+            .into_owned()
+    });
+    let sibling = |ext: &str| {
+        Path::new(&output)
+            .with_extension(ext)
+            .to_string_lossy()
+            .into_owned()
+    };
+    let sym_path = args.symbols.clone().unwrap_or_else(|| sibling("sym"));
+    let map_path = args.map.clone().unwrap_or_else(|| sibling("map"));
+
+    let segments = result.segments();
+    let table = result.symbol_table();
+    for (path, bytes) in [
+        (&output, obj::to_bytes(&segments)),
+        (
+            &sym_path,
+            listing::generate_sym_file(&table, &output).into_bytes(),
+        ),
+        (&map_path, result.map(&output).into_bytes()),
+    ] {
+        fs::write(path, bytes).unwrap_or_else(|e| {
+            eprintln!("error: cannot write '{path}': {e}");
+            std::process::exit(1);
+        });
+    }
+
+    let words: usize = segments.iter().map(|s| s.words.len()).sum();
+    eprintln!(
+        "linked {} object{} → '{output}'  [{words} word{}, {} segment{}]",
+        objects.len(),
+        if objects.len() == 1 { "" } else { "s" },
+        if words == 1 { "" } else { "s" },
+        segments.len(),
+        if segments.len() == 1 { "" } else { "s" },
+    );
+    eprintln!("symbols  → '{sym_path}'");
+    eprintln!("map      → '{map_path}'");
+}
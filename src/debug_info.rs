@@ -0,0 +1,418 @@
+//! # Debug Info
+//!
+//! `lc3-assembler --debug-info <file>` writes a text sidecar recording where
+//! every word of the program came from: the file, line and column of the
+//! line that emitted it, whether it is code or data (`.FILL`, `.BLKW`,
+//! `.STRINGZ`), and for words produced by a macro, each macro and the line
+//! that invoked it, from the outermost invocation inward.  `lc3-sim` reads
+//! it to show and step through the source of a `.obj` file; `lc3-disasm`
+//! reads it so data is not disassembled as instructions.
+//!
+//! ## Format
+//!
+//! ```text
+//! ; LC-3 debug info — prog.obj
+//! LC3DBG 1
+//! FILE 0 prog.asm
+//! FILE 1 lib/macros.asm
+//! CODE x3000 x3004
+//! DATA x3005 x300B
+//! x3000 0:6:9
+//! x3001 1:2:9 INC2 0:7
+//! x3003 1:5:9 TWICE 0:8 INC2 1:4
+//! ```
+//!
+//! Lines starting with `;` are comments.  `FILE` numbers the source files;
+//! `CODE` and `DATA` give inclusive address ranges.  Every other line maps
+//! one word to `file:line:col`, followed for macro output by a macro name
+//! and the `file:line` of its invocation for every level of expansion,
+//! outermost first.
+
+use std::collections::BTreeMap;
+
+use crate::parser::ast::LineContent;
+use crate::Assembly;
+
+/// First line of a debug-info file, before the version.
+pub const MAGIC: &str = "LC3DBG";
+
+/// Format version written by [`DebugInfo::to_text`].
+pub const VERSION: u16 = 1;
+
+/// Whether a word is an instruction or data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WordKind {
+    Code,
+    /// Emitted by `.FILL`, `.BLKW` or `.STRINGZ`.
+    Data,
+}
+
+/// One macro invocation a word was expanded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    /// Macro name.
+    pub name: String,
+    /// Index into [`DebugInfo::files`] of the invoking line.
+    pub file: usize,
+    /// Line of the invocation.
+    pub line: usize,
+}
+
+/// Where one word came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WordInfo {
+    pub address: u16,
+    /// Index into [`DebugInfo::files`].
+    pub file: usize,
+    pub line: usize,
+    pub col: usize,
+    pub kind: WordKind,
+    /// The macro invocations the word was expanded from, outermost first;
+    /// empty for a word written directly in a file.
+    pub expansions: Vec<Expansion>,
+}
+
+/// Source locations for every word of a program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// Source files, by display name.
+    pub files: Vec<String>,
+    /// One entry per word, sorted by address.
+    pub words: Vec<WordInfo>,
+}
+
+impl DebugInfo {
+    /// The debug info for everything `asm` emitted.  Where `.ORIG` sections
+    /// overlap, the later word wins, as it does when the program is loaded.
+    pub fn from_assembly(asm: &Assembly) -> Self {
+        let mut info = DebugInfo::default();
+        let mut words = BTreeMap::new();
+        for line_info in &asm.encoded.line_infos {
+            let Some(line) = asm.first.source_lines.get(line_info.source_line_idx) else {
+                continue;
+            };
+            let kind = match line.content {
+                LineContent::Instruction(_) => WordKind::Code,
+                _ => WordKind::Data,
+            };
+            let loc = asm.source_map.resolve(line.span);
+            let file = info.file_index(&loc.file);
+            let expansions: Vec<Expansion> = loc
+                .expansions
+                .iter()
+                .map(|e| Expansion {
+                    file: info.file_index(&e.file),
+                    name: e.name.clone(),
+                    line: e.line,
+                })
+                .collect();
+            for offset in 0..line_info.words.len() {
+                let address = line_info.address.wrapping_add(offset as u16);
+                words.insert(
+                    address,
+                    WordInfo {
+                        address,
+                        file,
+                        line: loc.line,
+                        col: loc.col,
+                        kind,
+                        expansions: expansions.clone(),
+                    },
+                );
+            }
+        }
+        info.words = words.into_values().collect();
+        info
+    }
+
+    /// Where the word at `address` came from.
+    pub fn word(&self, address: u16) -> Option<&WordInfo> {
+        self.words
+            .binary_search_by_key(&address, |w| w.address)
+            .ok()
+            .map(|i| &self.words[i])
+    }
+
+    /// Whether the word at `address` is code or data.
+    pub fn kind(&self, address: u16) -> Option<WordKind> {
+        self.word(address).map(|w| w.kind)
+    }
+
+    /// Runs of consecutive addresses of the same kind, as inclusive
+    /// `(start, end, kind)`.
+    pub fn ranges(&self) -> Vec<(u16, u16, WordKind)> {
+        let mut ranges: Vec<(u16, u16, WordKind)> = Vec::new();
+        for w in &self.words {
+            match ranges.last_mut() {
+                Some((_, end, kind))
+                    if *kind == w.kind && end.checked_add(1) == Some(w.address) =>
+                {
+                    *end = w.address;
+                }
+                _ => ranges.push((w.address, w.address, w.kind)),
+            }
+        }
+        ranges
+    }
+
+    /// Serialise in the format described in the [module docs](self).
+    /// `output` names the object file in the header comment.
+    pub fn to_text(&self, output: &str) -> String {
+        let mut out = format!("; LC-3 debug info — {output}\n{MAGIC} {VERSION}\n");
+        for (i, file) in self.files.iter().enumerate() {
+            out.push_str(&format!("FILE {i} {file}\n"));
+        }
+        for (start, end, kind) in self.ranges() {
+            let kind = match kind {
+                WordKind::Code => "CODE",
+                WordKind::Data => "DATA",
+            };
+            out.push_str(&format!("{kind} x{start:04X} x{end:04X}\n"));
+        }
+        for w in &self.words {
+            out.push_str(&format!(
+                "x{:04X} {}:{}:{}",
+                w.address, w.file, w.line, w.col
+            ));
+            for e in &w.expansions {
+                out.push_str(&format!(" {} {}:{}", e.name, e.file, e.line));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Parse a file written by [`to_text`](Self::to_text).
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, l)| (i + 1, l.trim()))
+            .filter(|(_, l)| !l.is_empty() && !l.starts_with(';'));
+
+        match lines.next() {
+            Some((_, header)) if header.split_whitespace().next() == Some(MAGIC) => {
+                let version = header[MAGIC.len()..].trim();
+                if version != VERSION.to_string() {
+                    return Err(format!(
+                        "unsupported debug info version {version} (expected {VERSION})"
+                    ));
+                }
+            }
+            _ => return Err(format!("not a debug info file (missing {MAGIC} header)")),
+        }
+
+        let mut info = DebugInfo::default();
+        let mut ranges = Vec::new();
+        for (n, line) in lines {
+            let bad = |what: &str| format!("line {n}: {what} '{line}'");
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("FILE") => {
+                    let rest = line["FILE".len()..].trim_start();
+                    let (index, name) = rest
+                        .split_once(char::is_whitespace)
+                        .ok_or_else(|| bad("malformed FILE entry"))?;
+                    if index.parse::<usize>().ok() != Some(info.files.len()) {
+                        return Err(bad("out-of-order FILE entry"));
+                    }
+                    info.files.push(name.trim().to_string());
+                }
+                Some(kind @ ("CODE" | "DATA")) => {
+                    let kind = if kind == "CODE" {
+                        WordKind::Code
+                    } else {
+                        WordKind::Data
+                    };
+                    let start = fields.next().and_then(parse_hex);
+                    let end = fields.next().and_then(parse_hex);
+                    match (start, end) {
+                        (Some(start), Some(end)) if start <= end => {
+                            ranges.push((start, end, kind));
+                        }
+                        _ => return Err(bad("malformed range")),
+                    }
+                }
+                Some(addr) => {
+                    let address = parse_hex(addr).ok_or_else(|| bad("bad address in"))?;
+                    let [file, line_no, col] = fields
+                        .next()
+                        .and_then(parse_numbers::<3>)
+                        .ok_or_else(|| bad("bad location in"))?;
+                    let mut expansions = Vec::new();
+                    while let Some(name) = fields.next() {
+                        let [file, line] = fields
+                            .next()
+                            .and_then(parse_numbers::<2>)
+                            .ok_or_else(|| bad("bad macro call in"))?;
+                        expansions.push(Expansion {
+                            name: name.to_string(),
+                            file,
+                            line,
+                        });
+                    }
+                    let files = info.files.len();
+                    if file >= files || expansions.iter().any(|e| e.file >= files) {
+                        return Err(bad("unknown file in"));
+                    }
+                    let kind = ranges
+                        .iter()
+                        .find(|(start, end, _)| (*start..=*end).contains(&address))
+                        .map_or(WordKind::Code, |r| r.2);
+                    info.words.push(WordInfo {
+                        address,
+                        file,
+                        line: line_no,
+                        col,
+                        kind,
+                        expansions,
+                    });
+                }
+                None => {}
+            }
+        }
+        info.words.sort_by_key(|w| w.address);
+        info.words.dedup_by_key(|w| w.address);
+        Ok(info)
+    }
+
+    /// The index of `name` in `files`, adding it if needed.
+    fn file_index(&mut self, name: &str) -> usize {
+        if let Some(i) = self.files.iter().position(|f| f == name) {
+            return i;
+        }
+        self.files.push(name.to_string());
+        self.files.len() - 1
+    }
+}
+
+/// Parse `xNNNN`.
+fn parse_hex(s: &str) -> Option<u16> {
+    u16::from_str_radix(s.strip_prefix('x')?, 16).ok()
+}
+
+/// Parse `N` colon-separated decimal numbers.
+fn parse_numbers<const N: usize>(s: &str) -> Option<[usize; N]> {
+    let mut out = [0; N];
+    let mut parts = s.split(':');
+    for slot in &mut out {
+        *slot = parts.next()?.parse().ok()?;
+    }
+    parts.next().is_none().then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble_source, AssembleOptions};
+
+    const PROGRAM: &str = "\
+.MACRO INC2 %R
+        ADD %R, %R, #1
+        ADD %R, %R, #1
+.ENDM
+.ORIG x3000
+        LEA R0, MSG
+        INC2 R1
+        HALT
+MSG     .STRINGZ \"Hi\"
+        .BLKW 2
+.END
+";
+
+    fn info() -> DebugInfo {
+        let asm = assemble_source(PROGRAM, "prog.asm", &AssembleOptions::default());
+        assert!(!asm.has_errors());
+        DebugInfo::from_assembly(&asm)
+    }
+
+    #[test]
+    fn maps_every_word_to_its_line() {
+        let info = info();
+        assert_eq!(info.files, ["prog.asm"]);
+        assert_eq!(info.words.len(), 4 + 3 + 2);
+
+        let lea = info.word(0x3000).unwrap();
+        assert_eq!((lea.line, lea.col, lea.kind), (6, 9, WordKind::Code));
+        let add = info.word(0x3002).unwrap();
+        assert_eq!(add.line, 3);
+        assert_eq!(
+            add.expansions,
+            [Expansion {
+                name: "INC2".into(),
+                file: 0,
+                line: 7
+            }]
+        );
+        assert_eq!(info.word(0x3005).unwrap().line, 9);
+        assert_eq!(info.word(0x3009), None);
+    }
+
+    #[test]
+    fn separates_code_and_data_ranges() {
+        assert_eq!(
+            info().ranges(),
+            [
+                (0x3000, 0x3003, WordKind::Code),
+                (0x3004, 0x3008, WordKind::Data)
+            ]
+        );
+    }
+
+    #[test]
+    fn text_round_trips() {
+        let info = info();
+        let text = info.to_text("prog.obj");
+        assert!(text.contains("\nCODE x3000 x3003\nDATA x3004 x3008\n"));
+        assert!(text.contains("\nx3002 0:3:9 INC2 0:7\n"));
+        assert_eq!(DebugInfo::parse(&text).unwrap(), info);
+    }
+
+    #[test]
+    fn nested_macros_record_every_invocation() {
+        let source = "\
+.MACRO INC2 %R
+        ADD %R, %R, #1
+        ADD %R, %R, #1
+.ENDM
+.MACRO INC4 %R
+        INC2 %R
+        INC2 %R
+.ENDM
+.ORIG x3000
+        INC4 R0
+        HALT
+.END
+";
+        let asm = assemble_source(source, "prog.asm", &AssembleOptions::default());
+        assert!(!asm.has_errors());
+        let info = DebugInfo::from_assembly(&asm);
+        let calls = |addr| -> Vec<(String, usize)> {
+            let w = info.word(addr).unwrap();
+            w.expansions
+                .iter()
+                .map(|e| (e.name.clone(), e.line))
+                .collect()
+        };
+        assert_eq!(calls(0x3000), [("INC4".into(), 10), ("INC2".into(), 6)]);
+        assert_eq!(calls(0x3003), [("INC4".into(), 10), ("INC2".into(), 7)]);
+        assert!(calls(0x3004).is_empty());
+
+        let text = info.to_text("prog.obj");
+        assert!(
+            text.contains("\nx3003 0:3:9 INC4 0:10 INC2 0:7\n"),
+            "{text}"
+        );
+        assert_eq!(DebugInfo::parse(&text).unwrap(), info);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert!(DebugInfo::parse("FILE 0 a.asm\n").is_err());
+        assert!(DebugInfo::parse("LC3DBG 2\n").is_err());
+        assert!(DebugInfo::parse("LC3DBG 1\nx3000 0:1:1\n").is_err()); // no FILE 0
+        assert!(DebugInfo::parse("LC3DBG 1\nFILE 0 a.asm\nx3000 0:1\n").is_err());
+        assert!(DebugInfo::parse("LC3DBG 1\nFILE 0 a.asm\nCODE x3001 x3000\n").is_err());
+        assert!(DebugInfo::parse("LC3DBG 1\nFILE 0 a.asm\nx3000 0:1:1 M 0:2 N\n").is_err());
+    }
+}
@@ -1,8 +1,16 @@
 //! # Diagnostic Formatter
 //!
 //! Renders assembler errors and warnings in Rust-compiler-style output:
-//! bold labels, source-line context with column-pointer carets, and
+//! bold labels, source-line context with the offending span underlined,
+//! secondary spans (such as a label's first definition) marked with `-`, and
 //! optional ANSI colours (auto-detected from the terminal type).
+//! [`write_json`] writes the same diagnostics as JSON Lines for tools.
+//!
+//! Spans refer to the source after `.INCLUDE` and macro expansion; give the
+//! formatter a [`SourceMap`] (or call the `emit_*_at` methods with a resolved
+//! [`SourceLocation`]) to report them in the file the user wrote.  Lines that
+//! came from a macro body get a note naming the invocation, one per level
+//! for macros called from other macros, outermost first.
 //!
 //! ## Example output
 //!
@@ -11,18 +19,28 @@
 //!  --> program.asm:5:6
 //!   |
 //! 5 | TRAP x200
-//!   |      ^
+//!   |      ^^^^
 //!
-//! warning: label 'LOOP' is defined but never referenced
-//!  --> program.asm:3:1
+//! error: Duplicate label 'LOOP' (first defined at x3000)
+//!  --> program.asm:9:1
 //!   |
+//! 9 | LOOP  BRnzp LOOP
+//!   | ^^^^
 //! 3 | LOOP  ADD R1, R1, #-1
-//!   | ^
+//!   | ---- first defined here
+//!
+//! error: ADD immediate value 99 is out of 5-bit signed range (-16 to 15)
+//!  --> lib.asm:2:17
+//!   |
+//! 2 |     ADD %R, %R, #99
+//!   |                 ^^^
+//!   = note: in expansion of macro BUMP invoked at program.asm:7
 //! ```
 
-use crate::error::AsmError;
+use crate::error::{AsmError, Label, Span, Stage};
+use crate::source_map::{SourceLocation, SourceMap};
 use crate::warning::AsmWarning;
-use std::io::IsTerminal as _;
+use std::io::{self, IsTerminal as _, Write};
 
 // ── ANSI escape codes ────────────────────────────────────────────────────────
 
@@ -43,6 +61,7 @@ pub struct Diagnostics<'src> {
     lines: Vec<&'src str>,
     filename: String,
     color: bool,
+    map: Option<&'src SourceMap>,
 }
 
 impl<'src> Diagnostics<'src> {
@@ -55,9 +74,17 @@ impl<'src> Diagnostics<'src> {
             lines: source.lines().collect(),
             filename: filename.into(),
             color: std::io::stderr().is_terminal(),
+            map: None,
         }
     }
 
+    /// Resolve spans through `map` before printing them.
+    #[must_use]
+    pub fn with_source_map(mut self, map: &'src SourceMap) -> Self {
+        self.map = Some(map);
+        self
+    }
+
     /// Override automatic terminal-detection for colour output.
     #[must_use]
     pub fn with_color(mut self, on: bool) -> Self {
@@ -69,7 +96,12 @@ impl<'src> Diagnostics<'src> {
 
     /// Print a single error.
     pub fn emit_error(&self, err: &AsmError) {
-        self.emit(Level::Error, &err.message, err.span.line, err.span.col);
+        self.emit_error_at(err, &self.locate(err.span));
+    }
+
+    /// Print a single error at an already-resolved location.
+    pub fn emit_error_at(&self, err: &AsmError, loc: &SourceLocation) {
+        self.emit(Level::Error, &err.message, loc, &err.labels, None);
     }
 
     /// Print a slice of errors then a final summary.
@@ -100,7 +132,12 @@ impl<'src> Diagnostics<'src> {
 
     /// Print a single warning.
     pub fn emit_warning(&self, warn: &AsmWarning) {
-        self.emit(Level::Warning, &warn.message, warn.span.line, warn.span.col);
+        self.emit_warning_at(warn, &self.locate(warn.span));
+    }
+
+    /// Print a single warning at an already-resolved location.
+    pub fn emit_warning_at(&self, warn: &AsmWarning, loc: &SourceLocation) {
+        self.emit(Level::Warning, &warn.message, loc, &[], None);
     }
 
     /// Print all warnings, then a summary count.
@@ -109,18 +146,22 @@ impl<'src> Diagnostics<'src> {
             self.emit_warning(w);
         }
         if !warnings.is_empty() {
-            let n = warnings.len();
-            eprintln!(
-                "{yellow}warning{reset}{bold}: {n} warning{s} emitted{reset}",
-                yellow = self.c(YELLOW),
-                bold = self.c(BOLD),
-                reset = self.c(RESET),
-                s = if n == 1 { "" } else { "s" },
-            );
-            eprintln!();
+            self.emit_warning_summary(warnings.len());
         }
     }
 
+    /// Print the "N warning(s) emitted" banner.
+    pub(crate) fn emit_warning_summary(&self, n: usize) {
+        eprintln!(
+            "{yellow}warning{reset}{bold}: {n} warning{s} emitted{reset}",
+            yellow = self.c(YELLOW),
+            bold = self.c(BOLD),
+            reset = self.c(RESET),
+            s = if n == 1 { "" } else { "s" },
+        );
+        eprintln!();
+    }
+
     // ── Private ──────────────────────────────────────────────────────────────
 
     /// Return the ANSI code if colour is enabled, empty string otherwise.
@@ -133,7 +174,40 @@ impl<'src> Diagnostics<'src> {
         }
     }
 
-    fn emit(&self, level: Level, message: &str, line: usize, col: usize) {
+    /// Where `span` points in the files the user wrote.
+    fn locate(&self, span: Span) -> SourceLocation {
+        match self.map {
+            Some(map) => map.resolve(span),
+            None => SourceLocation {
+                file: self.filename.clone(),
+                line: span.line,
+                col: span.col,
+                width: span.width(),
+                expansions: Vec::new(),
+            },
+        }
+    }
+
+    /// The text of `line` in `file`, for the excerpt under the header.
+    fn source_line(&self, file: &str, line: usize) -> Option<&str> {
+        if let Some(text) = self.map.and_then(|map| map.line_text(file, line)) {
+            return Some(text);
+        }
+        if file == self.filename {
+            return line.checked_sub(1).and_then(|i| self.lines.get(i)).copied();
+        }
+        None
+    }
+
+    /// Print one diagnostic, ending with a `= hint:` line when `hint` is given.
+    fn emit(
+        &self,
+        level: Level,
+        message: &str,
+        loc: &SourceLocation,
+        labels: &[Label],
+        hint: Option<&str>,
+    ) {
         let (label, color) = match level {
             Level::Error => ("error", self.c(RED)),
             Level::Warning => ("warning", self.c(YELLOW)),
@@ -150,19 +224,26 @@ impl<'src> Diagnostics<'src> {
 
         // ──  --> file:line:col ────────────────────────────────────────────
         eprintln!(
-            " {dim}-->{reset} {file}:{line}:{col}",
+            " {dim}-->{reset} {loc}",
             dim = self.c(DIM),
             reset = self.c(RESET),
-            file = self.filename,
         );
 
         // ── source excerpt ────────────────────────────────────────────────
-        if line == 0 {
+        if loc.line == 0 {
+            self.emit_hint(hint);
             eprintln!();
             return;
         }
 
-        let gutter_w = digits(line);
+        let secondary: Vec<(SourceLocation, &str)> = labels
+            .iter()
+            .map(|l| (self.locate(l.span), l.message.as_str()))
+            .collect();
+        let gutter_w = secondary
+            .iter()
+            .map(|(l, _)| digits(l.line))
+            .fold(digits(loc.line), usize::max);
         let pad = " ".repeat(gutter_w);
 
         // blank gutter line before source
@@ -172,27 +253,80 @@ impl<'src> Diagnostics<'src> {
             reset = self.c(RESET),
         );
 
-        // the source line itself (lines are 1-indexed)
-        if let Some(&src) = self.lines.get(line - 1) {
+        self.excerpt(loc, gutter_w, '^', color, None);
+        for (at, note) in &secondary {
+            if at.file != loc.file {
+                eprintln!(
+                    " {dim}{pad}:::{reset} {at}",
+                    dim = self.c(DIM),
+                    reset = self.c(RESET),
+                );
+            }
+            self.excerpt(at, gutter_w, '-', self.c(CYAN), Some(note));
+        }
+
+        for exp in &loc.expansions {
             eprintln!(
-                " {dim}{line:>gutter_w$} |{reset} {src}",
-                dim = self.c(DIM),
+                "  {cyan}={reset} note: in expansion of macro {name} invoked at {file}:{line}",
+                cyan = self.c(CYAN),
                 reset = self.c(RESET),
+                name = exp.name,
+                file = exp.file,
+                line = exp.line,
             );
+        }
 
-            // caret row: indent by (col - 1) spaces, then ^
-            let indent = col.saturating_sub(1);
+        self.emit_hint(hint);
+        eprintln!();
+    }
+
+    fn emit_hint(&self, hint: Option<&str>) {
+        if let Some(hint) = hint {
             eprintln!(
-                " {dim}{pad} |{reset} {spaces}{color}{bold}^{reset}",
-                dim = self.c(DIM),
+                "  {cyan}={reset} hint: {hint}",
+                cyan = self.c(CYAN),
                 reset = self.c(RESET),
-                color = color,
-                bold = self.c(BOLD),
-                spaces = " ".repeat(indent),
             );
         }
+    }
 
-        eprintln!();
+    /// Print `at`'s source line and underline the span with `marker`,
+    /// followed by `note` if given.  Nothing is printed for unknown lines.
+    fn excerpt(
+        &self,
+        at: &SourceLocation,
+        gutter_w: usize,
+        marker: char,
+        color: &str,
+        note: Option<&str>,
+    ) {
+        let Some(src) = self.source_line(&at.file, at.line) else {
+            return;
+        };
+        eprintln!(
+            " {dim}{line:>gutter_w$} |{reset} {src}",
+            dim = self.c(DIM),
+            reset = self.c(RESET),
+            line = at.line,
+        );
+
+        // underline row: indent by (col - 1) spaces, then one marker per
+        // character of the span (kept within the line)
+        let indent = at.col.saturating_sub(1);
+        let width = at
+            .width
+            .min(src.chars().count().saturating_sub(indent))
+            .max(1);
+        eprintln!(
+            " {dim}{pad} |{reset} {spaces}{color}{bold}{underline}{note}{reset}",
+            dim = self.c(DIM),
+            reset = self.c(RESET),
+            bold = self.c(BOLD),
+            pad = " ".repeat(gutter_w),
+            spaces = " ".repeat(indent),
+            underline = marker.to_string().repeat(width),
+            note = note.map(|n| format!(" {n}")).unwrap_or_default(),
+        );
     }
 }
 
@@ -223,38 +357,35 @@ use crate::error::ErrorKind;
 /// Return a short suggestion string for a known error kind, if one exists.
 pub fn suggestion_for(kind: &ErrorKind) -> Option<&'static str> {
     match kind {
-        ErrorKind::TooFewOperands => Some("hint: check the instruction's required operand count"),
-        ErrorKind::TooManyOperands => Some("hint: remove the extra operand"),
+        ErrorKind::TooFewOperands => Some("check the instruction's required operand count"),
+        ErrorKind::TooManyOperands => Some("remove the extra operand"),
         ErrorKind::ExpectedComma => {
-            Some("hint: LC-3 operands are separated by commas (e.g. ADD R0, R1, R2)")
+            Some("LC-3 operands are separated by commas (e.g. ADD R0, R1, R2)")
         }
-        ErrorKind::ExpectedRegister => Some("hint: registers are R0–R7 (e.g. R0, R3)"),
+        ErrorKind::ExpectedRegister => Some("registers are R0–R7 (e.g. R0, R3)"),
         ErrorKind::InvalidHexLiteral => {
-            Some("hint: hex literals use the 'x' prefix without '0x' (e.g. x3000)")
+            Some("hex literals use the 'x' prefix without '0x' (e.g. x3000)")
         }
         ErrorKind::InvalidDecimalLiteral => {
-            Some("hint: decimal literals require a '#' prefix (e.g. #10 or #-5)")
-        }
-        ErrorKind::InvalidBinaryLiteral => {
-            Some("hint: binary literals use the 'b' prefix (e.g. b1010)")
+            Some("decimal literals require a '#' prefix (e.g. #10 or #-5)")
         }
-        ErrorKind::InvalidOctalLiteral => {
-            Some("hint: octal literals use the '0o' prefix (e.g. 0o777)")
+        ErrorKind::InvalidBinaryLiteral => Some("binary literals use the 'b' prefix (e.g. b1010)"),
+        ErrorKind::InvalidOctalLiteral => Some("octal literals use the '0o' prefix (e.g. 0o777)"),
+        ErrorKind::InvalidExpression => {
+            Some("expressions use + - * << >> & | and parentheses (e.g. TABLE+3)")
         }
-        ErrorKind::UndefinedLabel => Some("hint: check spelling — labels are case-insensitive"),
+        ErrorKind::UndefinedLabel => Some("check spelling — labels are case-insensitive"),
         ErrorKind::MissingOrig => {
-            Some("hint: every LC-3 program must begin with .ORIG (e.g. .ORIG x3000)")
+            Some("every LC-3 program must begin with .ORIG (e.g. .ORIG x3000)")
         }
-        ErrorKind::MissingEnd => Some("hint: every LC-3 program must end with .END"),
+        ErrorKind::MissingEnd => Some("every LC-3 program must end with .END"),
         ErrorKind::OffsetOutOfRange => {
-            Some("hint: move the label closer, or use indirect addressing (LDI/STI)")
+            Some("move the label closer, or use indirect addressing (LDI/STI)")
         }
-        ErrorKind::NonAsciiInStringz => {
-            Some("hint: LC-3 strings are ASCII only (code points 0x00–0x7F)")
+        ErrorKind::NonAsciiInStringz => Some("LC-3 strings are ASCII only (code points 0x00–0x7F)"),
+        ErrorKind::LabelIsReservedWord => {
+            Some("rename the label or use a dotted form (e.g. .FILL instead of FILL as a label)")
         }
-        ErrorKind::LabelIsReservedWord => Some(
-            "hint: rename the label or use a dotted form (e.g. .FILL instead of FILL as a label)",
-        ),
         _ => None,
     }
 }
@@ -274,16 +405,24 @@ impl<'src> RichDiagnostics<'src> {
         self
     }
 
+    #[must_use]
+    pub fn with_source_map(mut self, map: &'src SourceMap) -> Self {
+        self.0 = self.0.with_source_map(map);
+        self
+    }
+
     pub fn emit_error(&self, err: &AsmError) {
-        self.0.emit_error(err);
-        if let Some(hint) = suggestion_for(&err.kind) {
-            eprintln!(
-                "  {cyan}={reset} {hint}",
-                cyan = self.0.c(CYAN),
-                reset = self.0.c(RESET),
-            );
-            eprintln!();
-        }
+        self.emit_error_at(err, &self.0.locate(err.span));
+    }
+
+    pub fn emit_error_at(&self, err: &AsmError, loc: &SourceLocation) {
+        self.0.emit(
+            Level::Error,
+            &err.message,
+            loc,
+            &err.labels,
+            suggestion_for(&err.kind),
+        );
     }
 
     pub fn emit_all_errors(&self, errors: &[&AsmError]) {
@@ -299,7 +438,189 @@ impl<'src> RichDiagnostics<'src> {
         self.0.emit_warning(warn);
     }
 
+    pub fn emit_warning_at(&self, warn: &AsmWarning, loc: &SourceLocation) {
+        self.0.emit_warning_at(warn, loc);
+    }
+
     pub fn emit_all_warnings(&self, warnings: &[AsmWarning]) {
         self.0.emit_all_warnings(warnings);
     }
 }
+
+// ── Machine-readable output ───────────────────────────────────────────────────
+// One JSON object per line, for autograders and editor plugins:
+//
+//   {"severity":"error","kind":"UndefinedLabel","code":"E026","message":"…",
+//    "file":"prog.asm","line":5,"column":4,"suggestion":"check …","stage":"encode",
+//    "expansion":{"macro":"PUSH","file":"prog.asm","line":9},
+//    "expansions":[{"macro":"PUSH","file":"prog.asm","line":9}]}
+//
+// `suggestion` and `expansion` are null when there is none.  `expansion` is
+// the innermost macro invocation; `expansions` lists every enclosing one,
+// outermost first.  Field names, `kind` and `code` values are stable.
+
+/// Render one error as a single-line JSON object.
+#[must_use]
+pub fn error_to_json(err: &AsmError, stage: Stage, loc: &SourceLocation) -> String {
+    json_object(
+        "error",
+        &format!("{:?}", err.kind),
+        err.kind.code(),
+        &err.message,
+        loc,
+        suggestion_for(&err.kind),
+        stage,
+    )
+}
+
+/// Render one warning as a single-line JSON object.
+#[must_use]
+pub fn warning_to_json(warn: &AsmWarning, stage: Stage, loc: &SourceLocation) -> String {
+    json_object(
+        "warning",
+        &format!("{:?}", warn.kind),
+        warn.kind.code(),
+        &warn.message,
+        loc,
+        None,
+        stage,
+    )
+}
+
+/// Write every warning, then every error, as JSON Lines.
+pub fn write_json(
+    out: &mut impl Write,
+    warnings: &[(Stage, &SourceLocation, &AsmWarning)],
+    errors: &[(Stage, &SourceLocation, &AsmError)],
+) -> io::Result<()> {
+    for (stage, loc, w) in warnings {
+        writeln!(out, "{}", warning_to_json(w, *stage, loc))?;
+    }
+    for (stage, loc, e) in errors {
+        writeln!(out, "{}", error_to_json(e, *stage, loc))?;
+    }
+    Ok(())
+}
+
+fn json_object(
+    severity: &str,
+    kind: &str,
+    code: &str,
+    message: &str,
+    loc: &SourceLocation,
+    suggestion: Option<&str>,
+    stage: Stage,
+) -> String {
+    let expansions: Vec<String> = loc
+        .expansions
+        .iter()
+        .map(|exp| {
+            format!(
+                "{{\"macro\":{},\"file\":{},\"line\":{}}}",
+                json_string(&exp.name),
+                json_string(&exp.file),
+                exp.line,
+            )
+        })
+        .collect();
+    let expansion = expansions.last().map_or("null", String::as_str);
+    format!(
+        "{{\"severity\":{},\"kind\":{},\"code\":{},\"message\":{},\"file\":{},\"line\":{},\"column\":{},\"suggestion\":{},\"stage\":{},\"expansion\":{},\"expansions\":[{}]}}",
+        json_string(severity),
+        json_string(kind),
+        json_string(code),
+        json_string(message),
+        json_string(&loc.file),
+        loc.line,
+        loc.col,
+        suggestion.map_or_else(|| "null".to_string(), json_string),
+        json_string(stage.as_str()),
+        expansion,
+        expansions.join(","),
+    )
+}
+
+/// Quote and escape `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_map::MacroExpansion;
+
+    fn at(file: &str, line: usize, col: usize) -> SourceLocation {
+        SourceLocation {
+            file: file.to_string(),
+            line,
+            col,
+            width: 1,
+            expansions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn error_json_has_every_field() {
+        let err = AsmError::undefined_label("LOOP", Span::point(3, 7));
+        let mut loc = at("dir\\prog \"1\".asm", 3, 7);
+        loc.expansions = vec![
+            MacroExpansion {
+                name: "OUTER".to_string(),
+                file: "main.asm".to_string(),
+                line: 20,
+            },
+            MacroExpansion {
+                name: "JUMP".to_string(),
+                file: "main.asm".to_string(),
+                line: 12,
+            },
+        ];
+        let json = error_to_json(&err, Stage::Encode, &loc);
+        assert_eq!(
+            json,
+            format!(
+                "{{\"severity\":\"error\",\"kind\":\"UndefinedLabel\",\"code\":\"E026\",\"message\":{},\"file\":\"dir\\\\prog \\\"1\\\".asm\",\"line\":3,\"column\":7,\"suggestion\":{},\"stage\":\"encode\",\"expansion\":{{\"macro\":\"JUMP\",\"file\":\"main.asm\",\"line\":12}},\"expansions\":[{{\"macro\":\"OUTER\",\"file\":\"main.asm\",\"line\":20}},{{\"macro\":\"JUMP\",\"file\":\"main.asm\",\"line\":12}}]}}",
+                json_string(&err.message),
+                json_string("check spelling — labels are case-insensitive"),
+            )
+        );
+    }
+
+    #[test]
+    fn warning_json_has_null_suggestion() {
+        let warn = AsmWarning::unused_label("X", Span::point(1, 1));
+        let json = warning_to_json(&warn, Stage::FirstPass, &at("a.asm", 1, 1));
+        assert!(
+            json.starts_with(
+                "{\"severity\":\"warning\",\"kind\":\"UnusedLabel\",\"code\":\"W001\""
+            ),
+            "{json}"
+        );
+        assert!(
+            json.ends_with(
+                "\"suggestion\":null,\"stage\":\"first-pass\",\"expansion\":null,\"expansions\":[]}"
+            ),
+            "{json}"
+        );
+    }
+
+    #[test]
+    fn control_characters_are_escaped() {
+        assert_eq!(json_string("a\nb\u{1}"), "\"a\\nb\\u0001\"");
+    }
+}
@@ -0,0 +1,506 @@
+//! # Disassembler
+//!
+//! Turns LC-3 machine code back into assembly text.
+//!
+//! - [`disassemble`] formats a single word for display (used by the
+//!   simulator's memory panel).
+//! - [`disassemble_program`] rebuilds a complete, re-assemblable source file
+//!   from object segments: `.ORIG`/`.END` per segment, a label at every
+//!   branch/load target, `.STRINGZ` for NUL-terminated ASCII runs, `.BLKW`
+//!   for runs of zero words and `.FILL` for anything that does not decode to
+//!   a canonical instruction.
+//!
+//! Re-assembling the output of [`disassemble_program`] produces exactly the
+//! words it was given.  Without source, code and data are told apart by
+//! guesswork; [`disassemble_program_with`] takes the assembler's
+//! [`DebugInfo`] instead, so data words are never shown as instructions.
+//!
+//! ```text
+//!         .ORIG x3000
+//!         LEA R0, L_3003
+//!         PUTS
+//!         HALT
+//! L_3003  .STRINGZ "Hi"
+//!         .END
+//! ```
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::debug_info::{DebugInfo, WordKind};
+use crate::encoder::Segment;
+
+/// Shortest ASCII run (excluding the terminator) emitted as `.STRINGZ`.
+const MIN_STRING_LEN: usize = 2;
+
+/// Shortest run of zero words emitted as `.BLKW` rather than `.FILL`.
+const MIN_BLKW_LEN: usize = 2;
+
+/// Disassemble one 16-bit word at `addr` into a human-readable string.
+///
+/// `addr` is the word address of `word` in memory.  PC-relative targets are
+/// computed as `addr + 1 + offset` (matching LC-3 execution semantics).
+/// If `syms` is provided, target addresses are replaced with label names.
+pub fn disassemble(word: u16, addr: u16, syms: Option<&HashMap<u16, String>>) -> String {
+    decode(word, addr, &|target, _| resolve(target, syms))
+}
+
+/// Rebuild assembly source for `segments`.
+///
+/// `syms` maps addresses to label names (e.g. from a `.sym` file); every
+/// other branch/load target inside a segment gets a generated `L_XXXX`
+/// label.  Targets outside every segment are written as numeric offsets.
+#[must_use]
+pub fn disassemble_program(segments: &[Segment], syms: &HashMap<u16, String>) -> String {
+    disassemble_program_with(segments, syms, None)
+}
+
+/// [`disassemble_program`], using `debug` to tell code from data.
+///
+/// Words `debug` marks as code are always decoded as instructions; words
+/// marked as data become `.STRINGZ`, `.BLKW` or `.FILL` and never produce
+/// branch-target labels.  Words it does not cover are guessed at as usual.
+#[must_use]
+pub fn disassemble_program_with(
+    segments: &[Segment],
+    syms: &HashMap<u16, String>,
+    debug: Option<&DebugInfo>,
+) -> String {
+    let kind = |addr: u16| debug.and_then(|d| d.kind(addr));
+    let labels = collect_labels(segments, syms, &kind);
+    let width = labels
+        .values()
+        .map(|l| l.len() + 1)
+        .max()
+        .unwrap_or(0)
+        .max(8);
+
+    let mut out = String::new();
+    let mut push = |label: Option<&String>, text: &str| {
+        let label = label.map_or("", String::as_str);
+        out.push_str(&format!("{label:width$}{text}\n"));
+    };
+
+    for seg in segments {
+        push(None, &format!(".ORIG x{:04X}", seg.origin));
+        let mut i = 0;
+        while i < seg.words.len() {
+            let addr = seg.origin.wrapping_add(i as u16);
+            let label = labels.get(&addr);
+            let word = seg.words[i];
+            let this = kind(addr);
+            // A data run stays within words of the same kind.
+            let end = (i..seg.words.len())
+                .find(|&j| kind(seg.origin.wrapping_add(j as u16)) != this)
+                .unwrap_or(seg.words.len());
+            let (text, used) = match this {
+                Some(WordKind::Code) => (decode_canonical(word, addr, &labels), 1),
+                Some(WordKind::Data) => data_run(seg, i, end, &labels)
+                    .unwrap_or_else(|| (format!(".FILL x{word:04X}"), 1)),
+                None => data_run(seg, i, end, &labels)
+                    .unwrap_or_else(|| (decode_canonical(word, addr, &labels), 1)),
+            };
+            push(label, &text);
+            i += used;
+        }
+        push(None, ".END");
+    }
+    out
+}
+
+/// Parse a `.sym` file in the format `LABEL=xADDR` into an address→label map.
+///
+/// Comment lines (`;`) and entries whose value is not a hex address (such as
+/// `.EQU` constants written as `NAME=#value`) are ignored.
+#[must_use]
+pub fn parse_sym_file(text: &str) -> HashMap<u16, String> {
+    let mut map = HashMap::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with(';') || line.is_empty() {
+            continue;
+        }
+        if let Some((label, addr_str)) = line.split_once('=') {
+            let addr_str = addr_str.trim().trim_start_matches('x');
+            if let Ok(addr) = u16::from_str_radix(addr_str, 16) {
+                map.insert(addr, label.trim().to_string());
+            }
+        }
+    }
+    map
+}
+
+// ── Whole-program helpers ─────────────────────────────────────────────────────
+
+/// Label every address that is named in `syms` or targeted by a PC-relative
+/// instruction, as long as it lies inside one of the segments.  Words `kind`
+/// marks as data are not instructions, so they target nothing.
+fn collect_labels(
+    segments: &[Segment],
+    syms: &HashMap<u16, String>,
+    kind: &dyn Fn(u16) -> Option<WordKind>,
+) -> HashMap<u16, String> {
+    let contains = |addr: u16| {
+        segments
+            .iter()
+            .any(|s| addr >= s.origin && usize::from(addr - s.origin) < s.words.len())
+    };
+
+    let mut targets = BTreeSet::new();
+    for seg in segments {
+        for (i, &word) in seg.words.iter().enumerate() {
+            let addr = seg.origin.wrapping_add(i as u16);
+            if kind(addr) == Some(WordKind::Data) {
+                continue;
+            }
+            if let Some(target) = pc_target(word, addr) {
+                if is_canonical(word) && contains(target) {
+                    targets.insert(target);
+                }
+            }
+        }
+    }
+
+    let mut labels: HashMap<u16, String> = syms
+        .iter()
+        .filter(|(addr, _)| contains(**addr))
+        .map(|(addr, name)| (*addr, name.clone()))
+        .collect();
+    for target in targets {
+        labels
+            .entry(target)
+            .or_insert_with(|| format!("L_{target:04X}"));
+    }
+    labels
+}
+
+/// The address a PC-relative instruction refers to, if `word` is one.
+fn pc_target(word: u16, addr: u16) -> Option<u16> {
+    let pc = addr.wrapping_add(1);
+    match word >> 12 {
+        0b0000 | 0b0010 | 0b0011 | 0b1010 | 0b1011 | 0b1110 => {
+            Some(pc.wrapping_add(sext(word & 0x1FF, 9)))
+        }
+        0b0100 if (word >> 11) & 1 == 1 => Some(pc.wrapping_add(sext(word & 0x7FF, 11))),
+        _ => None,
+    }
+}
+
+/// True when the assembler would produce exactly `word` for the instruction
+/// [`disassemble`] prints — i.e. the opcode is defined and every unused
+/// field is zero (or all ones, for `NOT`).
+fn is_canonical(word: u16) -> bool {
+    match word >> 12 {
+        // ADD / AND register mode: bits [4:3] unused
+        0b0001 | 0b0101 => (word >> 5) & 1 == 1 || word & 0x0018 == 0,
+        // NOT: bits [5:0] must be all ones
+        0b1001 => word & 0x003F == 0x003F,
+        // BR with no condition flags is a no-op the assembler cannot write
+        0b0000 => word & 0x0E00 != 0,
+        // JMP / RET
+        0b1100 => word & 0x0E3F == 0,
+        // JSR is fully used; JSRR leaves bits [10:9] and [5:0] unused
+        0b0100 => (word >> 11) & 1 == 1 || word & 0x063F == 0,
+        // RTI
+        0b1000 => word & 0x0FFF == 0,
+        // TRAP: bits [11:8] unused
+        0b1111 => word & 0x0F00 == 0,
+        // Reserved opcode
+        0b1101 => false,
+        // LD, LDI, LDR, LEA, ST, STI, STR use every bit
+        _ => true,
+    }
+}
+
+/// Format one word as an instruction whose PC-relative operands use
+/// `labels`, falling back to `.FILL` if it cannot be re-assembled as is.
+fn decode_canonical(word: u16, addr: u16, labels: &HashMap<u16, String>) -> String {
+    if !is_canonical(word) {
+        return format!(".FILL x{word:04X}");
+    }
+    decode(word, addr, &|target, offset| {
+        labels
+            .get(&target)
+            .cloned()
+            .unwrap_or_else(|| format!("#{offset}"))
+    })
+}
+
+/// Recognise a `.STRINGZ` or `.BLKW` run starting at index `start` and
+/// ending before index `limit`.
+///
+/// A run never swallows a labelled address other than its first, so every
+/// label still has a line to sit on.  Returns the directive text and the
+/// number of words it covers.
+fn data_run(
+    seg: &Segment,
+    start: usize,
+    limit: usize,
+    labels: &HashMap<u16, String>,
+) -> Option<(String, usize)> {
+    let unlabelled = |i: usize| !labels.contains_key(&seg.origin.wrapping_add(i as u16));
+    let words = &seg.words[..limit];
+
+    // .STRINGZ: printable characters followed by a NUL terminator.
+    let mut end = start;
+    while end < words.len()
+        && string_char(words[end]).is_some()
+        && (end == start || unlabelled(end))
+    {
+        end += 1;
+    }
+    if end - start >= MIN_STRING_LEN && words.get(end) == Some(&0) && unlabelled(end) {
+        let text: String = words[start..end]
+            .iter()
+            .filter_map(|&w| string_char(w))
+            .collect();
+        return Some((format!(".STRINGZ \"{text}\""), end - start + 1));
+    }
+
+    // .BLKW: a run of zero words.
+    let mut end = start;
+    while end < words.len() && words[end] == 0 && (end == start || unlabelled(end)) {
+        end += 1;
+    }
+    let count = end - start;
+    (count >= MIN_BLKW_LEN).then(|| (format!(".BLKW #{count}"), count))
+}
+
+/// The `.STRINGZ` spelling of `word`, if it is a printable ASCII character
+/// or one of the escapes the lexer understands.
+fn string_char(word: u16) -> Option<String> {
+    let s = match word {
+        0x0A => "\\n".to_string(),
+        0x0D => "\\r".to_string(),
+        0x09 => "\\t".to_string(),
+        0x22 => "\\\"".to_string(),
+        0x5C => "\\\\".to_string(),
+        0x20..=0x7E => char::from(word as u8).to_string(),
+        _ => return None,
+    };
+    Some(s)
+}
+
+// ── Single-word decoding ──────────────────────────────────────────────────────
+
+/// Decode `word`, formatting PC-relative operands with `target(address,
+/// offset)`.
+fn decode(word: u16, addr: u16, target: &dyn Fn(u16, i32) -> String) -> String {
+    // PC seen during execution of this instruction = addr + 1.
+    let pc = addr.wrapping_add(1);
+    let pc_operand = |bits: u8| {
+        let offset = sext_signed(word & ((1 << bits) - 1), bits);
+        target(pc.wrapping_add(offset as u16), offset)
+    };
+
+    match word >> 12 {
+        // ADD
+        0b0001 => {
+            let dr = (word >> 9) & 7;
+            let sr1 = (word >> 6) & 7;
+            if (word >> 5) & 1 == 0 {
+                format!("ADD R{dr}, R{sr1}, R{}", word & 7)
+            } else {
+                format!("ADD R{dr}, R{sr1}, #{}", sext_signed(word & 0x1F, 5))
+            }
+        }
+        // AND
+        0b0101 => {
+            let dr = (word >> 9) & 7;
+            let sr1 = (word >> 6) & 7;
+            if (word >> 5) & 1 == 0 {
+                format!("AND R{dr}, R{sr1}, R{}", word & 7)
+            } else {
+                format!("AND R{dr}, R{sr1}, #{}", sext_signed(word & 0x1F, 5))
+            }
+        }
+        // NOT
+        0b1001 => {
+            let dr = (word >> 9) & 7;
+            let sr = (word >> 6) & 7;
+            format!("NOT R{dr}, R{sr}")
+        }
+        // LD, LDI, LEA, ST, STI: register + PC offset9
+        0b0010 => format!("LD R{}, {}", (word >> 9) & 7, pc_operand(9)),
+        0b1010 => format!("LDI R{}, {}", (word >> 9) & 7, pc_operand(9)),
+        0b1110 => format!("LEA R{}, {}", (word >> 9) & 7, pc_operand(9)),
+        0b0011 => format!("ST R{}, {}", (word >> 9) & 7, pc_operand(9)),
+        0b1011 => format!("STI R{}, {}", (word >> 9) & 7, pc_operand(9)),
+        // LDR
+        0b0110 => {
+            let dr = (word >> 9) & 7;
+            let br = (word >> 6) & 7;
+            format!("LDR R{dr}, R{br}, #{}", sext_signed(word & 0x3F, 6))
+        }
+        // STR
+        0b0111 => {
+            let sr = (word >> 9) & 7;
+            let br = (word >> 6) & 7;
+            format!("STR R{sr}, R{br}, #{}", sext_signed(word & 0x3F, 6))
+        }
+        // BR
+        0b0000 => {
+            let n = (word >> 11) & 1 != 0;
+            let z = (word >> 10) & 1 != 0;
+            let p = (word >> 9) & 1 != 0;
+            if !n && !z && !p {
+                "NOP".into()
+            } else if n && z && p {
+                format!("BR {}", pc_operand(9))
+            } else {
+                let mut m = String::from("BR");
+                if n {
+                    m.push('n');
+                }
+                if z {
+                    m.push('z');
+                }
+                if p {
+                    m.push('p');
+                }
+                format!("{m} {}", pc_operand(9))
+            }
+        }
+        // JMP / RET
+        0b1100 => {
+            let base = (word >> 6) & 7;
+            if base == 7 {
+                "RET".into()
+            } else {
+                format!("JMP R{base}")
+            }
+        }
+        // JSR / JSRR
+        0b0100 => {
+            if (word >> 11) & 1 == 1 {
+                format!("JSR {}", pc_operand(11))
+            } else {
+                format!("JSRR R{}", (word >> 6) & 7)
+            }
+        }
+        // RTI
+        0b1000 => "RTI".into(),
+        // TRAP
+        0b1111 => match word & 0xFF {
+            0x20 => "GETC".into(),
+            0x21 => "OUT".into(),
+            0x22 => "PUTS".into(),
+            0x23 => "IN".into(),
+            0x24 => "PUTSP".into(),
+            0x25 => "HALT".into(),
+            v => format!("TRAP x{v:02X}"),
+        },
+        // Data / unknown opcode
+        _ => format!(".FILL x{word:04X}"),
+    }
+}
+
+/// Sign-extend `value` from `bits` wide to 16 bits.
+fn sext(value: u16, bits: u8) -> u16 {
+    let shift = 16u8 - bits;
+    (((value as i16) << shift) >> shift) as u16
+}
+
+/// Sign-extended value as a signed i32 (for display with `#` prefix).
+fn sext_signed(value: u16, bits: u8) -> i32 {
+    sext(value, bits) as i16 as i32
+}
+
+/// Resolve an address to a label name or `xADDR` fallback.
+fn resolve(addr: u16, syms: Option<&HashMap<u16, String>>) -> String {
+    syms.and_then(|m| m.get(&addr))
+        .cloned()
+        .unwrap_or_else(|| format!("x{addr:04X}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seg(origin: u16, words: &[u16]) -> Segment {
+        Segment {
+            origin,
+            words: words.to_vec(),
+        }
+    }
+
+    #[test]
+    fn single_word_uses_symbols() {
+        let syms = HashMap::from([(0x3000, "LOOP".to_string())]);
+        assert_eq!(disassemble(0x0FFF, 0x3000, Some(&syms)), "BR LOOP");
+        assert_eq!(disassemble(0x0FFF, 0x3000, None), "BR x3000");
+        assert_eq!(disassemble(0x1021, 0x3000, None), "ADD R0, R0, #1");
+    }
+
+    #[test]
+    fn program_labels_targets() {
+        // LEA R0, +2 ; PUTS ; HALT ; "Hi"
+        let out = disassemble_program(
+            &[seg(0x3000, &[0xE002, 0xF022, 0xF025, 0x48, 0x69, 0])],
+            &HashMap::new(),
+        );
+        assert!(out.contains("LEA R0, L_3003"), "{out}");
+        assert!(out.contains("L_3003  .STRINGZ \"Hi\""), "{out}");
+        assert!(out.trim_end().ends_with(".END"));
+    }
+
+    #[test]
+    fn program_prefers_sym_names() {
+        let syms = HashMap::from([(0x3000, "START".to_string())]);
+        let out = disassemble_program(&[seg(0x3000, &[0x0FFF])], &syms);
+        assert!(out.contains("START   BR START"), "{out}");
+    }
+
+    #[test]
+    fn out_of_segment_targets_are_offsets() {
+        let out = disassemble_program(&[seg(0x3000, &[0x2005])], &HashMap::new());
+        assert!(out.contains("LD R0, #5"), "{out}");
+    }
+
+    #[test]
+    fn non_canonical_words_are_fill() {
+        // NOP, ADD with stray bits, reserved opcode, TRAP with stray bits
+        for word in [0x0000_u16, 0x1018, 0xD000, 0xF125] {
+            assert!(!is_canonical(word), "{word:04X}");
+        }
+        let out = disassemble_program(&[seg(0x3000, &[0x1018])], &HashMap::new());
+        assert!(out.contains(".FILL x1018"), "{out}");
+    }
+
+    #[test]
+    fn debug_info_keeps_data_out_of_instructions() {
+        use crate::{assemble_source, AssembleOptions};
+
+        let source = ".ORIG x3000\nLD R0, K\nHALT\nK .FILL x1021\n.FILL x0FFE\n.END\n";
+        let asm = assemble_source(source, "k.asm", &AssembleOptions::default());
+        let debug = DebugInfo::from_assembly(&asm);
+        let guessed = disassemble_program(asm.segments(), &HashMap::new());
+        assert!(guessed.contains("ADD R0, R0, #1"), "{guessed}");
+        assert!(guessed.contains("BR L_3002"), "{guessed}");
+
+        let out = disassemble_program_with(asm.segments(), &HashMap::new(), Some(&debug));
+        assert!(out.contains("L_3002  .FILL x1021"), "{out}");
+        assert!(out.contains("        .FILL x0FFE"), "{out}");
+        assert!(!out.contains("ADD") && !out.contains("BR"), "{out}");
+    }
+
+    #[test]
+    fn zero_runs_become_blkw() {
+        let out = disassemble_program(&[seg(0x3000, &[0xF025, 0, 0, 0])], &HashMap::new());
+        assert!(out.contains(".BLKW #3"), "{out}");
+    }
+
+    #[test]
+    fn runs_stop_at_labels() {
+        // BR to the second zero word splits the run.
+        let out = disassemble_program(&[seg(0x3000, &[0x0E01, 0, 0, 0])], &HashMap::new());
+        assert!(out.contains(".FILL x0000"), "{out}");
+        assert!(out.contains("L_3002  .BLKW #2"), "{out}");
+    }
+
+    #[test]
+    fn parse_sym_file_skips_constants() {
+        let map = parse_sym_file("; header\nLOOP=x3002\nSIZE=#10\n");
+        assert_eq!(map.len(), 1);
+        assert_eq!(map[&0x3002], "LOOP");
+    }
+}
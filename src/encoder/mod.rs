@@ -12,15 +12,44 @@
 //!
 //! ## Directives
 //!
-//! - **.ORIG** - Set origin address
-//! - **.FILL** - Fill one word with value or label address
+//! - **.ORIG** - Set origin address (starts a new segment)
+//! - **.FILL** - Fill one word with a value, label address or expression
 //! - **.BLKW** - Allocate block of words
 //! - **.STRINGZ** - Store null-terminated string
-//! - **.END** - End of program
+//! - **.END** - End of the current section
+//!
+//! ## Segments
+//!
+//! Every `.ORIG`/`.END` section becomes one [`Segment`] in
+//! [`EncodeResult::segments`]. Lines between an `.END` and the next `.ORIG`
+//! are ignored, matching the first pass.
+//!
+//! ## Operand Expressions
+//!
+//! Immediates, offsets, trap vectors, `.FILL` values and PC-relative targets
+//! arrive as [`Expr`] trees and are evaluated here against the complete symbol
+//! table. Range checks (imm5, offset6, trapvect8, 16-bit words, PC offsets)
+//! apply to the evaluated result; signed fields first read x8000–xFFFF as
+//! 16-bit two's complement, so `ADD R0, R0, xFFFF` is `#-1`.
+//!
+//! ## Relocations
+//!
+//! A `.FILL` value or PC-relative target written as `SYM`, `SYM+n` or `SYM-n`
+//! depends on where `SYM` ends up once a linker places the sections, so it is
+//! recorded in [`EncodeResult::relocations`]:
+//!
+//! - `.EXTERNAL` symbols have no address here; the word is encoded with a zero
+//!   field and the relocation is the only record of the reference.
+//! - Local labels are encoded as usual. `.FILL` relocations are always kept;
+//!   PC-relative ones only when the target lies in another section, since a
+//!   section always moves as a whole.
+//!
+//! Other expressions that use labels are treated as absolute values.
 
 use crate::error::{AsmError, ErrorKind, Span};
-use crate::first_pass::{symbol_table::SymbolTable, FirstPassResult};
-use crate::parser::ast::{Instruction, LineContent, SourceLine};
+use crate::first_pass::symbol_table::{SymbolKind, SymbolTable};
+use crate::first_pass::FirstPassResult;
+use crate::parser::ast::{Expr, Instruction, LineContent, PcTarget, SourceLine};
 use crate::warning::AsmWarning;
 
 // LC-3 opcode constants — bits 15:12 of every instruction word.
@@ -59,18 +88,72 @@ pub struct LineInfo {
     pub source_line_idx: usize,
 }
 
+/// One contiguous block of machine code produced by an `.ORIG` section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// Load address of the first word.
+    pub origin: u16,
+    /// Machine-code words, in address order.
+    pub words: Vec<u16>,
+}
+
+/// How the linker patches a word once symbol addresses are final.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocKind {
+    /// Bits 8:0 hold `target - (address + 1)` (BR, LD, LDI, LEA, ST, STI).
+    PcOffset9,
+    /// Bits 10:0 hold `target - (address + 1)` (JSR).
+    PcOffset11,
+    /// The whole word holds the target address (`.FILL`).
+    Abs16,
+}
+
+impl RelocKind {
+    /// Width of the patched field.
+    #[must_use]
+    pub fn bits(self) -> u8 {
+        match self {
+            RelocKind::PcOffset9 => 9,
+            RelocKind::PcOffset11 => 11,
+            RelocKind::Abs16 => 16,
+        }
+    }
+}
+
+/// A word whose value depends on where `symbol` is placed: the target is
+/// `symbol + addend`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    /// Index into [`EncodeResult::segments`].
+    pub segment: usize,
+    /// Word offset of the patched word within its segment.
+    pub offset: u16,
+    pub kind: RelocKind,
+    pub symbol: String,
+    pub addend: i32,
+    /// `true` for `.EXTERNAL` symbols, which have no address in this file.
+    pub external: bool,
+    /// The operand the relocation came from.
+    pub span: Span,
+}
+
 /// Result of the encoding process
 pub struct EncodeResult {
-    /// Generated machine code as 16-bit words
+    /// Generated machine code as 16-bit words, all segments concatenated in
+    /// source order. Use `segments` when the load address of each word matters.
     pub machine_code: Vec<u16>,
-    /// Origin address where program should be loaded
+    /// Origin address of the first segment
     pub orig_address: u16,
+    /// One entry per `.ORIG` section, in source order.
+    pub segments: Vec<Segment>,
     /// Errors encountered during encoding
     pub errors: Vec<AsmError>,
     /// Warnings produced during encoding (e.g. unused labels).
     pub warnings: Vec<AsmWarning>,
     /// Per-source-line metadata for listing file generation.
     pub line_infos: Vec<LineInfo>,
+    /// Words the linker must patch (see the module docs).
+    pub relocations: Vec<Relocation>,
 }
 
 impl EncodeResult {
@@ -95,7 +178,11 @@ impl EncodeResult {
 /// An `EncodeResult` containing the machine code and any errors encountered
 #[must_use]
 pub fn encode(first_pass: &FirstPassResult) -> EncodeResult {
-    let mut encoder = Encoder::new(&first_pass.symbol_table, first_pass.orig_address);
+    let mut encoder = Encoder::new(
+        &first_pass.symbol_table,
+        &first_pass.externals,
+        first_pass.orig_address,
+    );
 
     for (idx, line) in first_pass.source_lines.iter().enumerate() {
         encoder.encode_line(line, idx);
@@ -107,76 +194,127 @@ pub fn encode(first_pass: &FirstPassResult) -> EncodeResult {
     // reference it, it's still unused.
     let mut warnings = Vec::new();
     for (name, _addr) in first_pass.symbol_table.iter() {
-        if !encoder.resolved_labels.contains(name) {
+        if !encoder.resolved_labels.contains(name) && !first_pass.globals.iter().any(|g| g == name)
+        {
             // Find the span for this label by scanning source lines
             let span = first_pass
                 .source_lines
                 .iter()
                 .find(|sl| sl.label.as_deref() == Some(name))
                 .map(|sl| sl.span)
-                .unwrap_or(crate::error::Span { line: 1, col: 1 });
+                .unwrap_or(crate::error::Span::point(1, 1));
             warnings.push(AsmWarning::unused_label(name, span));
         }
     }
 
+    // A PC-relative reference to a label of the same section survives any
+    // placement.  The section is the one the label was defined in, not the
+    // one its address falls in: adjacent sections share a boundary address.
+    let table = &first_pass.symbol_table;
+    encoder.relocations.retain(|r| {
+        r.external || r.kind == RelocKind::Abs16 || table.section(&r.symbol) != Some(r.segment)
+    });
+
     EncodeResult {
         machine_code: encoder.machine_code,
-        orig_address: encoder.orig_address,
+        orig_address: encoder
+            .segments
+            .first()
+            .map_or(encoder.orig_address, |seg| seg.origin),
+        segments: encoder.segments,
         errors: encoder.errors,
         warnings,
         line_infos: encoder.line_infos,
+        relocations: encoder.relocations,
     }
 }
 
+/// A `.FILL` value or PC-relative target.
+enum Address {
+    Value(u16),
+    /// An `.EXTERNAL` symbol (plus addend), known only to the linker.
+    External,
+}
+
 struct Encoder<'a> {
     symbol_table: &'a SymbolTable,
+    externals: &'a [String],
     machine_code: Vec<u16>,
+    segments: Vec<Segment>,
     orig_address: u16,
     current_address: u16,
+    /// False between an `.END` and the next `.ORIG`; such lines emit nothing.
+    in_section: bool,
     errors: Vec<AsmError>,
     /// Labels successfully looked up during encoding (for unused-label detection).
     resolved_labels: std::collections::HashSet<String>,
+    /// Current value of each `.SET` variable, replayed in source order so a
+    /// use sees the most recent `.SET` above it rather than the final value.
+    variables: std::collections::HashMap<String, i32>,
     /// Per-source-line metadata for the listing file.
     line_infos: Vec<LineInfo>,
+    relocations: Vec<Relocation>,
 }
 
 impl<'a> Encoder<'a> {
-    fn new(symbol_table: &'a SymbolTable, orig_address: u16) -> Self {
+    fn new(symbol_table: &'a SymbolTable, externals: &'a [String], orig_address: u16) -> Self {
         Self {
             symbol_table,
+            externals,
             machine_code: Vec::new(),
+            segments: Vec::new(),
             orig_address,
             current_address: orig_address,
+            in_section: true,
             errors: Vec::new(),
             resolved_labels: std::collections::HashSet::new(),
+            variables: std::collections::HashMap::new(),
             line_infos: Vec::new(),
+            relocations: Vec::new(),
         }
     }
 
     fn encode_line(&mut self, line: &SourceLine, source_line_idx: usize) {
+        if let LineContent::Orig(addr) = line.content {
+            self.current_address = addr;
+            self.in_section = true;
+            self.segments.push(Segment {
+                origin: addr,
+                words: Vec::new(),
+            });
+        }
+
         let start_addr = self.current_address;
         let start_len = self.machine_code.len();
 
         match &line.content {
+            // Constants may sit outside any section. The first pass reported
+            // any error, so a failed .SET just leaves the old value in place.
+            LineContent::Equ(_) | LineContent::External(_) | LineContent::Global(_) => {}
+            LineContent::Set(value) => {
+                if let (Some(name), Ok(v)) = (&line.label, self.try_eval(value)) {
+                    self.variables.insert(name.clone(), v);
+                }
+            }
+            _ if !self.in_section => {} // Between .END and the next .ORIG
             LineContent::Empty => {}
-            LineContent::Orig(_) => {} // Already handled in first pass
-            LineContent::End => {}     // End of program
-            LineContent::FillImmediate(value) => {
-                self.emit(*value as u16);
+            LineContent::Orig(_) => {} // Segment opened above
+            LineContent::End => self.in_section = false,
+            LineContent::Fill(value) => {
+                let word = match self.address(value, RelocKind::Abs16) {
+                    Some(Address::Value(word)) => word,
+                    Some(Address::External) | None => 0,
+                };
+                self.emit(word);
             }
-            LineContent::FillLabel(label) => match self.symbol_table.get(label) {
-                Some(addr) => {
-                    self.resolved_labels.insert(label.clone());
-                    self.emit(addr);
-                }
-                None => {
-                    self.errors
-                        .push(AsmError::undefined_label(label, line.span));
-                    self.emit(0);
-                }
-            },
             LineContent::Blkw(count) => {
-                for _ in 0..*count {
+                // The first pass already validated the count and reported any
+                // error; evaluating again only marks the labels it uses.
+                let count = self
+                    .try_eval(count)
+                    .ok()
+                    .filter(|n| (1..=0xFFFF).contains(n));
+                for _ in 0..count.unwrap_or(0) {
                     self.emit(0);
                 }
             }
@@ -194,7 +332,7 @@ impl<'a> Encoder<'a> {
                 self.emit(0); // Null terminator
             }
             LineContent::Instruction(inst) => {
-                self.encode_instruction(inst, line.span);
+                self.encode_instruction(inst);
             }
         }
 
@@ -207,21 +345,21 @@ impl<'a> Encoder<'a> {
         });
     }
 
-    fn encode_instruction(&mut self, inst: &Instruction, span: Span) {
+    fn encode_instruction(&mut self, inst: &Instruction) {
         let encoded = match inst {
             // Operate instructions
             Instruction::AddReg { dr, sr1, sr2 } => {
                 (OP_ADD << 12) | ((*dr as u16) << 9) | ((*sr1 as u16) << 6) | (*sr2 as u16)
             }
             Instruction::AddImm { dr, sr1, imm5 } => {
-                let imm = sign_extend(*imm5, 5) & 0x1F;
+                let imm = self.signed_field(imm5, 5, "ADD immediate");
                 (OP_ADD << 12) | ((*dr as u16) << 9) | ((*sr1 as u16) << 6) | (1 << 5) | imm
             }
             Instruction::AndReg { dr, sr1, sr2 } => {
                 (OP_AND << 12) | ((*dr as u16) << 9) | ((*sr1 as u16) << 6) | (*sr2 as u16)
             }
             Instruction::AndImm { dr, sr1, imm5 } => {
-                let imm = sign_extend(*imm5, 5) & 0x1F;
+                let imm = self.signed_field(imm5, 5, "AND immediate");
                 (OP_AND << 12) | ((*dr as u16) << 9) | ((*sr1 as u16) << 6) | (1 << 5) | imm
             }
             Instruction::Not { dr, sr } => {
@@ -229,24 +367,24 @@ impl<'a> Encoder<'a> {
             }
 
             // Data movement with PC offset
-            Instruction::Ld { dr, label } => {
-                let offset = self.calc_pc_offset(label, 9, span);
+            Instruction::Ld { dr, target } => {
+                let offset = self.calc_pc_offset(target, 9);
                 (OP_LD << 12) | ((*dr as u16) << 9) | offset
             }
-            Instruction::Ldi { dr, label } => {
-                let offset = self.calc_pc_offset(label, 9, span);
+            Instruction::Ldi { dr, target } => {
+                let offset = self.calc_pc_offset(target, 9);
                 (OP_LDI << 12) | ((*dr as u16) << 9) | offset
             }
-            Instruction::Lea { dr, label } => {
-                let offset = self.calc_pc_offset(label, 9, span);
+            Instruction::Lea { dr, target } => {
+                let offset = self.calc_pc_offset(target, 9);
                 (OP_LEA << 12) | ((*dr as u16) << 9) | offset
             }
-            Instruction::St { sr, label } => {
-                let offset = self.calc_pc_offset(label, 9, span);
+            Instruction::St { sr, target } => {
+                let offset = self.calc_pc_offset(target, 9);
                 (OP_ST << 12) | ((*sr as u16) << 9) | offset
             }
-            Instruction::Sti { sr, label } => {
-                let offset = self.calc_pc_offset(label, 9, span);
+            Instruction::Sti { sr, target } => {
+                let offset = self.calc_pc_offset(target, 9);
                 (OP_STI << 12) | ((*sr as u16) << 9) | offset
             }
 
@@ -256,7 +394,7 @@ impl<'a> Encoder<'a> {
                 base_r,
                 offset6,
             } => {
-                let offset = sign_extend(*offset6, 6) & 0x3F;
+                let offset = self.signed_field(offset6, 6, "LDR offset");
                 (OP_LDR << 12) | ((*dr as u16) << 9) | ((*base_r as u16) << 6) | offset
             }
             Instruction::Str {
@@ -264,13 +402,13 @@ impl<'a> Encoder<'a> {
                 base_r,
                 offset6,
             } => {
-                let offset = sign_extend(*offset6, 6) & 0x3F;
+                let offset = self.signed_field(offset6, 6, "STR offset");
                 (OP_STR << 12) | ((*sr as u16) << 9) | ((*base_r as u16) << 6) | offset
             }
 
             // Branch (opcode 0000 — zero, so no shift needed; flags occupy bits 11:9)
-            Instruction::Br { flags, label } => {
-                let offset = self.calc_pc_offset(label, 9, span);
+            Instruction::Br { flags, target } => {
+                let offset = self.calc_pc_offset(target, 9);
                 // BrFlags::as_u16() encodes [N][Z][P] as a 3-bit value.
                 // Shifting left by 9 places n→bit11, z→bit10, p→bit9.
                 (OP_BR << 12) | (flags.as_u16() << 9) | offset
@@ -284,14 +422,14 @@ impl<'a> Encoder<'a> {
             }
 
             // Subroutine
-            Instruction::Jsr { label } => {
-                let offset = self.calc_pc_offset(label, 11, span);
+            Instruction::Jsr { target } => {
+                let offset = self.calc_pc_offset(target, 11);
                 (OP_JSR << 12) | (1 << 11) | offset
             }
             Instruction::Jsrr { base_r } => (OP_JSR << 12) | ((*base_r as u16) << 6),
 
             // Trap
-            Instruction::Trap { trapvect8 } => (OP_TRAP << 12) | (*trapvect8 as u16),
+            Instruction::Trap { trapvect8 } => (OP_TRAP << 12) | self.trap_vector(trapvect8),
             Instruction::Getc => TRAP_GETC,
             Instruction::Out => TRAP_OUT,
             Instruction::Puts => TRAP_PUTS,
@@ -315,46 +453,230 @@ impl<'a> Encoder<'a> {
     ///
     /// The offset must fit in the specified number of bits as a signed value.
     /// For example, with 9 bits: range is -256 to +255
-    fn calc_pc_offset(&mut self, label: &str, bits: u8, span: Span) -> u16 {
-        // Track every successfully resolved label for unused-label analysis.
-        match self.symbol_table.get(label) {
-            Some(target_addr) => {
-                self.resolved_labels.insert(label.to_string());
+    ///
+    /// A numeric operand (`BRnzp #-3`) is already an offset and only gets the
+    /// range check.
+    fn calc_pc_offset(&mut self, target: &PcTarget, bits: u8) -> u16 {
+        // Check if offset fits in the specified number of bits (signed range)
+        let max_offset = (1 << (bits - 1)) - 1;
+        let min_offset = -(1 << (bits - 1));
+
+        let (offset, span, to) = match target {
+            PcTarget::Offset(expr) => {
+                let Some(offset) = self.eval(expr) else {
+                    return 0; // Error already recorded
+                };
+                (as_signed_word(offset), expr.span, String::new())
+            }
+            PcTarget::Address(expr) => {
+                let kind = if bits == 11 {
+                    RelocKind::PcOffset11
+                } else {
+                    RelocKind::PcOffset9
+                };
+                let target_addr = match self.address(expr, kind) {
+                    Some(Address::Value(addr)) => addr,
+                    // Error already recorded, or left for the linker
+                    Some(Address::External) | None => return 0,
+                };
+
                 // PC will point to next instruction during execution
                 let pc = self.current_address.wrapping_add(1);
 
                 // Calculate signed offset from PC to target
                 let offset = (target_addr as i32) - (pc as i32);
+                let to = match expr.as_symbol() {
+                    Some(label) => format!(" to label '{label}'"),
+                    None => format!(" to '{expr}' (x{target_addr:04X})"),
+                };
+                (offset, expr.span, to)
+            }
+        };
 
-                // Check if offset fits in the specified number of bits (signed range)
-                let max_offset = (1 << (bits - 1)) - 1;
-                let min_offset = -(1 << (bits - 1));
-
-                if offset < min_offset || offset > max_offset {
-                    self.errors.push(AsmError {
-                        kind: ErrorKind::OffsetOutOfRange,
-                        message: format!("PC offset {offset} to label '{label}' exceeds {bits}-bit range [{min_offset}, {max_offset}]"),
-                        span,
-                    });
-                    0 // Use 0 on error, but error is recorded
+        if offset < min_offset || offset > max_offset {
+            self.errors.push(AsmError::new(
+                ErrorKind::OffsetOutOfRange,
+                format!(
+                    "PC offset {offset}{to} exceeds {bits}-bit range [{min_offset}, {max_offset}]"
+                ),
+                span,
+            ));
+            0 // Use 0 on error, but error is recorded
+        } else {
+            // Mask to keep only the lower 'bits' bits (preserves two's complement)
+            (offset as u16) & ((1 << bits) - 1)
+        }
+    }
+
+    /// Evaluate a `.FILL` value or PC-relative target, recording the
+    /// relocation of `kind` for the word about to be emitted when it has the
+    /// form `SYM`, `SYM+n` or `SYM-n`.
+    fn address(&mut self, expr: &Expr, kind: RelocKind) -> Option<Address> {
+        if let Some((name, sign, rest)) = expr.as_symbol_offset() {
+            let external = self.externals.iter().any(|e| e == name);
+            let label = self
+                .symbol_table
+                .lookup(name)
+                .is_some_and(|sym| sym.kind == SymbolKind::Label);
+            // `n` must be a plain number or constant for `SYM+n` to relocate.
+            let rest_is_constant = rest.map_or(true, |e| {
+                let mut labels = false;
+                e.for_each_symbol(&mut |n, _| {
+                    labels |=
+                        self.externals.iter().any(|x| x == n) || self.symbol_table.get(n).is_some();
+                });
+                !labels
+            });
+            if (external || label) && rest_is_constant {
+                let addend = match rest {
+                    Some(e) => sign * self.eval(e)?,
+                    None => 0,
+                };
+                let value = if external {
+                    Address::External
                 } else {
-                    // Mask to keep only the lower 'bits' bits (preserves two's complement)
-                    (offset as u16) & ((1 << bits) - 1)
-                }
+                    Address::Value(self.word_value(expr)?)
+                };
+                self.relocations.push(Relocation {
+                    segment: self.segments.len().saturating_sub(1),
+                    offset: self.segments.last().map_or(0, |s| s.words.len() as u16),
+                    kind,
+                    symbol: name.to_string(),
+                    addend,
+                    external,
+                    span: expr.span,
+                });
+                return Some(value);
             }
-            None => {
-                self.errors.push(AsmError::undefined_label(label, span));
-                0
+        }
+        self.word_value(expr).map(Address::Value)
+    }
+
+    /// Evaluate `expr` against the symbol table, marking every label it uses
+    /// as referenced (for unused-label analysis).
+    fn try_eval(&mut self, expr: &Expr) -> Result<i32, AsmError> {
+        let mut external = None;
+        expr.for_each_symbol(&mut |name, span| {
+            if external.is_none() && self.externals.iter().any(|e| e == name) {
+                external = Some((name.to_string(), span));
             }
+        });
+        if let Some((name, span)) = external {
+            return Err(AsmError::new(
+                ErrorKind::UnresolvedExternal,
+                format!(
+                    "'{name}' is .EXTERNAL, so its address is only known after linking; \
+                     use it as {name}, {name}+n or {name}-n in .FILL or a PC-relative operand"
+                ),
+                span,
+            ));
         }
+
+        let table = self.symbol_table;
+        let variables = &self.variables;
+        let resolved = &mut self.resolved_labels;
+        expr.eval(&mut |name| {
+            let sym = table.lookup(name)?;
+            match sym.kind {
+                SymbolKind::Label => {
+                    resolved.insert(name.to_string());
+                    Some(sym.value)
+                }
+                SymbolKind::Constant => Some(sym.value),
+                // A .SET variable used above its first .SET is undefined there.
+                SymbolKind::Variable => variables.get(name).copied(),
+            }
+        })
+    }
+
+    /// Evaluate `expr`, recording any error.
+    fn eval(&mut self, expr: &Expr) -> Option<i32> {
+        self.try_eval(expr)
+            .map_err(|err| self.errors.push(err))
+            .ok()
+    }
+
+    /// Evaluate `expr` as a 16-bit word (`.FILL` values, PC-relative targets).
+    ///
+    /// Accepts -32768..=65535 so that both signed (`#-1`) and unsigned
+    /// (`xFFFF`, `#65535`) spellings of a word are valid.
+    fn word_value(&mut self, expr: &Expr) -> Option<u16> {
+        let value = self.eval(expr)?;
+        if !(i16::MIN as i32..=0xFFFF_i32).contains(&value) {
+            self.errors.push(AsmError::invalid_operand_type(
+                format!("Value {value} of '{expr}' is out of 16-bit range (-32768 to 65535)"),
+                expr.span,
+            ));
+            return None;
+        }
+        Some(value as u16)
+    }
+
+    /// Evaluate `expr` and check that it fits a `bits`-wide signed field,
+    /// e.g. imm5 (-16..=15) or offset6 (-32..=31). `what` names the operand
+    /// in the error message ("ADD immediate", "LDR offset").
+    fn signed_field(&mut self, expr: &Expr, bits: u8, what: &str) -> u16 {
+        let Some(value) = self.eval(expr).map(as_signed_word) else {
+            return 0;
+        };
+        let max = (1 << (bits - 1)) - 1;
+        let min = -(1 << (bits - 1));
+        if !(min..=max).contains(&value) {
+            // Without this check, `ADD R1, R1, #100` would silently truncate.
+            self.errors.push(AsmError::invalid_operand_type(
+                format!("{what} value {value} is out of {bits}-bit signed range ({min} to {max})"),
+                expr.span,
+            ));
+            return 0;
+        }
+        sign_extend(value as i16, bits)
+    }
+
+    /// Evaluate a TRAP vector, which must fit in 8 bits (0x00–0xFF).
+    ///
+    /// Without this check, `TRAP x1FF` would silently truncate to `TRAP xFF`.
+    fn trap_vector(&mut self, expr: &Expr) -> u16 {
+        let Some(value) = self.eval(expr) else {
+            return 0;
+        };
+        if !(0..=0xFF).contains(&value) {
+            self.errors.push(AsmError::invalid_operand_type(
+                format!("TRAP vector {value} is out of range (must be 0x00-0xFF)"),
+                expr.span,
+            ));
+            return 0;
+        }
+        value as u16
     }
 
     fn emit(&mut self, word: u16) {
+        // Code before any .ORIG (already reported by the first pass) still
+        // needs a segment to land in.
+        if self.segments.is_empty() {
+            self.segments.push(Segment {
+                origin: self.current_address,
+                words: Vec::new(),
+            });
+        }
+        if let Some(seg) = self.segments.last_mut() {
+            seg.words.push(word);
+        }
         self.machine_code.push(word);
         self.current_address = self.current_address.wrapping_add(1);
     }
 }
 
+/// Read an unsigned 16-bit value (`xFFFF`, `#65535`) as two's complement, so
+/// `ADD R0, R0, xFFFF` means `#-1`. Other values are returned unchanged for
+/// the caller's range check.
+fn as_signed_word(value: i32) -> i32 {
+    if (0x8000..=0xFFFF).contains(&value) {
+        value - 0x10000
+    } else {
+        value
+    }
+}
+
 /// Truncate a signed value to N bits, preserving two's complement representation
 ///
 /// This function takes a signed i16 value and masks it to fit in the specified
@@ -371,6 +693,7 @@ const fn sign_extend(value: i16, bits: u8) -> u16 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::Span;
     use crate::first_pass::symbol_table::SymbolTable;
     use crate::lexer::token::BrFlags;
 
@@ -378,7 +701,7 @@ mod tests {
     // Helpers
     // ---------------------------------------------------------------
 
-    const DUMMY_SPAN: Span = Span { line: 1, col: 1 };
+    const DUMMY_SPAN: Span = Span::point(1, 1);
 
     /// Build a minimal `FirstPassResult` from an origin address, a list of
     /// `LineContent` items, and a symbol table.  Every line gets a dummy span
@@ -408,6 +731,8 @@ mod tests {
             orig_address: orig,
             errors: Vec::new(),
             warnings: Vec::new(),
+            externals: Vec::new(),
+            globals: Vec::new(),
         }
     }
 
@@ -473,7 +798,7 @@ mod tests {
         let word = encode_single(Instruction::AddImm {
             dr: 1,
             sr1: 1,
-            imm5: 5,
+            imm5: Expr::num(5),
         });
         assert_eq!(word, 0x1265);
     }
@@ -484,7 +809,7 @@ mod tests {
         let word = encode_single(Instruction::AddImm {
             dr: 0,
             sr1: 0,
-            imm5: -1,
+            imm5: Expr::num(-1),
         });
         assert_eq!(word, 0x103F);
     }
@@ -506,7 +831,7 @@ mod tests {
         let word = encode_single(Instruction::AndImm {
             dr: 0,
             sr1: 0,
-            imm5: 0,
+            imm5: Expr::num(0),
         });
         assert_eq!(word, 0x5020);
     }
@@ -528,7 +853,7 @@ mod tests {
         let word = encode_single(Instruction::Ldr {
             dr: 2,
             base_r: 3,
-            offset6: 5,
+            offset6: Expr::num(5),
         });
         assert_eq!(word, 0x64C5);
     }
@@ -539,7 +864,7 @@ mod tests {
         let word = encode_single(Instruction::Ldr {
             dr: 0,
             base_r: 1,
-            offset6: -1,
+            offset6: Expr::num(-1),
         });
         assert_eq!(word, 0x607F);
     }
@@ -550,7 +875,7 @@ mod tests {
         let word = encode_single(Instruction::Str {
             sr: 7,
             base_r: 6,
-            offset6: 0,
+            offset6: Expr::num(0),
         });
         assert_eq!(word, 0x7F80);
     }
@@ -568,7 +893,7 @@ mod tests {
             0x3000,
             vec![LineContent::Instruction(Instruction::Ld {
                 dr: 3,
-                label: "TARGET".into(),
+                target: PcTarget::Address(Expr::symbol("TARGET")),
             })],
             st,
         );
@@ -586,7 +911,7 @@ mod tests {
             0x3000,
             vec![LineContent::Instruction(Instruction::Ldi {
                 dr: 0,
-                label: "PTR".into(),
+                target: PcTarget::Address(Expr::symbol("PTR")),
             })],
             st,
         );
@@ -604,7 +929,7 @@ mod tests {
             0x3000,
             vec![LineContent::Instruction(Instruction::Lea {
                 dr: 7,
-                label: "MSG".into(),
+                target: PcTarget::Address(Expr::symbol("MSG")),
             })],
             st,
         );
@@ -622,7 +947,7 @@ mod tests {
             0x3000,
             vec![LineContent::Instruction(Instruction::St {
                 sr: 2,
-                label: "DATA".into(),
+                target: PcTarget::Address(Expr::symbol("DATA")),
             })],
             st,
         );
@@ -640,7 +965,7 @@ mod tests {
             0x3000,
             vec![LineContent::Instruction(Instruction::Sti {
                 sr: 1,
-                label: "PTR".into(),
+                target: PcTarget::Address(Expr::symbol("PTR")),
             })],
             st,
         );
@@ -662,7 +987,7 @@ mod tests {
             0x3000,
             vec![LineContent::Instruction(Instruction::Br {
                 flags: BrFlags::new(true, true, true),
-                label: "LOOP".into(),
+                target: PcTarget::Address(Expr::symbol("LOOP")),
             })],
             st,
         );
@@ -680,7 +1005,7 @@ mod tests {
             0x3000,
             vec![LineContent::Instruction(Instruction::Br {
                 flags: BrFlags::new(true, false, false),
-                label: "BACK".into(),
+                target: PcTarget::Address(Expr::symbol("BACK")),
             })],
             st,
         );
@@ -698,7 +1023,7 @@ mod tests {
             0x3000,
             vec![LineContent::Instruction(Instruction::Br {
                 flags: BrFlags::new(false, true, true),
-                label: "FWD".into(),
+                target: PcTarget::Address(Expr::symbol("FWD")),
             })],
             st,
         );
@@ -729,7 +1054,7 @@ mod tests {
         let fp = build_first_pass(
             0x3000,
             vec![LineContent::Instruction(Instruction::Jsr {
-                label: "SUB".into(),
+                target: PcTarget::Address(Expr::symbol("SUB")),
             })],
             st,
         );
@@ -759,7 +1084,9 @@ mod tests {
     #[test]
     fn encode_trap() {
         // TRAP x25  →  1111 0000 00100101 = 0xF025
-        let word = encode_single(Instruction::Trap { trapvect8: 0x25 });
+        let word = encode_single(Instruction::Trap {
+            trapvect8: Expr::num(0x25),
+        });
         assert_eq!(word, 0xF025);
     }
 
@@ -781,7 +1108,7 @@ mod tests {
     fn encode_fill_immediate() {
         let fp = build_first_pass(
             0x3000,
-            vec![LineContent::FillImmediate(42)],
+            vec![LineContent::Fill(Expr::num(42))],
             SymbolTable::new(),
         );
         let result = encode(&fp);
@@ -794,7 +1121,7 @@ mod tests {
         // .FILL #-1  →  stored as 0xFFFF (i32 -1 cast to u16)
         let fp = build_first_pass(
             0x3000,
-            vec![LineContent::FillImmediate(-1)],
+            vec![LineContent::Fill(Expr::num(-1))],
             SymbolTable::new(),
         );
         let result = encode(&fp);
@@ -805,7 +1132,7 @@ mod tests {
     #[test]
     fn encode_fill_label() {
         let st = symbols_with_target("DATA", 0x4000);
-        let fp = build_first_pass(0x3000, vec![LineContent::FillLabel("DATA".into())], st);
+        let fp = build_first_pass(0x3000, vec![LineContent::Fill(Expr::symbol("DATA"))], st);
         let result = encode(&fp);
         assert!(result.errors.is_empty());
         assert_eq!(result.machine_code, vec![0x4000]);
@@ -813,7 +1140,11 @@ mod tests {
 
     #[test]
     fn encode_blkw() {
-        let fp = build_first_pass(0x3000, vec![LineContent::Blkw(5)], SymbolTable::new());
+        let fp = build_first_pass(
+            0x3000,
+            vec![LineContent::Blkw(Expr::num(5))],
+            SymbolTable::new(),
+        );
         let result = encode(&fp);
         assert!(result.errors.is_empty());
         assert_eq!(result.machine_code, vec![0, 0, 0, 0, 0]);
@@ -893,7 +1224,7 @@ mod tests {
             0x3000,
             vec![LineContent::Instruction(Instruction::Ld {
                 dr: 0,
-                label: "FAR".into(),
+                target: PcTarget::Address(Expr::symbol("FAR")),
             })],
             st,
         );
@@ -911,7 +1242,7 @@ mod tests {
             0x3100,
             vec![LineContent::Instruction(Instruction::Ld {
                 dr: 0,
-                label: "BACK".into(),
+                target: PcTarget::Address(Expr::symbol("BACK")),
             })],
             st,
         );
@@ -928,7 +1259,7 @@ mod tests {
             0x3000,
             vec![LineContent::Instruction(Instruction::Ld {
                 dr: 0,
-                label: "TOO_FAR".into(),
+                target: PcTarget::Address(Expr::symbol("TOO_FAR")),
             })],
             st,
         );
@@ -945,7 +1276,7 @@ mod tests {
             0x3100,
             vec![LineContent::Instruction(Instruction::Ld {
                 dr: 0,
-                label: "TOO_FAR_BACK".into(),
+                target: PcTarget::Address(Expr::symbol("TOO_FAR_BACK")),
             })],
             st,
         );
@@ -961,7 +1292,7 @@ mod tests {
         let fp = build_first_pass(
             0x3000,
             vec![LineContent::Instruction(Instruction::Jsr {
-                label: "FUNC".into(),
+                target: PcTarget::Address(Expr::symbol("FUNC")),
             })],
             st,
         );
@@ -977,7 +1308,7 @@ mod tests {
         let fp = build_first_pass(
             0x3000,
             vec![LineContent::Instruction(Instruction::Jsr {
-                label: "FUNC".into(),
+                target: PcTarget::Address(Expr::symbol("FUNC")),
             })],
             st,
         );
@@ -996,7 +1327,7 @@ mod tests {
             0x3000,
             vec![LineContent::Instruction(Instruction::Ld {
                 dr: 0,
-                label: "MISSING".into(),
+                target: PcTarget::Address(Expr::symbol("MISSING")),
             })],
             SymbolTable::new(),
         );
@@ -1010,7 +1341,7 @@ mod tests {
     fn undefined_label_in_fill() {
         let fp = build_first_pass(
             0x3000,
-            vec![LineContent::FillLabel("NOPE".into())],
+            vec![LineContent::Fill(Expr::symbol("NOPE"))],
             SymbolTable::new(),
         );
         let result = encode(&fp);
@@ -1034,16 +1365,16 @@ mod tests {
                 LineContent::Instruction(Instruction::AddImm {
                     dr: 0,
                     sr1: 0,
-                    imm5: 1,
+                    imm5: Expr::num(1),
                 }),
                 LineContent::Instruction(Instruction::AddImm {
                     dr: 1,
                     sr1: 1,
-                    imm5: -1,
+                    imm5: Expr::num(-1),
                 }),
                 LineContent::Instruction(Instruction::Br {
                     flags: BrFlags::new(false, false, true),
-                    label: "TOP".into(),
+                    target: PcTarget::Address(Expr::symbol("TOP")),
                 }),
             ],
             st,
@@ -1063,10 +1394,10 @@ mod tests {
         let fp = build_first_pass(
             0x3000,
             vec![
-                LineContent::Blkw(10),
+                LineContent::Blkw(Expr::num(10)),
                 LineContent::Instruction(Instruction::Ld {
                     dr: 0,
-                    label: "TARGET".into(),
+                    target: PcTarget::Address(Expr::symbol("TARGET")),
                 }),
             ],
             st,
@@ -1089,4 +1420,171 @@ mod tests {
         assert_eq!(result.orig_address, 0x4000);
         assert!(result.machine_code.is_empty());
     }
+
+    #[test]
+    fn orig_after_end_starts_new_segment() {
+        let fp = build_first_pass(
+            0x3000,
+            vec![
+                LineContent::Instruction(Instruction::Halt),
+                LineContent::End,
+                LineContent::Orig(0x4000),
+                LineContent::Fill(Expr::num(7)),
+                LineContent::End,
+            ],
+            SymbolTable::new(),
+        );
+        let result = encode(&fp);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(
+            result.segments,
+            vec![
+                Segment {
+                    origin: 0x3000,
+                    words: vec![0xF025],
+                },
+                Segment {
+                    origin: 0x4000,
+                    words: vec![0x0007],
+                },
+            ]
+        );
+        assert_eq!(result.machine_code, vec![0xF025, 0x0007]);
+    }
+
+    #[test]
+    fn fill_expression_uses_label_addresses() {
+        use crate::parser::expr::{BinOp, ExprKind};
+        let mut st = SymbolTable::new();
+        st.insert("START".into(), 0x3000);
+        st.insert("END".into(), 0x3010);
+        let diff = Expr::new(
+            ExprKind::Binary {
+                op: BinOp::Sub,
+                lhs: Box::new(Expr::symbol("END")),
+                rhs: Box::new(Expr::symbol("START")),
+            },
+            DUMMY_SPAN,
+        );
+        let result = encode(&build_first_pass(0x3000, vec![LineContent::Fill(diff)], st));
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(result.machine_code, vec![0x0010]);
+        // Both labels count as referenced.
+        assert!(result.warnings.is_empty(), "{:?}", result.warnings);
+    }
+
+    #[test]
+    fn relocations_for_externals_and_cross_section_targets() {
+        let mut fp = crate::first_pass::first_pass(
+            crate::parser::parse_lines(
+                &crate::lexer::tokenize(
+                    ".EXTERNAL F\n.ORIG x3000\nA JSR F\nBR A\nLD R0, B\n.FILL F+1\n.END\n.ORIG x3010\nB .FILL A\n.END\n",
+                )
+                .tokens,
+            )
+            .lines,
+        );
+        assert!(fp.errors.is_empty(), "{:?}", fp.errors);
+        let result = encode(&fp);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(result.segments[0].words[0], 0x4800);
+        let relocs: Vec<_> = result
+            .relocations
+            .iter()
+            .map(|r| {
+                (
+                    r.segment,
+                    r.offset,
+                    r.kind,
+                    r.symbol.as_str(),
+                    r.addend,
+                    r.external,
+                )
+            })
+            .collect();
+        // `BR A` stays inside its section and needs no relocation.
+        assert_eq!(
+            relocs,
+            vec![
+                (0, 0, RelocKind::PcOffset11, "F", 0, true),
+                (0, 2, RelocKind::PcOffset9, "B", 0, false),
+                (0, 3, RelocKind::Abs16, "F", 1, true),
+                (1, 0, RelocKind::Abs16, "A", 0, false),
+            ]
+        );
+
+        // Anything other than SYM±n cannot be patched by the linker.
+        fp.source_lines[4].content = LineContent::Instruction(Instruction::AddImm {
+            dr: 0,
+            sr1: 0,
+            imm5: Expr::symbol("F"),
+        });
+        let result = encode(&fp);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].kind, ErrorKind::UnresolvedExternal);
+    }
+
+    #[test]
+    fn imm5_range_checked_after_evaluation() {
+        let fp = build_first_pass(
+            0x3000,
+            vec![LineContent::Instruction(Instruction::AddImm {
+                dr: 1,
+                sr1: 1,
+                imm5: Expr::num(16),
+            })],
+            SymbolTable::new(),
+        );
+        let result = encode(&fp);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].kind, ErrorKind::InvalidOperandType);
+        assert!(result.errors[0].message.contains("ADD immediate value 16"));
+    }
+
+    #[test]
+    fn numeric_offsets_encode_as_is() {
+        let br = encode_single(Instruction::Br {
+            flags: BrFlags::new(true, true, true),
+            target: PcTarget::Offset(Expr::num(-3)),
+        });
+        assert_eq!(br, 0x0FFD);
+        let ld = encode_single(Instruction::Ld {
+            dr: 0,
+            target: PcTarget::Offset(Expr::num(5)),
+        });
+        assert_eq!(ld, 0x2005);
+        let jsr = encode_single(Instruction::Jsr {
+            target: PcTarget::Offset(Expr::num(-1024)),
+        });
+        assert_eq!(jsr, 0x4C00);
+    }
+
+    #[test]
+    fn numeric_offset_out_of_range() {
+        for (inst, bits) in [
+            (
+                Instruction::Lea {
+                    dr: 0,
+                    target: PcTarget::Offset(Expr::num(256)),
+                },
+                9,
+            ),
+            (
+                Instruction::Jsr {
+                    target: PcTarget::Offset(Expr::num(1024)),
+                },
+                11,
+            ),
+        ] {
+            let fp = build_first_pass(
+                0x3000,
+                vec![LineContent::Instruction(inst)],
+                SymbolTable::new(),
+            );
+            let result = encode(&fp);
+            assert_eq!(result.errors.len(), 1);
+            assert_eq!(result.errors[0].kind, ErrorKind::OffsetOutOfRange);
+            assert!(result.errors[0].message.contains(&format!("{bits}-bit")));
+        }
+    }
 }
@@ -1,12 +1,55 @@
 /// Source location used for error reporting.
 ///
-/// Stores the 1-based line and column of the first character of a token.
-/// Byte offsets were removed because they were computed but never read —
-/// if source-underline diagnostics are added later, add `start`/`end` back.
+/// `line` and `col` are the 1-based position of the first character; `start`
+/// and `end` are the byte range covered in the lexed source, which lets
+/// diagnostics underline the whole token or operand.  Spans built from a
+/// line and column alone ([`Span::point`]) have an empty range.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Span {
     pub line: usize,
     pub col: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// A span known only by its line and column.
+    #[must_use]
+    pub const fn point(line: usize, col: usize) -> Self {
+        Self {
+            line,
+            col,
+            start: 0,
+            end: 0,
+        }
+    }
+
+    /// Number of characters to underline (at least one).
+    #[must_use]
+    pub fn width(&self) -> usize {
+        self.end.saturating_sub(self.start).max(1)
+    }
+
+    /// The span from the start of `self` to the end of `other`.
+    #[must_use]
+    pub fn to(self, other: Span) -> Span {
+        if other.end > self.start && other.line == self.line {
+            Span {
+                end: other.end.max(self.end),
+                ..self
+            }
+        } else {
+            self
+        }
+    }
+}
+
+/// An extra span in a diagnostic, with a note saying why it is relevant
+/// (e.g. where a duplicate label was first defined).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -14,6 +57,8 @@ pub struct AsmError {
     pub kind: ErrorKind,
     pub message: String,
     pub span: Span,
+    /// Secondary spans shown under the primary one.
+    pub labels: Vec<Label>,
 }
 
 impl AsmError {
@@ -23,9 +68,20 @@ impl AsmError {
             kind,
             message: message.into(),
             span,
+            labels: Vec::new(),
         }
     }
 
+    /// Attach a secondary span with a note.
+    #[must_use]
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
     /// Builder-style constructor for common error patterns
     pub fn too_few_operands(message: impl Into<String>, span: Span) -> Self {
         Self::new(ErrorKind::TooFewOperands, message, span)
@@ -111,6 +167,7 @@ pub enum ErrorKind {
     TooManyOperands,
     TooFewOperands,
     InvalidOperandType,
+    InvalidExpression, // malformed or unevaluable constant expression
 
     DuplicateLabel,
     MissingOrig,
@@ -118,6 +175,7 @@ pub enum ErrorKind {
     // OrigNotFirst was removed — the first pass handles this via MissingOrig instead,
     // and this variant was never constructed anywhere in the codebase.
     MissingEnd,
+    SegmentOverlap, // two .ORIG sections cover the same addresses
     InvalidOrigAddress,
     InvalidBlkwCount,
     AddressOverflow,
@@ -127,6 +185,51 @@ pub enum ErrorKind {
     LabelIsReservedWord, // label shadows a directive name
     IoError,             // file I/O error (used by preprocessor for .INCLUDE)
     MacroError,          // macro definition/invocation error
+    UnresolvedExternal,  // .EXTERNAL symbol used where only the linker can resolve it
+    ConditionalError,    // unbalanced or invalid .IF/.ELSE/.ENDIF
+}
+
+impl ErrorKind {
+    /// Stable identifier for tools (`E001`…).  Codes are never reused or
+    /// renumbered; new kinds get the next free number.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnterminatedString => "E001",
+            Self::InvalidEscapeSequence => "E002",
+            Self::InvalidDecimalLiteral => "E003",
+            Self::InvalidHexLiteral => "E004",
+            Self::InvalidBinaryLiteral => "E005",
+            Self::InvalidOctalLiteral => "E006",
+            Self::InvalidRegister => "E007",
+            Self::UnknownDirective => "E008",
+            Self::UnexpectedCharacter => "E009",
+            Self::ExpectedOperand => "E010",
+            Self::ExpectedRegister => "E011",
+            Self::ExpectedComma => "E012",
+            Self::UnexpectedToken => "E013",
+            Self::TooManyOperands => "E014",
+            Self::TooFewOperands => "E015",
+            Self::InvalidOperandType => "E016",
+            Self::InvalidExpression => "E017",
+            Self::DuplicateLabel => "E018",
+            Self::MissingOrig => "E019",
+            Self::MultipleOrig => "E020",
+            Self::MissingEnd => "E021",
+            Self::SegmentOverlap => "E022",
+            Self::InvalidOrigAddress => "E023",
+            Self::InvalidBlkwCount => "E024",
+            Self::AddressOverflow => "E025",
+            Self::UndefinedLabel => "E026",
+            Self::OffsetOutOfRange => "E027",
+            Self::NonAsciiInStringz => "E028",
+            Self::LabelIsReservedWord => "E029",
+            Self::IoError => "E030",
+            Self::MacroError => "E031",
+            Self::UnresolvedExternal => "E032",
+            Self::ConditionalError => "E033",
+        }
+    }
 }
 
 impl std::fmt::Display for ErrorKind {
@@ -148,10 +251,12 @@ impl std::fmt::Display for ErrorKind {
             Self::TooManyOperands => "too many operands",
             Self::TooFewOperands => "too few operands",
             Self::InvalidOperandType => "invalid operand type",
+            Self::InvalidExpression => "invalid expression",
             Self::DuplicateLabel => "duplicate label",
             Self::MissingOrig => "missing .ORIG directive",
-            Self::MultipleOrig => "multiple .ORIG directives",
+            Self::MultipleOrig => ".ORIG inside an open section",
             Self::MissingEnd => "missing .END directive",
+            Self::SegmentOverlap => "overlapping sections",
             Self::InvalidOrigAddress => "invalid .ORIG address",
             Self::InvalidBlkwCount => "invalid .BLKW count",
             Self::AddressOverflow => "address overflow",
@@ -161,6 +266,8 @@ impl std::fmt::Display for ErrorKind {
             Self::LabelIsReservedWord => "label shadows a reserved word",
             Self::IoError => "I/O error",
             Self::MacroError => "macro error",
+            Self::UnresolvedExternal => "unresolved external symbol",
+            Self::ConditionalError => "conditional assembly error",
         };
         f.write_str(s)
     }
@@ -179,3 +286,35 @@ impl std::fmt::Display for AsmError {
 /// Make AsmError compatible with the standard Rust error-handling ecosystem.
 /// This allows it to be used with `?`, `Box<dyn Error>`, `anyhow`, etc.
 impl std::error::Error for AsmError {}
+
+/// The pipeline stage that reported a diagnostic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Preprocess,
+    Macro,
+    Lex,
+    Parse,
+    FirstPass,
+    Encode,
+}
+
+impl Stage {
+    /// Lower-case name used in machine-readable output.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Preprocess => "preprocess",
+            Self::Macro => "macro",
+            Self::Lex => "lex",
+            Self::Parse => "parse",
+            Self::FirstPass => "first-pass",
+            Self::Encode => "encode",
+        }
+    }
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -10,15 +10,31 @@
 //! 2. **Address Calculation**: Tracks the location counter as it processes each line
 //! 3. **Structure Validation**: Ensures .ORIG comes first, .END is present, no duplicates
 //! 4. **Overflow Detection**: Checks that the program doesn't exceed 16-bit address space
+//! 5. **Segment Layout**: Tracks every `.ORIG`/`.END` section and rejects overlaps
+//! 6. **.BLKW Counts**: Evaluates count expressions (`.BLKW SIZE*2`), since they
+//!    move the location counter; all other expressions wait for the encoder
 //!
 //! ## State Machine
 //!
 //! The first pass uses a state machine with three states:
 //! - `WaitingForOrig`: Initial state, expecting .ORIG directive
-//! - `Processing`: Normal processing after .ORIG
-//! - `AfterEnd`: After .END directive (ignores subsequent lines)
+//! - `Processing`: Normal processing inside an `.ORIG`/`.END` section
+//! - `AfterEnd`: After .END directive (ignores everything except a new `.ORIG`)
 //!
 //! This replaces error-prone boolean flags and makes the logic clearer.
+//!
+//! ## Multiple Sections
+//!
+//! A file may contain several `.ORIG`/`.END` sections, e.g. a main program at
+//! x3000, a data table at x4000 and a trap handler at x1000. Each section has
+//! its own location counter, but labels live in one shared symbol table so
+//! code in one section can refer to labels in another.
+//!
+//! ## Linkage
+//!
+//! `.EXTERNAL` names are left out of the symbol table: the encoder turns every
+//! use into a relocation for the linker.  `.GLOBAL` names must be defined in
+//! this file, and an `.EXTERNAL` name must not be.
 
 pub mod symbol_table;
 
@@ -26,9 +42,9 @@ pub mod symbol_table;
 mod tests;
 
 use crate::error::{AsmError, ErrorKind, Span};
-use crate::parser::ast::{Instruction, LineContent, SourceLine};
+use crate::parser::ast::{Expr, Instruction, LineContent, SourceLine};
 use crate::warning::AsmWarning;
-use symbol_table::SymbolTable;
+use symbol_table::{SymbolKind, SymbolTable};
 
 pub struct FirstPassResult {
     pub symbol_table: SymbolTable,
@@ -36,6 +52,10 @@ pub struct FirstPassResult {
     pub orig_address: u16,
     pub errors: Vec<AsmError>,
     pub warnings: Vec<AsmWarning>,
+    /// Names declared `.EXTERNAL`, in declaration order.
+    pub externals: Vec<String>,
+    /// Names declared `.GLOBAL`, in declaration order.
+    pub globals: Vec<String>,
 }
 
 impl FirstPassResult {
@@ -66,19 +86,44 @@ pub fn first_pass(lines: Vec<SourceLine>) -> FirstPassResult {
     let mut location_counter: Option<u16> = None;
     let mut orig_address: u16 = 0;
     let mut state = AssemblerState::WaitingForOrig;
+    let mut sections: Vec<SectionExtent> = Vec::new();
+    let mut linkage = Linkage::default();
 
     for line in &lines {
         match state {
-            AssemblerState::WaitingForOrig => match &line.content {
+            AssemblerState::WaitingForOrig | AssemblerState::AfterEnd => match &line.content {
                 LineContent::Orig(addr) => {
+                    if state == AssemblerState::WaitingForOrig {
+                        orig_address = *addr;
+                    }
                     state = AssemblerState::Processing;
-                    orig_address = *addr;
                     location_counter = Some(*addr);
+                    sections.push(SectionExtent::new(*addr, line.span));
                     if let Some(ref label) = line.label {
-                        record_label(&mut symbol_table, label, *addr, line.span, &mut errors);
+                        record_label(
+                            &mut symbol_table,
+                            label,
+                            *addr,
+                            sections.len() - 1,
+                            line.span,
+                            &lines,
+                            &mut errors,
+                        );
                     }
                     continue;
                 }
+                // Constants may be defined outside any section.
+                LineContent::Equ(_) | LineContent::Set(_) => {
+                    record_constant(&mut symbol_table, line, &lines, &mut errors);
+                    continue;
+                }
+                // So may linkage declarations.
+                LineContent::External(_) | LineContent::Global(_) => {
+                    linkage.record(line);
+                    continue;
+                }
+                // Anything between an .END and the next .ORIG is ignored.
+                _ if state == AssemblerState::AfterEnd => continue,
                 LineContent::Empty => continue,
                 _ => {
                     errors.push(AsmError::new(
@@ -89,41 +134,58 @@ pub fn first_pass(lines: Vec<SourceLine>) -> FirstPassResult {
                     state = AssemblerState::Processing;
                     orig_address = 0x3000;
                     location_counter = Some(0x3000);
+                    sections.push(SectionExtent::new(0x3000, line.span));
                 }
             },
-            AssemblerState::AfterEnd => continue,
             AssemblerState::Processing => {}
         }
 
+        // A `.ORIG` inside an open section: report the missing .END, then start
+        // the new section anyway so later labels get the addresses the user meant.
+        if let LineContent::Orig(addr) = &line.content {
+            let open = sections.last().map_or(orig_address, |s| s.origin);
+            errors.push(AsmError::new(
+                ErrorKind::MultipleOrig,
+                format!(
+                    ".ORIG x{addr:04X} starts a new section before the section at x{open:04X} was closed with .END"
+                ),
+                line.span,
+            ));
+            location_counter = Some(*addr);
+            sections.push(SectionExtent::new(*addr, line.span));
+        }
+
+        // A .EQU/.SET label names the constant, not the current address.
+        if matches!(line.content, LineContent::Equ(_) | LineContent::Set(_)) {
+            record_constant(&mut symbol_table, line, &lines, &mut errors);
+            continue;
+        }
+
+        linkage.record(line);
+
         let lc = location_counter.unwrap();
 
         if let Some(ref label) = line.label {
-            record_label(&mut symbol_table, label, lc, line.span, &mut errors);
+            let section = sections.len() - 1;
+            record_label(
+                &mut symbol_table,
+                label,
+                lc,
+                section,
+                line.span,
+                &lines,
+                &mut errors,
+            );
         }
 
-        // Handle special cases before word counting
-        match &line.content {
-            LineContent::Orig(_) => {
-                errors.push(AsmError::new(
-                    ErrorKind::MultipleOrig,
-                    "Multiple .ORIG directives are not supported",
-                    line.span,
-                ));
-            }
-            LineContent::End => {
-                state = AssemblerState::AfterEnd;
-            }
-            LineContent::Blkw(n) if *n == 0 => {
-                errors.push(AsmError::new(
-                    ErrorKind::InvalidBlkwCount,
-                    ".BLKW count must be positive",
-                    line.span,
-                ));
-            }
-            _ => {}
+        if matches!(line.content, LineContent::End) {
+            state = AssemblerState::AfterEnd;
         }
 
-        let words = line.content.word_count();
+        let words = match &line.content {
+            LineContent::Blkw(count) => blkw_count(count, &symbol_table, &mut errors),
+            content => content.word_count(),
+        };
 
         // Check for address overflow (LC-3 only has 16-bit address space)
         let new_lc = (lc as u32) + words;
@@ -137,13 +199,19 @@ pub fn first_pass(lines: Vec<SourceLine>) -> FirstPassResult {
         } else {
             location_counter = Some(new_lc as u16);
         }
+        if let Some(section) = sections.last_mut() {
+            section.end = new_lc.min(0x10000);
+        }
     }
 
+    check_section_overlap(&sections, &mut errors);
+    linkage.check(&symbol_table, &lines, &mut errors);
+
     if state == AssemblerState::WaitingForOrig {
         errors.push(AsmError::new(
             ErrorKind::MissingOrig,
             "No .ORIG directive found",
-            Span { line: 1, col: 1 },
+            Span::point(1, 1),
         ));
     }
 
@@ -151,7 +219,7 @@ pub fn first_pass(lines: Vec<SourceLine>) -> FirstPassResult {
         errors.push(AsmError::new(
             ErrorKind::MissingEnd,
             "No .END directive found",
-            Span { line: 1, col: 1 },
+            Span::point(1, 1),
         ));
     }
 
@@ -165,6 +233,132 @@ pub fn first_pass(lines: Vec<SourceLine>) -> FirstPassResult {
         orig_address,
         errors,
         warnings,
+        externals: linkage
+            .externals
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect(),
+        globals: linkage.globals.into_iter().map(|(name, _)| name).collect(),
+    }
+}
+
+/// `.EXTERNAL` and `.GLOBAL` declarations, with the span of the first
+/// declaration of each name.
+#[derive(Default)]
+struct Linkage {
+    externals: Vec<(String, Span)>,
+    globals: Vec<(String, Span)>,
+}
+
+impl Linkage {
+    fn record(&mut self, line: &SourceLine) {
+        let (names, list) = match &line.content {
+            LineContent::External(names) => (names, &mut self.externals),
+            LineContent::Global(names) => (names, &mut self.globals),
+            _ => return,
+        };
+        for name in names {
+            if !list.iter().any(|(n, _)| n == name) {
+                list.push((name.clone(), line.span));
+            }
+        }
+    }
+
+    /// Externals must not be defined here; globals must be.
+    fn check(&self, table: &SymbolTable, lines: &[SourceLine], errors: &mut Vec<AsmError>) {
+        for (name, span) in &self.externals {
+            if table.lookup(name).is_some() {
+                errors.push(with_first_definition(
+                    AsmError::new(
+                        ErrorKind::DuplicateLabel,
+                        format!("'{name}' is declared .EXTERNAL but is also defined in this file"),
+                        *span,
+                    ),
+                    lines,
+                    name,
+                ));
+            }
+        }
+        for (name, span) in &self.globals {
+            if table.lookup(name).is_none() {
+                errors.push(AsmError::new(
+                    ErrorKind::UndefinedLabel,
+                    format!("'{name}' is declared .GLOBAL but never defined"),
+                    *span,
+                ));
+            }
+        }
+    }
+}
+
+/// Evaluate a `.BLKW` count. The count decides where every later label lands,
+/// so it can only use symbols defined above the directive. Invalid counts are
+/// reported and reserve no words.
+fn blkw_count(count: &Expr, table: &SymbolTable, errors: &mut Vec<AsmError>) -> u32 {
+    let value = match count.eval(&mut |name| table.value(name)) {
+        Ok(value) => value,
+        Err(mut err) => {
+            if err.kind == ErrorKind::UndefinedLabel {
+                err.message
+                    .push_str(" (a .BLKW count may only use symbols defined above it)");
+            }
+            errors.push(err);
+            return 0;
+        }
+    };
+    // Reject negative or zero counts with a clear error. Without this check,
+    // `.BLKW #-1` would cast to 65535 and silently allocate a huge block.
+    if !(1..=0xFFFF).contains(&value) {
+        errors.push(AsmError::new(
+            ErrorKind::InvalidBlkwCount,
+            format!(".BLKW count {value} is out of range (must be 1-65535)"),
+            count.span,
+        ));
+        return 0;
+    }
+    value as u32
+}
+
+/// Address range `[origin, end)` covered by one `.ORIG` section.
+struct SectionExtent {
+    origin: u16,
+    end: u32,
+    span: Span,
+}
+
+impl SectionExtent {
+    fn new(origin: u16, span: Span) -> Self {
+        Self {
+            origin,
+            end: origin as u32,
+            span,
+        }
+    }
+}
+
+/// Report every pair of sections whose address ranges overlap.
+///
+/// The error is attached to the `.ORIG` of the later section, since that is
+/// usually the one the user just added.
+fn check_section_overlap(sections: &[SectionExtent], errors: &mut Vec<AsmError>) {
+    for (i, later) in sections.iter().enumerate() {
+        for earlier in &sections[..i] {
+            let overlaps =
+                (later.origin as u32) < earlier.end && (earlier.origin as u32) < later.end;
+            if overlaps {
+                errors.push(AsmError::new(
+                    ErrorKind::SegmentOverlap,
+                    format!(
+                        "Section x{:04X}-x{:04X} overlaps the section at x{:04X}-x{:04X}",
+                        later.origin,
+                        later.end - 1,
+                        earlier.origin,
+                        earlier.end - 1
+                    ),
+                    later.span,
+                ));
+            }
+        }
     }
 }
 
@@ -176,7 +370,11 @@ fn detect_unreachable_code(lines: &[SourceLine]) -> Vec<AsmWarning> {
 
     for line in lines {
         match &line.content {
-            LineContent::Orig(_) | LineContent::End | LineContent::Empty => {
+            LineContent::Orig(_)
+            | LineContent::End
+            | LineContent::Empty
+            | LineContent::Equ(_)
+            | LineContent::Set(_) => {
                 // Structural lines — reset or ignore
                 if matches!(line.content, LineContent::End) {
                     after_terminator = false;
@@ -232,13 +430,15 @@ fn is_unconditional_terminator(content: &LineContent) -> bool {
 /// Directive names, however, are only recognised when preceded by a `.`; without the dot
 /// they fall through to `Label(…)`, so this check catches the most likely user mistake
 /// of writing e.g. `FILL ADD R1, R2, R3` intending `FILL` as a data label.
-const DIRECTIVE_RESERVED_WORDS: &[&str] = &["ORIG", "END", "FILL", "BLKW", "STRINGZ"];
+const DIRECTIVE_RESERVED_WORDS: &[&str] = &["ORIG", "END", "FILL", "BLKW", "STRINGZ", "EQU", "SET"];
 
 fn record_label(
     table: &mut SymbolTable,
     label: &str,
     address: u16,
+    section: usize,
     span: Span,
+    lines: &[SourceLine],
     errors: &mut Vec<AsmError>,
 ) {
     // Flag labels that shadow assembler directives.  These are almost certainly typos
@@ -251,9 +451,101 @@ fn record_label(
 
     // Single lookup: if Some, it's a duplicate; if None, insert it.
     // The old code called `contains` (hash lookup) then `get` (another lookup).
-    if let Some(first_addr) = table.get(label) {
-        errors.push(AsmError::duplicate_label(label, first_addr, span));
-    } else {
-        table.insert(label.to_string(), address);
+    match table.lookup(label) {
+        Some(sym) if sym.kind == SymbolKind::Label => {
+            errors.push(with_first_definition(
+                AsmError::duplicate_label(label, sym.value as u16, span),
+                lines,
+                label,
+            ));
+        }
+        Some(_) => errors.push(with_first_definition(
+            AsmError::new(
+                ErrorKind::DuplicateLabel,
+                format!("Label '{label}' is already defined as a constant"),
+                span,
+            ),
+            lines,
+            label,
+        )),
+        None => table.insert_in_section(label.to_string(), address, section),
+    }
+}
+
+/// Point a duplicate-definition error at the line that first defined `name`.
+fn with_first_definition(err: AsmError, lines: &[SourceLine], name: &str) -> AsmError {
+    match lines.iter().find(|l| l.label.as_deref() == Some(name)) {
+        Some(first) if first.span != err.span => err.with_label(first.span, "first defined here"),
+        _ => err,
+    }
+}
+
+/// Define the constant named by the label of a `.EQU`/`.SET` line.
+///
+/// The value is evaluated immediately, so it can only use symbols defined
+/// above it. `.SET` may redefine an earlier `.SET` of the same name; every
+/// other redefinition is a duplicate.
+fn record_constant(
+    table: &mut SymbolTable,
+    line: &SourceLine,
+    lines: &[SourceLine],
+    errors: &mut Vec<AsmError>,
+) {
+    let (expr, kind, directive) = match &line.content {
+        LineContent::Equ(expr) => (expr, SymbolKind::Constant, ".EQU"),
+        LineContent::Set(expr) => (expr, SymbolKind::Variable, ".SET"),
+        _ => return,
+    };
+    // A nameless .EQU/.SET was already reported by the parser.
+    let Some(name) = &line.label else {
+        return;
+    };
+
+    if DIRECTIVE_RESERVED_WORDS.contains(&name.as_str()) {
+        errors.push(AsmError::label_is_reserved_word(name, line.span));
+    }
+
+    let value = match expr.eval(&mut |n| table.value(n)) {
+        Ok(value) => value,
+        Err(mut err) => {
+            if err.kind == ErrorKind::UndefinedLabel {
+                err.message.push_str(&format!(
+                    " (a {directive} value may only use symbols defined above it)"
+                ));
+            }
+            errors.push(err);
+            return;
+        }
+    };
+    if !(i16::MIN as i32..=0xFFFF_i32).contains(&value) {
+        errors.push(AsmError::invalid_operand_type(
+            format!(
+                "{directive} value {value} for '{name}' is out of 16-bit range (-32768 to 65535)"
+            ),
+            expr.span,
+        ));
+        return;
+    }
+
+    match table.lookup(name) {
+        Some(sym) if !(sym.kind == SymbolKind::Variable && kind == SymbolKind::Variable) => {
+            let what = match sym.kind {
+                SymbolKind::Label => "a label",
+                SymbolKind::Constant => "a .EQU constant",
+                SymbolKind::Variable => "a .SET variable",
+            };
+            errors.push(with_first_definition(
+                AsmError::new(
+                    ErrorKind::DuplicateLabel,
+                    format!(
+                        "'{name}' is already defined as {what}; only .SET variables can be reassigned"
+                    ),
+                    line.span,
+                ),
+                lines,
+                name,
+            ));
+        }
+        _ => table.define(name.clone(), value, kind),
     }
 }
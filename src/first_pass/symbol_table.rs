@@ -1,11 +1,37 @@
-/// Insertion-ordered symbol table mapping labels to addresses.
+/// What a symbol stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    /// A label: the address of the line it is defined on.
+    Label,
+    /// A `.EQU` constant: fixed once defined.
+    Constant,
+    /// A `.SET` variable: may be reassigned; the table holds its latest value.
+    Variable,
+}
+
+/// A symbol's value and kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol {
+    /// Address for labels; any 16-bit value (signed or unsigned) for constants.
+    pub value: i32,
+    pub kind: SymbolKind,
+    /// For labels, the index of the `.ORIG` section they were defined in.
+    pub section: Option<usize>,
+}
+
+/// Insertion-ordered symbol table mapping names to labels and constants.
 ///
-/// Uses a single `Vec<(String, u16)>` instead of the previous `HashMap + Vec`
+/// Uses a single `Vec<(String, Symbol)>` instead of the previous `HashMap + Vec`
 /// approach, eliminating string duplication. Lookups are O(n) linear scans,
 /// which is perfectly adequate for LC-3 programs (typically <50 labels).
+///
+/// The address-oriented accessors (`get`, `iter`, `sorted_by_*`) only see
+/// labels, so constants never show up as addresses in listings, `.sym` files
+/// or the simulator. Use [`lookup`](Self::lookup) and
+/// [`constants`](Self::constants) for everything else.
 #[derive(Debug, Clone)]
 pub struct SymbolTable {
-    entries: Vec<(String, u16)>,
+    entries: Vec<(String, Symbol)>,
 }
 
 impl Default for SymbolTable {
@@ -21,23 +47,69 @@ impl SymbolTable {
         }
     }
 
+    /// Define (or move) the label `label` at `address`.
     pub fn insert(&mut self, label: String, address: u16) {
-        // If the label already exists, update its address in place.
-        // Otherwise, append a new entry to preserve insertion order.
-        if let Some(entry) = self.entries.iter_mut().find(|(l, _)| l == &label) {
-            entry.1 = address;
+        self.define(label, i32::from(address), SymbolKind::Label);
+    }
+
+    /// Define the label `label` at `address` in `.ORIG` section `section`.
+    pub fn insert_in_section(&mut self, label: String, address: u16, section: usize) {
+        self.put(
+            label,
+            Symbol {
+                value: i32::from(address),
+                kind: SymbolKind::Label,
+                section: Some(section),
+            },
+        );
+    }
+
+    /// Define any kind of symbol. Redefining a name replaces its value and
+    /// kind in place, preserving the original insertion order.
+    pub fn define(&mut self, name: String, value: i32, kind: SymbolKind) {
+        let symbol = Symbol {
+            value,
+            kind,
+            section: None,
+        };
+        self.put(name, symbol);
+    }
+
+    /// Add `symbol`, or replace an existing one of the same name in place.
+    fn put(&mut self, name: String, symbol: Symbol) {
+        if let Some(entry) = self.entries.iter_mut().find(|(n, _)| n == &name) {
+            entry.1 = symbol;
         } else {
-            self.entries.push((label, address));
+            self.entries.push((name, symbol));
         }
     }
 
-    pub fn get(&self, label: &str) -> Option<u16> {
+    /// Look up a symbol of any kind.
+    pub fn lookup(&self, name: &str) -> Option<Symbol> {
         self.entries
             .iter()
-            .find(|(l, _)| l == label)
-            .map(|(_, addr)| *addr)
+            .find(|(n, _)| n == name)
+            .map(|(_, sym)| *sym)
+    }
+
+    /// The value of any symbol, as used when evaluating expressions.
+    pub fn value(&self, name: &str) -> Option<i32> {
+        self.lookup(name).map(|sym| sym.value)
+    }
+
+    /// The address of the label `label` (`None` for constants).
+    pub fn get(&self, label: &str) -> Option<u16> {
+        self.lookup(label)
+            .filter(|sym| sym.kind == SymbolKind::Label)
+            .map(|sym| sym.value as u16)
     }
 
+    /// The index of the `.ORIG` section the label `label` was defined in.
+    pub fn section(&self, label: &str) -> Option<usize> {
+        self.lookup(label).and_then(|sym| sym.section)
+    }
+
+    /// Number of symbols of every kind.
     pub fn len(&self) -> usize {
         self.entries.len()
     }
@@ -46,18 +118,34 @@ impl SymbolTable {
         self.entries.is_empty()
     }
 
+    /// Labels and their addresses, in definition order.
     pub fn iter(&self) -> impl Iterator<Item = (&str, u16)> {
-        self.entries.iter().map(|(l, a)| (l.as_str(), *a))
+        self.entries
+            .iter()
+            .filter(|(_, sym)| sym.kind == SymbolKind::Label)
+            .map(|(l, sym)| (l.as_str(), sym.value as u16))
+    }
+
+    /// `.EQU` constants and `.SET` variables, sorted alphabetically.
+    pub fn constants(&self) -> Vec<(&str, Symbol)> {
+        let mut v: Vec<(&str, Symbol)> = self
+            .entries
+            .iter()
+            .filter(|(_, sym)| sym.kind != SymbolKind::Label)
+            .map(|(n, sym)| (n.as_str(), *sym))
+            .collect();
+        v.sort_by(|a, b| a.0.cmp(b.0));
+        v
     }
 
-    /// Return entries sorted alphabetically by label name.
+    /// Return label entries sorted alphabetically by label name.
     pub fn sorted_by_name(&self) -> Vec<(&str, u16)> {
         let mut v: Vec<(&str, u16)> = self.iter().collect();
         v.sort_by(|a, b| a.0.cmp(b.0));
         v
     }
 
-    /// Return entries sorted by address (ascending).
+    /// Return label entries sorted by address (ascending).
     pub fn sorted_by_address(&self) -> Vec<(&str, u16)> {
         let mut v: Vec<(&str, u16)> = self.iter().collect();
         v.sort_by_key(|(_, addr)| *addr);
@@ -57,10 +57,15 @@ fn empty_stringz() {
 #[test]
 fn duplicate_label_error() {
     let result = run_first_pass(".ORIG x3000\nLOOP ADD R1, R1, #1\nLOOP ADD R1, R1, #1\n.END\n");
-    assert!(result
+    let dup = result
         .errors
         .iter()
-        .any(|e| matches!(e.kind, crate::error::ErrorKind::DuplicateLabel)));
+        .find(|e| matches!(e.kind, crate::error::ErrorKind::DuplicateLabel))
+        .unwrap();
+    // The secondary label points back at the first definition.
+    assert_eq!(dup.span.line, 3);
+    assert_eq!(dup.labels[0].span.line, 2);
+    assert_eq!(dup.labels[0].message, "first defined here");
 }
 
 #[test]
@@ -88,6 +93,114 @@ fn content_after_end_ignored() {
     assert!(result.symbol_table.get("EXTRA_LABEL").is_none());
 }
 
+#[test]
+fn multiple_sections_share_symbol_table() {
+    let result = run_first_pass(
+        ".ORIG x3000\nMAIN LD R0, VALUE\nHALT\n.END\n.ORIG x4000\nVALUE .FILL #7\n.END\n",
+    );
+    assert!(result.errors.is_empty(), "{:?}", result.errors);
+    assert_eq!(result.orig_address, 0x3000);
+    assert_eq!(result.symbol_table.get("MAIN"), Some(0x3000));
+    assert_eq!(result.symbol_table.get("VALUE"), Some(0x4000));
+}
+
+#[test]
+fn orig_inside_open_section_error() {
+    let result = run_first_pass(".ORIG x3000\nHALT\n.ORIG x4000\nNEXT HALT\n.END\n");
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| matches!(e.kind, crate::error::ErrorKind::MultipleOrig)));
+    // The nested .ORIG still moves the location counter.
+    assert_eq!(result.symbol_table.get("NEXT"), Some(0x4000));
+}
+
+#[test]
+fn overlapping_sections_error() {
+    let result = run_first_pass(".ORIG x3000\n.BLKW #4\n.END\n.ORIG x3002\nHALT\n.END\n");
+    let overlap: Vec<_> = result
+        .errors
+        .iter()
+        .filter(|e| matches!(e.kind, crate::error::ErrorKind::SegmentOverlap))
+        .collect();
+    assert_eq!(overlap.len(), 1);
+    assert_eq!(overlap[0].span.line, 4);
+}
+
+#[test]
+fn adjacent_sections_do_not_overlap() {
+    let result = run_first_pass(".ORIG x3000\n.BLKW #2\n.END\n.ORIG x3002\nHALT\n.END\n");
+    assert!(result.errors.is_empty(), "{:?}", result.errors);
+}
+
+#[test]
+fn blkw_count_expression() {
+    let result =
+        run_first_pass(".ORIG x3000\nSTART .BLKW #2\nBUF .BLKW (START-x2FFE)*2\nNEXT HALT\n.END\n");
+    assert!(result.errors.is_empty(), "{:?}", result.errors);
+    assert_eq!(result.symbol_table.get("NEXT"), Some(0x3006));
+}
+
+#[test]
+fn blkw_forward_reference_error() {
+    let result = run_first_pass(".ORIG x3000\n.BLKW LATER*2\nLATER HALT\n.END\n");
+    let err = result
+        .errors
+        .iter()
+        .find(|e| matches!(e.kind, crate::error::ErrorKind::UndefinedLabel))
+        .expect("forward reference in .BLKW should be rejected");
+    assert_eq!((err.span.line, err.span.col), (2, 7));
+}
+
+#[test]
+fn blkw_expression_out_of_range() {
+    let result = run_first_pass(".ORIG x3000\n.BLKW 2-3\n.END\n");
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| matches!(e.kind, crate::error::ErrorKind::InvalidBlkwCount)));
+}
+
+#[test]
+fn equ_defines_constant_not_address() {
+    let result = run_first_pass("SIZE .EQU #10\n.ORIG x3000\nBUF .BLKW SIZE\nNEXT HALT\n.END\n");
+    assert!(result.errors.is_empty(), "{:?}", result.errors);
+    assert_eq!(result.symbol_table.get("SIZE"), None);
+    assert_eq!(result.symbol_table.value("SIZE"), Some(10));
+    // The .EQU line takes no space and does not start the program.
+    assert_eq!(result.orig_address, 0x3000);
+    assert_eq!(result.symbol_table.get("NEXT"), Some(0x300A));
+}
+
+#[test]
+fn set_can_be_reassigned() {
+    let result =
+        run_first_pass(".ORIG x3000\nN .SET #1\nA .BLKW N\nN .SET N+2\nB .BLKW N\nC HALT\n.END\n");
+    assert!(result.errors.is_empty(), "{:?}", result.errors);
+    assert_eq!(result.symbol_table.get("B"), Some(0x3001));
+    assert_eq!(result.symbol_table.get("C"), Some(0x3004));
+    assert_eq!(result.symbol_table.value("N"), Some(3));
+}
+
+#[test]
+fn equ_redefinition_error() {
+    let result = run_first_pass(".ORIG x3000\nK .EQU #1\nK .SET #2\nHALT\n.END\n");
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| matches!(e.kind, crate::error::ErrorKind::DuplicateLabel)));
+    assert_eq!(result.symbol_table.value("K"), Some(1));
+}
+
+#[test]
+fn label_clashing_with_constant_error() {
+    let result = run_first_pass(".ORIG x3000\nK .EQU #1\nK HALT\n.END\n");
+    assert!(result
+        .errors
+        .iter()
+        .any(|e| matches!(e.kind, crate::error::ErrorKind::DuplicateLabel)));
+}
+
 #[test]
 fn fill_with_label_still_advances() {
     let result = run_first_pass(".ORIG x3000\nDATA .FILL SOMEVAR\nNEXT ADD R1, R1, #1\n.END\n");
@@ -169,3 +282,25 @@ fn normal_label_no_reserved_word_error() {
         "LOOP should not be considered a reserved word"
     );
 }
+
+#[test]
+fn linkage_declarations_are_checked() {
+    let result = run_first_pass(
+        ".EXTERNAL PRINT\n.GLOBAL MAIN, SIZE\nSIZE .EQU 4\n.ORIG x3000\nMAIN JSR PRINT\nHALT\n.END\n",
+    );
+    assert!(result.errors.is_empty(), "{:?}", result.errors);
+    assert_eq!(result.externals, vec!["PRINT"]);
+    assert_eq!(result.globals, vec!["MAIN", "SIZE"]);
+    assert!(result.symbol_table.lookup("PRINT").is_none());
+
+    let result = run_first_pass(".EXTERNAL MAIN\n.GLOBAL NOPE\n.ORIG x3000\nMAIN HALT\n.END\n");
+    let kinds: Vec<_> = result.errors.iter().map(|e| e.kind.clone()).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            crate::error::ErrorKind::DuplicateLabel,
+            crate::error::ErrorKind::UndefinedLabel
+        ]
+    );
+    assert_eq!(result.errors[0].labels[0].message, "first defined here");
+}
@@ -0,0 +1,471 @@
+//! # Source Formatter
+//!
+//! Rewrites LC-3 source in a canonical layout, as used by `lc3-fmt`:
+//!
+//! ```text
+//! ; Count down from ten
+//!         .ORIG x3000
+//! LOOP    ADD   R0, R0, #-1 ; decrement
+//!         BRp   LOOP
+//!         HALT
+//!         .END
+//! ```
+//!
+//! - Labels start in column 0; mnemonics, operands and trailing comments are
+//!   each aligned to one column across the file.
+//! - Mnemonics, directives and registers are upper case; `BR` condition
+//!   flags stay lower case (`BRnz`).
+//! - Literals keep their base but get a lower-case prefix and upper-case
+//!   digits (`xFF`, `b0101`).  `.ORIG` and `TRAP` operands, which are
+//!   addresses, are always written in hex.
+//! - Operands are separated by `", "`; expressions are written without
+//!   spaces.
+//!
+//! Every comment and blank line is kept.  Full-line comments that were
+//! indented move to the mnemonic column; the rest stay in column 0.  Macro
+//! bodies are formatted like any other lines, with `%PARAM` and `%%LABEL`
+//! kept as written.  Lines the lexer rejects — including `.INCLUDE`,
+//! `.MACRO`/`.ENDM`, `.IF`/`.ENDIF` and `.REPT`/`.ENDR` — are copied
+//! unchanged apart from trailing whitespace.  Formatting is idempotent:
+//! formatting the output again yields the same text.
+
+use crate::lexer::token::{Token, TokenKind};
+use crate::lexer::tokenize;
+use crate::macro_expand;
+
+/// Minimum width of the label column, so short labels still line up with the
+/// usual 8-column tab stop.
+const MIN_LABEL_WIDTH: usize = 8;
+
+/// Stands in for the `%` of macro parameters while a line is lexed, so
+/// `%R` and `%%LOOP` read as labels.
+const PARAM_MARKER: &str = "__pct__";
+
+/// Stands in for `#%`, as in `ADD R0, R0, #%N`.
+const IMM_PARAM_MARKER: &str = "__hashpct__";
+
+/// One source line, split into its fields.
+enum Line {
+    Blank,
+    /// Copied as written (without trailing whitespace).
+    Verbatim(String),
+    /// A comment on a line of its own; `indented` if it did not start the line.
+    Comment {
+        text: String,
+        indented: bool,
+    },
+    Code {
+        label: Option<String>,
+        mnemonic: Option<String>,
+        operands: String,
+        comment: Option<String>,
+    },
+}
+
+/// Format a whole source file.  The result always ends with a newline.
+#[must_use]
+pub fn format_source(source: &str) -> String {
+    let macros: Vec<String> = macro_expand::expand(source)
+        .macros
+        .into_iter()
+        .map(|m| m.name.to_ascii_uppercase())
+        .collect();
+    let lines: Vec<Line> = source.lines().map(|l| split_line(l, &macros)).collect();
+
+    let label_width = lines
+        .iter()
+        .filter_map(|l| match l {
+            Line::Code {
+                label: Some(label), ..
+            } => Some(label.chars().count() + 1),
+            _ => None,
+        })
+        .fold(MIN_LABEL_WIDTH, usize::max);
+    let mnemonic_width = lines
+        .iter()
+        .filter_map(|l| match l {
+            Line::Code {
+                mnemonic: Some(m),
+                operands,
+                ..
+            } if !operands.is_empty() => Some(m.chars().count() + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    let code = |label: &Option<String>, mnemonic: &Option<String>, operands: &str| {
+        let mut out = String::new();
+        if let Some(label) = label {
+            out.push_str(label);
+        }
+        if let Some(mnemonic) = mnemonic {
+            pad_to(&mut out, label_width);
+            out.push_str(mnemonic);
+            if !operands.is_empty() {
+                pad_to(&mut out, label_width + mnemonic_width);
+                out.push_str(operands);
+            }
+        }
+        out
+    };
+    let comment_column = lines
+        .iter()
+        .filter_map(|l| match l {
+            Line::Code {
+                label,
+                mnemonic,
+                operands,
+                comment: Some(_),
+            } => Some(code(label, mnemonic, operands).chars().count() + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+
+    let mut out = String::with_capacity(source.len());
+    for line in &lines {
+        match line {
+            Line::Blank => {}
+            Line::Verbatim(text) => out.push_str(text),
+            Line::Comment { text, indented } => {
+                if *indented {
+                    pad_to(&mut out, label_width);
+                }
+                out.push_str(text);
+            }
+            Line::Code {
+                label,
+                mnemonic,
+                operands,
+                comment,
+            } => {
+                out.push_str(&code(label, mnemonic, operands));
+                if let Some(comment) = comment {
+                    pad_to(&mut out, comment_column);
+                    out.push_str(comment);
+                }
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Returns `true` if `source` is already formatted.
+#[must_use]
+pub fn is_formatted(source: &str) -> bool {
+    format_source(source) == source
+}
+
+/// Pad the current (last) line of `out` with spaces up to `column`, leaving
+/// at least one space after any existing text.
+fn pad_to(out: &mut String, column: usize) {
+    let line_len = out.rsplit('\n').next().unwrap_or("").chars().count();
+    let target = if line_len == 0 {
+        column
+    } else {
+        column.max(line_len + 1)
+    };
+    out.extend(std::iter::repeat(' ').take(target - line_len));
+}
+
+fn split_line(text: &str, macros: &[String]) -> Line {
+    let trimmed = text.trim_end();
+    if trimmed.trim_start().is_empty() {
+        return Line::Blank;
+    }
+
+    let lexed = tokenize(&hide_params(trimmed));
+    if !lexed.errors.is_empty() {
+        return Line::Verbatim(trimmed.to_string());
+    }
+    let mut tokens: Vec<&Token> = lexed
+        .tokens
+        .iter()
+        .filter(|t| !matches!(t.kind, TokenKind::Eof | TokenKind::Newline))
+        .collect();
+
+    let comment = match tokens.last() {
+        Some(t) if matches!(t.kind, TokenKind::Comment(_)) => {
+            tokens.pop().map(|t| t.lexeme.trim_end().to_string())
+        }
+        _ => None,
+    }
+    .map(|c| show_params(&c));
+    if tokens.is_empty() {
+        return Line::Comment {
+            text: comment.unwrap_or_default(),
+            indented: trimmed.starts_with([' ', '\t']),
+        };
+    }
+
+    // A leading word is a label unless it invokes a macro or is followed by
+    // operands (a macro from an included file).
+    let mut rest = &tokens[..];
+    let mut label = None;
+    if let TokenKind::Label(name) = &rest[0].kind {
+        let starts_instruction = |t: &Token| match &t.kind {
+            TokenKind::Label(n) => macros.contains(n),
+            kind => is_mnemonic(kind),
+        };
+        let next_is_operand = rest.get(1).is_some_and(|t| !starts_instruction(t));
+        if !macros.contains(name) && !next_is_operand {
+            label = Some(show_params(&rest[0].lexeme));
+            rest = &rest[1..];
+        }
+    }
+
+    let mut mnemonic = None;
+    let mut operands = String::new();
+    if let Some((first, args)) = rest.split_first() {
+        mnemonic = Some(show_params(&mnemonic_text(first)));
+        operands = show_params(&operand_text(&first.kind, args));
+    }
+
+    Line::Code {
+        label,
+        mnemonic,
+        operands,
+        comment,
+    }
+}
+
+/// Replace each macro parameter or local label's `%` in `line` with
+/// [`PARAM_MARKER`] (`#%` with [`IMM_PARAM_MARKER`]).  Lines that already
+/// contain a marker are returned unchanged.
+fn hide_params(line: &str) -> String {
+    if line.contains(PARAM_MARKER) || line.contains(IMM_PARAM_MARKER) {
+        return line.to_string();
+    }
+    let starts_name =
+        |c: Option<char>| c.is_some_and(|c| c == '%' || c == '_' || c.is_ascii_alphanumeric());
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let after = |n: usize| rest[n..].chars().next();
+        if c == '#' && after(1) == Some('%') && starts_name(after(2)) {
+            out.push_str(IMM_PARAM_MARKER);
+            rest = &rest[2..];
+        } else if c == '%' && starts_name(after(1)) {
+            out.push_str(PARAM_MARKER);
+            rest = &rest[1..];
+        } else {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
+}
+
+/// Undo [`hide_params`].
+fn show_params(text: &str) -> String {
+    text.replace(IMM_PARAM_MARKER, "#%")
+        .replace(PARAM_MARKER, "%")
+}
+
+/// True for tokens that can only start the instruction part of a line.
+fn is_mnemonic(kind: &TokenKind) -> bool {
+    use TokenKind::*;
+    matches!(
+        kind,
+        OpAdd
+            | OpAnd
+            | OpNot
+            | OpBr(_)
+            | OpJmp
+            | OpJsr
+            | OpJsrr
+            | OpLd
+            | OpLdi
+            | OpLdr
+            | OpLea
+            | OpSt
+            | OpSti
+            | OpStr
+            | OpTrap
+            | OpRti
+            | PseudoRet
+            | PseudoGetc
+            | PseudoOut
+            | PseudoPuts
+            | PseudoIn
+            | PseudoPutsp
+            | PseudoHalt
+            | DirOrig
+            | DirEnd
+            | DirFill
+            | DirBlkw
+            | DirStringz
+            | DirEqu
+            | DirSet
+            | DirExternal
+            | DirGlobal
+    )
+}
+
+fn mnemonic_text(tok: &Token) -> String {
+    match tok.kind {
+        TokenKind::OpBr(flags) if tok.lexeme.len() > 2 => {
+            let mut m = String::from("BR");
+            for (set, ch) in [(flags.n, 'n'), (flags.z, 'z'), (flags.p, 'p')] {
+                if set {
+                    m.push(ch);
+                }
+            }
+            m
+        }
+        // Macro names are case-insensitive, but keep them as written.
+        TokenKind::Label(_) => tok.lexeme.clone(),
+        _ => tok.lexeme.to_ascii_uppercase(),
+    }
+}
+
+/// The operands after `mnemonic`, comma-separated.
+fn operand_text(mnemonic: &TokenKind, args: &[&Token]) -> String {
+    // A lone number after .ORIG or TRAP is an address / vector: write it in hex.
+    if let [arg] = args {
+        let value = match arg.kind {
+            TokenKind::NumDecimal(v)
+            | TokenKind::NumHex(v)
+            | TokenKind::NumBinary(v)
+            | TokenKind::NumOctal(v) => Some(v as u16),
+            _ => None,
+        };
+        match (mnemonic, value) {
+            (TokenKind::DirOrig, Some(v)) => return format!("x{v:04X}"),
+            (TokenKind::OpTrap, Some(v)) => return format!("x{v:02X}"),
+            _ => {}
+        }
+    }
+
+    let mut out = String::new();
+    let mut prev: Option<&Token> = None;
+    for tok in args {
+        if tok.kind == TokenKind::Comma {
+            out.push_str(", ");
+            prev = None;
+            continue;
+        }
+        if let Some(prev) = prev {
+            if is_word(&prev.kind) && is_word(&tok.kind) {
+                out.push(' ');
+            }
+        }
+        out.push_str(&operand_token_text(tok));
+        prev = Some(tok);
+    }
+    out
+}
+
+/// Tokens that would merge into one if written next to each other.
+fn is_word(kind: &TokenKind) -> bool {
+    !matches!(
+        kind,
+        TokenKind::Plus
+            | TokenKind::Minus
+            | TokenKind::Star
+            | TokenKind::ShiftLeft
+            | TokenKind::ShiftRight
+            | TokenKind::Ampersand
+            | TokenKind::Pipe
+            | TokenKind::LParen
+            | TokenKind::RParen
+            | TokenKind::Hash
+    )
+}
+
+fn operand_token_text(tok: &Token) -> String {
+    let lexeme = &tok.lexeme;
+    match tok.kind {
+        TokenKind::Register(r) => format!("R{r}"),
+        TokenKind::NumHex(_) => format!("x{}", lexeme[1..].to_ascii_uppercase()),
+        TokenKind::NumBinary(_) => format!("b{}", &lexeme[1..]),
+        TokenKind::NumOctal(_) => format!("0o{}", &lexeme[2..]),
+        _ => lexeme.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_columns() {
+        let src = "; demo\n.orig x3000\nloop add r0,r0,#-1 ; dec\n  brp loop\nmessage .stringz \"hi\"\nhalt\n.end\n";
+        assert_eq!(
+            format_source(src),
+            "; demo\n\
+             \x20       .ORIG    x3000\n\
+             loop    ADD      R0, R0, #-1 ; dec\n\
+             \x20       BRp      loop\n\
+             message .STRINGZ \"hi\"\n\
+             \x20       HALT\n\
+             \x20       .END\n"
+        );
+    }
+
+    #[test]
+    fn normalises_literals() {
+        let src = ".ORIG #12288\nTRAP #37\nAND R0, R0, X1f\n.FILL B0101\n.FILL 0O17\n.END\n";
+        let out = format_source(src);
+        assert!(out.contains(".ORIG x3000"), "{out}");
+        assert!(out.contains("TRAP  x25"), "{out}");
+        assert!(out.contains("R0, R0, x1F"), "{out}");
+        assert!(out.contains(".FILL b0101"), "{out}");
+        assert!(out.contains(".FILL 0o17"), "{out}");
+    }
+
+    #[test]
+    fn keeps_comments_blank_lines_and_unlexable_lines() {
+        let src = ".MACRO PUSH %R\n   STR %R, R6, #0\n.ENDM\n\n\n    ; indented\n.ORIG x3000\nPUSH R0\nTOP PUSH R1\n.END\n";
+        let out = format_source(src);
+        assert!(
+            out.starts_with(".MACRO PUSH %R\n        STR   %R, R6, #0\n.ENDM\n\n\n"),
+            "{out}"
+        );
+        assert!(out.contains("\n        ; indented\n"), "{out}");
+        assert!(out.contains("\n        PUSH  R0\n"), "{out}");
+        assert!(out.contains("\nTOP     PUSH  R1\n"), "{out}");
+    }
+
+    #[test]
+    fn leaves_c_style_hex_as_written() {
+        let out = format_source(".FILL 0x10\nAND R0, R0, 0X1f\n");
+        assert!(out.contains(".FILL 0x10\n"), "{out}");
+        assert!(out.contains("R0, R0, 0X1f\n"), "{out}");
+    }
+
+    #[test]
+    fn formats_macro_bodies_like_other_lines() {
+        let src = ".MACRO CLEAR %R, %N\nand %R,%R,#0\n  add r1,r1,#1 ; plain\n%%LOOP add %R,%R,#%N\n brp %%LOOP\n.ENDM\n.orig x3000\nCLEAR r0, 2\n.end\n";
+        assert_eq!(
+            format_source(src),
+            ".MACRO CLEAR %R, %N\n\
+             \x20       AND   %R, %R, #0\n\
+             \x20       ADD   R1, R1, #1 ; plain\n\
+             %%LOOP  ADD   %R, %R, #%N\n\
+             \x20       BRp   %%LOOP\n\
+             .ENDM\n\
+             \x20       .ORIG x3000\n\
+             \x20       CLEAR R0, 2\n\
+             \x20       .END\n"
+        );
+    }
+
+    #[test]
+    fn expressions_lose_inner_spaces() {
+        let out = format_source("LEA R0, TABLE + 3\nADD R1, R1, #-( TEND - TABLE )\n");
+        assert!(out.contains("TABLE+3"), "{out}");
+        assert!(out.contains("#-(TEND-TABLE)"), "{out}");
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let src = "LOOP LD R1,VALUE;c\n BRnzp LOOP\nVERY_LONG_LABEL .FILL x0\n;x\n";
+        let once = format_source(src);
+        assert_eq!(format_source(&once), once);
+        assert!(is_formatted(&once));
+        assert!(!is_formatted(src));
+    }
+}
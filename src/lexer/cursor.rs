@@ -10,6 +10,14 @@
 
 use crate::error::Span;
 
+/// A point in the source: 1-based line and column plus byte offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+    pub offset: usize,
+}
+
 /// A cursor for iterating through source code bytes
 ///
 /// Tracks position in multiple ways:
@@ -40,6 +48,11 @@ impl<'a> Cursor<'a> {
         self.bytes.get(self.pos).map(|&b| b as char)
     }
 
+    /// Look `n` bytes ahead without consuming anything (`peek_nth(0) == peek()`).
+    pub fn peek_nth(&self, n: usize) -> Option<char> {
+        self.bytes.get(self.pos + n).map(|&b| b as char)
+    }
+
     /// Advance to the next byte and return it as a `char`.
     ///
     /// Updates line/column tracking:
@@ -64,16 +77,22 @@ impl<'a> Cursor<'a> {
         self.pos >= self.bytes.len()
     }
 
-    /// Returns `(line, col)` of the current cursor position.
-    pub fn current_pos(&self) -> (usize, usize) {
-        (self.line, self.col)
+    /// Returns the current cursor position.
+    pub fn current_pos(&self) -> Position {
+        Position {
+            line: self.line,
+            col: self.col,
+            offset: self.pos,
+        }
     }
 
-    /// Build a `Span` anchored at the given start position.
-    pub fn make_span(&self, start_line: usize, start_col: usize) -> Span {
+    /// Build a `Span` from `start` to the current position.
+    pub fn make_span(&self, start: Position) -> Span {
         Span {
-            line: start_line,
-            col: start_col,
+            line: start.line,
+            col: start.col,
+            start: start.offset,
+            end: self.pos,
         }
     }
 }
@@ -9,8 +9,11 @@
 //! - **String Literals**: Handles escape sequences (\n, \r, \t, \\, \", \0)
 //! - **Comments**: Line comments starting with semicolon
 //! - **Instructions**: All LC-3 opcodes and pseudo-ops
-//! - **Directives**: .ORIG, .FILL, .BLKW, .STRINGZ, .END
+//! - **Directives**: .ORIG, .FILL, .BLKW, .STRINGZ, .END, .EQU, .SET
 //! - **Branch Variants**: Dynamic parsing of BR, BRn, BRz, BRp, BRnz, BRnp, etc.
+//! - **Expression Operators**: `+ - * << >> & | ( )` for constant expressions
+//!   such as `TABLE+3` or `#-(OFFSET)`; bare digit runs (`2` in `SIZE*2`) are
+//!   decimal literals
 //!
 //! ## Two's Complement Handling
 //!
@@ -25,8 +28,8 @@ pub mod token;
 #[cfg(test)]
 mod tests;
 
-use crate::error::{AsmError, ErrorKind, Span};
-use cursor::Cursor;
+use crate::error::{AsmError, ErrorKind};
+use cursor::{Cursor, Position};
 use token::{BrFlags, Token, TokenKind};
 
 pub struct LexResult {
@@ -78,11 +81,11 @@ pub fn tokenize(source: &str) -> LexResult {
         }
     }
 
-    let (l, c) = cursor.current_pos();
+    let end = cursor.current_pos();
     tokens.push(Token {
         kind: TokenKind::Eof,
         lexeme: String::new(),
-        span: Span { line: l, col: c },
+        span: cursor.make_span(end),
     });
 
     LexResult { tokens, errors }
@@ -98,36 +101,72 @@ fn lex_token(cursor: &mut Cursor) -> Result<Option<Token>, AsmError> {
         return Ok(None);
     }
 
-    let (sl, sc) = cursor.current_pos();
+    let start = cursor.current_pos();
     let ch = cursor.peek().unwrap();
 
     match ch {
-        '\n' | '\r' => lex_newline(cursor, sl, sc),
-        ';' => lex_comment(cursor, sl, sc),
+        '\n' | '\r' => lex_newline(cursor, start),
+        ';' => lex_comment(cursor, start),
         ',' => {
             cursor.advance();
             Ok(Some(Token {
                 kind: TokenKind::Comma,
                 lexeme: ",".into(),
-                span: cursor.make_span(sl, sc),
+                span: cursor.make_span(start),
             }))
         }
-        '"' => lex_string(cursor, sl, sc),
-        '#' => lex_decimal(cursor, sl, sc),
-        '.' => lex_directive(cursor, sl, sc),
-        c if c.is_ascii_alphanumeric() || c == '_' => lex_word(cursor, sl, sc),
+        '"' => lex_string(cursor, start),
+        // `#(` / `#-(` / `#+(`: an immediate written as an expression.
+        '#' if cursor.peek_nth(1) == Some('(')
+            || (matches!(cursor.peek_nth(1), Some('-' | '+'))
+                && cursor.peek_nth(2) == Some('(')) =>
+        {
+            lex_punct(cursor, start, 1, TokenKind::Hash)
+        }
+        '#' => lex_decimal(cursor, start),
+        '+' => lex_punct(cursor, start, 1, TokenKind::Plus),
+        '-' => lex_punct(cursor, start, 1, TokenKind::Minus),
+        '*' => lex_punct(cursor, start, 1, TokenKind::Star),
+        '&' => lex_punct(cursor, start, 1, TokenKind::Ampersand),
+        '|' => lex_punct(cursor, start, 1, TokenKind::Pipe),
+        '(' => lex_punct(cursor, start, 1, TokenKind::LParen),
+        ')' => lex_punct(cursor, start, 1, TokenKind::RParen),
+        '<' if cursor.peek_nth(1) == Some('<') => lex_punct(cursor, start, 2, TokenKind::ShiftLeft),
+        '>' if cursor.peek_nth(1) == Some('>') => {
+            lex_punct(cursor, start, 2, TokenKind::ShiftRight)
+        }
+        '.' => lex_directive(cursor, start),
+        c if c.is_ascii_alphanumeric() || c == '_' => lex_word(cursor, start),
         _ => {
             cursor.advance();
-            Err(AsmError {
-                kind: ErrorKind::UnexpectedCharacter,
-                message: format!("Unexpected character: '{ch}'"),
-                span: cursor.make_span(sl, sc),
-            })
+            Err(AsmError::new(
+                ErrorKind::UnexpectedCharacter,
+                format!("Unexpected character: '{ch}'"),
+                cursor.make_span(start),
+            ))
         }
     }
 }
 
-fn lex_newline(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token>, AsmError> {
+/// Consume a `len`-character operator and emit it as `kind`.
+fn lex_punct(
+    cursor: &mut Cursor,
+    start: Position,
+    len: usize,
+    kind: TokenKind,
+) -> Result<Option<Token>, AsmError> {
+    let mut lexeme = String::with_capacity(len);
+    for _ in 0..len {
+        lexeme.extend(cursor.advance());
+    }
+    Ok(Some(Token {
+        kind,
+        lexeme,
+        span: cursor.make_span(start),
+    }))
+}
+
+fn lex_newline(cursor: &mut Cursor, start: Position) -> Result<Option<Token>, AsmError> {
     if cursor.peek() == Some('\r') {
         cursor.advance();
         if cursor.peek() == Some('\n') {
@@ -139,11 +178,11 @@ fn lex_newline(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token
     Ok(Some(Token {
         kind: TokenKind::Newline,
         lexeme: "\n".into(),
-        span: cursor.make_span(sl, sc),
+        span: cursor.make_span(start),
     }))
 }
 
-fn lex_comment(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token>, AsmError> {
+fn lex_comment(cursor: &mut Cursor, start: Position) -> Result<Option<Token>, AsmError> {
     cursor.advance(); // consume ';'
     let mut text = String::new();
     while let Some(ch) = cursor.peek() {
@@ -159,31 +198,31 @@ fn lex_comment(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token
     Ok(Some(Token {
         kind: TokenKind::Comment(text),
         lexeme,
-        span: cursor.make_span(sl, sc),
+        span: cursor.make_span(start),
     }))
 }
 
-fn lex_string(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token>, AsmError> {
+fn lex_string(cursor: &mut Cursor, start: Position) -> Result<Option<Token>, AsmError> {
     cursor.advance();
     let mut processed = String::new();
     let mut raw = String::from("\"");
 
     loop {
         if cursor.is_at_end() {
-            return Err(AsmError {
-                kind: ErrorKind::UnterminatedString,
-                message: "Unterminated string literal".into(),
-                span: cursor.make_span(sl, sc),
-            });
+            return Err(AsmError::new(
+                ErrorKind::UnterminatedString,
+                "Unterminated string literal",
+                cursor.make_span(start),
+            ));
         }
 
         let ch = cursor.peek().unwrap();
         if ch == '\n' || ch == '\r' {
-            return Err(AsmError {
-                kind: ErrorKind::UnterminatedString,
-                message: "Unterminated string literal".into(),
-                span: cursor.make_span(sl, sc),
-            });
+            return Err(AsmError::new(
+                ErrorKind::UnterminatedString,
+                "Unterminated string literal",
+                cursor.make_span(start),
+            ));
         }
 
         if ch == '"' {
@@ -196,11 +235,11 @@ fn lex_string(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token>
             cursor.advance();
             raw.push('\\');
             if cursor.is_at_end() {
-                return Err(AsmError {
-                    kind: ErrorKind::UnterminatedString,
-                    message: "Unterminated string literal".into(),
-                    span: cursor.make_span(sl, sc),
-                });
+                return Err(AsmError::new(
+                    ErrorKind::UnterminatedString,
+                    "Unterminated string literal",
+                    cursor.make_span(start),
+                ));
             }
 
             let esc = cursor.peek().unwrap();
@@ -211,11 +250,11 @@ fn lex_string(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token>
                     raw.push(esc);
                 }
                 None => {
-                    return Err(AsmError {
-                        kind: ErrorKind::InvalidEscapeSequence,
-                        message: format!("Invalid escape sequence: \\{esc}"),
-                        span: cursor.make_span(sl, sc),
-                    });
+                    return Err(AsmError::new(
+                        ErrorKind::InvalidEscapeSequence,
+                        format!("Invalid escape sequence: \\{esc}"),
+                        cursor.make_span(start),
+                    ));
                 }
             }
         } else {
@@ -228,11 +267,11 @@ fn lex_string(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token>
     Ok(Some(Token {
         kind: TokenKind::StringLiteral(processed),
         lexeme: raw,
-        span: cursor.make_span(sl, sc),
+        span: cursor.make_span(start),
     }))
 }
 
-fn lex_decimal(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token>, AsmError> {
+fn lex_decimal(cursor: &mut Cursor, start: Position) -> Result<Option<Token>, AsmError> {
     cursor.advance();
     let mut raw = String::from("#");
     let mut sign = String::new();
@@ -251,29 +290,31 @@ fn lex_decimal(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token
     }
 
     if digits.is_empty() {
-        return Err(AsmError {
-            kind: ErrorKind::InvalidDecimalLiteral,
-            message: "Expected digits after #".into(),
-            span: cursor.make_span(sl, sc),
-        });
+        return Err(AsmError::new(
+            ErrorKind::InvalidDecimalLiteral,
+            "Expected digits after #",
+            cursor.make_span(start),
+        ));
     }
 
     // Reuse `sign` (either "" or "-"/"+") by appending digits into it — one allocation.
     sign.push_str(&digits);
-    let value = sign.parse::<i32>().map_err(|_| AsmError {
-        kind: ErrorKind::InvalidDecimalLiteral,
-        message: format!("Invalid decimal literal: {raw}"),
-        span: cursor.make_span(sl, sc),
+    let value = sign.parse::<i32>().map_err(|_| {
+        AsmError::new(
+            ErrorKind::InvalidDecimalLiteral,
+            format!("Invalid decimal literal: {raw}"),
+            cursor.make_span(start),
+        )
     })?;
 
     Ok(Some(Token {
         kind: TokenKind::NumDecimal(value),
         lexeme: raw,
-        span: cursor.make_span(sl, sc),
+        span: cursor.make_span(start),
     }))
 }
 
-fn lex_directive(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token>, AsmError> {
+fn lex_directive(cursor: &mut Cursor, start: Position) -> Result<Option<Token>, AsmError> {
     cursor.advance();
     let mut raw = String::from(".");
     let mut word = String::new();
@@ -291,23 +332,27 @@ fn lex_directive(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Tok
         "FILL" => TokenKind::DirFill,
         "BLKW" => TokenKind::DirBlkw,
         "STRINGZ" => TokenKind::DirStringz,
+        "EQU" => TokenKind::DirEqu,
+        "SET" => TokenKind::DirSet,
+        "EXTERNAL" => TokenKind::DirExternal,
+        "GLOBAL" => TokenKind::DirGlobal,
         _ => {
-            return Err(AsmError {
-                kind: ErrorKind::UnknownDirective,
-                message: format!("Unknown directive .{upper}"),
-                span: cursor.make_span(sl, sc),
-            })
+            return Err(AsmError::new(
+                ErrorKind::UnknownDirective,
+                format!("Unknown directive .{upper}"),
+                cursor.make_span(start),
+            ))
         }
     };
 
     Ok(Some(Token {
         kind,
         lexeme: raw,
-        span: cursor.make_span(sl, sc),
+        span: cursor.make_span(start),
     }))
 }
 
-fn lex_word(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token>, AsmError> {
+fn lex_word(cursor: &mut Cursor, start: Position) -> Result<Option<Token>, AsmError> {
     let mut word = String::new();
     while matches!(cursor.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_') {
         let ch = cursor.advance().unwrap();
@@ -324,15 +369,15 @@ fn lex_word(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token>,
                 return Ok(Some(Token {
                     kind: TokenKind::Register(reg),
                     lexeme: word,
-                    span: cursor.make_span(sl, sc),
+                    span: cursor.make_span(start),
                 }));
             }
             if reg == 8 || reg == 9 {
-                return Err(AsmError {
-                    kind: ErrorKind::InvalidRegister,
-                    message: format!("Invalid register R{reg} (must be R0-R7)"),
-                    span: cursor.make_span(sl, sc),
-                });
+                return Err(AsmError::new(
+                    ErrorKind::InvalidRegister,
+                    format!("Invalid register R{reg} (must be R0-R7)"),
+                    cursor.make_span(start),
+                ));
             }
         }
     }
@@ -366,7 +411,7 @@ fn lex_word(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token>,
                 return Ok(Some(Token {
                     kind: TokenKind::OpBr(flags),
                     lexeme: word,
-                    span: cursor.make_span(sl, sc),
+                    span: cursor.make_span(start),
                 }));
             }
             // HEX LITERAL: Parse as u32 first, then handle 16-bit two's complement
@@ -381,22 +426,22 @@ fn lex_word(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token>,
                         return Ok(Some(Token {
                             kind: TokenKind::NumHex(value),
                             lexeme: word,
-                            span: cursor.make_span(sl, sc),
+                            span: cursor.make_span(start),
                         }));
                     }
                     Ok(_) => {
-                        return Err(AsmError {
-                            kind: ErrorKind::InvalidHexLiteral,
-                            message: format!("Hex literal {word} exceeds 16 bits"),
-                            span: cursor.make_span(sl, sc),
-                        });
+                        return Err(AsmError::new(
+                            ErrorKind::InvalidHexLiteral,
+                            format!("Hex literal {word} exceeds 16 bits"),
+                            cursor.make_span(start),
+                        ));
                     }
                     Err(_) => {
-                        return Err(AsmError {
-                            kind: ErrorKind::InvalidHexLiteral,
-                            message: format!("Invalid hex literal: {word}"),
-                            span: cursor.make_span(sl, sc),
-                        });
+                        return Err(AsmError::new(
+                            ErrorKind::InvalidHexLiteral,
+                            format!("Invalid hex literal: {word}"),
+                            cursor.make_span(start),
+                        ));
                     }
                 }
             }
@@ -413,22 +458,22 @@ fn lex_word(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token>,
                         return Ok(Some(Token {
                             kind: TokenKind::NumBinary(value),
                             lexeme: word,
-                            span: cursor.make_span(sl, sc),
+                            span: cursor.make_span(start),
                         }));
                     }
                     Ok(_) => {
-                        return Err(AsmError {
-                            kind: ErrorKind::InvalidBinaryLiteral,
-                            message: format!("Binary literal {word} exceeds 16 bits"),
-                            span: cursor.make_span(sl, sc),
-                        });
+                        return Err(AsmError::new(
+                            ErrorKind::InvalidBinaryLiteral,
+                            format!("Binary literal {word} exceeds 16 bits"),
+                            cursor.make_span(start),
+                        ));
                     }
                     Err(_) => {
-                        return Err(AsmError {
-                            kind: ErrorKind::InvalidBinaryLiteral,
-                            message: format!("Invalid binary literal: {word}"),
-                            span: cursor.make_span(sl, sc),
-                        });
+                        return Err(AsmError::new(
+                            ErrorKind::InvalidBinaryLiteral,
+                            format!("Invalid binary literal: {word}"),
+                            cursor.make_span(start),
+                        ));
                     }
                 }
             }
@@ -445,26 +490,43 @@ fn lex_word(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token>,
                         return Ok(Some(Token {
                             kind: TokenKind::NumOctal(value),
                             lexeme: word,
-                            span: cursor.make_span(sl, sc),
+                            span: cursor.make_span(start),
                         }));
                     }
                     Ok(_) => {
-                        return Err(AsmError {
-                            kind: ErrorKind::InvalidOctalLiteral,
-                            message: format!("Octal literal {word} exceeds 16 bits"),
-                            span: cursor.make_span(sl, sc),
-                        });
+                        return Err(AsmError::new(
+                            ErrorKind::InvalidOctalLiteral,
+                            format!("Octal literal {word} exceeds 16 bits"),
+                            cursor.make_span(start),
+                        ));
                     }
                     Err(_) => {
-                        return Err(AsmError {
-                            kind: ErrorKind::InvalidOctalLiteral,
-                            message: format!("Invalid octal literal: {word}"),
-                            span: cursor.make_span(sl, sc),
-                        });
+                        return Err(AsmError::new(
+                            ErrorKind::InvalidOctalLiteral,
+                            format!("Invalid octal literal: {word}"),
+                            cursor.make_span(start),
+                        ));
                     }
                 }
             }
 
+            // BARE DECIMAL: `2` in `SIZE*2`. Words such as `3ABC` stay labels
+            // so the parser can report them in context.
+            if upper.chars().all(|c| c.is_ascii_digit()) {
+                return match upper.parse::<i32>() {
+                    Ok(value) => Ok(Some(Token {
+                        kind: TokenKind::NumDecimal(value),
+                        lexeme: word,
+                        span: cursor.make_span(start),
+                    })),
+                    Err(_) => Err(AsmError::new(
+                        ErrorKind::InvalidDecimalLiteral,
+                        format!("Invalid decimal literal: {word}"),
+                        cursor.make_span(start),
+                    )),
+                };
+            }
+
             TokenKind::Label(upper.clone())
         }
     };
@@ -472,6 +534,6 @@ fn lex_word(cursor: &mut Cursor, sl: usize, sc: usize) -> Result<Option<Token>,
     Ok(Some(Token {
         kind,
         lexeme: word,
-        span: cursor.make_span(sl, sc),
+        span: cursor.make_span(start),
     }))
 }
@@ -158,7 +158,7 @@ mod lexer_tests {
 
     #[test]
     fn all_directives() {
-        let kinds = lex_ok(".ORIG .END .FILL .BLKW .STRINGZ");
+        let kinds = lex_ok(".ORIG .END .FILL .BLKW .STRINGZ .EQU .SET");
         assert_eq!(
             kinds,
             vec![
@@ -167,6 +167,8 @@ mod lexer_tests {
                 TokenKind::DirFill,
                 TokenKind::DirBlkw,
                 TokenKind::DirStringz,
+                TokenKind::DirEqu,
+                TokenKind::DirSet,
                 TokenKind::Eof
             ]
         );
@@ -562,4 +564,66 @@ mod lexer_tests {
             TokenKind::StringLiteral("a\rb".into())
         );
     }
+
+    #[test]
+    fn expression_operators() {
+        assert_eq!(
+            lex_ok("A+1-B*2<<3>>1&C|(D)"),
+            vec![
+                TokenKind::Label("A".into()),
+                TokenKind::Plus,
+                TokenKind::NumDecimal(1),
+                TokenKind::Minus,
+                TokenKind::Label("B".into()),
+                TokenKind::Star,
+                TokenKind::NumDecimal(2),
+                TokenKind::ShiftLeft,
+                TokenKind::NumDecimal(3),
+                TokenKind::ShiftRight,
+                TokenKind::NumDecimal(1),
+                TokenKind::Ampersand,
+                TokenKind::Label("C".into()),
+                TokenKind::Pipe,
+                TokenKind::LParen,
+                TokenKind::Label("D".into()),
+                TokenKind::RParen,
+                TokenKind::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn hash_before_parenthesised_expression() {
+        assert_eq!(
+            lex_ok("#-(OFFSET)"),
+            vec![
+                TokenKind::Hash,
+                TokenKind::Minus,
+                TokenKind::LParen,
+                TokenKind::Label("OFFSET".into()),
+                TokenKind::RParen,
+                TokenKind::Eof,
+            ]
+        );
+        // A plain signed literal is still one token.
+        assert_eq!(lex_ok("#-5")[0], TokenKind::NumDecimal(-5));
+    }
+
+    #[test]
+    fn single_angle_bracket_is_error() {
+        assert_eq!(lex_errors("<"), vec![ErrorKind::UnexpectedCharacter]);
+    }
+
+    #[test]
+    fn spans_cover_the_token_bytes() {
+        let source = "LOOP ADD R0, R0, #100\n  .FILL x3000";
+        let tokens = tokenize(source).tokens;
+        for tok in tokens.iter().filter(|t| t.kind != TokenKind::Eof) {
+            assert_eq!(&source[tok.span.start..tok.span.end], tok.lexeme);
+        }
+        let imm = tokens.iter().find(|t| t.lexeme == "#100").unwrap();
+        assert_eq!((imm.span.col, imm.span.width()), (18, 4));
+        let fill = tokens.iter().find(|t| t.lexeme == ".FILL").unwrap();
+        assert_eq!((fill.span.line, fill.span.col, fill.span.start), (2, 3, 24));
+    }
 }
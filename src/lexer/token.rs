@@ -50,11 +50,15 @@ pub enum TokenKind {
     PseudoHalt,  // TRAP x25
 
     // === Assembler Directives ===
-    DirOrig,    // .ORIG
-    DirEnd,     // .END
-    DirFill,    // .FILL
-    DirBlkw,    // .BLKW
-    DirStringz, // .STRINGZ
+    DirOrig,     // .ORIG
+    DirEnd,      // .END
+    DirFill,     // .FILL
+    DirBlkw,     // .BLKW
+    DirStringz,  // .STRINGZ
+    DirEqu,      // .EQU
+    DirSet,      // .SET
+    DirExternal, // .EXTERNAL
+    DirGlobal,   // .GLOBAL
 
     // === Operands ===
     Register(u8), // R0-R7
@@ -68,6 +72,18 @@ pub enum TokenKind {
 
     Label(String), // Identifier (uppercase)
 
+    // === Expression Operators ===
+    Plus,       // +
+    Minus,      // -
+    Star,       // *
+    ShiftLeft,  // <<
+    ShiftRight, // >>
+    Ampersand,  // &
+    Pipe,       // |
+    LParen,     // (
+    RParen,     // )
+    Hash,       // # directly before a parenthesised expression, e.g. #-(OFFSET)
+
     // === Punctuation & Structural ===
     Comma,
     Newline,
@@ -164,7 +180,7 @@ impl TokenKind {
             // Trap & pseudos
             OpTrap | PseudoRet | PseudoGetc | PseudoOut | PseudoPuts | PseudoIn | PseudoPutsp | PseudoHalt |
             // Directives
-            DirOrig | DirEnd | DirFill | DirBlkw | DirStringz
+            DirOrig | DirEnd | DirFill | DirBlkw | DirStringz | DirEqu | DirSet | DirExternal | DirGlobal
         )
     }
 }
@@ -21,6 +21,9 @@
 //!
 //! Errors are accumulated at every stage rather than halting on the first
 //! failure, so a single assembly run reports as many problems as possible.
+//! [`assemble`] runs all of the stages in order and collects their results;
+//! its [`source_map`] maps every diagnostic back through the preprocessor and
+//! macro expander to the file and line the user wrote.
 //!
 //! ## Example
 //!
@@ -44,13 +47,23 @@
 //! }
 //! ```
 
+pub mod assembly;
+pub mod debug_info;
 pub mod diagnostic;
+pub mod disasm;
 pub mod encoder;
 pub mod error;
 pub mod first_pass;
+pub mod formatter;
 pub mod lexer;
+pub mod linker;
 pub mod listing;
 pub mod macro_expand;
+pub mod obj;
 pub mod parser;
 pub mod preprocessor;
+pub mod relocatable;
+pub mod source_map;
 pub mod warning;
+
+pub use assembly::{assemble, assemble_source, AssembleOptions, Assembly};
@@ -0,0 +1,539 @@
+//! # Linker
+//!
+//! Combines [relocatable objects](crate::relocatable) written by
+//! `lc3-assembler --emit rel` into one program.  `lc3-link` is a thin CLI
+//! over [`link`].
+//!
+//! 1. **Placement** — every section starts at its `.ORIG` address.  A
+//!    [`Placement`] moves a whole object (its first section goes to the given
+//!    address and the others keep their distance to it) or one section.
+//! 2. **Resolution** — a relocation names a symbol of its own object first,
+//!    then a `.GLOBAL` symbol of any object.  A global defined twice, a
+//!    symbol nobody defines and overlapping sections are errors.
+//! 3. **Patching** — `.FILL` words get the final address; PC-relative
+//!    fields get `target - (address + 1)`, which must still fit in 9 or 11
+//!    bits.  An offset that no longer fits is reported at both the reference
+//!    and the symbol's definition.
+//!
+//! ## Placement scripts
+//!
+//! `lc3-link -T layout.ld` reads one placement per line, in the same
+//! `OBJECT[:SECTION]=ADDRESS` form as `--base` (or with a space instead of
+//! `=`).  `;` and `#` start comments.
+//!
+//! ```text
+//! ; layout.ld
+//! main      x3000
+//! print     x3100
+//! tables:1  x4000   ; second section of tables.asm
+//! ```
+//!
+//! `OBJECT` is the name the object was assembled as (`main.asm`) or its file
+//! stem (`main`).
+
+use std::path::Path;
+
+use crate::encoder::{RelocKind, Segment};
+use crate::first_pass::symbol_table::{SymbolKind, SymbolTable};
+use crate::relocatable::{Location, RelocatableObject};
+
+/// Where to put an object or one of its sections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    /// Object name or file stem.
+    pub object: String,
+    /// Section index; `None` moves the whole object.
+    pub section: Option<u16>,
+    pub address: u16,
+}
+
+impl Placement {
+    /// Parse `OBJECT[:SECTION]=ADDRESS` (also accepting a space for `=`).
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        let (target, address) = spec
+            .split_once('=')
+            .or_else(|| spec.split_once(char::is_whitespace))
+            .ok_or_else(|| format!("expected OBJECT[:SECTION]=ADDRESS, found '{spec}'"))?;
+        let (object, section) = match target.trim().split_once(':') {
+            Some((object, section)) => {
+                let section = section
+                    .parse::<u16>()
+                    .map_err(|_| format!("invalid section index '{section}'"))?;
+                (object, Some(section))
+            }
+            None => (target.trim(), None),
+        };
+        if object.is_empty() {
+            return Err(format!("missing object name in '{spec}'"));
+        }
+        let address = parse_address(address.trim())
+            .ok_or_else(|| format!("invalid address '{}'", address.trim()))?;
+        Ok(Self {
+            object: object.to_string(),
+            section,
+            address,
+        })
+    }
+
+    fn matches(&self, obj: &RelocatableObject) -> bool {
+        obj.name == self.object
+            || Path::new(&obj.name)
+                .file_stem()
+                .is_some_and(|stem| stem.to_string_lossy() == self.object)
+    }
+}
+
+/// Parse a placement script (see the module docs).
+pub fn parse_script(text: &str) -> Result<Vec<Placement>, String> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let line = line.split([';', '#']).next().unwrap_or("").trim();
+            (!line.is_empty())
+                .then(|| Placement::parse(line).map_err(|e| format!("line {}: {e}", i + 1)))
+        })
+        .collect()
+}
+
+/// `x3000`, `0x3000` or decimal.
+fn parse_address(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix(['x', 'X'])) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// A problem found while linking, with the source locations involved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkError {
+    pub message: String,
+    /// Each location with a note saying why it is relevant.
+    pub locations: Vec<(Location, String)>,
+}
+
+impl LinkError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            locations: Vec::new(),
+        }
+    }
+
+    fn at(mut self, location: &Location, note: impl Into<String>) -> Self {
+        self.locations.push((location.clone(), note.into()));
+        self
+    }
+}
+
+impl std::fmt::Display for LinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        for (loc, note) in &self.locations {
+            write!(f, "\n  --> {loc}: {note}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A section at its final address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlacedSection {
+    /// Index into the objects passed to [`link`].
+    pub object: usize,
+    /// Index of the section within its object.
+    pub section: usize,
+    /// The section's words, patched, at their final origin.
+    pub segment: Segment,
+}
+
+/// A symbol at its final address (or value, for constants).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkedSymbol {
+    pub name: String,
+    pub value: u16,
+    /// `false` for `.EQU` constants.
+    pub is_label: bool,
+    pub global: bool,
+    /// Index into the objects passed to [`link`].
+    pub object: usize,
+    pub location: Location,
+}
+
+/// Result of linking.
+pub struct LinkResult {
+    /// Object names, in input order.
+    pub objects: Vec<String>,
+    /// Every section, sorted by address.
+    pub sections: Vec<PlacedSection>,
+    /// Every symbol of every object, sorted by value.
+    pub symbols: Vec<LinkedSymbol>,
+    pub errors: Vec<LinkError>,
+}
+
+impl LinkResult {
+    #[must_use]
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    /// The linked program's segments, sorted by address.
+    #[must_use]
+    pub fn segments(&self) -> Vec<Segment> {
+        self.sections.iter().map(|s| s.segment.clone()).collect()
+    }
+
+    /// Labels and global constants for a `.sym` file.  When several objects
+    /// use the same local name, the first one wins.
+    #[must_use]
+    pub fn symbol_table(&self) -> SymbolTable {
+        let mut table = SymbolTable::new();
+        let mut symbols: Vec<&LinkedSymbol> = self.symbols.iter().collect();
+        symbols.sort_by_key(|s| (s.object, !s.global));
+        for sym in symbols {
+            if table.lookup(&sym.name).is_some() {
+                continue;
+            }
+            if sym.is_label {
+                table.insert(sym.name.clone(), sym.value);
+            } else if sym.global {
+                table.define(sym.name.clone(), i32::from(sym.value), SymbolKind::Constant);
+            }
+        }
+        table
+    }
+
+    /// A text map listing every section and symbol with its final address.
+    #[must_use]
+    pub fn map(&self, output: &str) -> String {
+        let mut out = format!("; LC-3 link map — {output}\n;\n");
+        out.push_str("; Sections\n");
+        out.push_str("; Start  End    Words  Object               Section\n");
+        for s in &self.sections {
+            let len = s.segment.words.len() as u16;
+            out.push_str(&format!(
+                "  x{:04X}  x{:04X}  {:>5}  {:<20} {}\n",
+                s.segment.origin,
+                s.segment.origin.wrapping_add(len.max(1) - 1),
+                len,
+                self.objects[s.object],
+                s.section,
+            ));
+        }
+        out.push_str(";\n; Symbols\n");
+        out.push_str("; Value  Symbol               Scope   Defined at\n");
+        for sym in &self.symbols {
+            let scope = match (sym.global, sym.is_label) {
+                (true, true) => "global",
+                (false, true) => "local",
+                _ => "const",
+            };
+            out.push_str(&format!(
+                "  x{:04X}  {:<20} {:<7} {}\n",
+                sym.value, sym.name, scope, sym.location
+            ));
+        }
+        out
+    }
+}
+
+/// Link `objects`, moving sections as `placements` say.
+#[must_use]
+pub fn link(objects: &[RelocatableObject], placements: &[Placement]) -> LinkResult {
+    let mut errors = Vec::new();
+
+    // ── Placement ────────────────────────────────────────────────────────────
+
+    let mut origins: Vec<Vec<u16>> = objects
+        .iter()
+        .map(|o| o.sections.iter().map(|s| s.origin).collect())
+        .collect();
+    // Whole-object moves first, so a section placement can override one.
+    for p in placements.iter().filter(|p| p.section.is_none()) {
+        match objects.iter().position(|o| p.matches(o)) {
+            Some(i) if !objects[i].sections.is_empty() => {
+                let delta = p.address.wrapping_sub(objects[i].sections[0].origin);
+                for origin in &mut origins[i] {
+                    *origin = origin.wrapping_add(delta);
+                }
+            }
+            Some(_) => {}
+            None => errors.push(LinkError::new(format!("no object named '{}'", p.object))),
+        }
+    }
+    for p in placements.iter().filter(|p| p.section.is_some()) {
+        let section = p.section.unwrap_or(0) as usize;
+        match objects.iter().position(|o| p.matches(o)) {
+            Some(i) if section < origins[i].len() => origins[i][section] = p.address,
+            Some(i) => errors.push(LinkError::new(format!(
+                "{} has no section {section} (it has {})",
+                objects[i].name,
+                origins[i].len()
+            ))),
+            None => errors.push(LinkError::new(format!("no object named '{}'", p.object))),
+        }
+    }
+
+    let mut sections: Vec<PlacedSection> = Vec::new();
+    for (i, obj) in objects.iter().enumerate() {
+        for (j, seg) in obj.sections.iter().enumerate() {
+            let origin = origins[i][j];
+            if usize::from(origin) + seg.words.len() > 0x1_0000 {
+                errors.push(LinkError::new(format!(
+                    "{} section {j} placed at x{origin:04X} runs past xFFFF",
+                    obj.name
+                )));
+            }
+            sections.push(PlacedSection {
+                object: i,
+                section: j,
+                segment: Segment {
+                    origin,
+                    words: seg.words.clone(),
+                },
+            });
+        }
+    }
+    let end = |s: &PlacedSection| usize::from(s.segment.origin) + s.segment.words.len();
+    for (a_idx, a) in sections.iter().enumerate() {
+        for b in &sections[a_idx + 1..] {
+            let overlap =
+                usize::from(a.segment.origin) < end(b) && usize::from(b.segment.origin) < end(a);
+            if overlap {
+                errors.push(LinkError::new(format!(
+                    "{} section {} (x{:04X}) overlaps {} section {} (x{:04X}); \
+                     move one with --base",
+                    objects[a.object].name,
+                    a.section,
+                    a.segment.origin,
+                    objects[b.object].name,
+                    b.section,
+                    b.segment.origin,
+                )));
+            }
+        }
+    }
+
+    // ── Symbols ──────────────────────────────────────────────────────────────
+
+    let mut symbols: Vec<LinkedSymbol> = Vec::new();
+    for (i, obj) in objects.iter().enumerate() {
+        for sym in &obj.symbols {
+            let value = match sym.section {
+                Some(s) => origins[i][s as usize].wrapping_add(sym.value),
+                None => sym.value,
+            };
+            symbols.push(LinkedSymbol {
+                name: sym.name.clone(),
+                value,
+                is_label: sym.section.is_some(),
+                global: sym.global,
+                object: i,
+                location: sym.location.clone(),
+            });
+        }
+    }
+
+    let globals: Vec<&LinkedSymbol> = symbols.iter().filter(|s| s.global).collect();
+    for (idx, sym) in globals.iter().enumerate() {
+        if let Some(first) = globals[..idx].iter().find(|g| g.name == sym.name) {
+            errors.push(
+                LinkError::new(format!("'{}' is defined .GLOBAL more than once", sym.name))
+                    .at(&sym.location, "defined again here")
+                    .at(&first.location, "first defined here"),
+            );
+        }
+    }
+
+    // ── Relocations ──────────────────────────────────────────────────────────
+
+    for (i, obj) in objects.iter().enumerate() {
+        for r in &obj.relocations {
+            let target = symbols
+                .iter()
+                .find(|s| s.object == i && s.name == r.symbol)
+                .or_else(|| globals.iter().copied().find(|s| s.name == r.symbol));
+            let Some(target) = target else {
+                errors.push(
+                    LinkError::new(format!("undefined symbol '{}'", r.symbol))
+                        .at(&r.location, "referenced here"),
+                );
+                continue;
+            };
+            let address = i32::from(target.value) + i32::from(r.addend);
+
+            let Some(placed) = sections
+                .iter_mut()
+                .find(|s| s.object == i && s.section == r.section as usize)
+            else {
+                continue;
+            };
+            let pc = i32::from(placed.segment.origin) + i32::from(r.offset) + 1;
+            let word = &mut placed.segment.words[r.offset as usize];
+
+            if r.kind == RelocKind::Abs16 {
+                *word = address as u16;
+                continue;
+            }
+            let bits = r.kind.bits();
+            let offset = address - pc;
+            let (min, max) = (-(1 << (bits - 1)), (1 << (bits - 1)) - 1);
+            if !(min..=max).contains(&offset) {
+                errors.push(
+                    LinkError::new(format!(
+                        "PC offset {offset} to '{}' exceeds {bits}-bit range [{min}, {max}] \
+                         after linking",
+                        r.symbol
+                    ))
+                    .at(&r.location, format!("referenced here, at x{:04X}", pc - 1))
+                    .at(
+                        &target.location,
+                        format!("'{}' placed at x{:04X}", target.name, target.value),
+                    ),
+                );
+                continue;
+            }
+            let mask = (1u16 << bits) - 1;
+            *word = (*word & !mask) | (offset as u16 & mask);
+        }
+    }
+
+    sections.sort_by_key(|s| s.segment.origin);
+    symbols.sort_by_key(|s| s.value);
+
+    LinkResult {
+        objects: objects.iter().map(|o| o.name.clone()).collect(),
+        sections,
+        symbols,
+        errors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble_source, AssembleOptions};
+
+    fn object(name: &str, source: &str) -> RelocatableObject {
+        let asm = assemble_source(
+            source,
+            name,
+            &AssembleOptions {
+                relocatable: true,
+                ..Default::default()
+            },
+        );
+        assert!(!asm.has_errors(), "{:?}", asm.errors);
+        RelocatableObject::from_assembly(&asm)
+    }
+
+    fn main_and_print() -> Vec<RelocatableObject> {
+        vec![
+            object(
+                "main.asm",
+                ".EXTERNAL PRINT, MSG\n.ORIG x3000\nLEA R0, MSG\nJSR PRINT\nHALT\nPTR .FILL PRINT\n.END\n",
+            ),
+            object(
+                "print.asm",
+                ".GLOBAL PRINT, MSG\n.ORIG x3000\nPRINT PUTS\nRET\nMSG .STRINGZ \"hi\"\n.END\n",
+            ),
+        ]
+    }
+
+    #[test]
+    fn resolves_symbols_across_objects() {
+        let placements = vec![Placement::parse("print=x3010").unwrap()];
+        let result = link(&main_and_print(), &placements);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        let segments = result.segments();
+        assert_eq!(segments[0].origin, 0x3000);
+        // LEA R0, x3012 / JSR x3010 / HALT / .FILL x3010
+        assert_eq!(segments[0].words, vec![0xE011, 0x480E, 0xF025, 0x3010]);
+        assert_eq!(segments[1].origin, 0x3010);
+
+        let table = result.symbol_table();
+        assert_eq!(table.get("PRINT"), Some(0x3010));
+        assert_eq!(table.get("PTR"), Some(0x3003));
+        let map = result.map("out.obj");
+        assert!(
+            map.contains("  x3010  PRINT                global  print.asm:3:1"),
+            "{map}"
+        );
+    }
+
+    #[test]
+    fn overlapping_sections_are_reported() {
+        let result = link(&main_and_print(), &[]);
+        assert_eq!(result.errors.len(), 1, "{:?}", result.errors);
+        assert!(result.errors[0]
+            .message
+            .contains("overlaps print.asm section 0"));
+    }
+
+    #[test]
+    fn out_of_range_offsets_name_both_locations() {
+        let placements = vec![Placement::parse("print x4000").unwrap()];
+        let result = link(&main_and_print(), &placements);
+        let messages: Vec<String> = result.errors.iter().map(ToString::to_string).collect();
+        assert_eq!(messages.len(), 2, "{messages:?}");
+        assert_eq!(
+            messages[0],
+            "PC offset 4097 to 'MSG' exceeds 9-bit range [-256, 255] after linking\n  \
+             --> main.asm:3:9: referenced here, at x3000\n  \
+             --> print.asm:5:1: 'MSG' placed at x4002"
+        );
+    }
+
+    #[test]
+    fn moving_the_second_of_two_adjacent_sections() {
+        let objects = vec![object(
+            "adj.asm",
+            ".ORIG x3000\nLD R0, DATA\nHALT\n.END\n.ORIG x3002\nDATA .FILL x1234\n.END\n",
+        )];
+        let placements = vec![Placement::parse("adj:1=x3100").unwrap()];
+        let result = link(&objects, &placements);
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(result.symbol_table().get("DATA"), Some(0x3100));
+        let segments = result.segments();
+        // LD R0, x3100 / HALT
+        assert_eq!(segments[0].words, vec![0x20FF, 0xF025]);
+        assert_eq!(segments[1].origin, 0x3100);
+    }
+
+    #[test]
+    fn undefined_and_duplicate_globals() {
+        let objects = vec![
+            object(
+                "a.asm",
+                ".EXTERNAL NOPE\n.GLOBAL X\n.ORIG x3000\nX JSR NOPE\n.END\n",
+            ),
+            object("b.asm", ".GLOBAL X\n.ORIG x4000\nX HALT\n.END\n"),
+        ];
+        let result = link(&objects, &[]);
+        let messages: Vec<&str> = result.errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "'X' is defined .GLOBAL more than once",
+                "undefined symbol 'NOPE'"
+            ]
+        );
+        assert_eq!(result.errors[0].locations[1].0.to_string(), "a.asm:4:1");
+    }
+
+    #[test]
+    fn placement_scripts() {
+        let script = "; layout\nmain x3000\nprint:0 = 0x3100 # library\n\n";
+        let placements = parse_script(script).unwrap();
+        assert_eq!(
+            placements[1],
+            Placement {
+                object: "print".into(),
+                section: Some(0),
+                address: 0x3100
+            }
+        );
+        assert!(parse_script("main\n").unwrap_err().starts_with("line 1:"));
+        assert!(Placement::parse("main=x1FFFF").is_err());
+    }
+}
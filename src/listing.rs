@@ -22,7 +22,7 @@
 //! ```
 
 use crate::encoder::EncodeResult;
-use crate::first_pass::symbol_table::SymbolTable;
+use crate::first_pass::symbol_table::{Symbol, SymbolKind, SymbolTable};
 use crate::first_pass::FirstPassResult;
 
 /// Generate a listing string from the assembled program.
@@ -105,6 +105,16 @@ pub fn generate(
         encoded.machine_code.len(),
         encoded.machine_code.len() * 2,
     ));
+    if encoded.segments.len() > 1 {
+        for seg in &encoded.segments {
+            out.push_str(&format!(
+                "  Segment x{:04X}-x{:04X}   Words: {}\n",
+                seg.origin,
+                seg.origin as u32 + seg.words.len().max(1) as u32 - 1,
+                seg.words.len(),
+            ));
+        }
+    }
 
     // Append symbol table section if there are any symbols
     if !first.symbol_table.is_empty() {
@@ -115,7 +125,8 @@ pub fn generate(
     out
 }
 
-/// Append a human-readable symbol table section (sorted by address) to `out`.
+/// Append a human-readable symbol table section (sorted by address) to `out`,
+/// followed by a separate section for `.EQU`/`.SET` constants.
 fn append_symbol_table(out: &mut String, table: &SymbolTable) {
     let sep = "─".repeat(34);
     out.push_str("Symbol Table\n");
@@ -129,12 +140,50 @@ fn append_symbol_table(out: &mut String, table: &SymbolTable) {
     }
     out.push_str(&sep);
     out.push('\n');
+
+    let constants = table.constants();
+    if constants.is_empty() {
+        return;
+    }
+    let sep = "─".repeat(42);
+    out.push_str("\nConstants\n");
+    out.push_str(&sep);
+    out.push('\n');
+    out.push_str(&format!("  {:<20}  {:<10}  {}\n", "Name", "Value", "Kind"));
+    out.push_str(&sep);
+    out.push('\n');
+    for (name, sym) in constants {
+        out.push_str(&format!(
+            "  {:<20}  {:<10}  {}\n",
+            name,
+            constant_value(sym),
+            constant_kind(sym)
+        ));
+    }
+    out.push_str(&sep);
+    out.push('\n');
+}
+
+/// `#10`-style decimal rendering of a constant, so it can't be mistaken
+/// for an `xNNNN` address.
+fn constant_value(sym: Symbol) -> String {
+    format!("#{}", sym.value)
+}
+
+fn constant_kind(sym: Symbol) -> &'static str {
+    match sym.kind {
+        SymbolKind::Variable => ".SET",
+        _ => ".EQU",
+    }
 }
 
 /// Generate a standalone `.sym` file (labels sorted alphabetically).
 ///
 /// The format is designed to be both human-readable and machine-parseable:
-/// each non-comment line is `LABEL=x<ADDR>`.
+/// each non-comment label line is `LABEL=x<ADDR>`. Constants follow in their
+/// own commented section as `NAME=#<value>` (decimal, `#` prefix), so tools
+/// that only understand addresses can skip them. `.SET` variables are
+/// written with their final value.
 #[must_use]
 pub fn generate_sym_file(table: &SymbolTable, filename: &str) -> String {
     let mut out = String::new();
@@ -150,5 +199,15 @@ pub fn generate_sym_file(table: &SymbolTable, filename: &str) -> String {
     for (label, addr) in table.sorted_by_name() {
         out.push_str(&format!("{label}=x{addr:04X}\n"));
     }
+
+    let constants = table.constants();
+    if !constants.is_empty() {
+        out.push_str(";\n");
+        out.push_str("; Constant             Value  (.EQU / .SET final value)\n");
+        out.push_str("; ─────────────────────────────\n");
+        for (name, sym) in constants {
+            out.push_str(&format!("{name}={}\n", constant_value(sym)));
+        }
+    }
     out
 }
@@ -28,21 +28,69 @@
 //! - Parameter names start with `%` and are replaced textually.
 //! - Multiple parameters are comma-separated in the `.MACRO` header.
 //! - Macro names are case-insensitive; parameter names are case-sensitive.
+//! - `%NAME=value` in the header gives a parameter a default; an empty or
+//!   missing argument then takes that value.
+//! - Arguments may be passed by name (`FILL COUNT=#3, REG=R2`) after any
+//!   positional ones.
+//!
+//! ### Local labels
+//!
+//! `%%NAME` is replaced with a name unique to each expansion (`NAME__1`,
+//! `NAME__2`, …), so a macro can define its own loop labels and still be
+//! used more than once:
+//!
+//! ```text
+//! .MACRO  CLEAR_N  %N
+//!         AND  R0, R0, #0
+//!         ADD  R0, R0, %N
+//! %%LOOP  ADD  R0, R0, #-1
+//!         BRp  %%LOOP
+//! .ENDM
+//! ```
+//!
+//! ### Repetition
+//!
+//! ```text
+//! .REPT 3              ; three copies of the body
+//!     ADD R1, R1, R1
+//! .ENDR
+//!
+//! .IRP %R, R1, R2, R3  ; one copy per value, with %R bound to it
+//!     AND %R, %R, #0
+//! .ENDR
+//! ```
+//!
+//! The `.REPT` count must be a constant (macro parameters are substituted
+//! first).  Outside a macro each repetition gets its own `%%NAME` labels;
+//! inside one, they belong to the macro's expansion.
 //!
 //! ## Processing order
 //!
 //! Macro expansion runs **after** `.INCLUDE` preprocessing and **before**
-//! lexing.  The output is a flat source string handed directly to `tokenize()`.
+//! lexing.  The output is a flat source string handed directly to `tokenize()`;
+//! [`MacroResult::origins`] records which input line (and which invocation)
+//! each output line came from.
 //!
 //! ## Restrictions
 //!
-//! - Macros may not be defined inside other macros.
-//! - Recursive macro invocations are detected and produce an error.
+//! - Macros may not be defined inside other macros or repetition blocks.
+//! - Macro bodies may call other macros, but a macro that ends up invoking
+//!   itself (directly or through others) produces an error.
 //! - Macro names must not shadow LC-3 instruction mnemonics or directives
 //!   (the assembler will catch any such errors in the subsequent parse stage).
 
 use std::collections::HashMap;
 
+use crate::lexer::token::TokenKind;
+use crate::lexer::tokenize;
+use crate::parser::expr::parse_expr;
+
+/// Maximum nesting of macro calls and repetition blocks.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// Largest count `.REPT` accepts.
+const MAX_REPT: i32 = 4096;
+
 // ── Public types ──────────────────────────────────────────────────────────────
 
 /// A defined macro: its parameter list and body lines.
@@ -51,8 +99,12 @@ pub struct MacroDef {
     pub name: String,
     /// Parameter names in declaration order, **without** the `%` prefix.
     pub params: Vec<String>,
+    /// Default value of each parameter (`%NAME=value`), parallel to `params`.
+    pub defaults: Vec<Option<String>>,
     /// Body lines exactly as written (with `%PARAM` placeholders intact).
     pub body: Vec<String>,
+    /// 1-based input line of each entry of `body`.
+    pub body_lines: Vec<usize>,
 }
 
 /// An error encountered during macro expansion.
@@ -69,12 +121,36 @@ impl std::fmt::Display for MacroError {
     }
 }
 
+/// Where one line of the expanded output came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineOrigin {
+    /// 1-based input line: the line itself, or for a line produced by a
+    /// macro, the body line in the macro's definition.
+    pub line: usize,
+    /// The macro invocations that produced the line, outermost first; empty
+    /// for lines written outside any macro.
+    pub expansions: Vec<Expansion>,
+}
+
+/// A macro invocation that produced output lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    /// The macro's name as written in its definition.
+    pub name: String,
+    /// 1-based input line of the invocation.
+    pub call_line: usize,
+}
+
 /// Result of macro expansion.
 pub struct MacroResult {
     /// Fully-expanded source text.
     pub source: String,
+    /// Origin of each line of `source`, in order.
+    pub origins: Vec<LineOrigin>,
     /// Errors encountered during expansion.
     pub errors: Vec<MacroError>,
+    /// Every macro defined in the source, sorted by name.
+    pub macros: Vec<MacroDef>,
 }
 
 impl MacroResult {
@@ -91,10 +167,7 @@ impl MacroResult {
 /// `source` should be the output of the `.INCLUDE` preprocessor.
 #[must_use]
 pub fn expand(source: &str) -> MacroResult {
-    let mut macros: HashMap<String, MacroDef> = HashMap::new();
-    let mut errors: Vec<MacroError> = Vec::new();
-    let mut output: Vec<String> = Vec::new();
-
+    let mut ex = Expander::default();
     let lines: Vec<&str> = source.lines().collect();
     let mut i = 0;
 
@@ -108,6 +181,7 @@ pub fn expand(source: &str) -> MacroResult {
             let start_line = line_num;
             i += 1;
             let mut body: Vec<String> = Vec::new();
+            let mut body_lines: Vec<usize> = Vec::new();
             let mut found_endm = false;
             while i < lines.len() {
                 let body_line = lines[i];
@@ -119,108 +193,383 @@ pub fn expand(source: &str) -> MacroResult {
                 // Nested .MACRO is an error; skip the header line entirely so it
                 // doesn't leak into the outer macro's body and confuse later passes.
                 if parse_macro_header(body_line).is_some() {
-                    errors.push(MacroError {
-                        message: "nested .MACRO definitions are not allowed".to_string(),
-                        line: i + 1,
-                    });
+                    ex.error("nested .MACRO definitions are not allowed", i + 1);
                     i += 1;
                     continue;
                 }
                 body.push(body_line.to_string());
+                body_lines.push(i + 1);
                 i += 1;
             }
             if !found_endm {
-                errors.push(MacroError {
-                    message: format!(
+                ex.error(
+                    format!(
                         "macro '{}' opened at line {start_line} has no matching .ENDM",
                         def.name
                     ),
-                    line: start_line,
-                });
+                    start_line,
+                );
             }
             // Register macro (last definition wins)
             let key = def.name.to_uppercase();
-            macros.insert(key, MacroDef { body, ..def });
+            ex.macros.insert(
+                key,
+                MacroDef {
+                    body,
+                    body_lines,
+                    ..def
+                },
+            );
             // .MACRO/.ENDM block itself produces no output
             continue;
         }
 
         // ── .ENDM without matching .MACRO ────────────────────────────────────
         if is_endm(line) {
-            errors.push(MacroError {
-                message: ".ENDM without a preceding .MACRO".to_string(),
-                line: line_num,
-            });
+            ex.error(".ENDM without a preceding .MACRO", line_num);
             i += 1;
             continue;
         }
 
-        // ── Possible macro invocation ────────────────────────────────────────
-        if let Some((name, call_args)) = parse_macro_call(line) {
-            let key = name.to_uppercase();
-            if let Some(def) = macros.get(&key).cloned() {
-                if def.params.len() != call_args.len() {
-                    errors.push(MacroError {
-                        message: format!(
-                            "macro '{}' expects {} argument{} but got {}",
-                            def.name,
-                            def.params.len(),
-                            if def.params.len() == 1 { "" } else { "s" },
-                            call_args.len()
-                        ),
-                        line: line_num,
-                    });
-                    // Emit blank lines to preserve line numbering
-                    for _ in &def.body {
-                        output.push(String::new());
-                    }
-                } else {
-                    // Detect direct recursive self-invocation inside the body.
-                    // The expander is single-pass, so recursive calls would just
-                    // emit the invocation text verbatim and produce confusing parse
-                    // errors downstream. Catch them here with a clear diagnostic.
-                    let recursive = def.body.iter().any(|body_line| {
-                        parse_macro_call(body_line)
-                            .is_some_and(|(call, _)| call.to_uppercase() == key)
-                    });
-                    if recursive {
-                        errors.push(MacroError {
-                            message: format!(
-                                "macro '{}' recursively invokes itself, which is not allowed",
-                                def.name
-                            ),
-                            line: line_num,
-                        });
-                        for _ in &def.body {
-                            output.push(String::new());
-                        }
-                    } else {
-                        // Substitute parameters and emit body lines
-                        for body_line in &def.body {
-                            let expanded = substitute_params(body_line, &def.params, &call_args);
-                            output.push(expanded);
-                        }
-                    }
-                }
+        // ── Everything up to the next definition ─────────────────────────────
+        // Expanded as one block so `.REPT`/`.IRP` can span several lines.
+        let start = i;
+        while i < lines.len() && parse_macro_header(lines[i]).is_none() && !is_endm(lines[i]) {
+            i += 1;
+        }
+        let block: Vec<SourceLine> = (start..i)
+            .map(|n| SourceLine {
+                text: lines[n].to_string(),
+                origin: LineOrigin {
+                    line: n + 1,
+                    expansions: Vec::new(),
+                },
+            })
+            .collect();
+        ex.expand_block(&block, None, &mut Vec::new());
+    }
+
+    let mut macros: Vec<MacroDef> = ex.macros.into_values().collect();
+    macros.sort_by(|a, b| a.name.cmp(&b.name));
+
+    MacroResult {
+        source: ex.output.join("\n") + "\n",
+        origins: ex.origins,
+        errors: ex.errors,
+        macros,
+    }
+}
+
+// ── Expansion ─────────────────────────────────────────────────────────────────
+
+/// A line waiting to be expanded, and where it came from.
+struct SourceLine {
+    text: String,
+    origin: LineOrigin,
+}
+
+/// What is currently being expanded, innermost last.
+enum Frame {
+    Macro(String),
+    Repeat,
+}
+
+#[derive(Default)]
+struct Expander {
+    macros: HashMap<String, MacroDef>,
+    errors: Vec<MacroError>,
+    output: Vec<String>,
+    origins: Vec<LineOrigin>,
+    /// Number of the last `%%NAME` scope handed out.
+    scopes: usize,
+}
+
+impl Expander {
+    fn error(&mut self, message: impl Into<String>, line: usize) {
+        self.errors.push(MacroError {
+            message: message.into(),
+            line,
+        });
+    }
+
+    fn new_scope(&mut self) -> usize {
+        self.scopes += 1;
+        self.scopes
+    }
+
+    /// Expand `lines`, whose `%%NAME` labels belong to `scope`.
+    fn expand_block(&mut self, lines: &[SourceLine], scope: Option<usize>, stack: &mut Vec<Frame>) {
+        let mut i = 0;
+        while i < lines.len() {
+            let src = &lines[i];
+            let line = src.origin.line;
+
+            // ── .REPT / .IRP block ───────────────────────────────────────────
+            if let Some(header) = parse_repeat_header(&src.text) {
+                let Some(end) = find_endr(lines, i + 1) else {
+                    self.error(
+                        format!("{} has no matching .ENDR", header.directive()),
+                        line,
+                    );
+                    i += 1;
+                    continue;
+                };
+                self.repeat(&header, &lines[i + 1..end], line, scope, stack);
+                i = end + 1;
+                continue;
+            }
+            if is_endr(&src.text) {
+                self.error(".ENDR without a preceding .REPT or .IRP", line);
                 i += 1;
                 continue;
             }
+
+            // Outside a macro or repeat body `%%` means nothing special.
+            let text = match scope.map(|scope| localize_labels(&src.text, scope)) {
+                None => src.text.clone(),
+                Some(Ok(text)) => text,
+                Some(Err(message)) => {
+                    self.error(message, line);
+                    src.text.clone()
+                }
+            };
+
+            // ── Possible macro invocation ────────────────────────────────────
+            if let Some((name, call_args)) = parse_macro_call(&text) {
+                if let Some(def) = self.macros.get(&name.to_uppercase()).cloned() {
+                    self.invoke(&def, &call_args, &src.origin, stack);
+                    i += 1;
+                    continue;
+                }
+            }
+
+            // ── Normal line — pass through ───────────────────────────────────
+            self.output.push(text);
+            self.origins.push(src.origin.clone());
+            i += 1;
+        }
+    }
+
+    /// Expand one invocation of `def` made on the line at `call`.
+    fn invoke(
+        &mut self,
+        def: &MacroDef,
+        call_args: &[String],
+        call: &LineOrigin,
+        stack: &mut Vec<Frame>,
+    ) {
+        let call_line = call.line;
+        let recursive = stack
+            .iter()
+            .any(|f| matches!(f, Frame::Macro(name) if name.eq_ignore_ascii_case(&def.name)));
+        if recursive {
+            self.error(
+                format!(
+                    "macro '{}' recursively invokes itself, which is not allowed",
+                    def.name
+                ),
+                call_line,
+            );
+            return;
+        }
+        if stack.len() >= MAX_EXPANSION_DEPTH {
+            self.error(
+                format!("macro expansion nested more than {MAX_EXPANSION_DEPTH} levels deep"),
+                call_line,
+            );
+            return;
+        }
+        let args = match bind_args(def, call_args) {
+            Ok(args) => args,
+            Err(message) => {
+                self.error(message, call_line);
+                return;
+            }
+        };
+
+        let mut expansions = call.expansions.clone();
+        expansions.push(Expansion {
+            name: def.name.clone(),
+            call_line,
+        });
+        let body: Vec<SourceLine> = def
+            .body
+            .iter()
+            .zip(&def.body_lines)
+            .map(|(body_line, &def_line)| SourceLine {
+                text: substitute_params(body_line, &def.params, &args),
+                origin: LineOrigin {
+                    line: def_line,
+                    expansions: expansions.clone(),
+                },
+            })
+            .collect();
+        let scope = self.new_scope();
+        stack.push(Frame::Macro(def.name.clone()));
+        self.expand_block(&body, Some(scope), stack);
+        stack.pop();
+    }
+
+    /// Expand the body of a `.REPT`/`.IRP` block opened at input line `line`.
+    fn repeat(
+        &mut self,
+        header: &Repeat,
+        body: &[SourceLine],
+        line: usize,
+        scope: Option<usize>,
+        stack: &mut Vec<Frame>,
+    ) {
+        if stack.len() >= MAX_EXPANSION_DEPTH {
+            self.error(
+                format!("macro expansion nested more than {MAX_EXPANSION_DEPTH} levels deep"),
+                line,
+            );
+            return;
         }
+        let bindings: Vec<Option<&str>> = match header {
+            Repeat::Rept(count) => match rept_count(count) {
+                Ok(n) => vec![None; n],
+                Err(message) => {
+                    self.error(message, line);
+                    return;
+                }
+            },
+            Repeat::Irp { param, values } => {
+                if param.is_empty() {
+                    self.error(".IRP requires a parameter: .IRP %NAME, value, ...", line);
+                    return;
+                }
+                values.iter().map(|v| Some(v.as_str())).collect()
+            }
+        };
 
-        // ── Normal line — pass through unchanged ─────────────────────────────
-        output.push(line.to_string());
-        i += 1;
+        let in_macro = stack.iter().any(|f| matches!(f, Frame::Macro(_)));
+        stack.push(Frame::Repeat);
+        for binding in bindings {
+            let iteration: Vec<SourceLine> = body
+                .iter()
+                .map(|src| SourceLine {
+                    text: match (header, binding) {
+                        (Repeat::Irp { param, .. }, Some(value)) => substitute_params(
+                            &src.text,
+                            std::slice::from_ref(param),
+                            &[value.to_string()],
+                        ),
+                        _ => src.text.clone(),
+                    },
+                    origin: src.origin.clone(),
+                })
+                .collect();
+            let scope = if in_macro {
+                scope
+            } else {
+                Some(self.new_scope())
+            };
+            self.expand_block(&iteration, scope, stack);
+        }
+        stack.pop();
+    }
+}
+
+/// Match `call_args` to the parameters of `def`, filling in defaults.
+fn bind_args(def: &MacroDef, call_args: &[String]) -> Result<Vec<String>, String> {
+    let mut values: Vec<Option<String>> = vec![None; def.params.len()];
+    let mut positional = 0;
+    let mut named = false;
+
+    for arg in call_args {
+        if let Some((name, value)) = split_named_arg(arg) {
+            let Some(idx) = def.params.iter().position(|p| p == name) else {
+                return Err(format!("macro '{}' has no parameter %{name}", def.name));
+            };
+            if values[idx].is_some() {
+                return Err(format!(
+                    "argument %{name} of macro '{}' given twice",
+                    def.name
+                ));
+            }
+            values[idx] = Some(value.to_string());
+            named = true;
+        } else {
+            if named {
+                return Err(format!(
+                    "positional argument '{arg}' follows a named argument in call to macro '{}'",
+                    def.name
+                ));
+            }
+            if positional < values.len() && !arg.is_empty() {
+                values[positional] = Some(arg.clone());
+            }
+            positional += 1;
+        }
     }
 
-    MacroResult {
-        source: output.join("\n") + "\n",
-        errors,
+    let required = def.defaults.iter().filter(|d| d.is_none()).count();
+    let count_error = || {
+        let total = def.params.len();
+        let expected = if required == total {
+            format!("{total} argument{}", if total == 1 { "" } else { "s" })
+        } else {
+            format!("{required} to {total} arguments")
+        };
+        format!(
+            "macro '{}' expects {expected} but got {}",
+            def.name,
+            call_args.len()
+        )
+    };
+    if positional > def.params.len() {
+        return Err(count_error());
+    }
+
+    values
+        .into_iter()
+        .zip(&def.defaults)
+        .zip(&def.params)
+        .map(|((value, default), param)| {
+            value.or_else(|| default.clone()).ok_or_else(|| {
+                if named {
+                    format!("macro '{}' is missing argument %{param}", def.name)
+                } else {
+                    count_error()
+                }
+            })
+        })
+        .collect()
+}
+
+/// Evaluate a `.REPT` count, which may only use numbers.
+fn rept_count(text: &str) -> Result<usize, String> {
+    let lexed = tokenize(text);
+    let tokens: Vec<_> = lexed
+        .tokens
+        .iter()
+        .filter(|t| !matches!(t.kind, TokenKind::Newline | TokenKind::Eof))
+        .collect();
+    if tokens.is_empty() {
+        return Err(".REPT requires a count: .REPT n".into());
     }
+    if let Some(err) = lexed.errors.first() {
+        return Err(err.message.clone());
+    }
+    let (expr, used) = parse_expr(&tokens).map_err(|e| e.message)?;
+    if used < tokens.len() {
+        return Err(format!("unexpected text after the .REPT count '{expr}'"));
+    }
+    let count = expr
+        .eval(&mut |_| None)
+        .map_err(|_| format!(".REPT count '{expr}' must be a constant number"))?;
+    if !(0..=MAX_REPT).contains(&count) {
+        return Err(format!(
+            ".REPT count {count} is out of range (0 to {MAX_REPT})"
+        ));
+    }
+    Ok(count as usize)
 }
 
 // ── Parsing helpers ───────────────────────────────────────────────────────────
 
-/// Parse a `.MACRO name [%P1, %P2, ...]` header.
+/// Parse a `.MACRO name [%P1, %P2=default, ...]` header.
 /// Returns `Some(MacroDef)` (with empty body) if this is a macro header.
 fn parse_macro_header(line: &str) -> Option<MacroDef> {
     let trimmed = line.trim();
@@ -239,21 +588,29 @@ fn parse_macro_header(line: &str) -> Option<MacroDef> {
         return None;
     }
 
-    // Collect comma-separated parameters (strip leading %)
-    let params: Vec<String> = if let Some(param_str) = parts.next() {
-        param_str
-            .split(',')
-            .map(|p| p.trim().trim_start_matches('%').to_string())
-            .filter(|p| !p.is_empty())
-            .collect()
-    } else {
-        Vec::new()
-    };
+    // Collect comma-separated parameters (strip leading %, split off defaults)
+    let (params, defaults) = parts
+        .next()
+        .unwrap_or("")
+        .split(',')
+        .map(|p| {
+            let p = p.trim().trim_start_matches('%');
+            match p.split_once('=') {
+                Some((name, default)) => {
+                    (name.trim().to_string(), Some(default.trim().to_string()))
+                }
+                None => (p.to_string(), None),
+            }
+        })
+        .filter(|(p, _)| !p.is_empty())
+        .unzip();
 
     Some(MacroDef {
         name,
         params,
+        defaults,
         body: Vec::new(),
+        body_lines: Vec::new(),
     })
 }
 
@@ -263,6 +620,80 @@ fn is_endm(line: &str) -> bool {
     t.eq_ignore_ascii_case(".ENDM")
 }
 
+/// The header of a repetition block.
+enum Repeat {
+    /// `.REPT count`
+    Rept(String),
+    /// `.IRP %PARAM, value, ...`
+    Irp { param: String, values: Vec<String> },
+}
+
+impl Repeat {
+    fn directive(&self) -> &'static str {
+        match self {
+            Repeat::Rept(_) => ".REPT",
+            Repeat::Irp { .. } => ".IRP",
+        }
+    }
+}
+
+/// Split off a leading directive word (`.REPT`) and the rest of the line
+/// without its comment.
+fn directive_word(line: &str) -> Option<(String, &str)> {
+    let trimmed = line.trim_start();
+    if !trimmed.starts_with('.') {
+        return None;
+    }
+    let end = trimmed
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| !c.is_alphanumeric() && *c != '_')
+        .map_or(trimmed.len(), |(i, _)| i);
+    let rest = &trimmed[end..];
+    let rest = rest.split(';').next().unwrap_or("").trim();
+    Some((trimmed[..end].to_uppercase(), rest))
+}
+
+/// Parse a `.REPT` or `.IRP` header.
+fn parse_repeat_header(line: &str) -> Option<Repeat> {
+    let (word, rest) = directive_word(line)?;
+    match word.as_str() {
+        ".REPT" => Some(Repeat::Rept(rest.to_string())),
+        ".IRP" => {
+            let mut parts = rest.split(',').map(str::trim);
+            let param = parts
+                .next()
+                .unwrap_or("")
+                .trim_start_matches('%')
+                .to_string();
+            let values = parts.map(str::to_string).collect();
+            Some(Repeat::Irp { param, values })
+        }
+        _ => None,
+    }
+}
+
+/// Returns `true` if `line` is a `.ENDR` directive.
+fn is_endr(line: &str) -> bool {
+    directive_word(line).is_some_and(|(word, _)| word == ".ENDR")
+}
+
+/// Index of the `.ENDR` closing a block whose body starts at `lines[from]`.
+fn find_endr(lines: &[SourceLine], from: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, src) in lines.iter().enumerate().skip(from) {
+        if parse_repeat_header(&src.text).is_some() {
+            depth += 1;
+        } else if is_endr(&src.text) {
+            if depth == 0 {
+                return Some(i);
+            }
+            depth -= 1;
+        }
+    }
+    None
+}
+
 /// Try to parse `line` as a macro invocation.
 ///
 /// Returns `Some((macro_name, args))` when the first token on the line is
@@ -313,17 +744,88 @@ fn split_first_token(s: &str) -> (&str, &str) {
     (&s[..end], s[end..].trim_start())
 }
 
+/// Split a `NAME=value` (or `%NAME=value`) argument.
+fn split_named_arg(arg: &str) -> Option<(&str, &str)> {
+    let (name, value) = arg.split_once('=')?;
+    let name = name.trim().trim_start_matches('%');
+    let is_name = name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    is_name.then(|| (name, value.trim()))
+}
+
+/// Length of the identifier at the start of `s`.
+fn ident_len(s: &str) -> usize {
+    s.find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or(s.len())
+}
+
 /// Replace `%PARAM` placeholders in `template` with the corresponding `args`.
+///
+/// Only whole names are replaced, so `%R` leaves `%REG` alone; `%%NAME`
+/// local labels are left for [`localize_labels`].
 fn substitute_params(template: &str, params: &[String], args: &[String]) -> String {
-    let mut result = template.to_string();
-    for (param, arg) in params.iter().zip(args.iter()) {
-        // Replace both `%PARAM` and `%param` (case-sensitive match as declared)
-        let placeholder = format!("%{param}");
-        result = result.replace(&placeholder, arg);
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(pos) = rest.find('%') {
+        result.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        if let Some(local) = after.strip_prefix('%') {
+            let len = ident_len(local);
+            result.push_str(&rest[pos..pos + 2 + len]);
+            rest = &local[len..];
+            continue;
+        }
+        let len = ident_len(after);
+        let name = &after[..len];
+        // Case-sensitive match, as declared
+        match params.iter().position(|p| p == name) {
+            Some(idx) if len > 0 => result.push_str(&args[idx]),
+            _ => result.push_str(&rest[pos..pos + 1 + len]),
+        }
+        rest = &after[len..];
     }
+    result.push_str(rest);
     result
 }
 
+/// Replace each `%%NAME` local label with `NAME__<scope>`.
+///
+/// Only the code part of the line is rewritten: `%%` inside a string literal
+/// or a `;` comment is left as written.
+fn localize_labels(line: &str, scope: usize) -> Result<String, String> {
+    if !line.contains("%%") {
+        return Ok(line.to_string());
+    }
+    let bytes = line.as_bytes();
+    let mut result = String::with_capacity(line.len() + 4);
+    let mut copied = 0;
+    let mut in_string = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if in_string => i += 1,
+            b'"' => in_string = !in_string,
+            b';' if !in_string => break,
+            b'%' if !in_string && bytes.get(i + 1) == Some(&b'%') => {
+                let after = &line[i + 2..];
+                let len = ident_len(after);
+                if len == 0 {
+                    return Err("'%%' must be followed by a label name".into());
+                }
+                result.push_str(&line[copied..i]);
+                result.push_str(&format!("{}__{scope}", &after[..len]));
+                i += 2 + len;
+                copied = i;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    result.push_str(&line[copied..]);
+    Ok(result)
+}
+
 // ── Tests ─────────────────────────────────────────────────────────────────────
 
 #[cfg(test)]
@@ -410,6 +912,22 @@ HALT_NOW
         );
     }
 
+    #[test]
+    fn origins_point_into_the_definition() {
+        let src =
+            ".MACRO TWICE %R\nADD %R, %R, %R\nADD %R, %R, %R\n.ENDM\n.ORIG x3000\nTWICE R1\n.END\n";
+        let r = expand_str(src);
+        let lines: Vec<usize> = r.origins.iter().map(|o| o.line).collect();
+        assert_eq!(lines, vec![5, 2, 3, 7]);
+        assert_eq!(r.origins[0].expansions, []);
+        let expansion = Expansion {
+            name: "TWICE".to_string(),
+            call_line: 6,
+        };
+        assert_eq!(r.origins[1].expansions, [expansion]);
+        assert_eq!(r.origins.len(), r.source.lines().count());
+    }
+
     // ── Parameterised macro ───────────────────────────────────────────────────
 
     #[test]
@@ -541,4 +1059,207 @@ HALT
         );
         assert_eq!(result, "STR R1, R6, #0");
     }
+
+    // ── Local labels ──────────────────────────────────────────────────────────
+
+    #[test]
+    fn local_labels_are_unique_per_expansion() {
+        let src = "\
+.MACRO WAIT %N
+        ADD R0, R0, %N
+%%LOOP  ADD R0, R0, #-1
+        BRp %%LOOP
+.ENDM
+WAIT #2
+WAIT #3
+";
+        let r = expand_str(src);
+        assert!(!r.has_errors(), "errors: {:?}", r.errors);
+        assert!(
+            r.source.contains("LOOP__1  ADD R0, R0, #-1"),
+            "{}",
+            r.source
+        );
+        assert!(r.source.contains("BRp LOOP__1"), "{}", r.source);
+        assert!(
+            r.source.contains("LOOP__2  ADD R0, R0, #-1"),
+            "{}",
+            r.source
+        );
+        assert!(r.source.contains("BRp LOOP__2"), "{}", r.source);
+    }
+
+    #[test]
+    fn percent_signs_outside_macros_are_left_alone() {
+        let src = "HALT ; done 100%% of the time\nMSG .STRINGZ \"50%%\"\n";
+        let r = expand_str(src);
+        assert!(!r.has_errors(), "errors: {:?}", r.errors);
+        assert_eq!(r.source, src);
+    }
+
+    #[test]
+    fn local_labels_skip_strings_and_comments() {
+        let src = "\
+.MACRO SHOW
+%%MSG   .STRINGZ \"%%MSG is 100%%\" ; prints %%MSG
+        LEA R0, %%MSG
+.ENDM
+SHOW
+";
+        let r = expand_str(src);
+        assert!(!r.has_errors(), "errors: {:?}", r.errors);
+        assert!(
+            r.source
+                .contains("MSG__1   .STRINGZ \"%%MSG is 100%%\" ; prints %%MSG"),
+            "{}",
+            r.source
+        );
+        assert!(r.source.contains("LEA R0, MSG__1"), "{}", r.source);
+    }
+
+    // ── Defaults and named arguments ──────────────────────────────────────────
+
+    #[test]
+    fn parse_macro_header_with_defaults() {
+        let def = parse_macro_header(".MACRO PUSH %REG, %STACK=R6").unwrap();
+        assert_eq!(def.params, vec!["REG", "STACK"]);
+        assert_eq!(def.defaults, vec![None, Some("R6".to_string())]);
+    }
+
+    #[test]
+    fn default_and_named_arguments() {
+        let src = "\
+.MACRO PUSH %REG, %STACK=R6
+    STR %REG, %STACK, #0
+.ENDM
+PUSH R1
+PUSH R2, R5
+PUSH STACK=R4, REG=R3
+PUSH , R5
+";
+        let r = expand_str(src);
+        assert_eq!(r.errors.len(), 1, "errors: {:?}", r.errors);
+        assert!(r.errors[0].message.contains("expects 1 to 2 arguments"));
+        assert_eq!(r.errors[0].line, 7);
+        let lines: Vec<&str> = r.source.lines().map(str::trim).collect();
+        assert_eq!(
+            lines,
+            vec!["STR R1, R6, #0", "STR R2, R5, #0", "STR R3, R4, #0"]
+        );
+    }
+
+    #[test]
+    fn bad_named_arguments_are_errors() {
+        let src = "\
+.MACRO COPY %SRC, %DST
+    ADD %DST, %SRC, #0
+.ENDM
+COPY SRC=R1
+COPY R1, SRC=R2
+COPY DST=R1, R2
+COPY WHAT=R1
+";
+        let r = expand_str(src);
+        let messages: Vec<&str> = r.errors.iter().map(|e| e.message.as_str()).collect();
+        assert!(
+            messages[0].contains("missing argument %DST"),
+            "{messages:?}"
+        );
+        assert!(messages[1].contains("%SRC of macro 'COPY' given twice"));
+        assert!(messages[2].contains("follows a named argument"));
+        assert!(messages[3].contains("has no parameter %WHAT"));
+    }
+
+    // ── Repetition ────────────────────────────────────────────────────────────
+
+    #[test]
+    fn rept_repeats_body() {
+        let r = expand_str(".REPT 3\nADD R1, R1, R1\n.ENDR\nHALT\n");
+        assert!(!r.has_errors(), "errors: {:?}", r.errors);
+        assert_eq!(r.source.matches("ADD R1, R1, R1").count(), 3);
+        let lines: Vec<usize> = r.origins.iter().map(|o| o.line).collect();
+        assert_eq!(lines, vec![2, 2, 2, 4]);
+    }
+
+    #[test]
+    fn irp_binds_each_value() {
+        let r = expand_str(".IRP %R, R1, R2\nAND %R, %R, #0\n.ENDR\n");
+        assert!(!r.has_errors(), "errors: {:?}", r.errors);
+        assert_eq!(r.source, "AND R1, R1, #0\nAND R2, R2, #0\n");
+    }
+
+    #[test]
+    fn rept_inside_macro_uses_parameters() {
+        let src = "\
+.MACRO SHL %R, %N=#1
+    .REPT %N
+    ADD %R, %R, %R
+    .ENDR
+.ENDM
+SHL R2, 2
+";
+        let r = expand_str(src);
+        assert!(!r.has_errors(), "errors: {:?}", r.errors);
+        assert_eq!(r.source.matches("ADD R2, R2, R2").count(), 2);
+        assert!(r.origins.iter().all(|o| !o.expansions.is_empty()));
+    }
+
+    #[test]
+    fn rept_gives_each_iteration_its_own_labels() {
+        let r = expand_str(".REPT 2\n%%L BR %%L\n.ENDR\n");
+        assert_eq!(r.source, "L__1 BR L__1\nL__2 BR L__2\n");
+    }
+
+    #[test]
+    fn repeat_errors() {
+        let r = expand_str(".REPT COUNT\nHALT\n.ENDR\n.ENDR\n.IRP %R, R1\nHALT\n");
+        let messages: Vec<&str> = r.errors.iter().map(|e| e.message.as_str()).collect();
+        assert!(messages[0].contains("must be a constant"), "{messages:?}");
+        assert!(messages[1].contains(".ENDR without a preceding"));
+        assert!(messages[2].contains(".IRP has no matching .ENDR"));
+        assert_eq!(r.errors[2].line, 5);
+    }
+
+    // ── Nested calls ──────────────────────────────────────────────────────────
+
+    #[test]
+    fn nested_macro_calls_expand() {
+        let src = "\
+.MACRO CLR %R
+    AND %R, %R, #0
+.ENDM
+.MACRO CLR2 %A, %B
+    CLR %A
+    CLR %B
+.ENDM
+CLR2 R1, R2
+";
+        let r = expand_str(src);
+        assert!(!r.has_errors(), "errors: {:?}", r.errors);
+        assert_eq!(r.source, "    AND R1, R1, #0\n    AND R2, R2, #0\n");
+        let chain: Vec<(&str, usize)> = r.origins[1]
+            .expansions
+            .iter()
+            .map(|e| (e.name.as_str(), e.call_line))
+            .collect();
+        assert_eq!(chain, [("CLR2", 8), ("CLR", 6)]);
+    }
+
+    #[test]
+    fn indirect_recursion_is_error() {
+        let src = ".MACRO A\nB\n.ENDM\n.MACRO B\nA\n.ENDM\nA\n";
+        let r = expand_str(src);
+        assert_eq!(r.errors.len(), 1);
+        assert!(r.errors[0]
+            .message
+            .contains("'A' recursively invokes itself"));
+    }
+
+    #[test]
+    fn substitute_matches_whole_names() {
+        let params = ["R".to_string(), "REG".to_string()];
+        let args = ["R1".to_string(), "R2".to_string()];
+        let result = substitute_params("ADD %REG, %R, %%R", &params, &args);
+        assert_eq!(result, "ADD R2, R1, %%R");
+    }
 }
@@ -2,15 +2,12 @@ use std::fs;
 use std::io::{self, Read};
 use std::path::Path;
 
-use lc3_assembler::diagnostic::RichDiagnostics;
-use lc3_assembler::encoder::encode;
-use lc3_assembler::error::{AsmError, ErrorKind, Span};
-use lc3_assembler::first_pass::first_pass;
-use lc3_assembler::lexer::tokenize;
+use lc3_assembler::debug_info::DebugInfo;
+use lc3_assembler::encoder::Segment;
 use lc3_assembler::listing;
-use lc3_assembler::macro_expand;
-use lc3_assembler::parser::parse_lines;
-use lc3_assembler::preprocessor;
+use lc3_assembler::obj;
+use lc3_assembler::relocatable::RelocatableObject;
+use lc3_assembler::{assemble_source, AssembleOptions};
 
 // ── CLI argument parsing ──────────────────────────────────────────────────────
 
@@ -21,6 +18,17 @@ enum EmitFormat {
     Obj,
     /// Intel HEX ASCII text — portable across tools/simulators.
     Hex,
+    /// Relocatable object for `lc3-link`; allows `.EXTERNAL` references.
+    Rel,
+}
+
+/// How errors and warnings are reported on stderr.
+#[derive(Clone, Copy, PartialEq)]
+enum DiagnosticsFormat {
+    /// Source excerpts with carets and hints (default).
+    Text,
+    /// One JSON object per line; nothing else is written to stderr.
+    Json,
 }
 
 struct Args {
@@ -32,12 +40,20 @@ struct Args {
     listing: Option<String>,
     /// Path for an optional symbol-table (.sym) file.
     symbols: Option<String>,
+    /// Path for an optional debug-info (.dbg) sidecar.
+    debug_info: Option<String>,
     /// Output format (binary obj or Intel HEX).
     emit: EmitFormat,
+    /// Write one .obj per `.ORIG` section instead of a multi-segment container.
+    split_segments: bool,
     /// Validate-only; do not write any output files.
     check: bool,
     /// Disable ANSI colour output regardless of TTY detection.
     no_color: bool,
+    /// Human-readable or JSON diagnostics.
+    diagnostics_format: DiagnosticsFormat,
+    /// `-D NAME[=value]` definitions for conditional assembly.
+    defines: Vec<(String, i32)>,
 }
 
 impl Args {
@@ -59,9 +75,13 @@ impl Args {
         let mut output: Option<String> = None;
         let mut listing_path: Option<String> = None;
         let mut symbols_path: Option<String> = None;
+        let mut debug_info_path: Option<String> = None;
         let mut emit = EmitFormat::Obj;
+        let mut split_segments = false;
         let mut check = false;
         let mut no_color = false;
+        let mut diagnostics_format = DiagnosticsFormat::Text;
+        let mut defines: Vec<(String, i32)> = Vec::new();
 
         let mut i = 1usize;
         while i < args.len() {
@@ -90,27 +110,77 @@ impl Args {
                     }
                     symbols_path = Some(args[i].to_string());
                 }
+                "-g" | "--debug-info" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("error: -g requires a filename argument");
+                        std::process::exit(1);
+                    }
+                    debug_info_path = Some(args[i].to_string());
+                }
                 "--emit" => {
                     i += 1;
                     if i >= args.len() {
-                        eprintln!("error: --emit requires a format argument (obj|hex)");
+                        eprintln!("error: --emit requires a format argument (obj|hex|rel)");
                         std::process::exit(1);
                     }
                     emit = match args[i] {
                         "obj" => EmitFormat::Obj,
                         "hex" => EmitFormat::Hex,
+                        "rel" => EmitFormat::Rel,
                         other => {
-                            eprintln!("error: unknown emit format '{other}' (expected: obj, hex)");
+                            eprintln!(
+                                "error: unknown emit format '{other}' (expected: obj, hex, rel)"
+                            );
                             std::process::exit(1);
                         }
                     };
                 }
+                "-D" | "--define" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!("error: -D requires a NAME[=value] argument");
+                        std::process::exit(1);
+                    }
+                    defines.push(parse_define(args[i]).unwrap_or_else(|err| {
+                        eprintln!("error: -D {}: {err}", args[i]);
+                        std::process::exit(1);
+                    }));
+                }
+                other if other.starts_with("-D") && other.len() > 2 => {
+                    defines.push(parse_define(&other[2..]).unwrap_or_else(|err| {
+                        eprintln!("error: {other}: {err}");
+                        std::process::exit(1);
+                    }));
+                }
+                "--split-segments" => {
+                    split_segments = true;
+                }
                 "--check" => {
                     check = true;
                 }
                 "--no-color" => {
                     no_color = true;
                 }
+                "--diagnostics-format" => {
+                    i += 1;
+                    if i >= args.len() {
+                        eprintln!(
+                            "error: --diagnostics-format requires a format argument (text|json)"
+                        );
+                        std::process::exit(1);
+                    }
+                    diagnostics_format = match args[i] {
+                        "text" => DiagnosticsFormat::Text,
+                        "json" => DiagnosticsFormat::Json,
+                        other => {
+                            eprintln!(
+                                "error: unknown diagnostics format '{other}' (expected: text, json)"
+                            );
+                            std::process::exit(1);
+                        }
+                    };
+                }
                 // Anything else is treated as the positional input file.
                 other => {
                     if input.is_some() {
@@ -135,9 +205,13 @@ impl Args {
             output,
             listing: listing_path,
             symbols: symbols_path,
+            debug_info: debug_info_path,
             emit,
+            split_segments,
             check,
             no_color,
+            diagnostics_format,
+            defines,
         }
     }
 }
@@ -164,106 +238,40 @@ fn main() {
         (src, args.input.clone())
     };
 
-    // ── Stage 0: Preprocessing (.INCLUDE expansion) ──────────────────────────
-
-    let prep = preprocessor::preprocess(&display_name, Some(&source));
+    // ── Assemble (preprocess → macros → lex → parse → first pass → encode) ──
 
-    // Convert preprocessor errors to AsmError so they flow through the same
-    // diagnostic machinery as everything else.
-    let prep_errors: Vec<AsmError> = prep
-        .errors
-        .iter()
-        .map(|e| {
-            AsmError::new(
-                ErrorKind::IoError,
-                e.message.clone(),
-                Span {
-                    line: e.line.max(1),
-                    col: 1,
-                },
-            )
-        })
-        .collect();
-
-    // ── Stage 1: Macro expansion ─────────────────────────────────────────────
-
-    let macro_result = macro_expand::expand(&prep.source);
-    let macro_errors: Vec<AsmError> = macro_result
-        .errors
-        .iter()
-        .map(|e| {
-            AsmError::new(
-                ErrorKind::MacroError,
-                e.message.clone(),
-                Span {
-                    line: e.line.max(1),
-                    col: 1,
-                },
-            )
-        })
-        .collect();
-
-    // Use the macro-expanded source for all downstream stages.
-    //
-    // If *preprocessing* failed (e.g., an included file could not be found), the
-    // expanded source may be truncated or missing whole sections, so we fall back
-    // to the original source to avoid a cascade of spurious errors.
-    //
-    // If only *macro expansion* had errors (wrong argument count, recursive call,
-    // etc.), the expanded source is still structurally complete: bad invocations
-    // are replaced with blank lines to preserve line numbering, and passing it
-    // downstream gives more accurate diagnostics than re-using the original text.
-    let expanded_source = if prep.has_errors() {
-        source.clone()
-    } else {
-        macro_result.source.clone()
+    let options = AssembleOptions {
+        relocatable: args.emit == EmitFormat::Rel,
+        defines: args.defines.clone(),
     };
+    let asm = assemble_source(&source, &display_name, &options);
+
+    // Warnings first (they don't block assembly), then errors.
+    let json = args.diagnostics_format == DiagnosticsFormat::Json;
+    if json {
+        asm.write_diagnostics_json(&mut io::stderr().lock())
+            .unwrap_or_else(|err| {
+                eprintln!("error: failed to write diagnostics: {err}");
+                std::process::exit(1);
+            });
+    } else {
+        asm.emit_diagnostics(!args.no_color);
+    }
 
-    // ── Stage 2–5: Lex → Parse → First pass → Encode ─────────────────────────
-
-    let lexed = tokenize(&expanded_source);
-    let parsed = parse_lines(&lexed.tokens);
-    let first = first_pass(parsed.lines);
-    let encoded = encode(&first);
-
-    // ── Diagnostics ──────────────────────────────────────────────────────────
-
-    // Use the *original* source for diagnostics (line numbers from prep/macro
-    // stages point into the original file; lex/parse/encode point into the
-    // expanded source, which only differs when macros/includes were used).
-    let diag = RichDiagnostics::new(&source, &display_name).with_color(!args.no_color);
-
-    // Collect all errors (preprocess → macro → lex → parse → first-pass → encode)
-    let all_errors: Vec<_> = prep_errors
-        .iter()
-        .chain(macro_errors.iter())
-        .chain(lexed.errors.iter())
-        .chain(parsed.errors.iter())
-        .chain(first.errors.iter())
-        .chain(encoded.errors.iter())
-        .collect();
-
-    // Collect all warnings (first-pass → encode)
-    let all_warnings: Vec<_> = first
-        .warnings
-        .iter()
-        .chain(encoded.warnings.iter())
-        .cloned()
-        .collect();
-
-    // Emit warnings first (they don't block assembly)
-    diag.emit_all_warnings(&all_warnings);
-
-    // Emit errors
-    diag.emit_all_errors(&all_errors);
-
-    if !all_errors.is_empty() {
+    if asm.has_errors() {
         std::process::exit(1);
     }
 
+    let first = &asm.first;
+    let encoded = &asm.encoded;
+    let all_warnings = &asm.warnings;
+
     // ── Check mode: stop here (no file output) ────────────────────────────────
 
     if args.check {
+        if json {
+            std::process::exit(0);
+        }
         if all_warnings.is_empty() {
             eprintln!(
                 "ok — '{}' assembles without errors or warnings",
@@ -285,6 +293,7 @@ fn main() {
     let default_ext = match args.emit {
         EmitFormat::Obj => "obj",
         EmitFormat::Hex => "hex",
+        EmitFormat::Rel => "rel",
     };
 
     let output_path: String = match &args.output {
@@ -303,51 +312,55 @@ fn main() {
 
     // ── Write output file ─────────────────────────────────────────────────────
 
+    if args.split_segments && args.emit != EmitFormat::Obj {
+        eprintln!("error: --split-segments only applies to --emit obj");
+        std::process::exit(1);
+    }
+    let split = args.split_segments && encoded.segments.len() > 1;
+
     if args.input == "-" && args.output.is_none() {
+        if split {
+            eprintln!("error: --split-segments needs an output path (-o) when reading stdin");
+            std::process::exit(1);
+        }
         // stdin → stdout
-        match args.emit {
-            EmitFormat::Obj => {
-                write_obj_stdout(encoded.orig_address, &encoded.machine_code).unwrap_or_else(
-                    |err| {
-                        eprintln!("error: failed to write to stdout: {err}");
-                        std::process::exit(1);
-                    },
-                );
-            }
-            EmitFormat::Hex => {
-                use std::io::Write as _;
-                let hex = intel_hex(encoded.orig_address, &encoded.machine_code);
-                std::io::stdout()
-                    .write_all(hex.as_bytes())
-                    .unwrap_or_else(|err| {
-                        eprintln!("error: failed to write to stdout: {err}");
-                        std::process::exit(1);
-                    });
-            }
+        use std::io::Write as _;
+        let bytes = match args.emit {
+            EmitFormat::Obj => obj::to_bytes(&encoded.segments),
+            EmitFormat::Hex => intel_hex(&encoded.segments).into_bytes(),
+            EmitFormat::Rel => RelocatableObject::from_assembly(&asm).to_bytes(),
+        };
+        let mut out = io::stdout();
+        out.write_all(&bytes)
+            .and_then(|()| out.flush())
+            .unwrap_or_else(|err| {
+                eprintln!("error: failed to write to stdout: {err}");
+                std::process::exit(1);
+            });
+    } else if split {
+        for seg in &encoded.segments {
+            let path = segment_path(&output_path, seg.origin);
+            fs::write(&path, obj::write_image(seg.origin, &seg.words)).unwrap_or_else(|err| {
+                eprintln!("error: failed to write '{}': {err}", path);
+                std::process::exit(1);
+            });
         }
     } else {
-        match args.emit {
-            EmitFormat::Obj => {
-                write_obj_file(&output_path, encoded.orig_address, &encoded.machine_code)
-                    .unwrap_or_else(|err| {
-                        eprintln!("error: failed to write '{}': {err}", output_path);
-                        std::process::exit(1);
-                    });
-            }
-            EmitFormat::Hex => {
-                let hex = intel_hex(encoded.orig_address, &encoded.machine_code);
-                fs::write(&output_path, hex).unwrap_or_else(|err| {
-                    eprintln!("error: failed to write '{}': {err}", output_path);
-                    std::process::exit(1);
-                });
-            }
-        }
+        let bytes = match args.emit {
+            EmitFormat::Obj => obj::to_bytes(&encoded.segments),
+            EmitFormat::Hex => intel_hex(&encoded.segments).into_bytes(),
+            EmitFormat::Rel => RelocatableObject::from_assembly(&asm).to_bytes(),
+        };
+        fs::write(&output_path, bytes).unwrap_or_else(|err| {
+            eprintln!("error: failed to write '{}': {err}", output_path);
+            std::process::exit(1);
+        });
     }
 
     // ── Write listing file ────────────────────────────────────────────────────
 
     if let Some(ref lst_path) = args.listing {
-        let lst = listing::generate(&source, &first, &encoded, &display_name);
+        let lst = listing::generate(&source, first, encoded, &display_name);
         fs::write(lst_path, &lst).unwrap_or_else(|err| {
             eprintln!("error: failed to write listing '{}': {err}", lst_path);
             std::process::exit(1);
@@ -364,8 +377,27 @@ fn main() {
         });
     }
 
+    // ── Write debug-info sidecar ──────────────────────────────────────────────
+
+    if let Some(ref dbg_path) = args.debug_info {
+        let output_name = if output_path.is_empty() {
+            args.output.as_deref().unwrap_or("-")
+        } else {
+            &output_path
+        };
+        let dbg = DebugInfo::from_assembly(&asm).to_text(output_name);
+        fs::write(dbg_path, &dbg).unwrap_or_else(|err| {
+            eprintln!("error: failed to write debug info '{}': {err}", dbg_path);
+            std::process::exit(1);
+        });
+    }
+
     // ── Success banner ────────────────────────────────────────────────────────
 
+    if json {
+        return;
+    }
+
     let warnings_note = if all_warnings.is_empty() {
         String::new()
     } else {
@@ -376,9 +408,26 @@ fn main() {
         )
     };
 
-    if args.input != "-" || args.output.is_some() {
+    let layout = if encoded.segments.len() > 1 {
+        format!("{} segments", encoded.segments.len())
+    } else {
+        format!("origin x{:04X}", encoded.orig_address)
+    };
+
+    if split && args.emit == EmitFormat::Obj {
+        for seg in &encoded.segments {
+            eprintln!(
+                "assembled '{}' → '{}'  [{} word{}, origin x{:04X}]",
+                display_name,
+                segment_path(&output_path, seg.origin),
+                seg.words.len(),
+                if seg.words.len() == 1 { "" } else { "s" },
+                seg.origin,
+            );
+        }
+    } else if args.input != "-" || args.output.is_some() {
         eprintln!(
-            "assembled '{}' → '{}'  [{} word{}, origin x{:04X}]{}",
+            "assembled '{}' → '{}'  [{} word{}, {}]{}",
             display_name,
             if args.input == "-" {
                 args.output.as_deref().unwrap_or("-")
@@ -391,7 +440,7 @@ fn main() {
             } else {
                 "s"
             },
-            encoded.orig_address,
+            layout,
             warnings_note,
         );
     }
@@ -410,6 +459,9 @@ fn main() {
             }
         );
     }
+    if let Some(ref dbg_path) = args.debug_info {
+        eprintln!("debug    → '{dbg_path}'");
+    }
 }
 
 // ── Help text ─────────────────────────────────────────────────────────────────
@@ -429,9 +481,21 @@ fn print_help() {
     println!("  -o, --output <file>    Write machine code to <file> (default: <input>.obj)");
     println!("  -l, --listing <file>   Write a human-readable listing (includes symbol table)");
     println!("  -s, --symbols <file>   Write the symbol table to <file>");
-    println!("      --emit <format>    Output format: obj (default) or hex (Intel HEX)");
+    println!("  -g, --debug-info <file>");
+    println!("                         Write source lines and code/data ranges for every");
+    println!("                         word to <file>, for lc3-sim and lc3-disasm");
+    println!("      --emit <format>    Output format: obj (default), hex (Intel HEX)");
+    println!("                         or rel (relocatable object for lc3-link)");
+    println!("      --split-segments   Write one .obj per .ORIG section (<output>_x<ORIG>.obj)");
+    println!("                         instead of a multi-segment container");
+    println!("  -D, --define <NAME[=value]>");
+    println!("                         Define NAME for .IF/.IFDEF (value defaults to 1;");
+    println!("                         decimal, x hex or b binary)");
     println!("      --check            Validate only; do not write any output files");
     println!("      --no-color         Disable ANSI colour in diagnostics");
+    println!("      --diagnostics-format <text|json>");
+    println!("                         Report diagnostics as text (default) or as one");
+    println!("                         JSON object per line on stderr");
     println!("  -h, --help             Print this help message");
     println!("  -V, --version          Print version information");
     println!();
@@ -440,32 +504,55 @@ fn print_help() {
     println!("  lc3-assembler program.asm -o out.obj           # Explicit output path");
     println!("  lc3-assembler program.asm -l prog.lst          # Listing with symbol table");
     println!("  lc3-assembler program.asm -s prog.sym          # Symbol table only");
+    println!("  lc3-assembler program.asm -g program.dbg       # Debug info for lc3-sim");
     println!("  lc3-assembler program.asm --emit hex           # Intel HEX output");
+    println!("  lc3-assembler util.asm --emit rel              # util.rel for lc3-link");
+    println!("  lc3-assembler os.asm --split-segments          # os_x0200.obj, os_x1000.obj, …");
+    println!("  lc3-assembler program.asm -D DEBUG             # Assemble .IFDEF DEBUG blocks");
     println!("  lc3-assembler --check program.asm              # Validate without writing");
+    println!("  lc3-assembler --check --diagnostics-format json program.asm");
     println!("  lc3-assembler - < program.asm > program.obj    # stdin → stdout");
 }
 
-// ── File I/O helpers ──────────────────────────────────────────────────────────
-
-/// Write an LC-3 object file (big-endian origin word followed by code words).
-fn write_obj_file(path: &str, orig: u16, code: &[u16]) -> io::Result<()> {
-    let mut buf = Vec::with_capacity((1 + code.len()) * 2);
-    buf.extend_from_slice(&orig.to_be_bytes());
-    for &word in code {
-        buf.extend_from_slice(&word.to_be_bytes());
+/// Parse a `-D NAME[=value]` argument.
+fn parse_define(spec: &str) -> Result<(String, i32), String> {
+    let (name, value) = spec.split_once('=').unwrap_or((spec, "1"));
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("invalid name '{name}'"));
     }
-    fs::write(path, &buf)
+    let value = value.trim_start_matches('#');
+    let (digits, radix, negative) = match value.strip_prefix('-') {
+        Some(v) => (v, 10, true),
+        None => (value, 10, false),
+    };
+    let (digits, radix) = if let Some(hex) = digits.strip_prefix(['x', 'X']) {
+        (hex, 16)
+    } else if let Some(bin) = digits.strip_prefix(['b', 'B']) {
+        (bin, 2)
+    } else {
+        (digits, radix)
+    };
+    let magnitude = i32::from_str_radix(digits, radix)
+        .ok()
+        .filter(|v| *v <= 0xFFFF)
+        .ok_or_else(|| format!("invalid value '{value}'"))?;
+    Ok((
+        name.to_uppercase(),
+        if negative { -magnitude } else { magnitude },
+    ))
 }
 
-/// Write an LC-3 object file to stdout (used when input is "-" and no -o given).
-fn write_obj_stdout(orig: u16, code: &[u16]) -> io::Result<()> {
-    use io::Write as _;
-    let mut out = io::stdout();
-    out.write_all(&orig.to_be_bytes())?;
-    for &word in code {
-        out.write_all(&word.to_be_bytes())?;
-    }
-    out.flush()
+// ── File I/O helpers ──────────────────────────────────────────────────────────
+
+/// Output path for one segment when `--split-segments` is used:
+/// `prog.obj` + origin x4000 → `prog_x4000.obj`.
+fn segment_path(output_path: &str, origin: u16) -> String {
+    let path = Path::new(output_path);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("out");
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("obj");
+    path.with_file_name(format!("{stem}_x{origin:04X}.{ext}"))
+        .to_string_lossy()
+        .into_owned()
 }
 
 /// Generate an Intel HEX representation of the assembled program.
@@ -473,7 +560,21 @@ fn write_obj_stdout(orig: u16, code: &[u16]) -> io::Result<()> {
 /// Each data record holds up to 16 bytes (8 words).  The origin address is the
 /// byte address of the first word (word_addr * 2).  All records use the standard
 /// `:LLAAAATT…CC` format where CC is the two's-complement checksum byte.
-fn intel_hex(orig: u16, code: &[u16]) -> String {
+/// Every segment gets its own run of records, so multi-section programs need no
+/// special container.
+fn intel_hex(segments: &[Segment]) -> String {
+    let mut out = String::new();
+    for seg in segments {
+        intel_hex_records(&mut out, seg.origin, &seg.words);
+    }
+
+    // End-of-file record
+    out.push_str(":00000001FF\n");
+    out
+}
+
+/// Append the data records for one segment to `out`.
+fn intel_hex_records(out: &mut String, orig: u16, code: &[u16]) {
     // Byte address of the first word (LC-3 word addresses → byte addresses × 2).
     let byte_origin = (orig as u32) * 2;
 
@@ -483,7 +584,6 @@ fn intel_hex(orig: u16, code: &[u16]) -> String {
         .flat_map(|&w| [((w >> 8) & 0xFF) as u8, (w & 0xFF) as u8])
         .collect();
 
-    let mut out = String::new();
     const CHUNK: usize = 16; // bytes per data record
 
     for (chunk_idx, chunk) in bytes.chunks(CHUNK).enumerate() {
@@ -508,8 +608,4 @@ fn intel_hex(orig: u16, code: &[u16]) -> String {
         }
         out.push_str(&format!("{:02X}\n", checksum));
     }
-
-    // End-of-file record
-    out.push_str(":00000001FF\n");
-    out
 }
@@ -0,0 +1,174 @@
+//! # Object File Formats
+//!
+//! Serialises encoded [`Segment`]s to bytes and reads them back.
+//!
+//! ## Single-segment image (`.obj`)
+//!
+//! The classic LC-3 object format used by every LC-3 toolchain: a big-endian
+//! origin word followed by the code words.
+//!
+//! ```text
+//! [origin] [word 0] [word 1] ...
+//! ```
+//!
+//! ## Multi-segment container
+//!
+//! A file with several `.ORIG` sections cannot be expressed as one image, so
+//! it is written as a container: the magic bytes `LC3M`, a big-endian segment
+//! count and its bitwise complement, then for each segment its origin, word
+//! count and words.
+//!
+//! ```text
+//! "LC3M" [count] [!count] ([origin] [len] [word]*len)*count
+//! ```
+//!
+//! [`to_bytes`] picks the classic format whenever there is exactly one
+//! segment, so single-section programs stay compatible with other tools.
+//!
+//! Any byte string is a valid classic image, so [`read`] only treats a file
+//! as a container when the magic is followed by a matching count and
+//! complement; an image that merely starts with `LC3M` (origin `x4C43`,
+//! first word `x334D`) is still read as a classic image.
+
+use crate::encoder::Segment;
+
+/// Magic bytes at the start of a multi-segment container.
+pub const CONTAINER_MAGIC: [u8; 4] = *b"LC3M";
+
+/// Serialise one segment as a classic LC-3 object image.
+#[must_use]
+pub fn write_image(origin: u16, words: &[u16]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity((1 + words.len()) * 2);
+    buf.extend_from_slice(&origin.to_be_bytes());
+    for &word in words {
+        buf.extend_from_slice(&word.to_be_bytes());
+    }
+    buf
+}
+
+/// Serialise any number of segments as a multi-segment container.
+#[must_use]
+pub fn write_container(segments: &[Segment]) -> Vec<u8> {
+    let total: usize = segments.iter().map(|s| 2 + s.words.len()).sum();
+    let mut buf = Vec::with_capacity(CONTAINER_MAGIC.len() + 4 + total * 2);
+    let count = segments.len() as u16;
+    buf.extend_from_slice(&CONTAINER_MAGIC);
+    buf.extend_from_slice(&count.to_be_bytes());
+    buf.extend_from_slice(&(!count).to_be_bytes());
+    for seg in segments {
+        buf.extend_from_slice(&seg.origin.to_be_bytes());
+        buf.extend_from_slice(&(seg.words.len() as u16).to_be_bytes());
+        for &word in &seg.words {
+            buf.extend_from_slice(&word.to_be_bytes());
+        }
+    }
+    buf
+}
+
+/// Serialise segments, using the classic image format when there is only one.
+#[must_use]
+pub fn to_bytes(segments: &[Segment]) -> Vec<u8> {
+    match segments {
+        [] => write_image(0x3000, &[]),
+        [only] => write_image(only.origin, &only.words),
+        _ => write_container(segments),
+    }
+}
+
+/// Parse either object format back into segments.
+pub fn read(data: &[u8]) -> Result<Vec<Segment>, String> {
+    if let Some(body) = container_body(data) {
+        return read_container(body);
+    }
+    if data.len() < 2 {
+        return Err("object file too short (missing origin word)".into());
+    }
+    let origin = u16::from_be_bytes([data[0], data[1]]);
+    let words = data[2..]
+        .chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], c.get(1).copied().unwrap_or(0)]))
+        .collect();
+    Ok(vec![Segment { origin, words }])
+}
+
+/// The bytes after the magic, if `data` has a valid container header.
+fn container_body(data: &[u8]) -> Option<&[u8]> {
+    let body = data.strip_prefix(&CONTAINER_MAGIC)?;
+    let count = u16::from_be_bytes([*body.first()?, *body.get(1)?]);
+    let check = u16::from_be_bytes([*body.get(2)?, *body.get(3)?]);
+    (check == !count).then_some(body)
+}
+
+fn read_container(data: &[u8]) -> Result<Vec<Segment>, String> {
+    let mut pos = 0usize;
+    let mut next_word = |what: &str| -> Result<u16, String> {
+        let bytes = data
+            .get(pos..pos + 2)
+            .ok_or_else(|| format!("object container truncated (expected {what})"))?;
+        pos += 2;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+
+    let count = next_word("segment count")?;
+    next_word("segment count check")?;
+    let mut segments = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let origin = next_word("segment origin")?;
+        let len = next_word("segment length")?;
+        let words = (0..len)
+            .map(|_| next_word("segment word"))
+            .collect::<Result<Vec<u16>, String>>()?;
+        segments.push(Segment { origin, words });
+    }
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seg(origin: u16, words: &[u16]) -> Segment {
+        Segment {
+            origin,
+            words: words.to_vec(),
+        }
+    }
+
+    #[test]
+    fn single_segment_uses_classic_format() {
+        let bytes = to_bytes(&[seg(0x3000, &[0xF025])]);
+        assert_eq!(bytes, vec![0x30, 0x00, 0xF0, 0x25]);
+    }
+
+    #[test]
+    fn multi_segment_round_trip() {
+        let segments = vec![seg(0x3000, &[0x1021, 0xF025]), seg(0x4000, &[0x000A])];
+        let bytes = to_bytes(&segments);
+        assert!(bytes.starts_with(&CONTAINER_MAGIC));
+        assert_eq!(read(&bytes).unwrap(), segments);
+    }
+
+    #[test]
+    fn classic_image_round_trip() {
+        let bytes = write_image(0x3000, &[0xE002, 0xF022]);
+        assert_eq!(read(&bytes).unwrap(), vec![seg(0x3000, &[0xE002, 0xF022])]);
+    }
+
+    #[test]
+    fn truncated_container_is_error() {
+        let mut bytes = write_container(&[seg(0x3000, &[1, 2, 3])]);
+        bytes.truncate(bytes.len() - 2);
+        assert!(read(&bytes).is_err());
+    }
+
+    #[test]
+    fn classic_image_starting_with_magic_is_not_a_container() {
+        // Origin x4C43 and first word x334D spell "LC3M".
+        let words = [0x334D, 0x0002, 0x1234, 0xF025];
+        let bytes = write_image(0x4C43, &words);
+        assert!(bytes.starts_with(&CONTAINER_MAGIC));
+        assert_eq!(read(&bytes).unwrap(), vec![seg(0x4C43, &words)]);
+        let bytes = write_image(0x4C43, &[0x334D]);
+        assert_eq!(read(&bytes).unwrap(), vec![seg(0x4C43, &[0x334D])]);
+    }
+}
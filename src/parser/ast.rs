@@ -15,9 +15,14 @@
 //! The AST is designed to be simple and directly map to the LC-3 ISA. Each
 //! instruction variant explicitly lists its operands, making the encoder's job
 //! straightforward.
+//!
+//! Numeric and address operands are stored as [`Expr`] trees rather than
+//! resolved values: `LD R0, TABLE+3` keeps `TABLE+3` until the encoder has a
+//! complete symbol table to evaluate it against.
 
 use crate::error::Span;
 use crate::lexer::token::BrFlags;
+pub use crate::parser::expr::Expr;
 
 /// A single line of LC-3 assembly source code
 #[derive(Debug, Clone, PartialEq)]
@@ -41,75 +46,108 @@ pub enum LineContent {
     Orig(u16),
     /// .END directive - marks end of program
     End,
-    /// .FILL directive with immediate value
-    FillImmediate(i32),
-    /// .FILL directive with label reference
-    FillLabel(String),
-    /// .BLKW directive - allocates N words
-    Blkw(u16),
+    /// .FILL directive - one word holding a number, label address or expression
+    Fill(Expr),
+    /// .BLKW directive - allocates N words; the count is evaluated by the first pass
+    Blkw(Expr),
     /// .STRINGZ directive - null-terminated string
     Stringz(String),
+    /// `NAME .EQU value` - fixed constant; the line's label is the name
+    Equ(Expr),
+    /// `NAME .SET value` - reassignable constant; the line's label is the name
+    Set(Expr),
+    /// `.EXTERNAL NAME, …` - labels defined in another object, resolved by the linker
+    External(Vec<String>),
+    /// `.GLOBAL NAME, …` - labels this object exports to the linker
+    Global(Vec<String>),
     /// LC-3 instruction
     Instruction(Instruction),
 }
 
 impl LineContent {
     /// Calculate how many words this line content will occupy in memory
+    ///
+    /// A `.BLKW` whose count is not a plain literal reports 0 here; the first
+    /// pass evaluates such counts against the symbol table itself.
     pub fn word_count(&self) -> u32 {
         match self {
             LineContent::Empty => 0,
             LineContent::Orig(_) => 0,
             LineContent::End => 0,
-            LineContent::FillImmediate(_) => 1,
-            LineContent::FillLabel(_) => 1,
-            LineContent::Blkw(n) => *n as u32,
+            LineContent::Equ(_) | LineContent::Set(_) => 0,
+            LineContent::External(_) | LineContent::Global(_) => 0,
+            LineContent::Fill(_) => 1,
+            LineContent::Blkw(count) => count.as_literal().map_or(0, |n| n.max(0) as u32),
             LineContent::Stringz(s) => (s.chars().count() as u32) + 1, // +1 for null terminator
             LineContent::Instruction(_) => 1,
         }
     }
 }
 
+/// Operand of a PC-relative instruction (LD, LDI, LEA, ST, STI, BR, JSR).
+#[derive(Debug, Clone, PartialEq)]
+pub enum PcTarget {
+    /// A label or address expression (`LOOP`, `TABLE+3`); the encoder
+    /// computes `target - (PC + 1)`.
+    Address(Expr),
+    /// A raw offset written as a number (`#-3`, `x5`), encoded as-is.
+    /// Any expression that names a symbol is an [`Address`](Self::Address).
+    Offset(Expr),
+}
+
+impl PcTarget {
+    /// The label name if the operand is a bare label.
+    pub fn as_symbol(&self) -> Option<&str> {
+        match self {
+            PcTarget::Address(expr) => expr.as_symbol(),
+            PcTarget::Offset(_) => None,
+        }
+    }
+}
+
 /// LC-3 Instruction
 ///
 /// Each variant explicitly represents an LC-3 instruction with its operands.
-/// Register operands are stored as u8 (0-7), immediates as i16.
+/// Register operands are stored as u8 (0-7); immediates and offsets as
+/// [`Expr`] and PC-relative operands as [`PcTarget`], range-checked by the
+/// encoder.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     // === Operate Instructions ===
     /// ADD (register mode): DR = SR1 + SR2
     AddReg { dr: u8, sr1: u8, sr2: u8 },
     /// ADD (immediate mode): DR = SR1 + imm5
-    AddImm { dr: u8, sr1: u8, imm5: i16 },
+    AddImm { dr: u8, sr1: u8, imm5: Expr },
     /// AND (register mode): DR = SR1 & SR2
     AndReg { dr: u8, sr1: u8, sr2: u8 },
     /// AND (immediate mode): DR = SR1 & imm5
-    AndImm { dr: u8, sr1: u8, imm5: i16 },
+    AndImm { dr: u8, sr1: u8, imm5: Expr },
     /// NOT: DR = ~SR (bitwise complement)
     Not { dr: u8, sr: u8 },
 
     // === Data Movement (PC-relative) ===
     /// LD: Load from PC-relative address
-    Ld { dr: u8, label: String },
+    Ld { dr: u8, target: PcTarget },
     /// LDI: Load indirect from PC-relative address
-    Ldi { dr: u8, label: String },
+    Ldi { dr: u8, target: PcTarget },
     /// LDR: Load from base register + offset
-    Ldr { dr: u8, base_r: u8, offset6: i16 },
+    Ldr { dr: u8, base_r: u8, offset6: Expr },
     /// LEA: Load effective address (PC + offset)
-    Lea { dr: u8, label: String },
+    Lea { dr: u8, target: PcTarget },
     /// ST: Store to PC-relative address
-    St { sr: u8, label: String },
+    St { sr: u8, target: PcTarget },
     /// STI: Store indirect to PC-relative address
-    Sti { sr: u8, label: String },
+    Sti { sr: u8, target: PcTarget },
     /// STR: Store to base register + offset
-    Str { sr: u8, base_r: u8, offset6: i16 },
+    Str { sr: u8, base_r: u8, offset6: Expr },
 
     // === Control Flow ===
     /// BR: Conditional branch based on NZP flags
-    Br { flags: BrFlags, label: String },
+    Br { flags: BrFlags, target: PcTarget },
     /// JMP: Jump to address in register (RET is JMP R7)
     Jmp { base_r: u8 },
     /// JSR: Jump to subroutine (PC-relative)
-    Jsr { label: String },
+    Jsr { target: PcTarget },
     /// JSRR: Jump to subroutine (register)
     Jsrr { base_r: u8 },
     /// RET: Return from subroutine (pseudo-op for JMP R7)
@@ -119,7 +157,7 @@ pub enum Instruction {
 
     // === Trap & System ===
     /// TRAP: System call with 8-bit trap vector
-    Trap { trapvect8: u8 },
+    Trap { trapvect8: Expr },
     /// GETC: Read character from keyboard (TRAP x20)
     Getc,
     /// OUT: Write character to console (TRAP x21)
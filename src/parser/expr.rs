@@ -0,0 +1,521 @@
+//! # Constant Expressions
+//!
+//! Operands that take a number or an address accept a small expression
+//! language, so programs can write `LEA R0, TABLE+3`, `.FILL END-START`,
+//! `.BLKW SIZE*2` or `ADD R1, R1, #-(OFFSET)`.
+//!
+//! ## Grammar
+//!
+//! From lowest to highest precedence; all binary operators are left-associative:
+//!
+//! ```text
+//! expr    := and ('|' and)*
+//! and     := shift ('&' shift)*
+//! shift   := sum (('<<' | '>>') sum)*
+//! sum     := product (('+' | '-') product)*
+//! product := unary ('*' unary)*
+//! unary   := ('-' | '+' | '#') unary | primary
+//! primary := NUMBER | LABEL | '(' expr ')'
+//! ```
+//!
+//! A leading `#` only marks an immediate (`#-(OFFSET)`) and has no effect on
+//! the value.
+//!
+//! ## Evaluation
+//!
+//! Expressions are kept as trees in the AST and evaluated once labels have
+//! addresses: `.BLKW` counts in the first pass (only symbols defined above
+//! the directive are visible), everything else in the encoder. Evaluation uses
+//! `i32` arithmetic; each consumer then applies its own range check (imm5,
+//! offset6, trap vector, 16-bit word, …) to the result.
+//!
+//! Hex, binary and octal literals keep the value they are written with, so
+//! `x8000>>1` is `x4000` and `xFFFF+1` is 65536 (out of range for `.FILL`).
+//! Reading them as 16-bit two's complement (`xFFFF` as -1) is left to the
+//! signed fields that encode the result.
+
+use crate::error::{AsmError, ErrorKind, Span};
+use crate::lexer::token::{Token, TokenKind};
+
+/// A constant expression together with the span it was written over.
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+/// Expression node kinds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    /// Numeric literal; hex/binary/octal hold their unsigned written value
+    Num(i32),
+    /// Label reference, resolved through the symbol table
+    Symbol(String),
+    /// Unary minus
+    Neg(Box<Expr>),
+    /// Binary operation
+    Binary {
+        op: BinOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+}
+
+/// Binary operators, listed from highest to lowest precedence group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Mul,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    And,
+    Or,
+}
+
+impl BinOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Mul => "*",
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::And => "&",
+            BinOp::Or => "|",
+        }
+    }
+
+    /// Binding strength; higher binds tighter.
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Mul => 5,
+            BinOp::Add | BinOp::Sub => 4,
+            BinOp::Shl | BinOp::Shr => 3,
+            BinOp::And => 2,
+            BinOp::Or => 1,
+        }
+    }
+
+    fn from_token(kind: &TokenKind) -> Option<Self> {
+        match kind {
+            TokenKind::Star => Some(BinOp::Mul),
+            TokenKind::Plus => Some(BinOp::Add),
+            TokenKind::Minus => Some(BinOp::Sub),
+            TokenKind::ShiftLeft => Some(BinOp::Shl),
+            TokenKind::ShiftRight => Some(BinOp::Shr),
+            TokenKind::Ampersand => Some(BinOp::And),
+            TokenKind::Pipe => Some(BinOp::Or),
+            _ => None,
+        }
+    }
+}
+
+/// Structural equality: spans are ignored so tests (and callers) can compare
+/// a parsed expression against one built with [`Expr::num`] / [`Expr::symbol`].
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+const NO_SPAN: Span = Span::point(0, 0);
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// A literal with no source location (for synthesised AST nodes and tests).
+    pub fn num(value: i32) -> Self {
+        Self::new(ExprKind::Num(value), NO_SPAN)
+    }
+
+    /// A label reference with no source location.
+    pub fn symbol(name: impl Into<String>) -> Self {
+        Self::new(ExprKind::Symbol(name.into()), NO_SPAN)
+    }
+
+    /// The label name if this expression is a bare label.
+    pub fn as_symbol(&self) -> Option<&str> {
+        match &self.kind {
+            ExprKind::Symbol(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The value if this expression is a plain literal.
+    pub fn as_literal(&self) -> Option<i32> {
+        match self.kind {
+            ExprKind::Num(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Split `NAME`, `NAME + e`, `e + NAME` or `NAME - e` into the symbol and
+    /// the signed remainder, as used by relocations: `(name, sign, rest)`.
+    pub fn as_symbol_offset(&self) -> Option<(&str, i32, Option<&Expr>)> {
+        match &self.kind {
+            ExprKind::Symbol(name) => Some((name, 1, None)),
+            ExprKind::Binary { op, lhs, rhs } => match (op, &lhs.kind, &rhs.kind) {
+                (BinOp::Add | BinOp::Sub, ExprKind::Symbol(name), _) => {
+                    let sign = if *op == BinOp::Add { 1 } else { -1 };
+                    Some((name, sign, Some(rhs)))
+                }
+                (BinOp::Add, _, ExprKind::Symbol(name)) => Some((name, 1, Some(lhs))),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the expression names at least one symbol.
+    pub fn has_symbols(&self) -> bool {
+        let mut found = false;
+        self.for_each_symbol(&mut |_, _| found = true);
+        found
+    }
+
+    /// Call `f` for every label referenced by this expression, left to right.
+    pub fn for_each_symbol(&self, f: &mut impl FnMut(&str, Span)) {
+        match &self.kind {
+            ExprKind::Num(_) => {}
+            ExprKind::Symbol(name) => f(name, self.span),
+            ExprKind::Neg(inner) => inner.for_each_symbol(f),
+            ExprKind::Binary { lhs, rhs, .. } => {
+                lhs.for_each_symbol(f);
+                rhs.for_each_symbol(f);
+            }
+        }
+    }
+
+    /// Evaluate the expression, resolving labels through `lookup`.
+    ///
+    /// Errors carry the span of the sub-expression that failed: the undefined
+    /// label, the out-of-range shift amount, or the operation that overflowed.
+    pub fn eval(&self, lookup: &mut impl FnMut(&str) -> Option<i32>) -> Result<i32, AsmError> {
+        match &self.kind {
+            ExprKind::Num(v) => Ok(*v),
+            ExprKind::Symbol(name) => {
+                lookup(name).ok_or_else(|| AsmError::undefined_label(name, self.span))
+            }
+            ExprKind::Neg(inner) => inner.eval(lookup)?.checked_neg().ok_or_else(|| {
+                AsmError::new(
+                    ErrorKind::InvalidExpression,
+                    format!("Arithmetic overflow in '{self}'"),
+                    self.span,
+                )
+            }),
+            ExprKind::Binary { op, lhs, rhs } => {
+                let a = lhs.eval(lookup)?;
+                let b = rhs.eval(lookup)?;
+                let result = match op {
+                    BinOp::Add => a.checked_add(b),
+                    BinOp::Sub => a.checked_sub(b),
+                    BinOp::Mul => a.checked_mul(b),
+                    BinOp::Shl | BinOp::Shr => {
+                        if !(0..=15).contains(&b) {
+                            return Err(AsmError::new(
+                                ErrorKind::InvalidExpression,
+                                format!("Shift amount {b} is out of range (must be 0-15)"),
+                                rhs.span,
+                            ));
+                        }
+                        Some(if *op == BinOp::Shl { a << b } else { a >> b })
+                    }
+                    BinOp::And => Some(a & b),
+                    BinOp::Or => Some(a | b),
+                };
+                result.ok_or_else(|| {
+                    AsmError::new(
+                        ErrorKind::InvalidExpression,
+                        format!("Arithmetic overflow in '{self}'"),
+                        self.span,
+                    )
+                })
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ExprKind::Num(v) => write!(f, "{v}"),
+            ExprKind::Symbol(name) => f.write_str(name),
+            ExprKind::Neg(inner) => match inner.kind {
+                ExprKind::Binary { .. } => write!(f, "-({inner})"),
+                _ => write!(f, "-{inner}"),
+            },
+            ExprKind::Binary { op, lhs, rhs } => {
+                // Parenthesise children that bind more loosely than this node;
+                // the right child also needs them at equal precedence (a-(b-c)).
+                let wrap = |child: &Expr, strict: bool| match child.kind {
+                    ExprKind::Binary { op: inner, .. } => {
+                        inner.precedence() < op.precedence()
+                            || (strict && inner.precedence() == op.precedence())
+                    }
+                    _ => false,
+                };
+                if wrap(lhs, false) {
+                    write!(f, "({lhs})")?;
+                } else {
+                    write!(f, "{lhs}")?;
+                }
+                f.write_str(op.symbol())?;
+                if wrap(rhs, true) {
+                    write!(f, "({rhs})")
+                } else {
+                    write!(f, "{rhs}")
+                }
+            }
+        }
+    }
+}
+
+// ── Parsing ──────────────────────────────────────────────────────────────────
+
+/// Returns `true` if `token` can begin an expression.
+pub(crate) fn starts_expr(token: &Token) -> bool {
+    matches!(
+        token.kind,
+        TokenKind::NumDecimal(_)
+            | TokenKind::NumHex(_)
+            | TokenKind::NumBinary(_)
+            | TokenKind::NumOctal(_)
+            | TokenKind::Label(_)
+            | TokenKind::Minus
+            | TokenKind::Plus
+            | TokenKind::Hash
+            | TokenKind::LParen
+    )
+}
+
+/// Parse the longest expression at the start of `tokens`.
+///
+/// Returns the expression and the number of tokens consumed; the caller
+/// decides whether anything may follow.
+pub(crate) fn parse_expr(tokens: &[&Token]) -> Result<(Expr, usize), AsmError> {
+    let mut parser = ExprParser { tokens, pos: 0 };
+    let expr = parser.binary(1)?;
+    Ok((expr, parser.pos))
+}
+
+struct ExprParser<'t> {
+    tokens: &'t [&'t Token],
+    pos: usize,
+}
+
+impl ExprParser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).copied()
+    }
+
+    /// Precedence climbing: parse operands joined by operators binding at
+    /// least as tightly as `min_prec`.
+    fn binary(&mut self, min_prec: u8) -> Result<Expr, AsmError> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.peek().and_then(|t| BinOp::from_token(&t.kind)) {
+            if op.precedence() < min_prec {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.binary(op.precedence() + 1)?;
+            let span = lhs.span.to(rhs.span);
+            lhs = Expr::new(
+                ExprKind::Binary {
+                    op,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                },
+                span,
+            );
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, AsmError> {
+        let Some(token) = self.peek() else {
+            return Err(self.missing_operand());
+        };
+        let span = token.span;
+        match token.kind {
+            TokenKind::Minus => {
+                self.pos += 1;
+                let inner = self.unary()?;
+                let span = span.to(inner.span);
+                // Fold `-5` straight to a literal so simple operands stay simple.
+                Ok(match inner.kind {
+                    ExprKind::Num(v) => Expr::new(ExprKind::Num(-v), span),
+                    _ => Expr::new(ExprKind::Neg(Box::new(inner)), span),
+                })
+            }
+            TokenKind::Plus | TokenKind::Hash => {
+                self.pos += 1;
+                let inner = self.unary()?;
+                Ok(Expr {
+                    span: span.to(inner.span),
+                    ..inner
+                })
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, AsmError> {
+        let Some(token) = self.peek() else {
+            return Err(self.missing_operand());
+        };
+        let span = token.span;
+        let kind = match &token.kind {
+            TokenKind::NumDecimal(v) => ExprKind::Num(*v),
+            // The lexer reads these as 16-bit two's complement; undo that.
+            TokenKind::NumHex(v) | TokenKind::NumBinary(v) | TokenKind::NumOctal(v) => {
+                ExprKind::Num(*v & 0xFFFF)
+            }
+            TokenKind::Label(name) => ExprKind::Symbol(name.clone()),
+            TokenKind::LParen => {
+                self.pos += 1;
+                let inner = self.binary(1)?;
+                match self.peek() {
+                    Some(t) if t.kind == TokenKind::RParen => {
+                        let span = span.to(t.span);
+                        self.pos += 1;
+                        return Ok(Expr { span, ..inner });
+                    }
+                    Some(t) => {
+                        return Err(AsmError::new(
+                            ErrorKind::InvalidExpression,
+                            format!("Expected ')' but found '{}'", t.lexeme),
+                            t.span,
+                        ))
+                    }
+                    None => {
+                        return Err(AsmError::new(
+                            ErrorKind::InvalidExpression,
+                            "Unclosed '(' in expression",
+                            span,
+                        ))
+                    }
+                }
+            }
+            _ => {
+                return Err(AsmError::new(
+                    ErrorKind::InvalidExpression,
+                    format!("Expected a number or label but found '{}'", token.lexeme),
+                    span,
+                ))
+            }
+        };
+        self.pos += 1;
+        Ok(Expr::new(kind, span))
+    }
+
+    fn missing_operand(&self) -> AsmError {
+        // Point at the operator that is missing its right-hand side.
+        let span = self
+            .pos
+            .checked_sub(1)
+            .and_then(|i| self.tokens.get(i))
+            .map_or(Span::point(1, 1), |t| t.span);
+        AsmError::new(
+            ErrorKind::InvalidExpression,
+            "Expression ends where an operand was expected",
+            span,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+
+    fn parse_str(src: &str) -> Result<Expr, AsmError> {
+        let lexed = tokenize(src);
+        assert!(lexed.errors.is_empty(), "Lexer errors: {:?}", lexed.errors);
+        let tokens: Vec<&Token> = lexed
+            .tokens
+            .iter()
+            .filter(|t| t.kind != TokenKind::Eof)
+            .collect();
+        let (expr, used) = parse_expr(&tokens)?;
+        assert_eq!(used, tokens.len(), "unconsumed tokens in {src:?}");
+        Ok(expr)
+    }
+
+    fn eval_str(src: &str) -> Result<i32, AsmError> {
+        parse_str(src)?.eval(&mut |name| match name {
+            "START" => Some(0x3000),
+            "END" => Some(0x3010),
+            "SIZE" => Some(4),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(eval_str("1+2*3").unwrap(), 7);
+        assert_eq!(eval_str("10-4-3").unwrap(), 3);
+        assert_eq!(eval_str("(1+2)*3").unwrap(), 9);
+        assert_eq!(eval_str("1<<2+1").unwrap(), 8);
+        assert_eq!(eval_str("x0F&x3C|1").unwrap(), 0x0D);
+    }
+
+    #[test]
+    fn non_decimal_literals_are_unsigned() {
+        assert_eq!(eval_str("x8000>>1").unwrap(), 0x4000);
+        assert_eq!(eval_str("xF000>>4").unwrap(), 0x0F00);
+        assert_eq!(eval_str("(xFFFF+1)").unwrap(), 0x10000);
+        assert_eq!(eval_str("b1000000000000000").unwrap(), 0x8000);
+    }
+
+    #[test]
+    fn labels_and_unary_minus() {
+        assert_eq!(eval_str("END-START").unwrap(), 16);
+        assert_eq!(eval_str("SIZE*2").unwrap(), 8);
+        assert_eq!(eval_str("#-(SIZE)").unwrap(), -4);
+        assert_eq!(eval_str("-SIZE+1").unwrap(), -3);
+    }
+
+    #[test]
+    fn undefined_label_points_at_symbol() {
+        let err = eval_str("START+MISSING").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UndefinedLabel);
+        assert_eq!(err.span.col, 7);
+    }
+
+    #[test]
+    fn bad_shift_points_at_amount() {
+        let err = eval_str("1<<(SIZE*8)").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidExpression);
+        assert_eq!(err.span.col, 4);
+        assert_eq!(err.span.width(), "(SIZE*8)".len());
+    }
+
+    #[test]
+    fn span_covers_the_whole_expression() {
+        let expr = parse_str("#-(SIZE) + 1").unwrap();
+        assert_eq!((expr.span.col, expr.span.width()), (1, 12));
+    }
+
+    #[test]
+    fn unbalanced_parens_rejected() {
+        assert!(parse_str("(1+2").is_err());
+        assert!(parse_str("1+").is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for src in [
+            "TABLE+3",
+            "END-START",
+            "A-(B-C)",
+            "(A|B)&C",
+            "-(A+1)",
+            "A*B+C",
+        ] {
+            assert_eq!(parse_str(src).unwrap().to_string(), src);
+        }
+    }
+}
@@ -31,11 +31,7 @@ macro_rules! parse_reg_reg_or_imm {
             use $crate::parser::ast::LineContent;
 
             if tokens.len() < 6 {
-                return Err(AsmError {
-                    kind: ErrorKind::TooFewOperands,
-                    message: concat!($name, " requires 3 operands: ", $name, " DR, SR1, SR2/imm5").into(),
-                    span: tokens[0].span,
-                });
+                return Err(AsmError::new(ErrorKind::TooFewOperands, concat!($name, " requires 3 operands: ", $name, " DR, SR1, SR2/imm5"), tokens[0].span));
             }
             $crate::parser::macros::expect_comma(tokens, 2, "Expected comma after first operand")?;
             $crate::parser::macros::expect_comma(tokens, 4, "Expected comma after second operand")?;
@@ -45,33 +41,23 @@ macro_rules! parse_reg_reg_or_imm {
             if let Some(sr2) = $crate::parser::macros::token_to_register(tokens[5]) {
                 $crate::parser::macros::ensure_no_extra(tokens, 6)?;
                 Ok(LineContent::Instruction($reg_variant(dr, sr1, sr2)))
-            } else if let Some(imm) = $crate::parser::macros::token_to_i32(tokens[5]) {
-                // Validate the 5-bit signed immediate range (-16..=15).
-                // Without this, `ADD R1, R1, #100` silently truncates to a wrong value.
-                if !(-16..=15).contains(&imm) {
-                    return Err(AsmError {
-                        kind: ErrorKind::InvalidOperandType,
-                        message: format!(
-                            "{} immediate value {} is out of 5-bit signed range (-16 to 15)",
-                            $name, imm
-                        ),
-                        span: tokens[5].span,
-                    });
-                }
-                $crate::parser::macros::ensure_no_extra(tokens, 6)?;
-                Ok(LineContent::Instruction($imm_variant(dr, sr1, imm as i16)))
             } else {
-                Err(AsmError {
-                    kind: ErrorKind::InvalidOperandType,
-                    message: concat!($name, " third operand must be a register (R0-R7) or immediate (#n)").into(),
-                    span: tokens[5].span,
-                })
+                // The 5-bit range check happens in the encoder, after evaluation.
+                let imm = $crate::parser::macros::expect_expr(
+                    tokens,
+                    5,
+                    ErrorKind::InvalidOperandType,
+                    concat!($name, " third operand must be a register (R0-R7) or immediate (#n)"),
+                )?;
+                Ok(LineContent::Instruction($imm_variant(dr, sr1, imm)))
             }
         }
     };
 }
 
 /// Macro to generate parsers for reg-label instructions (LD, LDI, LEA, ST, STI)
+///
+/// The second operand is a label/address expression or a numeric offset.
 macro_rules! parse_reg_label {
     ($name:literal, $variant:expr) => {
         |tokens: &[&$crate::lexer::token::Token]| -> Result<$crate::parser::ast::LineContent, $crate::error::AsmError> {
@@ -79,17 +65,12 @@ macro_rules! parse_reg_label {
             use $crate::parser::ast::LineContent;
 
             if tokens.len() < 4 {
-                return Err(AsmError {
-                    kind: ErrorKind::TooFewOperands,
-                    message: concat!($name, " requires 2 operands: ", $name, " DR, LABEL").into(),
-                    span: tokens[0].span,
-                });
+                return Err(AsmError::new(ErrorKind::TooFewOperands, concat!($name, " requires 2 operands: ", $name, " DR, LABEL"), tokens[0].span));
             }
             $crate::parser::macros::expect_comma(tokens, 2, "Expected comma after first operand")?;
             let reg = $crate::parser::macros::expect_register(tokens, 1, concat!($name, " first operand must be a register (R0-R7)"))?;
-            let label = $crate::parser::macros::expect_label(tokens, 3, concat!($name, " requires a label operand"))?;
-            $crate::parser::macros::ensure_no_extra(tokens, 4)?;
-            Ok(LineContent::Instruction($variant(reg, label)))
+            let target = $crate::parser::macros::expect_pc_target(tokens, 3, concat!($name, " requires a label or offset operand"))?;
+            Ok(LineContent::Instruction($variant(reg, target)))
         }
     };
 }
@@ -102,35 +83,20 @@ macro_rules! parse_reg_reg_imm {
             use $crate::parser::ast::LineContent;
 
             if tokens.len() < 6 {
-                return Err(AsmError {
-                    kind: ErrorKind::TooFewOperands,
-                    message: concat!($name, " requires 3 operands: ", $name, " DR, BaseR, #offset6").into(),
-                    span: tokens[0].span,
-                });
+                return Err(AsmError::new(ErrorKind::TooFewOperands, concat!($name, " requires 3 operands: ", $name, " DR, BaseR, #offset6"), tokens[0].span));
             }
             $crate::parser::macros::expect_comma(tokens, 2, "Expected comma after first operand")?;
             $crate::parser::macros::expect_comma(tokens, 4, "Expected comma after second operand")?;
             let r1 = $crate::parser::macros::expect_register(tokens, 1, concat!($name, " first operand must be a register (R0-R7)"))?;
             let r2 = $crate::parser::macros::expect_register(tokens, 3, concat!($name, " second operand must be a register (R0-R7)"))?;
-            let value = $crate::parser::macros::token_to_i32(tokens[5]).ok_or_else(|| AsmError {
-                kind: ErrorKind::InvalidOperandType,
-                message: concat!($name, " third operand must be an immediate (#n)").into(),
-                span: tokens[5].span,
-            })?;
-            // Validate the 6-bit signed offset range (-32..=31).
-            // Without this, `LDR R0, R1, #100` silently truncates to a wrong offset.
-            if !(-32..=31).contains(&value) {
-                return Err(AsmError {
-                    kind: ErrorKind::InvalidOperandType,
-                    message: format!(
-                        "{} offset value {} is out of 6-bit signed range (-32 to 31)",
-                        $name, value
-                    ),
-                    span: tokens[5].span,
-                });
-            }
-            $crate::parser::macros::ensure_no_extra(tokens, 6)?;
-            Ok(LineContent::Instruction($variant(r1, r2, value as i16)))
+            // The 6-bit range check happens in the encoder, after evaluation.
+            let offset = $crate::parser::macros::expect_expr(
+                tokens,
+                5,
+                ErrorKind::InvalidOperandType,
+                concat!($name, " third operand must be an immediate (#n)"),
+            )?;
+            Ok(LineContent::Instruction($variant(r1, r2, offset)))
         }
     };
 }
@@ -143,11 +109,7 @@ macro_rules! parse_single_reg {
             use $crate::parser::ast::LineContent;
 
             if tokens.len() < 2 {
-                return Err(AsmError {
-                    kind: ErrorKind::TooFewOperands,
-                    message: concat!($name, " requires 1 operand: ", $name, " BaseR").into(),
-                    span: tokens[0].span,
-                });
+                return Err(AsmError::new(ErrorKind::TooFewOperands, concat!($name, " requires 1 operand: ", $name, " BaseR"), tokens[0].span));
             }
             let base_r = $crate::parser::macros::expect_register(tokens, 1, concat!($name, " operand must be a register (R0-R7)"))?;
             $crate::parser::macros::ensure_no_extra(tokens, 2)?;
@@ -157,6 +119,8 @@ macro_rules! parse_single_reg {
 }
 
 /// Macro to generate parsers for single-label instructions (JSR)
+///
+/// The operand is a label/address expression or a numeric offset.
 macro_rules! parse_single_label {
     ($name:literal, $variant:expr) => {
         |tokens: &[&$crate::lexer::token::Token]| -> Result<$crate::parser::ast::LineContent, $crate::error::AsmError> {
@@ -164,15 +128,10 @@ macro_rules! parse_single_label {
             use $crate::parser::ast::LineContent;
 
             if tokens.len() < 2 {
-                return Err(AsmError {
-                    kind: ErrorKind::TooFewOperands,
-                    message: concat!($name, " requires 1 operand: ", $name, " LABEL").into(),
-                    span: tokens[0].span,
-                });
+                return Err(AsmError::new(ErrorKind::TooFewOperands, concat!($name, " requires 1 operand: ", $name, " LABEL"), tokens[0].span));
             }
-            let label = $crate::parser::macros::expect_label(tokens, 1, concat!($name, " requires a label operand"))?;
-            $crate::parser::macros::ensure_no_extra(tokens, 2)?;
-            Ok(LineContent::Instruction($variant(label)))
+            let target = $crate::parser::macros::expect_pc_target(tokens, 1, concat!($name, " requires a label or offset operand"))?;
+            Ok(LineContent::Instruction($variant(target)))
         }
     };
 }
@@ -185,11 +144,7 @@ macro_rules! parse_no_operands {
             use $crate::parser::ast::LineContent;
 
             if tokens.len() > 1 {
-                return Err(AsmError {
-                    kind: ErrorKind::TooManyOperands,
-                    message: concat!($name, " takes no operands").into(),
-                    span: tokens[1].span,
-                });
+                return Err(AsmError::new(ErrorKind::TooManyOperands, concat!($name, " takes no operands"), tokens[1].span));
             }
             Ok(LineContent::Instruction($variant))
         }
@@ -199,5 +154,6 @@ macro_rules! parse_no_operands {
 // Re-export helpers at parser::macros so macro expansions can reach them via
 // `$crate::parser::macros::*`. Using pub(crate) keeps them out of the public API.
 pub(crate) use super::{
-    ensure_no_extra, expect_comma, expect_label, expect_register, token_to_i32, token_to_register,
+    ensure_no_extra, expect_comma, expect_expr, expect_pc_target, expect_register,
+    token_to_register,
 };
@@ -7,7 +7,7 @@
 //! The parser processes tokens line by line, handling:
 //! - Optional labels at the start of lines
 //! - Instructions with their operands
-//! - Assembler directives (.ORIG, .FILL, etc.)
+//! - Assembler directives (.ORIG, .FILL, .EQU, etc.)
 //! - Comments (filtered out during parsing)
 //!
 //! ## Macro-Based Parsing
@@ -18,21 +18,29 @@
 //! - `parse_reg_label!` - LD, LDI, LEA, ST, STI (PC-relative addressing)
 //! - `parse_reg_reg_imm!` - LDR, STR (base+offset addressing)
 //! - `parse_single_reg!` - JMP, JSRR (single register operand)
-//! - `parse_single_label!` - JSR (single label operand)
+//! - `parse_single_label!` - JSR (single label or offset operand)
 //! - `parse_no_operands!` - RET, HALT, etc. (no operands)
 //!
 //! This reduced the parser from 606 to 450 lines (-26% code reduction).
+//!
+//! ## Expressions
+//!
+//! Numeric and label operands are parsed as constant expressions (see
+//! [`expr`]). An expression is always the last operand on its line, so it
+//! runs to the end of the line; value range checks happen in the first pass
+//! (`.BLKW`) and the encoder (everything else), once labels are known.
 
 #[macro_use]
 mod macros;
 pub mod ast;
+pub mod expr;
 
 #[cfg(test)]
 mod tests;
 
 use crate::error::{AsmError, ErrorKind, Span};
 use crate::lexer::token::{Token, TokenKind};
-use ast::{Instruction, LineContent, SourceLine};
+use ast::{Expr, Instruction, LineContent, PcTarget, SourceLine};
 
 pub struct ParseResult {
     pub lines: Vec<SourceLine>,
@@ -120,11 +128,11 @@ fn process_line(
             content_tokens = &filtered[..];
         }
         _ => {
-            errors.push(AsmError {
-                kind: ErrorKind::UnexpectedToken,
-                message: "Unexpected token at start of line".into(),
-                span: first.span,
-            });
+            errors.push(AsmError::new(
+                ErrorKind::UnexpectedToken,
+                "Unexpected token at start of line",
+                first.span,
+            ));
             lines.push(SourceLine {
                 label: None,
                 content: LineContent::Empty,
@@ -136,6 +144,21 @@ fn process_line(
     }
 
     match parse_content(content_tokens) {
+        // The label of a .EQU/.SET line is the constant's name, so it is required.
+        Ok(LineContent::Equ(_) | LineContent::Set(_)) if label.is_none() => {
+            let directive = first.lexeme.to_ascii_uppercase();
+            errors.push(AsmError::new(
+                ErrorKind::ExpectedOperand,
+                format!("{directive} needs a name: NAME {directive} value"),
+                first.span,
+            ));
+            lines.push(SourceLine {
+                label,
+                content: LineContent::Empty,
+                line_number,
+                span,
+            });
+        }
         Ok(content) => lines.push(SourceLine {
             label,
             content,
@@ -155,17 +178,9 @@ fn process_line(
 }
 
 fn line_span(tokens: &[Token], line_number: usize) -> Span {
-    if let Some(first) = tokens.first() {
-        Span {
-            line: first.span.line,
-            col: first.span.col,
-        }
-    } else {
-        Span {
-            line: line_number,
-            col: 1,
-        }
-    }
+    tokens
+        .first()
+        .map_or(Span::point(line_number, 1), |first| first.span)
 }
 
 fn parse_content(tokens: &[&Token]) -> Result<LineContent, AsmError> {
@@ -191,19 +206,19 @@ fn parse_content(tokens: &[&Token]) -> Result<LineContent, AsmError> {
 
         // Data movement - PC offset (consolidated with macros)
         TokenKind::OpLd => {
-            parse_reg_label!("LD", |dr, label| Instruction::Ld { dr, label })(tokens)
+            parse_reg_label!("LD", |dr, target| Instruction::Ld { dr, target })(tokens)
         }
         TokenKind::OpLdi => {
-            parse_reg_label!("LDI", |dr, label| Instruction::Ldi { dr, label })(tokens)
+            parse_reg_label!("LDI", |dr, target| Instruction::Ldi { dr, target })(tokens)
         }
         TokenKind::OpLea => {
-            parse_reg_label!("LEA", |dr, label| Instruction::Lea { dr, label })(tokens)
+            parse_reg_label!("LEA", |dr, target| Instruction::Lea { dr, target })(tokens)
         }
         TokenKind::OpSt => {
-            parse_reg_label!("ST", |sr, label| Instruction::St { sr, label })(tokens)
+            parse_reg_label!("ST", |sr, target| Instruction::St { sr, target })(tokens)
         }
         TokenKind::OpSti => {
-            parse_reg_label!("STI", |sr, label| Instruction::Sti { sr, label })(tokens)
+            parse_reg_label!("STI", |sr, target| Instruction::Sti { sr, target })(tokens)
         }
 
         // Data movement - base+offset (consolidated with macros)
@@ -220,7 +235,9 @@ fn parse_content(tokens: &[&Token]) -> Result<LineContent, AsmError> {
 
         // Control flow (consolidated with macros)
         TokenKind::OpJmp => parse_single_reg!("JMP", |base_r| Instruction::Jmp { base_r })(tokens),
-        TokenKind::OpJsr => parse_single_label!("JSR", |label| Instruction::Jsr { label })(tokens),
+        TokenKind::OpJsr => {
+            parse_single_label!("JSR", |target| Instruction::Jsr { target })(tokens)
+        }
         TokenKind::OpJsrr => {
             parse_single_reg!("JSRR", |base_r| Instruction::Jsrr { base_r })(tokens)
         }
@@ -244,12 +261,16 @@ fn parse_content(tokens: &[&Token]) -> Result<LineContent, AsmError> {
         TokenKind::DirFill => parse_fill(tokens),
         TokenKind::DirBlkw => parse_blkw(tokens),
         TokenKind::DirStringz => parse_stringz(tokens),
-
-        _ => Err(AsmError {
-            kind: ErrorKind::UnexpectedToken,
-            message: "Unexpected token in line".into(),
-            span: first.span,
-        }),
+        TokenKind::DirEqu => parse_constant(tokens, ".EQU").map(LineContent::Equ),
+        TokenKind::DirSet => parse_constant(tokens, ".SET").map(LineContent::Set),
+        TokenKind::DirExternal => parse_names(tokens, ".EXTERNAL").map(LineContent::External),
+        TokenKind::DirGlobal => parse_names(tokens, ".GLOBAL").map(LineContent::Global),
+
+        _ => Err(AsmError::new(
+            ErrorKind::UnexpectedToken,
+            "Unexpected token in line",
+            first.span,
+        )),
     }
 }
 
@@ -257,11 +278,11 @@ fn parse_content(tokens: &[&Token]) -> Result<LineContent, AsmError> {
 
 fn parse_not(tokens: &[&Token]) -> Result<LineContent, AsmError> {
     if tokens.len() < 4 {
-        return Err(AsmError {
-            kind: ErrorKind::TooFewOperands,
-            message: "NOT requires 2 operands: NOT DR, SR".into(),
-            span: tokens[0].span,
-        });
+        return Err(AsmError::new(
+            ErrorKind::TooFewOperands,
+            "NOT requires 2 operands: NOT DR, SR",
+            tokens[0].span,
+        ));
     }
     expect_comma(tokens, 2, "Expected comma after first operand")?;
     let dr = expect_register(tokens, 1, "NOT first operand must be a register (R0-R7)")?;
@@ -275,57 +296,48 @@ fn parse_br(
     flags: crate::lexer::token::BrFlags,
 ) -> Result<LineContent, AsmError> {
     if tokens.len() < 2 {
-        return Err(AsmError {
-            kind: ErrorKind::TooFewOperands,
-            message: "BR requires a label operand".into(),
-            span: tokens[0].span,
-        });
+        return Err(AsmError::new(
+            ErrorKind::TooFewOperands,
+            "BR requires a label operand",
+            tokens[0].span,
+        ));
     }
-    let label = expect_label(tokens, 1, "BR requires a label operand")?;
-    ensure_no_extra(tokens, 2)?;
-    Ok(LineContent::Instruction(Instruction::Br { flags, label }))
+    let target = expect_pc_target(tokens, 1, "BR requires a label or offset operand")?;
+    Ok(LineContent::Instruction(Instruction::Br { flags, target }))
 }
 
 fn parse_trap(tokens: &[&Token]) -> Result<LineContent, AsmError> {
     if tokens.len() < 2 {
-        return Err(AsmError {
-            kind: ErrorKind::TooFewOperands,
-            message: "TRAP requires a numeric trap vector (e.g., TRAP x25)".into(),
-            span: tokens[0].span,
-        });
+        return Err(AsmError::new(
+            ErrorKind::TooFewOperands,
+            "TRAP requires a numeric trap vector (e.g., TRAP x25)",
+            tokens[0].span,
+        ));
     }
-    let value = token_to_i32(tokens[1]).ok_or_else(|| AsmError {
-        kind: ErrorKind::InvalidOperandType,
-        message: "TRAP requires a numeric trap vector (e.g., TRAP x25)".into(),
-        span: tokens[1].span,
-    })?;
-    // Trap vector must fit in 8 bits (0x00–0xFF). Without this check,
-    // `TRAP x1FF` silently truncates to `TRAP xFF`, producing wrong machine code.
-    if !(0..=0xFF).contains(&value) {
-        return Err(AsmError {
-            kind: ErrorKind::InvalidOperandType,
-            message: format!("TRAP vector {value} is out of range (must be 0x00-0xFF)"),
-            span: tokens[1].span,
-        });
-    }
-    ensure_no_extra(tokens, 2)?;
-    Ok(LineContent::Instruction(Instruction::Trap {
-        trapvect8: value as u8,
-    }))
+    // The 8-bit range check happens in the encoder, once the vector is known.
+    let trapvect8 = expect_expr(
+        tokens,
+        1,
+        ErrorKind::InvalidOperandType,
+        "TRAP requires a numeric trap vector (e.g., TRAP x25)",
+    )?;
+    Ok(LineContent::Instruction(Instruction::Trap { trapvect8 }))
 }
 
 fn parse_orig(tokens: &[&Token]) -> Result<LineContent, AsmError> {
     if tokens.len() < 2 {
-        return Err(AsmError {
-            kind: ErrorKind::TooFewOperands,
-            message: ".ORIG requires a numeric operand".into(),
-            span: tokens[0].span,
-        });
+        return Err(AsmError::new(
+            ErrorKind::TooFewOperands,
+            ".ORIG requires a numeric operand",
+            tokens[0].span,
+        ));
     }
-    let value = token_to_i32(tokens[1]).ok_or_else(|| AsmError {
-        kind: ErrorKind::InvalidOperandType,
-        message: ".ORIG requires a numeric operand".into(),
-        span: tokens[1].span,
+    let value = token_to_i32(tokens[1]).ok_or_else(|| {
+        AsmError::new(
+            ErrorKind::InvalidOperandType,
+            ".ORIG requires a numeric operand",
+            tokens[1].span,
+        )
     })?;
     // Accept any value whose 16-bit representation is valid (0x0000–0xFFFF).
     // Hex/binary literals above 0x7FFF arrive as negative i32 values due to the
@@ -333,11 +345,11 @@ fn parse_orig(tokens: &[&Token]) -> Result<LineContent, AsmError> {
     // Decimal literals arrive as positive i32 (e.g. #65535 → 65535).
     // Both representations must be accepted for the full 16-bit address space.
     if !(i16::MIN as i32..=0xFFFF_i32).contains(&value) {
-        return Err(AsmError {
-            kind: ErrorKind::InvalidOrigAddress,
-            message: ".ORIG address must be 0x0000-0xFFFF".into(),
-            span: tokens[1].span,
-        });
+        return Err(AsmError::new(
+            ErrorKind::InvalidOrigAddress,
+            ".ORIG address must be 0x0000-0xFFFF",
+            tokens[1].span,
+        ));
     }
     ensure_no_extra(tokens, 2)?;
     Ok(LineContent::Orig(value as u16))
@@ -345,93 +357,108 @@ fn parse_orig(tokens: &[&Token]) -> Result<LineContent, AsmError> {
 
 fn parse_end(tokens: &[&Token]) -> Result<LineContent, AsmError> {
     if tokens.len() > 1 {
-        return Err(AsmError {
-            kind: ErrorKind::TooManyOperands,
-            message: ".END takes no operands".into(),
-            span: tokens[1].span,
-        });
+        return Err(AsmError::new(
+            ErrorKind::TooManyOperands,
+            ".END takes no operands",
+            tokens[1].span,
+        ));
     }
     Ok(LineContent::End)
 }
 
 fn parse_fill(tokens: &[&Token]) -> Result<LineContent, AsmError> {
     if tokens.len() < 2 {
-        return Err(AsmError {
-            kind: ErrorKind::TooFewOperands,
-            message: ".FILL requires a numeric or label operand".into(),
-            span: tokens[0].span,
-        });
-    }
-    if let Some(value) = token_to_i32(tokens[1]) {
-        // Validate the value fits in a 16-bit slot.
-        // Hex/binary > 0x7FFF arrive as negative i32 (e.g. xFFFF → -1), so we
-        // accept -32768..=65535 to cover the full unsigned 16-bit range.
-        if !(i16::MIN as i32..=0xFFFF_i32).contains(&value) {
-            return Err(AsmError {
-                kind: ErrorKind::InvalidOperandType,
-                message: format!(".FILL value {value} is out of 16-bit range (-32768 to 65535)"),
-                span: tokens[1].span,
-            });
-        }
-        ensure_no_extra(tokens, 2)?;
-        Ok(LineContent::FillImmediate(value))
-    } else if let Some(label) = token_to_label(tokens[1]) {
-        ensure_no_extra(tokens, 2)?;
-        Ok(LineContent::FillLabel(label))
-    } else {
-        Err(AsmError {
-            kind: ErrorKind::InvalidOperandType,
-            message: ".FILL requires a numeric or label operand".into(),
-            span: tokens[1].span,
-        })
+        return Err(AsmError::new(
+            ErrorKind::TooFewOperands,
+            ".FILL requires a numeric or label operand",
+            tokens[0].span,
+        ));
     }
+    // The encoder checks that the value fits in a 16-bit word.
+    let value = expect_expr(
+        tokens,
+        1,
+        ErrorKind::InvalidOperandType,
+        ".FILL requires a numeric or label operand",
+    )?;
+    Ok(LineContent::Fill(value))
 }
 
 fn parse_blkw(tokens: &[&Token]) -> Result<LineContent, AsmError> {
     if tokens.len() < 2 {
-        return Err(AsmError {
-            kind: ErrorKind::TooFewOperands,
-            message: ".BLKW requires a numeric operand".into(),
-            span: tokens[0].span,
-        });
+        return Err(AsmError::new(
+            ErrorKind::TooFewOperands,
+            ".BLKW requires a numeric operand",
+            tokens[0].span,
+        ));
     }
-    let value = token_to_i32(tokens[1]).ok_or_else(|| AsmError {
-        kind: ErrorKind::InvalidOperandType,
-        message: ".BLKW requires a numeric operand".into(),
-        span: tokens[1].span,
-    })?;
-    // Reject negative or zero counts now, in the parser, with a clear error.
-    // Without this check, `.BLKW #-1` silently casts to 65535 (u16::MAX) and
-    // allocates a 65535-word block, producing silently wrong output.
-    if value <= 0 || value > 0xFFFF {
-        return Err(AsmError {
-            kind: ErrorKind::InvalidBlkwCount,
-            message: format!(".BLKW count {value} is out of range (must be 1-65535)"),
-            span: tokens[1].span,
-        });
+    // The first pass evaluates the count (it decides the layout) and rejects
+    // counts outside 1-65535.
+    let count = expect_expr(
+        tokens,
+        1,
+        ErrorKind::InvalidOperandType,
+        ".BLKW requires a numeric operand",
+    )?;
+    Ok(LineContent::Blkw(count))
+}
+
+fn parse_constant(tokens: &[&Token], directive: &str) -> Result<Expr, AsmError> {
+    let message = format!("{directive} requires a value: NAME {directive} value");
+    if tokens.len() < 2 {
+        return Err(AsmError::new(
+            ErrorKind::TooFewOperands,
+            message,
+            tokens[0].span,
+        ));
     }
-    ensure_no_extra(tokens, 2)?;
-    Ok(LineContent::Blkw(value as u16))
+    expect_expr(tokens, 1, ErrorKind::InvalidOperandType, &message)
 }
 
 fn parse_stringz(tokens: &[&Token]) -> Result<LineContent, AsmError> {
     if tokens.len() < 2 {
-        return Err(AsmError {
-            kind: ErrorKind::TooFewOperands,
-            message: ".STRINGZ requires a string literal operand".into(),
-            span: tokens[0].span,
-        });
+        return Err(AsmError::new(
+            ErrorKind::TooFewOperands,
+            ".STRINGZ requires a string literal operand",
+            tokens[0].span,
+        ));
     }
     match &tokens[1].kind {
         TokenKind::StringLiteral(s) => {
             ensure_no_extra(tokens, 2)?;
             Ok(LineContent::Stringz(s.clone()))
         }
-        _ => Err(AsmError {
-            kind: ErrorKind::InvalidOperandType,
-            message: ".STRINGZ requires a string literal operand".into(),
-            span: tokens[1].span,
-        }),
+        _ => Err(AsmError::new(
+            ErrorKind::InvalidOperandType,
+            ".STRINGZ requires a string literal operand",
+            tokens[1].span,
+        )),
+    }
+}
+
+/// `.EXTERNAL A, B` / `.GLOBAL A, B`: one or more comma-separated names.
+fn parse_names(tokens: &[&Token], directive: &str) -> Result<Vec<String>, AsmError> {
+    let message = format!("{directive} requires label names: {directive} NAME[, NAME]*");
+    let mut names = Vec::new();
+    let mut idx = 1;
+    loop {
+        match tokens.get(idx).map(|t| &t.kind) {
+            Some(TokenKind::Label(name)) => names.push(name.clone()),
+            Some(_) => {
+                return Err(AsmError::new(
+                    ErrorKind::InvalidOperandType,
+                    message,
+                    tokens[idx].span,
+                ))
+            }
+            None => return Err(AsmError::too_few_operands(message, tokens[0].span)),
+        }
+        idx += 1;
+        if idx == tokens.len() {
+            return Ok(names);
+        }
+        expect_comma(tokens, idx, &message)?;
+        idx += 1;
     }
 }
 
@@ -439,30 +466,30 @@ fn parse_stringz(tokens: &[&Token]) -> Result<LineContent, AsmError> {
 // `$crate::parser::macros::*` without exposing them in the public library API.
 pub(crate) fn ensure_no_extra(tokens: &[&Token], expected_len: usize) -> Result<(), AsmError> {
     if tokens.len() > expected_len {
-        return Err(AsmError {
-            kind: ErrorKind::UnexpectedToken,
-            message: "Unexpected token after instruction".into(),
-            span: tokens[expected_len].span,
-        });
+        return Err(AsmError::new(
+            ErrorKind::UnexpectedToken,
+            "Unexpected token after instruction",
+            tokens[expected_len].span,
+        ));
     }
     Ok(())
 }
 
 pub(crate) fn expect_comma(tokens: &[&Token], idx: usize, message: &str) -> Result<(), AsmError> {
     if tokens.len() <= idx {
-        return Err(AsmError {
-            kind: ErrorKind::ExpectedComma,
-            message: message.into(),
-            span: tokens[0].span,
-        });
+        return Err(AsmError::new(
+            ErrorKind::ExpectedComma,
+            message,
+            tokens[0].span,
+        ));
     }
     match tokens[idx].kind {
         TokenKind::Comma => Ok(()),
-        _ => Err(AsmError {
-            kind: ErrorKind::ExpectedComma,
-            message: message.into(),
-            span: tokens[idx].span,
-        }),
+        _ => Err(AsmError::new(
+            ErrorKind::ExpectedComma,
+            message,
+            tokens[idx].span,
+        )),
     }
 }
 
@@ -472,35 +499,56 @@ pub(crate) fn expect_register(
     message: &str,
 ) -> Result<u8, AsmError> {
     if tokens.len() <= idx {
-        return Err(AsmError {
-            kind: ErrorKind::ExpectedRegister,
-            message: message.into(),
-            span: tokens[0].span,
-        });
+        return Err(AsmError::new(
+            ErrorKind::ExpectedRegister,
+            message,
+            tokens[0].span,
+        ));
     }
-    token_to_register(tokens[idx]).ok_or_else(|| AsmError {
-        kind: ErrorKind::ExpectedRegister,
-        message: message.into(),
-        span: tokens[idx].span,
-    })
+    token_to_register(tokens[idx])
+        .ok_or_else(|| AsmError::new(ErrorKind::ExpectedRegister, message, tokens[idx].span))
 }
 
-pub(crate) fn expect_label(
+/// Parse the expression operand starting at `idx`.
+///
+/// Expressions are always the final operand, so the expression must run to
+/// the end of the line. `kind`/`message` describe an operand that is missing
+/// or cannot start an expression; malformed expressions report
+/// `InvalidExpression` and trailing tokens `UnexpectedToken`.
+pub(crate) fn expect_expr(
     tokens: &[&Token],
     idx: usize,
+    kind: ErrorKind,
     message: &str,
-) -> Result<String, AsmError> {
+) -> Result<Expr, AsmError> {
     if tokens.len() <= idx {
-        return Err(AsmError {
-            kind: ErrorKind::ExpectedOperand,
-            message: message.into(),
-            span: tokens[0].span,
-        });
+        return Err(AsmError::new(
+            ErrorKind::ExpectedOperand,
+            message,
+            tokens[0].span,
+        ));
     }
-    token_to_label(tokens[idx]).ok_or_else(|| AsmError {
-        kind: ErrorKind::ExpectedOperand,
-        message: message.into(),
-        span: tokens[idx].span,
+    if !expr::starts_expr(tokens[idx]) {
+        return Err(AsmError::new(kind, message, tokens[idx].span));
+    }
+    let (value, used) = expr::parse_expr(&tokens[idx..])?;
+    ensure_no_extra(tokens, idx + used)?;
+    Ok(value)
+}
+
+/// Parse the operand of a PC-relative instruction. A purely numeric
+/// expression (`#-3`, `x5`) is a raw offset; anything naming a symbol is an
+/// address the encoder turns into an offset.
+pub(crate) fn expect_pc_target(
+    tokens: &[&Token],
+    idx: usize,
+    message: &str,
+) -> Result<PcTarget, AsmError> {
+    let expr = expect_expr(tokens, idx, ErrorKind::ExpectedOperand, message)?;
+    Ok(if expr.has_symbols() {
+        PcTarget::Address(expr)
+    } else {
+        PcTarget::Offset(expr)
     })
 }
 
@@ -520,10 +568,3 @@ pub(crate) fn token_to_register(token: &Token) -> Option<u8> {
         _ => None,
     }
 }
-
-pub(crate) fn token_to_label(token: &Token) -> Option<String> {
-    match &token.kind {
-        TokenKind::Label(s) => Some(s.clone()),
-        _ => None,
-    }
-}
@@ -1,5 +1,5 @@
 use crate::lexer::tokenize;
-use crate::parser::ast::{Instruction, LineContent};
+use crate::parser::ast::{Expr, Instruction, LineContent, PcTarget};
 use crate::parser::parse_lines;
 
 fn parse_ok(input: &str) -> Vec<crate::parser::ast::SourceLine> {
@@ -41,7 +41,7 @@ fn parse_add_immediate() {
         LineContent::Instruction(Instruction::AddImm {
             dr: 1,
             sr1: 2,
-            imm5: 5
+            imm5: Expr::num(5)
         })
     );
 }
@@ -67,7 +67,7 @@ fn parse_and_immediate() {
         LineContent::Instruction(Instruction::AndImm {
             dr: 1,
             sr1: 2,
-            imm5: -1
+            imm5: Expr::num(-1)
         })
     );
 }
@@ -88,7 +88,7 @@ fn parse_ld() {
         lines[0].content,
         LineContent::Instruction(Instruction::Ld {
             dr: 0,
-            label: "DATA".into()
+            target: PcTarget::Address(Expr::symbol("DATA"))
         })
     );
 }
@@ -100,7 +100,7 @@ fn parse_ldi() {
         lines[0].content,
         LineContent::Instruction(Instruction::Ldi {
             dr: 0,
-            label: "PTR".into()
+            target: PcTarget::Address(Expr::symbol("PTR"))
         })
     );
 }
@@ -113,7 +113,7 @@ fn parse_ldr() {
         LineContent::Instruction(Instruction::Ldr {
             dr: 0,
             base_r: 1,
-            offset6: 5
+            offset6: Expr::num(5)
         })
     );
 }
@@ -125,7 +125,7 @@ fn parse_lea() {
         lines[0].content,
         LineContent::Instruction(Instruction::Lea {
             dr: 0,
-            label: "MSG".into()
+            target: PcTarget::Address(Expr::symbol("MSG"))
         })
     );
 }
@@ -137,7 +137,7 @@ fn parse_st() {
         lines[0].content,
         LineContent::Instruction(Instruction::St {
             sr: 0,
-            label: "RESULT".into()
+            target: PcTarget::Address(Expr::symbol("RESULT"))
         })
     );
 }
@@ -149,7 +149,7 @@ fn parse_sti() {
         lines[0].content,
         LineContent::Instruction(Instruction::Sti {
             sr: 0,
-            label: "PTR".into()
+            target: PcTarget::Address(Expr::symbol("PTR"))
         })
     );
 }
@@ -162,7 +162,7 @@ fn parse_str() {
         LineContent::Instruction(Instruction::Str {
             sr: 0,
             base_r: 1,
-            offset6: 0
+            offset6: Expr::num(0)
         })
     );
 }
@@ -170,9 +170,9 @@ fn parse_str() {
 #[test]
 fn parse_br_with_flags() {
     let lines = parse_ok("BRnz LOOP");
-    if let LineContent::Instruction(Instruction::Br { flags, label }) = &lines[0].content {
+    if let LineContent::Instruction(Instruction::Br { flags, target }) = &lines[0].content {
         assert!(flags.n && flags.z && !flags.p);
-        assert_eq!(label, "LOOP");
+        assert_eq!(target.as_symbol(), Some("LOOP"));
     } else {
         panic!("Expected BR instruction");
     }
@@ -193,7 +193,7 @@ fn parse_jsr() {
     assert_eq!(
         lines[0].content,
         LineContent::Instruction(Instruction::Jsr {
-            label: "SUB".into()
+            target: PcTarget::Address(Expr::symbol("SUB"))
         })
     );
 }
@@ -212,7 +212,9 @@ fn parse_trap() {
     let lines = parse_ok("TRAP x25");
     assert_eq!(
         lines[0].content,
-        LineContent::Instruction(Instruction::Trap { trapvect8: 0x25 })
+        LineContent::Instruction(Instruction::Trap {
+            trapvect8: Expr::num(0x25)
+        })
     );
 }
 
@@ -252,27 +254,27 @@ fn parse_end() {
 #[test]
 fn parse_fill_number() {
     let lines = parse_ok(".FILL #42");
-    assert_eq!(lines[0].content, LineContent::FillImmediate(42));
+    assert_eq!(lines[0].content, LineContent::Fill(Expr::num(42)));
 }
 
 #[test]
 fn parse_fill_hex() {
     let lines = parse_ok(".FILL xBEEF");
-    // xBEEF (0xBEEF > 0x7FFF) is converted to -16657 by the lexer's
-    // 16-bit two's complement handling. The i32 value is what gets stored.
-    assert_eq!(lines[0].content, LineContent::FillImmediate(-16657));
+    // Non-decimal literals keep their unsigned value; the encoder decides
+    // whether a field reads it as two's complement.
+    assert_eq!(lines[0].content, LineContent::Fill(Expr::num(0xBEEF)));
 }
 
 #[test]
 fn parse_fill_label() {
     let lines = parse_ok(".FILL MYVAR");
-    assert_eq!(lines[0].content, LineContent::FillLabel("MYVAR".into()));
+    assert_eq!(lines[0].content, LineContent::Fill(Expr::symbol("MYVAR")));
 }
 
 #[test]
 fn parse_blkw() {
     let lines = parse_ok(".BLKW #5");
-    assert_eq!(lines[0].content, LineContent::Blkw(5));
+    assert_eq!(lines[0].content, LineContent::Blkw(Expr::num(5)));
 }
 
 #[test]
@@ -302,7 +304,7 @@ fn parse_label_with_instr() {
 fn parse_label_with_dir() {
     let lines = parse_ok("DATA .FILL #0");
     assert_eq!(lines[0].label, Some("DATA".into()));
-    assert_eq!(lines[0].content, LineContent::FillImmediate(0));
+    assert_eq!(lines[0].content, LineContent::Fill(Expr::num(0)));
 }
 
 #[test]
@@ -324,3 +326,108 @@ fn parse_missing_comma() {
     let errors = parse_errors("ADD R1 R2 R3");
     assert_eq!(errors, vec![crate::error::ErrorKind::TooFewOperands]);
 }
+
+#[test]
+fn parse_lea_label_plus_offset() {
+    use crate::parser::expr::{BinOp, ExprKind};
+    let lines = parse_ok("LEA R0, TABLE+3");
+    let LineContent::Instruction(Instruction::Lea {
+        dr: 0,
+        target: PcTarget::Address(target),
+    }) = &lines[0].content
+    else {
+        panic!("Expected LEA instruction");
+    };
+    assert_eq!(
+        target.kind,
+        ExprKind::Binary {
+            op: BinOp::Add,
+            lhs: Box::new(Expr::symbol("TABLE")),
+            rhs: Box::new(Expr::num(3)),
+        }
+    );
+}
+
+#[test]
+fn parse_negated_parenthesised_immediate() {
+    let lines = parse_ok("ADD R1, R1, #-(OFFSET)");
+    let LineContent::Instruction(Instruction::AddImm { imm5, .. }) = &lines[0].content else {
+        panic!("Expected ADD immediate");
+    };
+    assert_eq!(imm5.to_string(), "-OFFSET");
+}
+
+#[test]
+fn parse_fill_and_blkw_expressions() {
+    let lines = parse_ok(".FILL END-START\n.BLKW SIZE*2");
+    assert_eq!(lines[0].content.word_count(), 1);
+    let LineContent::Blkw(count) = &lines[1].content else {
+        panic!("Expected .BLKW");
+    };
+    assert_eq!(count.to_string(), "SIZE*2");
+}
+
+#[test]
+fn parse_unbalanced_expression() {
+    let errors = parse_errors("LD R0, (TABLE+1");
+    assert_eq!(errors, vec![crate::error::ErrorKind::InvalidExpression]);
+}
+
+#[test]
+fn parse_trailing_token_after_expression() {
+    let errors = parse_errors("LD R0, TABLE+1 R2");
+    assert_eq!(errors, vec![crate::error::ErrorKind::UnexpectedToken]);
+}
+
+#[test]
+fn parse_equ_and_set() {
+    let lines = parse_ok("SIZE .EQU #10\nCOUNT .SET SIZE-1");
+    assert_eq!(lines[0].label, Some("SIZE".into()));
+    assert_eq!(lines[0].content, LineContent::Equ(Expr::num(10)));
+    assert_eq!(lines[1].label, Some("COUNT".into()));
+    assert!(matches!(lines[1].content, LineContent::Set(_)));
+    assert_eq!(lines[1].content.word_count(), 0);
+}
+
+#[test]
+fn parse_equ_without_name() {
+    let errors = parse_errors(".EQU #10");
+    assert_eq!(errors, vec![crate::error::ErrorKind::ExpectedOperand]);
+}
+
+#[test]
+fn parse_numeric_pc_offsets() {
+    let lines = parse_ok("BRnzp #-3\nLD R0, x5\nJSR #-(4)");
+    assert!(matches!(
+        &lines[0].content,
+        LineContent::Instruction(Instruction::Br { target: PcTarget::Offset(e), .. }) if *e == Expr::num(-3)
+    ));
+    assert_eq!(
+        lines[1].content,
+        LineContent::Instruction(Instruction::Ld {
+            dr: 0,
+            target: PcTarget::Offset(Expr::num(5))
+        })
+    );
+    assert!(matches!(
+        &lines[2].content,
+        LineContent::Instruction(Instruction::Jsr {
+            target: PcTarget::Offset(_)
+        })
+    ));
+}
+
+#[test]
+fn parse_linkage_declarations() {
+    let lines = parse_ok(".EXTERNAL PRINT, getc2\n.GLOBAL MAIN");
+    assert_eq!(
+        lines[0].content,
+        LineContent::External(vec!["PRINT".into(), "GETC2".into()])
+    );
+    assert_eq!(lines[1].content, LineContent::Global(vec!["MAIN".into()]));
+    assert_eq!(lines[0].content.word_count(), 0);
+    assert_eq!(
+        parse_errors(".EXTERNAL"),
+        vec![crate::error::ErrorKind::TooFewOperands]
+    );
+}
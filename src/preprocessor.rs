@@ -5,6 +5,8 @@
 //! - **`.INCLUDE "file"`** — Recursively inserts the contents of `file` at
 //!   the point of the directive, replacing the `.INCLUDE` line itself.
 //!   Cycle detection prevents infinite recursion.
+//! - **`.IF` / `.IFDEF` / `.IFNDEF` / `.ELSE` / `.ENDIF`** — Keeps or drops
+//!   blocks of lines (see [Conditional assembly](#conditional-assembly)).
 //!
 //! ## Usage
 //!
@@ -32,20 +34,46 @@
 //! Relative paths are resolved relative to the **directory of the file that
 //! contains the `.INCLUDE`** directive, mirroring C preprocessor behaviour.
 //!
-//! ## Line-number mapping
+//! ## Conditional assembly
+//!
+//! ```text
+//! .IFDEF DEBUG            ; kept when DEBUG is defined
+//!     LEA R0, TRACE
+//!     PUTS
+//! .ENDIF
+//! .IF VERSION-1           ; kept when the expression is nonzero
+//!     ...
+//! .ELSE
+//!     ...
+//! .ENDIF
+//! ```
 //!
-//! After expansion every source line carries a `#line N "file"` marker
-//! comment on the same row.  The lexer/parser strip these; the diagnostic
-//! formatter uses them so errors always point back to the *original* file and
-//! line number rather than a position in the flattened output.
+//! Conditions see `-D NAME=value` definitions (passed to
+//! [`preprocess_with_defines`]) and the `.EQU`/`.SET` constants defined above
+//! them whose values are plain expressions over numbers and other such
+//! names.  Labels have no address yet, so a condition cannot use them.
+//! Blocks nest, must be closed in the file that opened them, and are
+//! evaluated once, before macro expansion.  A skipped `.INCLUDE` is not read.
+//!
+//! Skipped lines and the directives themselves are replaced by blank lines,
+//! so every line keeps its number.
+//!
+//! ## Line-number mapping
 //!
-//! > **Note**: line markers are not yet consumed by the lexer.  For now the
-//! > fully-expanded source is the single string fed to `tokenize()`; the
-//! > file/line annotations appear only in `IncludedLine` metadata.
+//! Every line of the expanded output has an [`IncludedLine`] entry in
+//! [`PreprocessResult::lines`] recording the file and line it came from.
+//! [`SourceMap`](crate::source_map::SourceMap) combines these with the macro
+//! expander's line origins so diagnostics point back to the *original* file
+//! and line rather than a position in the flattened output.
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
+use crate::error::ErrorKind;
+use crate::lexer::token::TokenKind;
+use crate::lexer::tokenize;
+use crate::parser::expr::parse_expr;
+
 // ── Public types ──────────────────────────────────────────────────────────────
 
 /// A single line in the fully-expanded source, annotated with its origin.
@@ -80,13 +108,18 @@ impl PreprocessResult {
     }
 }
 
-/// An error that occurred while expanding `.INCLUDE` directives.
+/// An error that occurred while expanding `.INCLUDE` or conditional
+/// directives.
 #[derive(Debug, Clone)]
 pub struct PreprocessError {
+    /// [`ErrorKind::IoError`] for `.INCLUDE` problems,
+    /// [`ErrorKind::ConditionalError`] for `.IF` blocks.
+    pub kind: ErrorKind,
     pub message: String,
-    /// File that contained the offending `.INCLUDE` directive.
+    /// File that contained the offending directive (the root file itself if
+    /// it could not be read).
     pub file: String,
-    /// 1-based line number of the `.INCLUDE` directive.
+    /// 1-based line number of the directive, or 0 for the root.
     pub line: usize,
 }
 
@@ -106,25 +139,37 @@ impl std::fmt::Display for PreprocessError {
 ///   instead of reading from disk (used when `path == "-"`).
 #[must_use]
 pub fn preprocess(path: &str, raw_source: Option<&str>) -> PreprocessResult {
+    preprocess_with_defines(path, raw_source, &[])
+}
+
+/// Like [`preprocess`], with `-D NAME=value` definitions visible to
+/// conditional directives.
+#[must_use]
+pub fn preprocess_with_defines(
+    path: &str,
+    raw_source: Option<&str>,
+    defines: &[(String, i32)],
+) -> PreprocessResult {
     let mut result_lines: Vec<IncludedLine> = Vec::new();
     let mut errors: Vec<PreprocessError> = Vec::new();
     let mut seen: HashSet<String> = HashSet::new();
+    let mut symbols: HashMap<String, i32> = defines
+        .iter()
+        .map(|(name, value)| (name.to_uppercase(), *value))
+        .collect();
 
-    // Canonicalize the root file path so cycle detection works even with `..`.
-    let root_path = if path == "-" || path == "<stdin>" {
-        "<stdin>".to_string()
-    } else {
-        std::fs::canonicalize(path)
-            .map(|p| p.to_string_lossy().into_owned())
-            .unwrap_or_else(|_| path.to_string())
-    };
+    let root_path = root_path(path);
 
     expand_file(
         &root_path,
         raw_source,
-        &mut result_lines,
-        &mut errors,
-        &mut seen,
+        None,
+        &mut Context {
+            out: &mut result_lines,
+            errors: &mut errors,
+            seen: &mut seen,
+            symbols: &mut symbols,
+        },
         0,
     );
 
@@ -143,36 +188,91 @@ pub fn preprocess(path: &str, raw_source: Option<&str>) -> PreprocessResult {
     }
 }
 
+/// The name `preprocess` gives the root file in [`IncludedLine::file`] and
+/// [`PreprocessError::file`].
+///
+/// The path is canonicalized so cycle detection works even with `..`.
+#[must_use]
+pub fn root_path(path: &str) -> String {
+    if path == "-" || path == "<stdin>" {
+        "<stdin>".to_string()
+    } else {
+        std::fs::canonicalize(path)
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| path.to_string())
+    }
+}
+
 // ── Recursive expansion ───────────────────────────────────────────────────────
 
 /// Maximum nesting depth for `.INCLUDE` directives.
 const MAX_INCLUDE_DEPTH: usize = 64;
 
+/// State shared by every file of one [`preprocess`] run.
+struct Context<'a> {
+    out: &'a mut Vec<IncludedLine>,
+    errors: &'a mut Vec<PreprocessError>,
+    /// Files currently being expanded (for cycle detection).
+    seen: &'a mut HashSet<String>,
+    /// `-D` definitions and the constants seen so far, for conditions.
+    symbols: &'a mut HashMap<String, i32>,
+}
+
+/// One open `.IF`/`.IFDEF`/`.IFNDEF` block.
+struct Frame {
+    /// Line of the opening directive.
+    line: usize,
+    /// Whether the region around the block is kept.
+    outer: bool,
+    /// The condition's value.
+    cond: bool,
+    /// Whether `.ELSE` has been seen.
+    in_else: bool,
+}
+
+impl Frame {
+    fn active(&self) -> bool {
+        self.outer && (self.cond != self.in_else)
+    }
+}
+
+/// Append the lines of `path` to `ctx.out`, expanding its `.INCLUDE`s and
+/// conditional blocks.
+///
+/// `site` is the file and line of the `.INCLUDE` that pulled `path` in (None
+/// for the root file); errors about `path` itself are reported there.
 fn expand_file(
     path: &str,
     raw_source: Option<&str>,
-    out: &mut Vec<IncludedLine>,
-    errors: &mut Vec<PreprocessError>,
-    seen: &mut HashSet<String>,
+    site: Option<(&str, usize)>,
+    ctx: &mut Context,
     depth: usize,
 ) {
-    if depth > MAX_INCLUDE_DEPTH {
+    let (site_file, site_line) = site.unwrap_or((path, 0));
+    let errors = &mut *ctx.errors;
+    let mut error = |message: String| {
         errors.push(PreprocessError {
-            message: format!("`.INCLUDE` nesting too deep (max {MAX_INCLUDE_DEPTH})"),
-            file: path.to_string(),
-            line: 0,
+            kind: ErrorKind::IoError,
+            message,
+            file: site_file.to_string(),
+            line: site_line,
         });
+    };
+
+    if depth > MAX_INCLUDE_DEPTH {
+        error(format!(
+            "`.INCLUDE` nesting too deep (max {MAX_INCLUDE_DEPTH})"
+        ));
         return;
     }
 
     // Cycle / duplicate detection
+    let seen = &mut *ctx.seen;
     if path != "<stdin>" {
         if seen.contains(path) {
-            errors.push(PreprocessError {
-                message: format!("circular `.INCLUDE`: '{path}' is already being processed"),
-                file: path.to_string(),
-                line: 0,
-            });
+            error(format!(
+                "circular `.INCLUDE`: '{path}' is already being processed"
+            ));
             return;
         }
         seen.insert(path.to_string());
@@ -185,11 +285,7 @@ fn expand_file(
         match std::fs::read_to_string(path) {
             Ok(s) => s,
             Err(e) => {
-                errors.push(PreprocessError {
-                    message: format!("cannot open '{path}': {e}"),
-                    file: path.to_string(),
-                    line: 0,
-                });
+                error(format!("cannot open '{path}': {e}"));
                 if path != "<stdin>" {
                     seen.remove(path);
                 }
@@ -208,10 +304,30 @@ fn expand_file(
             .to_path_buf()
     };
 
+    let mut blocks: Vec<Frame> = Vec::new();
+
     for (idx, line_text) in source_text.lines().enumerate() {
         let line_num = idx + 1;
-
-        if let Some(include_path) = parse_include_directive(line_text) {
+        let blank = IncludedLine {
+            text: String::new(),
+            line: line_num,
+            file: path.to_string(),
+        };
+        let active = blocks.last().map_or(true, Frame::active);
+
+        if let Some((directive, rest)) = parse_conditional_directive(line_text) {
+            if let Err(message) = conditional(directive, rest, line_num, active, &mut blocks, ctx) {
+                ctx.errors.push(PreprocessError {
+                    kind: ErrorKind::ConditionalError,
+                    message,
+                    file: path.to_string(),
+                    line: line_num,
+                });
+            }
+            ctx.out.push(blank);
+        } else if !active {
+            ctx.out.push(blank);
+        } else if let Some(include_path) = parse_include_directive(line_text) {
             // Resolve path relative to the current file's directory
             let resolved = resolve_path(&base_dir, &include_path);
             let canonical = std::fs::canonicalize(&resolved)
@@ -219,23 +335,206 @@ fn expand_file(
                 .unwrap_or_else(|_| resolved.to_string_lossy().into_owned());
 
             // Recursively expand — no raw_source (always read from disk)
-            expand_file(&canonical, None, out, errors, seen, depth + 1);
+            expand_file(&canonical, None, Some((path, line_num)), ctx, depth + 1);
         } else {
-            out.push(IncludedLine {
+            record_constant(line_text, ctx.symbols);
+            ctx.out.push(IncludedLine {
                 text: line_text.to_string(),
-                line: line_num,
-                file: path.to_string(),
+                ..blank
             });
         }
     }
 
+    for frame in blocks {
+        ctx.errors.push(PreprocessError {
+            kind: ErrorKind::ConditionalError,
+            message: "this block is never closed with .ENDIF".into(),
+            file: path.to_string(),
+            line: frame.line,
+        });
+    }
+
     if path != "<stdin>" {
-        seen.remove(path);
+        ctx.seen.remove(path);
+    }
+}
+
+// ── Conditional blocks ────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Conditional {
+    If,
+    Ifdef,
+    Ifndef,
+    Else,
+    Endif,
+}
+
+impl Conditional {
+    fn name(self) -> &'static str {
+        match self {
+            Conditional::If => ".IF",
+            Conditional::Ifdef => ".IFDEF",
+            Conditional::Ifndef => ".IFNDEF",
+            Conditional::Else => ".ELSE",
+            Conditional::Endif => ".ENDIF",
+        }
+    }
+}
+
+/// Apply one conditional directive to the open `blocks`.
+fn conditional(
+    directive: Conditional,
+    rest: &str,
+    line: usize,
+    active: bool,
+    blocks: &mut Vec<Frame>,
+    ctx: &Context,
+) -> Result<(), String> {
+    let name = directive.name();
+    match directive {
+        Conditional::If | Conditional::Ifdef | Conditional::Ifndef => {
+            // Conditions in skipped regions are not evaluated, but still
+            // nest so their .ELSE/.ENDIF pair up.
+            let cond = if !active {
+                Ok(false)
+            } else if directive == Conditional::If {
+                eval_condition(rest, ctx.symbols)
+            } else {
+                defined_name(rest, name)
+                    .map(|n| ctx.symbols.contains_key(&n) == (directive == Conditional::Ifdef))
+            };
+            blocks.push(Frame {
+                line,
+                outer: active,
+                cond: *cond.as_ref().unwrap_or(&false),
+                in_else: false,
+            });
+            cond.map(|_| ())
+        }
+        Conditional::Else | Conditional::Endif => {
+            let Some(frame) = blocks.last_mut() else {
+                return Err(format!("{name} without a matching .IF"));
+            };
+            if directive == Conditional::Endif {
+                blocks.pop();
+            } else if frame.in_else {
+                return Err(format!(
+                    "second .ELSE for the block opened on line {}",
+                    frame.line
+                ));
+            } else {
+                frame.in_else = true;
+            }
+            if !rest.is_empty() {
+                return Err(format!("unexpected '{rest}' after {name}"));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Evaluate an `.IF` condition: nonzero is true.
+fn eval_condition(text: &str, symbols: &HashMap<String, i32>) -> Result<bool, String> {
+    let lexed = tokenize(text);
+    let tokens: Vec<_> = lexed
+        .tokens
+        .iter()
+        .filter(|t| !matches!(t.kind, TokenKind::Newline | TokenKind::Eof))
+        .collect();
+    if tokens.is_empty() {
+        return Err(".IF requires a condition: .IF expression".into());
+    }
+    if let Some(err) = lexed.errors.first() {
+        return Err(err.message.clone());
+    }
+    let (expr, used) = parse_expr(&tokens).map_err(|e| e.message)?;
+    if used < tokens.len() {
+        return Err(format!("unexpected text after the .IF condition '{expr}'"));
+    }
+    let mut undefined = None;
+    expr.for_each_symbol(&mut |name, _| {
+        if undefined.is_none() && !symbols.contains_key(name) {
+            undefined = Some(name.to_string());
+        }
+    });
+    if let Some(name) = undefined {
+        return Err(format!(
+            "'{name}' is not defined; .IF can only use -D names and .EQU/.SET constants \
+             defined above it (use .IFDEF to test whether a name is defined)"
+        ));
+    }
+    expr.eval(&mut |name: &str| symbols.get(name).copied())
+        .map(|value| value != 0)
+        .map_err(|e| e.message)
+}
+
+/// The single name after `.IFDEF`/`.IFNDEF`.
+fn defined_name(text: &str, directive: &str) -> Result<String, String> {
+    let mut words = text.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some(name), None) if name.chars().all(|c| c.is_alphanumeric() || c == '_') => {
+            Ok(name.to_uppercase())
+        }
+        _ => Err(format!("{directive} requires one name: {directive} NAME")),
+    }
+}
+
+/// Remember `NAME .EQU value` / `NAME .SET value` when `value` only uses
+/// numbers and names already known.
+fn record_constant(line: &str, symbols: &mut HashMap<String, i32>) {
+    let upper = line.to_uppercase();
+    if !upper.contains(".EQU") && !upper.contains(".SET") {
+        return;
+    }
+    let lexed = tokenize(line);
+    let tokens: Vec<_> = lexed
+        .tokens
+        .iter()
+        .filter(|t| !matches!(t.kind, TokenKind::Newline | TokenKind::Eof))
+        .collect();
+    let [name, directive, value @ ..] = tokens.as_slice() else {
+        return;
+    };
+    let (TokenKind::Label(name), TokenKind::DirEqu | TokenKind::DirSet) =
+        (&name.kind, &directive.kind)
+    else {
+        return;
+    };
+    if let Ok((expr, _)) = parse_expr(value) {
+        if let Ok(v) = expr.eval(&mut |n: &str| symbols.get(n).copied()) {
+            symbols.insert(name.clone(), v);
+        }
     }
 }
 
 // ── Helpers ───────────────────────────────────────────────────────────────────
 
+/// If `line` is a conditional directive, return it and the rest of the line
+/// (without any comment).
+fn parse_conditional_directive(line: &str) -> Option<(Conditional, &str)> {
+    let trimmed = line.trim_start();
+    let word_len = trimmed
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| !c.is_alphanumeric() && *c != '_')
+        .map_or(trimmed.len(), |(i, _)| i);
+    if !trimmed.starts_with('.') {
+        return None;
+    }
+    let directive = match trimmed[..word_len].to_uppercase().as_str() {
+        ".IF" => Conditional::If,
+        ".IFDEF" => Conditional::Ifdef,
+        ".IFNDEF" => Conditional::Ifndef,
+        ".ELSE" => Conditional::Else,
+        ".ENDIF" => Conditional::Endif,
+        _ => return None,
+    };
+    let rest = &trimmed[word_len..];
+    let rest = rest.split(';').next().unwrap_or("").trim();
+    Some((directive, rest))
+}
+
 /// If `line` is a `.INCLUDE "path"` directive, return the path string.
 /// Returns `None` for all other lines.
 fn parse_include_directive(line: &str) -> Option<String> {
@@ -343,5 +642,57 @@ mod tests {
         let result = preprocess("<stdin>", Some(src));
         assert!(result.has_errors());
         assert!(result.errors[0].message.contains("does_not_exist_xyz.asm"));
+        assert_eq!(result.errors[0].file, "<stdin>");
+        assert_eq!(result.errors[0].line, 1);
+    }
+
+    #[test]
+    fn conditional_blocks_keep_line_numbers() {
+        let src = "A .EQU 0\n.IF A\nSKIP\n.ELSE\nKEEP\n.ENDIF\n.ifdef dbg\n.INCLUDE \"nope.asm\"\n.endif\n";
+        let result = preprocess("<stdin>", Some(src));
+        assert!(!result.has_errors(), "{:?}", result.errors);
+        assert_eq!(result.source, "A .EQU 0\n\n\n\nKEEP\n\n\n\n\n");
+        assert_eq!(result.lines[4].line, 5);
+
+        let result = preprocess_with_defines("<stdin>", Some(src), &[("DBG".into(), 1)]);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].kind, ErrorKind::IoError);
+        assert_eq!(result.errors[0].line, 8);
+    }
+
+    #[test]
+    fn nested_blocks_in_skipped_regions_are_not_evaluated() {
+        let src = ".IFNDEF X\n.IF UNDEFINED\nA\n.ENDIF\n.ELSE\nB\n.ENDIF\n";
+        let result = preprocess_with_defines("<stdin>", Some(src), &[("x".into(), 0)]);
+        assert!(!result.has_errors(), "{:?}", result.errors);
+        assert_eq!(result.source.trim(), "B");
+    }
+
+    #[test]
+    fn unbalanced_conditionals_are_errors() {
+        let src = ".ELSE\n.IF 1\n.ELSE\n.ELSE\n.ENDIF extra\n.ENDIF\n.IFDEF A B\n.IF NOPE\n";
+        let result = preprocess("<stdin>", Some(src));
+        let errors: Vec<(usize, &str)> = result
+            .errors
+            .iter()
+            .map(|e| (e.line, e.message.as_str()))
+            .collect();
+        assert_eq!(errors.len(), 7, "{errors:?}");
+        assert_eq!(errors[0], (1, ".ELSE without a matching .IF"));
+        assert_eq!(
+            errors[1],
+            (4, "second .ELSE for the block opened on line 2")
+        );
+        assert_eq!(errors[2], (5, "unexpected 'extra' after .ENDIF"));
+        assert_eq!(errors[3], (6, ".ENDIF without a matching .IF"));
+        assert_eq!(errors[4], (7, ".IFDEF requires one name: .IFDEF NAME"));
+        // The failed .IFDEF leaves its block open; the nested .IF is not
+        // evaluated.
+        assert_eq!((errors[5].0, errors[6].0), (7, 8));
+        assert!(errors[5].1.contains("never closed"), "{errors:?}");
+        assert!(result
+            .errors
+            .iter()
+            .all(|e| e.kind == ErrorKind::ConditionalError));
     }
 }
@@ -0,0 +1,432 @@
+//! # Relocatable Objects
+//!
+//! `lc3-assembler --emit rel` writes a `.rel` file instead of a loadable
+//! `.obj`: the encoded sections plus everything `lc3-link` needs to move them
+//! and to connect several files.
+//!
+//! - **Symbols** — every label, as a section index and offset, and every
+//!   `.GLOBAL` constant as an absolute value.  Only `.GLOBAL` symbols are
+//!   visible to other objects.
+//! - **Relocations** — the words listed in
+//!   [`EncodeResult::relocations`](crate::encoder::EncodeResult::relocations).
+//!
+//! Symbols and relocations carry the file, line and column they came from,
+//! so the linker can point its errors at the source.
+//!
+//! ## Format
+//!
+//! All numbers are big-endian `u16` except lines and columns (`u32`);
+//! strings are a `u16` byte length followed by UTF-8.
+//!
+//! ```text
+//! "LC3R" [version=1] [name]
+//! [section count] ([origin] [len] [word]*len)*
+//! [symbol count]  ([name] [section+1 or 0] [value] [global] [location])*
+//! [reloc count]   ([section] [offset] [kind] [symbol] [addend] [location])*
+//! location = [file] [line:u32] [col:u32]
+//! ```
+//!
+//! A symbol's `value` is its offset within the section, or the value itself
+//! for section `0` (absolute).  `kind` is 0 for `PcOffset9`, 1 for
+//! `PcOffset11` and 2 for `Abs16`.
+
+use crate::encoder::{RelocKind, Segment};
+use crate::first_pass::symbol_table::SymbolKind;
+use crate::source_map::SourceLocation;
+use crate::Assembly;
+
+/// Magic bytes at the start of a relocatable object.
+pub const MAGIC: [u8; 4] = *b"LC3R";
+
+/// Format version written by [`RelocatableObject::to_bytes`].
+pub const VERSION: u16 = 1;
+
+/// Where a symbol or relocation came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl From<&SourceLocation> for Location {
+    fn from(loc: &SourceLocation) -> Self {
+        Self {
+            file: loc.file.clone(),
+            line: loc.line,
+            col: loc.col,
+        }
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.col)
+    }
+}
+
+/// A label or exported constant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    /// Index into [`RelocatableObject::sections`]; `None` for an absolute
+    /// value.
+    pub section: Option<u16>,
+    /// Offset within `section`, or the value itself when absolute.
+    pub value: u16,
+    /// Declared `.GLOBAL`, so other objects may refer to it.
+    pub global: bool,
+    pub location: Location,
+}
+
+/// A word to patch once `symbol` has an address: the target is
+/// `symbol + addend`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    /// Index into [`RelocatableObject::sections`].
+    pub section: u16,
+    /// Word offset within the section.
+    pub offset: u16,
+    pub kind: RelocKind,
+    pub symbol: String,
+    pub addend: i16,
+    pub location: Location,
+}
+
+/// One assembled source file, ready for `lc3-link`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelocatableObject {
+    /// The name the program was assembled as.
+    pub name: String,
+    /// One per `.ORIG` section.  The origin is where the section goes unless
+    /// the linker is told otherwise.
+    pub sections: Vec<Segment>,
+    pub symbols: Vec<Symbol>,
+    pub relocations: Vec<Relocation>,
+}
+
+impl RelocatableObject {
+    /// Collect the sections, symbols and relocations of an error-free
+    /// assembly.
+    #[must_use]
+    pub fn from_assembly(asm: &Assembly) -> Self {
+        let sections = asm.encoded.segments.clone();
+        let globals = &asm.first.globals;
+        let is_global = |name: &str| globals.iter().any(|g| g == name);
+
+        let definition = |name: &str| {
+            let span = asm
+                .first
+                .source_lines
+                .iter()
+                .find(|l| l.label.as_deref() == Some(name))
+                .map_or(crate::error::Span::point(1, 1), |l| l.span);
+            Location::from(&asm.source_map.resolve(span))
+        };
+
+        let mut symbols: Vec<Symbol> = asm
+            .first
+            .symbol_table
+            .iter()
+            .map(|(name, addr)| {
+                let section = asm
+                    .first
+                    .symbol_table
+                    .section(name)
+                    .filter(|&i| i < sections.len());
+                Symbol {
+                    name: name.to_string(),
+                    section: section.map(|i| i as u16),
+                    value: section.map_or(addr, |i| addr - sections[i].origin),
+                    global: is_global(name),
+                    location: definition(name),
+                }
+            })
+            .collect();
+        for (name, sym) in asm.first.symbol_table.constants() {
+            if sym.kind == SymbolKind::Constant && is_global(name) {
+                symbols.push(Symbol {
+                    name: name.to_string(),
+                    section: None,
+                    value: sym.value as u16,
+                    global: true,
+                    location: definition(name),
+                });
+            }
+        }
+
+        let relocations = asm
+            .encoded
+            .relocations
+            .iter()
+            .map(|r| Relocation {
+                section: r.segment as u16,
+                offset: r.offset,
+                kind: r.kind,
+                symbol: r.symbol.clone(),
+                addend: r.addend as i16,
+                location: Location::from(&asm.source_map.resolve(r.span)),
+            })
+            .collect();
+
+        Self {
+            name: asm.name.clone(),
+            sections,
+            symbols,
+            relocations,
+        }
+    }
+
+    /// Serialise in the `.rel` format (see the module docs).
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer(Vec::new());
+        w.0.extend_from_slice(&MAGIC);
+        w.u16(VERSION);
+        w.str(&self.name);
+
+        w.u16(self.sections.len() as u16);
+        for seg in &self.sections {
+            w.u16(seg.origin);
+            w.u16(seg.words.len() as u16);
+            for &word in &seg.words {
+                w.u16(word);
+            }
+        }
+
+        w.u16(self.symbols.len() as u16);
+        for sym in &self.symbols {
+            w.str(&sym.name);
+            w.u16(sym.section.map_or(0, |s| s + 1));
+            w.u16(sym.value);
+            w.u16(u16::from(sym.global));
+            w.location(&sym.location);
+        }
+
+        w.u16(self.relocations.len() as u16);
+        for r in &self.relocations {
+            w.u16(r.section);
+            w.u16(r.offset);
+            w.u16(match r.kind {
+                RelocKind::PcOffset9 => 0,
+                RelocKind::PcOffset11 => 1,
+                RelocKind::Abs16 => 2,
+            });
+            w.str(&r.symbol);
+            w.u16(r.addend as u16);
+            w.location(&r.location);
+        }
+        w.0
+    }
+
+    /// Parse a `.rel` file.
+    pub fn read(data: &[u8]) -> Result<Self, String> {
+        if !data.starts_with(&MAGIC) {
+            return Err("not a relocatable object (missing LC3R header)".into());
+        }
+        let mut r = Reader {
+            data,
+            pos: MAGIC.len(),
+        };
+        let version = r.u16("version")?;
+        if version != VERSION {
+            return Err(format!(
+                "unsupported relocatable object version {version} (expected {VERSION})"
+            ));
+        }
+        let name = r.str("object name")?;
+
+        let count = r.u16("section count")?;
+        let mut sections = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let origin = r.u16("section origin")?;
+            let len = r.u16("section length")?;
+            let words = (0..len)
+                .map(|_| r.u16("section word"))
+                .collect::<Result<Vec<u16>, String>>()?;
+            sections.push(Segment { origin, words });
+        }
+
+        let count = r.u16("symbol count")?;
+        let mut symbols = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let name = r.str("symbol name")?;
+            let section = r.u16("symbol section")?.checked_sub(1);
+            if section.is_some_and(|s| s as usize >= sections.len()) {
+                return Err(format!("symbol '{name}' refers to a missing section"));
+            }
+            symbols.push(Symbol {
+                name,
+                section,
+                value: r.u16("symbol value")?,
+                global: r.u16("symbol flags")? != 0,
+                location: r.location()?,
+            });
+        }
+
+        let count = r.u16("relocation count")?;
+        let mut relocations = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let section = r.u16("relocation section")?;
+            let offset = r.u16("relocation offset")?;
+            if sections
+                .get(section as usize)
+                .map_or(true, |s| offset as usize >= s.words.len())
+            {
+                return Err(format!(
+                    "relocation at section {section} offset {offset} is outside the object"
+                ));
+            }
+            let kind = match r.u16("relocation kind")? {
+                0 => RelocKind::PcOffset9,
+                1 => RelocKind::PcOffset11,
+                2 => RelocKind::Abs16,
+                other => return Err(format!("unknown relocation kind {other}")),
+            };
+            relocations.push(Relocation {
+                section,
+                offset,
+                kind,
+                symbol: r.str("relocation symbol")?,
+                addend: r.u16("relocation addend")? as i16,
+                location: r.location()?,
+            });
+        }
+
+        Ok(Self {
+            name,
+            sections,
+            symbols,
+            relocations,
+        })
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn str(&mut self, s: &str) {
+        self.u16(s.len() as u16);
+        self.0.extend_from_slice(s.as_bytes());
+    }
+
+    fn location(&mut self, loc: &Location) {
+        self.str(&loc.file);
+        self.u32(loc.line as u32);
+        self.u32(loc.col as u32);
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn bytes(&mut self, n: usize, what: &str) -> Result<&[u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + n)
+            .ok_or_else(|| format!("relocatable object truncated (expected {what})"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self, what: &str) -> Result<u16, String> {
+        let b = self.bytes(2, what)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self, what: &str) -> Result<u32, String> {
+        let b = self.bytes(4, what)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn str(&mut self, what: &str) -> Result<String, String> {
+        let len = self.u16(what)? as usize;
+        let bytes = self.bytes(len, what)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| format!("{what} is not valid UTF-8"))
+    }
+
+    fn location(&mut self) -> Result<Location, String> {
+        Ok(Location {
+            file: self.str("source file")?,
+            line: self.u32("source line")? as usize,
+            col: self.u32("source column")? as usize,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assemble_source, AssembleOptions};
+
+    fn assemble_rel(source: &str) -> Assembly {
+        let options = AssembleOptions {
+            relocatable: true,
+            ..Default::default()
+        };
+        assemble_source(source, "prog.asm", &options)
+    }
+
+    #[test]
+    fn externals_become_relocations() {
+        let asm = assemble_rel(
+            ".EXTERNAL PRINT, TABLE\n.GLOBAL MAIN\n.ORIG x3000\nMAIN JSR PRINT\nLEA R0, TABLE+2\nPTR .FILL MAIN\nHALT\n.END\n",
+        );
+        assert!(!asm.has_errors(), "{:?}", asm.errors);
+        let obj = RelocatableObject::from_assembly(&asm);
+        assert_eq!(obj.sections[0].words[..2], [0x4800, 0xE000]);
+
+        let relocs: Vec<_> = obj
+            .relocations
+            .iter()
+            .map(|r| (r.offset, r.kind, r.symbol.as_str(), r.addend))
+            .collect();
+        assert_eq!(
+            relocs,
+            vec![
+                (0, RelocKind::PcOffset11, "PRINT", 0),
+                (1, RelocKind::PcOffset9, "TABLE", 2),
+                (2, RelocKind::Abs16, "MAIN", 0),
+            ]
+        );
+        assert_eq!(obj.relocations[1].location.to_string(), "prog.asm:5:9");
+
+        let main = obj.symbols.iter().find(|s| s.name == "MAIN").unwrap();
+        assert_eq!((main.section, main.value, main.global), (Some(0), 0, true));
+        let ptr = obj.symbols.iter().find(|s| s.name == "PTR").unwrap();
+        assert_eq!((ptr.value, ptr.global), (2, false));
+        assert_eq!(ptr.location.line, 6);
+    }
+
+    #[test]
+    fn round_trip() {
+        let asm = assemble_rel(
+            ".EXTERNAL F\n.GLOBAL SIZE\nSIZE .EQU 4\n.ORIG x3000\nJSR F\n.END\n.ORIG x4000\nX .FILL x3000\n.END\n",
+        );
+        assert!(!asm.has_errors(), "{:?}", asm.errors);
+        let obj = RelocatableObject::from_assembly(&asm);
+        assert_eq!(RelocatableObject::read(&obj.to_bytes()).unwrap(), obj);
+        let size = obj.symbols.iter().find(|s| s.name == "SIZE").unwrap();
+        assert_eq!((size.section, size.value), (None, 4));
+    }
+
+    #[test]
+    fn truncated_object_is_error() {
+        let asm = assemble_rel(".EXTERNAL F\n.ORIG x3000\nJSR F\n.END\n");
+        let mut bytes = RelocatableObject::from_assembly(&asm).to_bytes();
+        bytes.truncate(bytes.len() - 3);
+        assert!(RelocatableObject::read(&bytes).is_err());
+        assert!(RelocatableObject::read(b"LC3M").is_err());
+    }
+}
@@ -0,0 +1,417 @@
+//! # Source Map
+//!
+//! The lexer sees one flat string: `.INCLUDE`s spliced in by the
+//! [`preprocessor`], macro definitions removed and
+//! invocations replaced by their bodies by the
+//! [`macro expander`](crate::macro_expand).  A [`Span`] in that string is
+//! resolved back to the file and line the user wrote with
+//! [`SourceMap::resolve`]:
+//!
+//! ```text
+//! main.asm                       lib.asm
+//!  1 .INCLUDE "lib.asm"           1 .MACRO PUSH %R
+//!  2 .ORIG x3000                  2     STR %R, R6, #0
+//!  3 PUSH R9              ───►    3 .ENDM
+//!
+//! expanded line 2 ("STR R9, R6, #0") → lib.asm:2,
+//!     in expansion of macro PUSH invoked at main.asm:3
+//! ```
+//!
+//! A line from a macro called inside another macro's body records the
+//! whole chain of invocations, from the one in the user's file inwards.
+//!
+//! Inside a macro body the lexer sees the line after parameter substitution,
+//! so columns are mapped back to the line as written.  Text on either side
+//! of the substituted part keeps its place; a span that touches substituted
+//! text covers the whole part of the written line it came from
+//! (`%REGISTER, %REGISTER` for `R1, R1`).
+
+use std::collections::HashMap;
+
+use crate::error::Span;
+use crate::macro_expand::MacroResult;
+use crate::preprocessor::{self, PreprocessResult};
+
+/// A macro invocation that produced the line a diagnostic points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroExpansion {
+    pub name: String,
+    /// File and 1-based line of the invocation.
+    pub file: String,
+    pub line: usize,
+}
+
+/// A position in a file the user wrote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// Display name: the name the program was assembled as for the root
+    /// file, otherwise the included file's path.
+    pub file: String,
+    pub line: usize,
+    pub col: usize,
+    /// Number of characters the span covers (at least one).
+    pub width: usize,
+    /// The macro invocations that produced the line, outermost first;
+    /// empty for lines written outside any macro.
+    pub expansions: Vec<MacroExpansion>,
+}
+
+impl std::fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.col)
+    }
+}
+
+/// A line of preprocessor output: index into `files` and 1-based line.
+#[derive(Debug, Clone, Copy)]
+struct FileLine {
+    file: usize,
+    line: usize,
+}
+
+#[derive(Debug, Clone)]
+struct ExpandedLine {
+    at: FileLine,
+    /// Macro name and invocation site of each enclosing call, outermost first.
+    expansions: Vec<(String, FileLine)>,
+    /// Set when the lexer saw different text from the line as written.
+    columns: Option<ColumnMap>,
+}
+
+/// How the columns of a substituted line relate to the line as written:
+/// both share everything before `prefix` and everything after the changed
+/// middle, which ends at `expanded_end` in one and `written_end` in the
+/// other.  All are 0-based character offsets.
+#[derive(Debug, Clone, Copy)]
+struct ColumnMap {
+    prefix: usize,
+    expanded_end: usize,
+    written_end: usize,
+}
+
+impl ColumnMap {
+    fn new(written: &str, expanded: &str) -> Option<Self> {
+        if written == expanded {
+            return None;
+        }
+        let written: Vec<char> = written.chars().collect();
+        let expanded: Vec<char> = expanded.chars().collect();
+        let prefix = written
+            .iter()
+            .zip(&expanded)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = written[prefix..]
+            .iter()
+            .rev()
+            .zip(expanded[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        Some(Self {
+            prefix,
+            expanded_end: expanded.len() - suffix,
+            written_end: written.len() - suffix,
+        })
+    }
+
+    /// Map the expanded columns `start..end` to the written line.
+    fn map(&self, start: usize, end: usize) -> (usize, usize) {
+        let after = |c: usize| c - self.expanded_end + self.written_end;
+        let start = if start < self.prefix {
+            start
+        } else if start >= self.expanded_end {
+            after(start)
+        } else {
+            self.prefix
+        };
+        let end = if end <= self.prefix {
+            end
+        } else if end >= self.expanded_end {
+            after(end)
+        } else {
+            self.written_end
+        };
+        (start, end.max(start + 1))
+    }
+}
+
+/// Maps lines of the expanded source back to their files.
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    /// Display names; index 0 is the root file.
+    files: Vec<String>,
+    /// The root file as the preprocessor names it (see
+    /// [`preprocessor::root_path`]).
+    root: String,
+    /// Number of lines in the root file.
+    root_lines: usize,
+    /// One entry per line of preprocessor output.
+    prep: Vec<FileLine>,
+    /// One entry per line of the source handed to the lexer.
+    expanded: Vec<ExpandedLine>,
+    /// Text of every known (file, line), for diagnostic excerpts.
+    texts: HashMap<(usize, usize), String>,
+}
+
+impl SourceMap {
+    /// A map for `source` assembled without includes or macros: every line
+    /// maps to itself in `name`.
+    #[must_use]
+    pub fn identity(name: &str, source: &str) -> Self {
+        let mut map = Self {
+            files: vec![name.to_string()],
+            root: preprocessor::root_path(name),
+            root_lines: source.lines().count(),
+            prep: Vec::new(),
+            expanded: Vec::new(),
+            texts: HashMap::new(),
+        };
+        for (i, text) in source.lines().enumerate() {
+            map.texts.insert((0, i + 1), text.to_string());
+        }
+        map.prep = (1..=map.root_lines)
+            .map(|line| FileLine { file: 0, line })
+            .collect();
+        map.expanded = map.identity_lines();
+        map
+    }
+
+    /// Build the map for `source`, assembled as `name`, from the outputs of
+    /// the preprocessor and the macro expander.
+    ///
+    /// Pass `None` for `macros` when the lexer was given the original
+    /// `source` instead of the expanded text (as `assemble` does after a
+    /// preprocessing error).
+    #[must_use]
+    pub fn new(
+        name: &str,
+        source: &str,
+        prep: &PreprocessResult,
+        macros: Option<&MacroResult>,
+    ) -> Self {
+        let mut map = Self::identity(name, source);
+
+        map.prep = prep
+            .lines
+            .iter()
+            .map(|l| {
+                let file = map.file_index(&l.file);
+                map.texts
+                    .entry((file, l.line))
+                    .or_insert_with(|| l.text.clone());
+                FileLine { file, line: l.line }
+            })
+            .collect();
+
+        if let Some(macros) = macros {
+            let mut texts = macros.source.lines();
+            map.expanded = macros
+                .origins
+                .iter()
+                .map(|o| {
+                    let at = map.prep_line(o.line);
+                    let written = map.texts.get(&(at.file, at.line));
+                    let columns = written
+                        .zip(texts.next())
+                        .and_then(|(written, expanded)| ColumnMap::new(written, expanded));
+                    ExpandedLine {
+                        at,
+                        expansions: o
+                            .expansions
+                            .iter()
+                            .map(|e| (e.name.clone(), map.prep_line(e.call_line)))
+                            .collect(),
+                        columns,
+                    }
+                })
+                .collect();
+        } else {
+            map.expanded = map.identity_lines();
+        }
+        map
+    }
+
+    /// Resolve a span in the expanded source (what the lexer saw).
+    #[must_use]
+    pub fn resolve(&self, span: Span) -> SourceLocation {
+        match span.line.checked_sub(1).and_then(|i| self.expanded.get(i)) {
+            Some(line) => {
+                let start = span.col.saturating_sub(1);
+                let end = start + span.width();
+                let (start, end) = line
+                    .columns
+                    .map_or((start, end), |cols| cols.map(start, end));
+                SourceLocation {
+                    file: self.files[line.at.file].clone(),
+                    line: line.at.line,
+                    col: start + 1,
+                    width: end - start,
+                    expansions: line
+                        .expansions
+                        .iter()
+                        .map(|(name, site)| MacroExpansion {
+                            name: name.clone(),
+                            file: self.files[site.file].clone(),
+                            line: site.line,
+                        })
+                        .collect(),
+                }
+            }
+            // Past the last line (e.g. a missing .END reported at EOF).
+            None => SourceLocation {
+                file: self.files[0].clone(),
+                line: span.line,
+                col: span.col,
+                width: span.width(),
+                expansions: Vec::new(),
+            },
+        }
+    }
+
+    /// Resolve a 1-based line of the preprocessor output (what the macro
+    /// expander saw).
+    #[must_use]
+    pub fn resolve_preprocessed(&self, line: usize) -> SourceLocation {
+        let at = self.prep_line(line);
+        SourceLocation {
+            file: self.files[at.file].clone(),
+            line: at.line,
+            col: 1,
+            width: 1,
+            expansions: Vec::new(),
+        }
+    }
+
+    /// The display name for a file as the preprocessor names it.
+    #[must_use]
+    pub fn display_name(&self, file: &str) -> String {
+        if file == self.root {
+            self.files[0].clone()
+        } else {
+            display_path(file)
+        }
+    }
+
+    /// The text of `line` in `file` (a display name), if the map has seen it.
+    #[must_use]
+    pub fn line_text(&self, file: &str, line: usize) -> Option<&str> {
+        let file = self.files.iter().position(|f| f == file)?;
+        self.texts.get(&(file, line)).map(String::as_str)
+    }
+
+    fn identity_lines(&self) -> Vec<ExpandedLine> {
+        (1..=self.root_lines)
+            .map(|line| ExpandedLine {
+                at: FileLine { file: 0, line },
+                expansions: Vec::new(),
+                columns: None,
+            })
+            .collect()
+    }
+
+    fn prep_line(&self, line: usize) -> FileLine {
+        line.checked_sub(1)
+            .and_then(|i| self.prep.get(i))
+            .copied()
+            .unwrap_or(FileLine { file: 0, line })
+    }
+
+    fn file_index(&mut self, file: &str) -> usize {
+        let display = self.display_name(file);
+        match self.files.iter().position(|f| *f == display) {
+            Some(i) => i,
+            None => {
+                self.files.push(display);
+                self.files.len() - 1
+            }
+        }
+    }
+}
+
+/// Show `path` relative to the working directory when it is below it.
+fn display_path(path: &str) -> String {
+    std::env::current_dir()
+        .ok()
+        .and_then(|cwd| std::fs::canonicalize(cwd).ok())
+        .and_then(|cwd| {
+            std::path::Path::new(path)
+                .strip_prefix(cwd)
+                .ok()
+                .map(|p| p.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| path.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{macro_expand, preprocessor};
+
+    fn map_for(source: &str) -> SourceMap {
+        let prep = preprocessor::preprocess("prog.asm", Some(source));
+        let macros = macro_expand::expand(&prep.source);
+        SourceMap::new("prog.asm", source, &prep, Some(&macros))
+    }
+
+    #[test]
+    fn macro_body_lines_resolve_to_the_definition() {
+        let map = map_for(".MACRO INC %R\nADD %R, %R, #1\n.ENDM\n.ORIG x3000\nINC R1\n.END\n");
+        // Expanded: .ORIG / ADD R1.. / .END
+        let loc = map.resolve(Span::point(2, 5));
+        assert_eq!(loc.to_string(), "prog.asm:2:5");
+        let expansion = &loc.expansions[0];
+        assert_eq!((expansion.name.as_str(), expansion.line), ("INC", 5));
+        assert_eq!(map.resolve(Span::point(3, 1)).line, 6);
+        assert_eq!(map.line_text("prog.asm", 2), Some("ADD %R, %R, #1"));
+    }
+
+    #[test]
+    fn macro_body_columns_map_back_through_substitution() {
+        let map = map_for(
+            ".MACRO BAD %REGISTER\nADD %REGISTER, %REGISTER, #99\n.ENDM\n.ORIG x3000\nBAD R1\n.END\n",
+        );
+        // Expanded line 2 is "ADD R1, R1, #99"; #99 starts at column 13.
+        let span = |col: usize, width: usize| Span {
+            line: 2,
+            col,
+            start: 0,
+            end: width,
+        };
+        let imm = map.resolve(span(13, 3));
+        assert_eq!((imm.col, imm.width), (27, 3));
+        let add = map.resolve(span(1, 3));
+        assert_eq!((add.col, add.width), (1, 3));
+        // The first R1 came from the substituted middle of the line.
+        let reg = map.resolve(span(5, 2));
+        assert_eq!((reg.col, reg.width), (5, 20));
+    }
+
+    #[test]
+    fn nested_macro_lines_keep_every_invocation() {
+        let map = map_for(
+            ".MACRO INNER %L\nBR %L\n.ENDM\n.MACRO OUTER %L\nINNER %L\n.ENDM\n.ORIG x3000\nOUTER A\nOUTER B\n.END\n",
+        );
+        let loc = map.resolve(Span::point(3, 1));
+        assert_eq!(loc.to_string(), "prog.asm:2:1");
+        let chain: Vec<(&str, usize)> = loc
+            .expansions
+            .iter()
+            .map(|e| (e.name.as_str(), e.line))
+            .collect();
+        assert_eq!(chain, [("OUTER", 9), ("INNER", 5)]);
+    }
+
+    #[test]
+    fn lines_past_the_end_stay_in_the_root_file() {
+        let map = map_for(".ORIG x3000\n");
+        assert_eq!(map.resolve(Span::point(9, 1)).to_string(), "prog.asm:9:1");
+    }
+
+    #[test]
+    fn identity_maps_every_line_to_itself() {
+        let map = SourceMap::identity("a.asm", "X\nY\n");
+        assert_eq!(map.resolve(Span::point(2, 3)).to_string(), "a.asm:2:3");
+        assert_eq!(map.resolve_preprocessed(1).line, 1);
+        assert_eq!(map.line_text("a.asm", 2), Some("Y"));
+    }
+}
@@ -25,6 +25,17 @@ pub enum WarnKind {
     UnreachableCode,
 }
 
+impl WarnKind {
+    /// Stable identifier for tools (`W001`…), never reused or renumbered.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::UnusedLabel => "W001",
+            Self::UnreachableCode => "W002",
+        }
+    }
+}
+
 impl std::fmt::Display for WarnKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -1,9 +1,17 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
 
+use lc3_assembler::disasm;
 use lc3_assembler::encoder::encode;
 use lc3_assembler::error::ErrorKind;
 use lc3_assembler::first_pass::first_pass;
+use lc3_assembler::formatter;
 use lc3_assembler::lexer::tokenize;
+use lc3_assembler::listing;
+use lc3_assembler::obj;
 use lc3_assembler::parser::parse_lines;
 
 /// Run lexer → parser → first pass, asserting no errors at any stage.
@@ -377,3 +385,587 @@ fn errors_asm_file_produces_errors() {
         "errors.asm should produce at least one error"
     );
 }
+
+#[test]
+fn multi_section_program() {
+    let encoded = run_full_pipeline("tests/test_programs/multi_section.asm");
+    assert_eq!(encoded.orig_address, 0x3000);
+    assert_eq!(encoded.segments.len(), 2);
+    assert_eq!(encoded.segments[0].origin, 0x3000);
+    assert_eq!(encoded.segments[1].origin, 0x4000);
+    // COUNT (x4000) is out of LD range from x3000, so the program goes
+    // through PTR (x3003): offset = x3003 - x3001 = 2.
+    assert_eq!(encoded.segments[0].words[0], 0x2002); // LD R0, PTR
+    assert_eq!(encoded.segments[0].words[3], 0x4000); // PTR .FILL COUNT
+    assert_eq!(encoded.segments[1].words, vec![0x0005, 0x0000]);
+    let total: usize = encoded.segments.iter().map(|s| s.words.len()).sum();
+    assert_eq!(encoded.machine_code.len(), total);
+}
+
+#[test]
+fn expressions_program() {
+    let first = run_pipeline("tests/test_programs/expressions.asm");
+    let table = first.symbol_table.get("TABLE").unwrap();
+    let encoded = encode(&first);
+    assert!(encoded.errors.is_empty(), "{:?}", encoded.errors);
+    let code = &encoded.machine_code;
+    // LEA R0, TABLE+3
+    assert_eq!(code[0] & 0x01FF, table + 3 - 0x3001);
+    // ADD R1, R1, #-(TEND-TABLE) → imm5 = -4
+    assert_eq!(code[1], 0x127C);
+    // LDR R2, R0, #(TEND-TABLE-1)
+    assert_eq!(code[2] & 0x3F, 3);
+    // SIZE .FILL TEND-TABLE
+    assert_eq!(code[4], 4);
+    // .BLKW (TEND-TABLE)*2 reserves 8 words after the 4-word table
+    let end = first.symbol_table.get("TEND").unwrap();
+    assert_eq!(first.symbol_table.get("AFTER"), Some(end + 8));
+}
+
+#[test]
+fn error_expression_range_checked_after_evaluation() {
+    let source = ".ORIG x3000\nA .FILL #0\nB .FILL #0\nADD R1, R1, (B-A)*20\n.END\n";
+    let errors = collect_all_errors(source);
+    assert_eq!(errors, vec![ErrorKind::InvalidOperandType]);
+}
+
+#[test]
+fn expressions_use_unsigned_literal_values() {
+    let source = ".ORIG x3000\n.FILL x8000>>1\n.FILL xF000>>4\nADD R0, R0, xFFFF\n.END\n";
+    let lexed = tokenize(source);
+    let encoded = encode(&first_pass(parse_lines(&lexed.tokens).lines));
+    assert!(encoded.errors.is_empty(), "{:?}", encoded.errors);
+    assert_eq!(encoded.machine_code, vec![0x4000, 0x0F00, 0x103F]);
+
+    let errors = collect_all_errors(".ORIG x3000\n.FILL (xFFFF+1)\n.END\n");
+    assert_eq!(errors, vec![ErrorKind::InvalidOperandType]);
+}
+
+#[test]
+fn error_expression_undefined_symbol() {
+    let source = ".ORIG x3000\nLEA R0, NOWHERE+1\n.END\n";
+    let errors = collect_all_errors(source);
+    assert_eq!(errors, vec![ErrorKind::UndefinedLabel]);
+}
+
+#[test]
+fn constants_program() {
+    let first = run_pipeline("tests/test_programs/constants.asm");
+    let encoded = encode(&first);
+    assert!(encoded.errors.is_empty(), "{:?}", encoded.errors);
+    let code = &encoded.machine_code;
+    assert_eq!(code[0], 0x1262); // ADD R1, R1, STEP        (imm5 = 2)
+    assert_eq!(code[1], 0x6441); // LDR R2, R1, FIELD       (offset6 = 1)
+    assert_eq!(code[2], 0xF021); // TRAP PUTCHAR
+    assert_eq!(code[3], 0x1263); // ADD R1, R1, STEP  after STEP .SET STEP+1
+    assert_eq!(code[4], 0xF025); // HALT
+    assert_eq!(code[5], 0xFFFF); // .FILL MASK
+                                 // BUF .BLKW WORDS reserves 3 words
+    assert_eq!(first.symbol_table.get("TAIL"), Some(0x3009));
+    assert_eq!(first.symbol_table.get("WORDS"), None);
+}
+
+#[test]
+fn sym_file_separates_constants() {
+    let first = run_pipeline("tests/test_programs/constants.asm");
+    let sym = lc3_assembler::listing::generate_sym_file(&first.symbol_table, "constants.asm");
+    assert!(sym.contains("BUF=x3006\n"));
+    assert!(sym.contains("WORDS=#3\n"));
+    assert!(sym.contains("STEP=#3\n")); // .SET: final value
+    assert!(!sym.contains("WORDS=x"));
+}
+
+#[test]
+fn numeric_pc_offsets() {
+    let source = ".ORIG x3000\nLOOP ADD R0, R0, #-1\nBRp #-2\nLD R1, x1\nHALT\n.FILL #7\n.END\n";
+    let lexed = tokenize(source);
+    let parsed = parse_lines(&lexed.tokens);
+    let encoded = encode(&first_pass(parsed.lines));
+    assert!(encoded.errors.is_empty(), "{:?}", encoded.errors);
+    // BRp #-2 branches back to LOOP; LD R1, x1 skips HALT to the .FILL.
+    assert_eq!(encoded.machine_code[1], 0x03FE);
+    assert_eq!(encoded.machine_code[2], 0x2201);
+}
+
+// ── Disassembler round trip ───────────────────────────────────────────────────
+
+/// Assemble `source` to object-file bytes, asserting no errors.
+fn assemble_to_obj(source: &str) -> Vec<u8> {
+    let lexed = tokenize(source);
+    let parsed = parse_lines(&lexed.tokens);
+    let first = first_pass(parsed.lines);
+    let encoded = encode(&first);
+    let errors: Vec<_> = lexed
+        .errors
+        .iter()
+        .chain(&parsed.errors)
+        .chain(&first.errors)
+        .chain(&encoded.errors)
+        .collect();
+    assert!(errors.is_empty(), "errors: {errors:?}\n{source}");
+    obj::to_bytes(&encoded.segments)
+}
+
+/// Disassemble `bytes` and check that re-assembling gives the same bytes.
+fn assert_round_trip(bytes: &[u8], syms: &HashMap<u16, String>) {
+    let segments = obj::read(bytes).unwrap();
+    let asm = disasm::disassemble_program(&segments, syms);
+    assert_eq!(assemble_to_obj(&asm), bytes, "\n{asm}");
+}
+
+#[test]
+fn disasm_round_trips_test_programs() {
+    for entry in fs::read_dir("tests/test_programs").unwrap() {
+        let path = entry.unwrap().path();
+        if path.file_name().unwrap() == "errors.asm" {
+            continue;
+        }
+        let first = run_pipeline(path.to_str().unwrap());
+        let bytes = obj::to_bytes(&encode(&first).segments);
+        assert_round_trip(&bytes, &HashMap::new());
+
+        // Labels from the .sym file must not change the encoding either.
+        let sym = listing::generate_sym_file(&first.symbol_table, "test");
+        assert_round_trip(&bytes, &disasm::parse_sym_file(&sym));
+    }
+}
+
+#[test]
+fn disasm_round_trips_arbitrary_words() {
+    // A stride coprime to 16 visits every opcode with varied operand bits
+    // while keeping the label count (and the linear symbol lookups) small.
+    let words: Vec<u16> = (0..=0xFFFF).step_by(7).collect();
+    assert_round_trip(&obj::write_image(0x0000, &words), &HashMap::new());
+}
+
+// ── Command-line tools ────────────────────────────────────────────────────────
+
+/// A scratch directory unique to this test process and `name`, removed
+/// when dropped.
+struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("lc3-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        ScratchDir(dir)
+    }
+
+    /// Write `contents` to `file` in the directory.
+    fn write(&self, file: &str, contents: impl AsRef<[u8]>) {
+        fs::write(self.0.join(file), contents).unwrap();
+    }
+
+    /// Read `file` from the directory.
+    fn read(&self, file: &str) -> Vec<u8> {
+        fs::read(self.0.join(file)).unwrap()
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
+
+/// Run the workspace binary `bin` in `dir` with `args`.
+fn run_bin<I, S>(bin: &str, dir: &Path, args: I) -> Output
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let exe = match bin {
+        "lc3-assembler" => env!("CARGO_BIN_EXE_lc3-assembler"),
+        "lc3-disasm" => env!("CARGO_BIN_EXE_lc3-disasm"),
+        "lc3-fmt" => env!("CARGO_BIN_EXE_lc3-fmt"),
+        "lc3-link" => env!("CARGO_BIN_EXE_lc3-link"),
+        _ => panic!("unknown binary {bin}"),
+    };
+    Command::new(exe)
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// `out`'s stderr, for assertion messages.
+fn stderr(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stderr).into_owned()
+}
+
+#[test]
+fn disasm_binary_writes_reassemblable_source() {
+    let dir = ScratchDir::new("disasm");
+    let source = fs::read_to_string("tests/test_programs/hello.asm").unwrap();
+    let bytes = assemble_to_obj(&source);
+    dir.write("hello.obj", &bytes);
+
+    let out = run_bin("lc3-disasm", &dir.0, ["hello.obj", "-o", "hello.asm"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+
+    let asm = String::from_utf8(dir.read("hello.asm")).unwrap();
+    assert!(asm.contains(".STRINGZ"), "{asm}");
+    assert_eq!(assemble_to_obj(&asm), bytes);
+}
+
+#[test]
+fn formatter_is_idempotent_and_preserves_encoding() {
+    for entry in fs::read_dir("tests/test_programs").unwrap() {
+        let path = entry.unwrap().path();
+        let source = fs::read_to_string(&path).unwrap();
+        let once = formatter::format_source(&source);
+        assert_eq!(formatter::format_source(&once), once, "{}", path.display());
+        assert_eq!(
+            once.lines().filter(|l| l.trim().is_empty()).count(),
+            source.lines().filter(|l| l.trim().is_empty()).count(),
+            "blank lines in {}",
+            path.display()
+        );
+
+        if path.file_name().unwrap() != "errors.asm" {
+            assert_eq!(assemble_to_obj(&once), assemble_to_obj(&source), "\n{once}");
+        }
+    }
+}
+
+#[test]
+fn fmt_binary_check_mode() {
+    let dir = ScratchDir::new("fmt");
+    dir.write(
+        "loop.asm",
+        ".orig x3000\nloop add r1,r1,#-1 ; count\nbrp loop\nhalt\n.end\n",
+    );
+    let check = || run_bin("lc3-fmt", &dir.0, ["--check", "loop.asm"]);
+
+    let out = check();
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stdout).contains("would reformat"));
+
+    assert!(run_bin("lc3-fmt", &dir.0, ["loop.asm"]).status.success());
+    let formatted = String::from_utf8(dir.read("loop.asm")).unwrap();
+    assert!(
+        formatted.contains("loop    ADD   R1, R1, #-1 ; count"),
+        "{formatted}"
+    );
+    assert!(check().status.success());
+}
+
+#[test]
+fn assembler_json_diagnostics() {
+    let out = run_bin(
+        "lc3-assembler",
+        Path::new("."),
+        [
+            "--check",
+            "--diagnostics-format",
+            "json",
+            "tests/test_programs/errors.asm",
+        ],
+    );
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(!stderr.is_empty());
+    for line in stderr.lines() {
+        assert!(line.starts_with('{') && line.ends_with('}'), "{line}");
+        for field in [
+            "\"kind\":",
+            "\"code\":",
+            "\"file\":\"tests/test_programs/errors.asm\"",
+        ] {
+            assert!(line.contains(field), "{line}");
+        }
+    }
+    assert!(stderr.contains("\"stage\":\"parse\""), "{stderr}");
+    assert!(!stderr.contains("hint:"), "{stderr}");
+}
+
+#[test]
+fn text_diagnostics_print_the_hint_under_the_excerpt() {
+    let dir = ScratchDir::new("hint");
+    dir.write("hint.asm", ".ORIG x3000\nBRnzp MISSING\n.END\n");
+    let out = run_bin(
+        "lc3-assembler",
+        &dir.0,
+        ["--check", "--no-color", "hint.asm"],
+    );
+    let stderr = stderr(&out);
+    assert!(
+        stderr.contains(
+            "   |       ^^^^^^^\n  = hint: check spelling — labels are case-insensitive\n\n"
+        ),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn diagnostics_point_into_included_files_and_macro_bodies() {
+    let dir = ScratchDir::new("srcmap");
+    dir.write(
+        "lib.asm",
+        ".MACRO BUMP %R\n    ADD %R, %R, #99\n.ENDM\nLD R0, MISSING\n",
+    );
+    dir.write(
+        "main.asm",
+        ".ORIG x3000\n.INCLUDE \"lib.asm\"\nHALT\nBUMP R1\n.END\n",
+    );
+
+    let out = run_bin(
+        "lc3-assembler",
+        &dir.0,
+        ["--check", "--no-color", "main.asm"],
+    );
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = stderr(&out);
+    assert!(stderr.contains("lib.asm:4:8"), "{stderr}");
+    assert!(stderr.contains("lib.asm:2:"), "{stderr}");
+    assert!(stderr.contains("2 |     ADD %R, %R, #99"), "{stderr}");
+    assert!(
+        stderr.contains("note: in expansion of macro BUMP invoked at main.asm:4"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn nested_macro_errors_name_every_invocation() {
+    let dir = ScratchDir::new("nested");
+    dir.write(
+        "nest.asm",
+        ".MACRO INNER %L\n    BRz %L\n.ENDM\n.MACRO OUTER %L\n    ADD R0, R0, #1\n    INNER %L\n.ENDM\n.ORIG x3000\nOUTER BADLABEL\nOUTER DONE\nDONE HALT\n.END\n",
+    );
+
+    let out = run_bin(
+        "lc3-assembler",
+        &dir.0,
+        ["--check", "--no-color", "nest.asm"],
+    );
+    let stderr = stderr(&out);
+    assert!(
+        stderr.contains(
+            "  = note: in expansion of macro OUTER invoked at nest.asm:9\n  \
+             = note: in expansion of macro INNER invoked at nest.asm:6\n"
+        ),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("nest.asm:10"), "{stderr}");
+}
+
+#[test]
+fn diagnostics_underline_operands_and_first_definitions() {
+    let dir = ScratchDir::new("underline");
+    dir.write(
+        "dup.asm",
+        ".ORIG x3000\nLOOP ADD R0, R0, #100\nLOOP BRnzp LOOP\n.END\n",
+    );
+    let out = run_bin(
+        "lc3-assembler",
+        &dir.0,
+        ["--check", "--no-color", "dup.asm"],
+    );
+    let stderr = stderr(&out);
+    assert!(
+        stderr.contains(" 2 | LOOP ADD R0, R0, #100\n   |                  ^^^^\n"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(" 2 | LOOP ADD R0, R0, #100\n   | ---- first defined here\n"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn emit_rel_writes_a_relocatable_object() {
+    use lc3_assembler::relocatable::RelocatableObject;
+
+    let dir = ScratchDir::new("emit-rel");
+    dir.write(
+        "main.asm",
+        ".EXTERNAL PRINT\n.GLOBAL MAIN\n.ORIG x3000\nMAIN JSR PRINT\nHALT\n.END\n",
+    );
+    let assemble =
+        |extra: &[&str]| run_bin("lc3-assembler", &dir.0, [extra, &["main.asm"]].concat());
+
+    // A plain .obj cannot leave PRINT unresolved.
+    let out = assemble(&["--no-color"]);
+    assert!(!out.status.success());
+    assert!(stderr(&out).contains("declared .EXTERNAL"));
+
+    let out = assemble(&["--emit", "rel"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let obj = RelocatableObject::read(&dir.read("main.rel")).unwrap();
+    assert_eq!(obj.sections[0].words, vec![0x4800, 0xF025]);
+    assert_eq!(obj.relocations[0].symbol, "PRINT");
+    assert!(obj.symbols.iter().any(|s| s.name == "MAIN" && s.global));
+}
+
+#[test]
+fn split_segments_rejects_other_emit_formats() {
+    let dir = ScratchDir::new("split");
+    dir.write(
+        "two.asm",
+        ".ORIG x3000\nHALT\n.END\n.ORIG x4000\n.FILL #1\n.END\n",
+    );
+    for emit in ["hex", "rel"] {
+        let out = run_bin(
+            "lc3-assembler",
+            &dir.0,
+            ["--split-segments", "--emit", emit, "two.asm"],
+        );
+        assert_eq!(out.status.code(), Some(1), "--emit {emit}");
+        assert!(stderr(&out).contains("only applies to --emit obj"));
+    }
+
+    let out = run_bin("lc3-assembler", &dir.0, ["--split-segments", "two.asm"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(dir.read("two_x3000.obj"), vec![0x30, 0x00, 0xF0, 0x25]);
+    assert_eq!(dir.read("two_x4000.obj"), vec![0x40, 0x00, 0x00, 0x01]);
+}
+
+#[test]
+fn lc3_link_combines_objects() {
+    let dir = ScratchDir::new("link");
+    dir.write(
+        "main.asm",
+        ".EXTERNAL PRINT\n.ORIG x3000\nJSR PRINT\nHALT\n.END\n",
+    );
+    dir.write(
+        "print.asm",
+        ".GLOBAL PRINT\n.ORIG x3000\nPRINT PUTS\nRET\n.END\n",
+    );
+    for name in ["main.asm", "print.asm"] {
+        let out = run_bin("lc3-assembler", &dir.0, ["--emit", "rel", name]);
+        assert!(out.status.success(), "{}", stderr(&out));
+    }
+    let link = |extra: &[&str]| {
+        let args = [&["main.rel", "print.rel", "-o", "prog.obj"], extra].concat();
+        run_bin("lc3-link", &dir.0, args)
+    };
+
+    // JSR reaches at most 1024 words ahead.
+    let out = link(&["--base", "print=x4000"]);
+    assert!(!out.status.success());
+    let stderr = stderr(&out);
+    assert!(stderr.contains("exceeds 11-bit range"), "{stderr}");
+    assert!(stderr.contains("main.asm:3:5: referenced here"), "{stderr}");
+    assert!(
+        stderr.contains("print.asm:3:1: 'PRINT' placed at x4000"),
+        "{stderr}"
+    );
+
+    dir.write("layout.ld", "; layout\nprint x3100\n");
+    let out = link(&["-T", "layout.ld"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let segments = lc3_assembler::obj::read(&dir.read("prog.obj")).unwrap();
+    assert_eq!(segments[0].words, vec![0x48FF, 0xF025]);
+    assert_eq!(segments[1].origin, 0x3100);
+    let sym = String::from_utf8(dir.read("prog.sym")).unwrap();
+    assert!(sym.contains("PRINT=x3100"), "{sym}");
+    let map = String::from_utf8(dir.read("prog.map")).unwrap();
+    assert!(map.contains("x3100  x3101"), "{map}");
+}
+
+#[test]
+fn define_flag_selects_conditional_blocks() {
+    let dir = ScratchDir::new("define");
+    dir.write(
+        "prog.asm",
+        ".ORIG x3000\n.IFDEF DEBUG\n    LEA R0, TRACE\n    PUTS\n.ENDIF\n    HALT\n\
+         .IFDEF DEBUG\n.IF DEBUG-1\nTRACE .STRINGZ \"t\"\n.ENDIF\n.ENDIF\n.END\n",
+    );
+    let assemble = |extra: &[&str]| {
+        let args = [&["--no-color", "-o", "prog.obj"], extra, &["prog.asm"]].concat();
+        run_bin("lc3-assembler", &dir.0, args)
+    };
+
+    let out = assemble(&[]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    assert_eq!(dir.read("prog.obj"), vec![0x30, 0x00, 0xF0, 0x25]);
+
+    let out = assemble(&["-D", "DEBUG=2"]);
+    assert!(out.status.success(), "{}", stderr(&out));
+    let words = dir.read("prog.obj");
+    assert_eq!(words.len(), 2 * (1 + 5), "{words:?}");
+
+    // With DEBUG=1 the inner `.IF DEBUG-1` is false, so TRACE is missing; the
+    // error points at the line as written.
+    let out = assemble(&["-DDEBUG"]);
+    assert!(!out.status.success());
+    let stderr = stderr(&out);
+    assert!(stderr.contains("prog.asm:3:13"), "{stderr}");
+}
+
+#[test]
+fn macros_with_local_labels_and_repetition_assemble() {
+    use lc3_assembler::assembly::{assemble_source, AssembleOptions};
+
+    let source = "\
+.MACRO DELAY %N=#2
+        ADD R0, R0, %N
+%%WAIT  ADD R0, R0, #-1
+        BRp %%WAIT
+.ENDM
+.MACRO TWICE
+        DELAY
+        DELAY N=#3
+.ENDM
+.ORIG x3000
+        TWICE
+.IRP %R, R1, R2
+        AND %R, %R, #0
+.ENDR
+.REPT 2
+        HALT
+.ENDR
+.END
+";
+    let asm = assemble_source(source, "delay.asm", &AssembleOptions::default());
+    assert!(!asm.has_errors(), "{:?}", asm.errors);
+    assert_eq!(
+        asm.encoded.segments[0].words,
+        vec![
+            0x1022, 0x103F, 0x03FE, // DELAY
+            0x1023, 0x103F, 0x03FE, // DELAY N=#3
+            0x5260, 0x54A0, // .IRP
+            0xF025, 0xF025, // .REPT
+        ]
+    );
+}
+
+#[test]
+fn debug_info_sidecar_drives_the_disassembler() {
+    use lc3_assembler::debug_info::{DebugInfo, WordKind};
+
+    let dir = ScratchDir::new("debug-info");
+    dir.write(
+        "prog.asm",
+        ".ORIG x3000\nLD R0, K\nHALT\nK .FILL x1021\n.FILL x0FFE\n.END\n",
+    );
+    let out = run_bin(
+        "lc3-assembler",
+        &dir.0,
+        ["prog.asm", "--debug-info", "prog.dbg"],
+    );
+    assert!(out.status.success(), "{}", stderr(&out));
+
+    let info = DebugInfo::parse(&String::from_utf8(dir.read("prog.dbg")).unwrap()).unwrap();
+    assert_eq!(info.files, ["prog.asm"]);
+    assert_eq!(info.word(0x3002).map(|w| (w.line, w.col)), Some((4, 1)));
+    assert_eq!(
+        info.ranges(),
+        [
+            (0x3000, 0x3001, WordKind::Code),
+            (0x3002, 0x3003, WordKind::Data)
+        ]
+    );
+
+    let out = run_bin("lc3-disasm", &dir.0, ["prog.obj", "-g", "prog.dbg"]);
+    let asm = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        asm.contains(".FILL x1021") && asm.contains(".FILL x0FFE"),
+        "{asm}"
+    );
+    assert!(!asm.contains("ADD"), "{asm}");
+}
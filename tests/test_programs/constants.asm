@@ -0,0 +1,18 @@
+; .EQU and .SET constants
+WORDS   .EQU #3
+MASK    .EQU xFFFF
+PUTCHAR .EQU x21
+FIELD   .EQU WORDS-2
+
+        .ORIG x3000
+STEP    .SET #2
+        ADD R1, R1, STEP
+        LDR R2, R1, FIELD
+        TRAP PUTCHAR
+STEP    .SET STEP+1
+        ADD R1, R1, STEP
+        HALT
+        .FILL MASK
+BUF     .BLKW WORDS
+TAIL    .FILL BUF
+        .END
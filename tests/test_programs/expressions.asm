@@ -0,0 +1,15 @@
+; Constant expressions in operands
+        .ORIG x3000
+START   LEA R0, TABLE+3             ; address of the last table entry
+        ADD R1, R1, #-(TEND-TABLE)  ; minus the table length
+        LDR R2, R0, #(TEND-TABLE-1)
+        HALT
+SIZE    .FILL TEND-TABLE
+TABLE   .FILL #1
+        .FILL #2
+        .FILL #3
+        .FILL #4
+TEND
+BUF     .BLKW (TEND-TABLE)*2        ; two words per table entry
+AFTER   .FILL START
+        .END
@@ -0,0 +1,12 @@
+; Code and data in separate sections
+        .ORIG x3000
+MAIN    LD R0, PTR          ; R0 <- address of COUNT
+        LDR R1, R0, #0      ; R1 <- COUNT
+        HALT
+PTR     .FILL COUNT
+        .END
+
+        .ORIG x4000
+COUNT   .FILL #5
+RESULT  .BLKW #1
+        .END